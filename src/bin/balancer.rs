@@ -1,12 +1,17 @@
 #[macro_use]
 extern crate log;
 
+// Send SIGHUP to reload the config. Routes and backends are updated in place, without
+// dropping connections, but the listen address, port, and TLS settings can't be changed
+// on reload.
+
 use std::fs;
 
 use argh::FromArgs;
+use tokio::signal::unix::{signal, SignalKind};
 
 use hype::{
-    lb::{picker::RRPicker, Backend, Http, HttpBackend},
+    lb::Routes,
     lbconfig::{self},
     server::Server,
 };
@@ -19,41 +24,53 @@ struct Args {
     config: String,
 }
 
-fn build_backend(backend: &lbconfig::Backend) -> HttpBackend {
-    let mut b = HttpBackend::new(format!("{}:{}", backend.host, backend.port));
-    if backend.enable_tls {
-        b.enable_tls(backend.host.clone());
-    }
-    b
-}
-
 #[tokio::main]
 async fn main() {
-    hype::logger::init();
     let args: Args = argh::from_env();
 
-    let input = fs::read_to_string(args.config).unwrap();
+    let input = fs::read_to_string(&args.config).unwrap();
     let config = lbconfig::Config::from(input).unwrap();
+    hype::logger::init_with_level(config.server.log_level);
     debug!("Config: {:?}", config);
 
-    let mut server = Server::new(config.server.listen_ip, config.server.port);
+    let mut server = Server::new(&config.server.listen_ip, config.server.port);
     if config.server.enable_tls {
         server.enable_tls(
-            config.server.tls_cert_file.into(),
-            config.server.tls_key_file.into(),
+            config.server.tls_cert_file.clone().into(),
+            config.server.tls_key_file.clone().into(),
         );
     }
 
-    for route in config.routes {
-        let backends: Vec<HttpBackend> = route.backends.iter().map(build_backend).collect();
+    let routes = Routes::new();
+    routes.apply(config.routes).await;
+    server.route_default(routes.clone());
 
-        let mut balancer = Http::new(backends, RRPicker::new());
-        if let Some(host_header) = route.host_header {
-            balancer.rewrite_header("host", host_header);
+    let mut hangup = signal(SignalKind::hangup()).unwrap();
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Reloading {}", args.config);
+            let reloaded = fs::read_to_string(&args.config)
+                .map_err(|e| e.to_string())
+                .and_then(|input| lbconfig::Config::from(input).map_err(|e| e.to_string()));
+
+            let reloaded = match reloaded {
+                Ok(reloaded) => reloaded,
+                Err(e) => {
+                    error!("Could not reload {}: {}", args.config, e);
+                    continue;
+                }
+            };
+
+            if reloaded.server.listen_ip != config.server.listen_ip
+                || reloaded.server.port != config.server.port
+                || reloaded.server.enable_tls != config.server.enable_tls
+            {
+                warn!("The listen address and TLS settings can't change on reload, restart to apply them");
+            }
+
+            routes.apply(reloaded.routes).await;
         }
+    });
 
-        let lb = hype::handlers::Lb::new(balancer);
-        server.route(route.location, lb);
-    }
     server.start().await.unwrap();
 }
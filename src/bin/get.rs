@@ -42,7 +42,7 @@ async fn main() {
     let mut client = client.connect().await.unwrap();
     let request = Request::new(hype::request::Method::GET, "/");
 
-    let response = client.send_request(&request).await.unwrap();
+    let response = client.send_request_decoded(&request).await.unwrap();
     info!("Status:\n {:?}", response.serialize_status());
     info!("Headers:\n {:?}", response.headers.serialize());
     info!("Body:\n {:?}", response.content().await);
@@ -3,6 +3,7 @@ extern crate log;
 
 use argh::FromArgs;
 
+use hype::body::Body;
 use hype::handler::{self};
 use hype::response::Response;
 use hype::{handlers, status};
@@ -32,7 +33,7 @@ struct Args {
     key_file: String,
 }
 
-async fn hello1(_r: Request) -> Result<impl Into<String>, handler::Error> {
+async fn hello1(_r: Request) -> Result<impl Into<Body>, handler::Error> {
     Ok("Hello world!")
 }
 
@@ -2,16 +2,24 @@
 extern crate log;
 
 // Test with:
-//   curl -d '{ "host": "foobar", "port": 3000 }'  -H "x-hype-auth-token: foo" -X POST http://localhost:5000/backends
-//   curl -H "x-hype-auth-token: foo" http://localhost:5000/backends/backend-ayGoPVg
+//   curl -H "content-type: application/json" -d '{ "host": "foobar", "port": 3000 }'  -H "authorization: Bearer foo" -X POST http://localhost:5000/backends
+//   curl -H "authorization: Bearer foo" http://localhost:5000/backends/backend-ayGoPVg
+//   curl -H "content-type: application/json" -d '{ "location": "/app", "backends": ["backend-ayGoPVg"] }' -H "authorization: Bearer foo" -X POST http://localhost:5000/routes
+//   curl -H "authorization: Bearer foo" -X POST http://localhost:5000/routes/rt-abcdefg/backends/backend-ayGoPVg
+//   curl -H "authorization: Bearer foo" -X DELETE http://localhost:5000/backends/backend-ayGoPVg
+//
+// Routes are served on the load balancer port (4000 by default), and changes take effect
+// right away.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use argh::FromArgs;
+use async_trait::async_trait;
 
 use hype::{
-    handler::{self, Action},
-    handlers::{self},
+    handler::{self, Action, AsyncWriteStream, Handler},
+    handlers::{self, Lb},
+    lb::{picker::RRPicker, Backend, Http, HttpBackend},
     lbconfig::{self, BackendId, RouteId},
     middleware::Stack,
     request::{Method, Request},
@@ -31,26 +39,10 @@ struct Args {
     /// admin server port
     #[argh(option, short = 'p', default = "5000")]
     port: u16,
-}
-
-/// A very basic auth mechanism used for development.
-#[derive(Clone, Debug, Default)]
-struct AuthState {
-    token: String,
-}
-
-async fn auth(r: Request, state: AuthState) -> Result<Action, handler::Error> {
-    let token = r
-        .headers
-        .get_first("x-hype-auth-token")
-        .ok_or(handler::Error::Status(status::UNAUTHORIZED.into()))?;
 
-    if *token != state.token {
-        return Err(handler::Error::Status((401, "Unauthorized").into()));
-    }
-
-    // Don't return a response as yet, simply pass the request on to the next handler.
-    Ok(Action::Next)
+    /// load balancer port
+    #[argh(option, short = 'l', default = "4000")]
+    lb_port: u16,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -62,24 +54,80 @@ struct RouteConfig {
     pub backends: Vec<BackendId>,
 }
 
-#[derive(Debug, Clone, Default)]
+/// The running balancers, keyed by route location. Requests go to the route with the
+/// longest location that prefixes their path.
+#[derive(Clone, Default)]
+struct LiveRoutes {
+    balancers: Arc<RwLock<HashMap<String, Lb<RRPicker>>>>,
+}
+
+fn matches_location(path: &str, location: &str) -> bool {
+    match path.strip_prefix(location) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/') || location.ends_with('/'),
+        None => false,
+    }
+}
+
+#[async_trait]
+impl Handler for LiveRoutes {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<Action, handler::Error> {
+        let path = r.abs_path();
+        let lb = self
+            .balancers
+            .read()
+            .await
+            .iter()
+            .filter(|(location, _)| matches_location(&path, location))
+            .max_by_key(|(location, _)| location.len())
+            .map(|(_, lb)| lb.clone())
+            .ok_or(handler::Error::Status(status::NOT_FOUND.into()))?;
+
+        lb.handle(r, w).await
+    }
+}
+
+#[derive(Clone, Default)]
 struct AppState {
     backends: Arc<RwLock<HashMap<lbconfig::BackendId, lbconfig::Backend>>>,
     routes: Arc<RwLock<HashMap<String, RouteConfig>>>,
+    live: LiveRoutes,
+}
+
+fn address(backend: &lbconfig::Backend) -> String {
+    format!("{}:{}", backend.host, backend.port)
+}
+
+fn build_backend(backend: &lbconfig::Backend) -> HttpBackend {
+    let mut b = HttpBackend::new(address(backend));
+    if backend.enable_tls {
+        b.enable_tls(backend.host.clone());
+    }
+    b
+}
+
+fn param(r: &Request, name: &str) -> Result<String, handler::Error> {
+    r.params
+        .get(name)
+        .cloned()
+        .ok_or(handler::Error::Failed(format!(
+            "missing parameter: {}",
+            name
+        )))
 }
 
 async fn add_backend(r: Request, state: AppState) -> Result<String, handler::Error> {
-    let backend: lbconfig::Backend = handlers::service::json(&r.body.content().await)?;
+    let backend: lbconfig::Backend = r.json().await?;
     let id = backend.id.clone();
     state.backends.write().await.insert(id.clone(), backend);
     Ok(format!("Got backend: {:?}", id))
 }
 
 async fn get_backend(r: Request, state: AppState) -> Result<String, handler::Error> {
-    let id = r
-        .params
-        .get("id")
-        .ok_or(handler::Error::Failed("missing parameter: id".to_string()))?;
+    let id = param(&r, "id")?;
 
     let lock = state.backends.read().await;
     let backend = lock
@@ -89,19 +137,96 @@ async fn get_backend(r: Request, state: AppState) -> Result<String, handler::Err
     Ok(format!("{:#?}", backend))
 }
 
+/// Remove a backend, and stop routing requests to it.
+async fn remove_backend(r: Request, state: AppState) -> Result<String, handler::Error> {
+    let id: BackendId = BackendId(param(&r, "id")?);
+    let backend = state
+        .backends
+        .write()
+        .await
+        .remove(&id)
+        .ok_or(handler::Error::Status(status::NOT_FOUND.into()))?;
+
+    let mut routes = state.routes.write().await;
+    let balancers = state.live.balancers.read().await;
+    for route in routes.values_mut() {
+        if !route.backends.contains(&id) {
+            continue;
+        }
+
+        route.backends.retain(|b| *b != id);
+        if let Some(lb) = balancers.get(&route.location) {
+            lb.balancer()
+                .read()
+                .await
+                .remove_backend(&address(&backend))
+                .await;
+        }
+    }
+
+    Ok(format!("Removed backend: {:?}", id))
+}
+
 async fn add_route(r: Request, state: AppState) -> Result<String, handler::Error> {
-    let route: RouteConfig = handlers::service::json(&r.body.content().await)?;
+    let route: RouteConfig = r.json().await?;
 
-    {
+    let backends = {
         let lock = state.backends.read().await;
-        if route.backends.iter().any(|b| !lock.contains_key(b)) {
-            return Err(handler::Error::Status(status::NOT_FOUND.into()));
-        }
+        route
+            .backends
+            .iter()
+            .map(|b| lock.get(b).map(build_backend))
+            .collect::<Option<Vec<HttpBackend>>>()
+            .ok_or(handler::Error::Status(status::NOT_FOUND.into()))?
+    };
+
+    let mut balancer = Http::new(backends, RRPicker::new());
+    if let Some(host_header) = &route.host_header {
+        balancer.rewrite_header("host", host_header);
     }
+    state
+        .live
+        .balancers
+        .write()
+        .await
+        .insert(route.location.clone(), Lb::new(balancer));
 
     let id = route.id.clone();
     state.routes.write().await.insert(id.clone().into(), route);
-    Ok(format!("Got backend: {:?}", id))
+    Ok(format!("Got route: {:?}", id))
+}
+
+/// Add an existing backend to a running route.
+async fn add_route_backend(r: Request, state: AppState) -> Result<String, handler::Error> {
+    let id = param(&r, "id")?;
+    let backend_id = BackendId(param(&r, "backend")?);
+
+    let backend = state
+        .backends
+        .read()
+        .await
+        .get(&backend_id)
+        .cloned()
+        .ok_or(handler::Error::Status(status::NOT_FOUND.into()))?;
+
+    let mut routes = state.routes.write().await;
+    let route = routes
+        .get_mut(&id)
+        .ok_or(handler::Error::Status(status::NOT_FOUND.into()))?;
+
+    if let Some(lb) = state.live.balancers.read().await.get(&route.location) {
+        lb.balancer()
+            .read()
+            .await
+            .add_backend(build_backend(&backend))
+            .await;
+    }
+    route.backends.push(backend_id.clone());
+
+    Ok(format!(
+        "Added backend {:?} to route {:?}",
+        backend_id, route.id
+    ))
 }
 
 #[tokio::main]
@@ -114,14 +239,21 @@ async fn main() {
 
     let middleware = Stack::new()
         .push(handlers::log())
-        .push(handlers::service(auth).with_state(&AuthState {
-            token: "foo".to_string(),
-        }));
+        .push(handlers::RateLimit::new(100, Duration::from_secs(60)))
+        // A very basic auth mechanism used for development.
+        .push(handlers::BearerAuth::new(
+            |token| async move { token == "foo" },
+        ));
 
-    let state = AppState {
-        backends: Arc::new(RwLock::new(HashMap::new())),
-        routes: Arc::new(RwLock::new(HashMap::new())),
-    };
+    let state = AppState::default();
+
+    let mut lb_server = Server::new(&args.host, args.lb_port);
+    lb_server.route_default(state.live.clone());
+    info!(
+        "Starting hype load balancer on {}:{}",
+        args.host, args.lb_port
+    );
+    tokio::spawn(async move { lb_server.start().await.unwrap() });
 
     server.route_method(
         Method::POST,
@@ -139,6 +271,14 @@ async fn main() {
             .push(handlers::service(get_backend).with_state(&state)),
     );
 
+    server.route_method(
+        Method::DELETE,
+        "/backends/:id",
+        middleware
+            .clone()
+            .push(handlers::service(remove_backend).with_state(&state)),
+    );
+
     server.route_method(
         Method::POST,
         "/routes",
@@ -147,6 +287,14 @@ async fn main() {
             .push(handlers::service(add_route).with_state(&state)),
     );
 
+    server.route_method(
+        Method::POST,
+        "/routes/:id/backends/:backend",
+        middleware
+            .clone()
+            .push(handlers::service(add_route_backend).with_state(&state)),
+    );
+
     server.route_default(handlers::NotFoundHandler());
     server.start().await.unwrap();
 }
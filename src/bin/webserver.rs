@@ -36,17 +36,16 @@ impl Handler for MyHandler {
 
 #[tokio::main]
 async fn main() {
-    hype::logger::init();
-
     let args: Vec<String> = std::env::args().collect();
 
     if args.len() < 2 {
-        error!("Usage: webserver [/path/to/config.yaml]");
+        eprintln!("Usage: webserver [/path/to/config.yaml]");
         std::process::exit(255);
     }
 
     let input = fs::read_to_string(&args[1]).unwrap();
     let config = Config::from(input).expect("bad configuration file");
+    hype::logger::init_with_level(config.server.log_level);
 
     info!("Starting hype...");
     debug!("config: {:?}", config);
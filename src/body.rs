@@ -2,13 +2,17 @@
 /// responses. It supports chunked encoding, and can be used to stream data to and from the
 /// server.
 use std::{
-    error, fmt,
+    error, fmt, io,
+    path::Path,
     pin::Pin,
-    sync::{Arc, RwLock},
-    task::{Context, Poll, Waker},
+    sync::{Arc, Mutex, RwLock},
+    task::{ready, Context, Poll, Waker},
 };
 
-use futures::{Stream, StreamExt};
+use futures::{Stream, StreamExt, TryStreamExt};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::headers::Headers;
 
 #[derive(Debug)]
 pub enum BodyError {
@@ -33,11 +37,33 @@ impl fmt::Display for BodyError {
 
 impl error::Error for BodyError {}
 
-/// We have two body types, based on their encoding: Chunked and Full.
+/// The size of the chunks that bodies are read from readers in.
+pub const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// We have three body types: Chunked and Full, based on their encoding, and Reader for
+/// bodies that are streamed from an async reader (e.g., a file) as they're consumed.
 #[derive(Debug, Clone)]
 enum Content {
     Chunked(Arc<RwLock<ChunkState>>),
     Full(Arc<RwLock<ContentState>>),
+    Reader(Arc<Mutex<ReaderState>>),
+}
+
+struct ReaderState {
+    // None once the reader is exhausted, or failed.
+    reader: Option<Pin<Box<dyn AsyncRead + Send>>>,
+
+    // The content of the reader, once it's been read into memory by `Body::buffer`.
+    buffered: Option<Arc<RwLock<ContentState>>>,
+}
+
+impl fmt::Debug for ReaderState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReaderState")
+            .field("done", &self.reader.is_none())
+            .field("buffered", &self.buffered.is_some())
+            .finish()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -48,6 +74,9 @@ struct ChunkState {
     // no more chunks
     complete: bool,
 
+    // trailer fields, sent after the last chunk
+    trailers: Headers,
+
     // wakers for stream futures
     wakers: Vec<Waker>,
 }
@@ -57,6 +86,7 @@ impl ChunkState {
         ChunkState {
             chunks: vec![],
             complete: false,
+            trailers: Headers::new(),
             wakers: vec![],
         }
     }
@@ -79,13 +109,11 @@ impl ContentState {
     }
 }
 
-impl<T: Into<String>> From<T> for ContentState {
-    fn from(val: T) -> Self {
-        let val = val.into();
-
+impl From<Vec<u8>> for ContentState {
+    fn from(val: Vec<u8>) -> Self {
         Self {
-            content: val.as_bytes().to_vec(),
             expected_length: val.len(),
+            content: val,
             wakers: vec![],
         }
     }
@@ -96,14 +124,38 @@ pub struct Body {
     content: Content,
 }
 
-impl<T: Into<String>> From<T> for Body {
-    fn from(val: T) -> Self {
+impl From<Vec<u8>> for Body {
+    fn from(val: Vec<u8>) -> Self {
         Self {
             content: Content::Full(Arc::new(RwLock::new(ContentState::from(val)))),
         }
     }
 }
 
+impl From<&[u8]> for Body {
+    fn from(val: &[u8]) -> Self {
+        Body::from(val.to_vec())
+    }
+}
+
+impl From<String> for Body {
+    fn from(val: String) -> Self {
+        Body::from(val.into_bytes())
+    }
+}
+
+impl From<&String> for Body {
+    fn from(val: &String) -> Self {
+        Body::from(val.as_bytes().to_vec())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(val: &str) -> Self {
+        Body::from(val.as_bytes().to_vec())
+    }
+}
+
 impl Body {
     pub fn new() -> Self {
         Self {
@@ -111,6 +163,56 @@ impl Body {
         }
     }
 
+    /// Create a body that's read from `reader` in chunks of up to `READ_CHUNK_SIZE` bytes as
+    /// it's streamed, instead of being buffered in memory. The body can only be consumed once,
+    /// and clones share the reader.
+    pub fn from_reader(reader: impl AsyncRead + Send + 'static) -> Self {
+        Self {
+            content: Content::Reader(Arc::new(Mutex::new(ReaderState {
+                reader: Some(Box::pin(reader)),
+                buffered: None,
+            }))),
+        }
+    }
+
+    /// Create a body that streams the file at `path`.
+    pub async fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_reader(tokio::fs::File::open(path).await?))
+    }
+
+    /// Returns true if the body is streamed from a reader, and so can only be read once.
+    /// Reader bodies that have been read into memory with `buffer` don't count.
+    pub fn is_reader(&self) -> bool {
+        matches!(self.content, Content::Reader(_)) && self.buffered().is_none()
+    }
+
+    /// Returns the in-memory content of a buffered reader body.
+    fn buffered(&self) -> Option<Arc<RwLock<ContentState>>> {
+        match &self.content {
+            Content::Reader(state) => state.lock().unwrap().buffered.clone(),
+            _ => None,
+        }
+    }
+
+    /// Read a reader body into memory, so it can be read again (e.g., by a handler after a
+    /// middleware that verifies it.) This waits for the whole body and keeps it in memory, so
+    /// it defeats streaming. Call it before anything else reads the body, since whatever has
+    /// been read already is gone. Other bodies are kept in memory anyway, so this does nothing
+    /// for them, and neither does calling it again.
+    pub async fn buffer(&self) -> io::Result<()> {
+        let Content::Reader(state) = &self.content else {
+            return Ok(());
+        };
+
+        if self.buffered().is_some() {
+            return Ok(());
+        }
+
+        let content: Vec<u8> = self.try_stream().try_concat().await?;
+        state.lock().unwrap().buffered = Some(Arc::new(RwLock::new(ContentState::from(content))));
+        Ok(())
+    }
+
     pub fn set_chunked(&mut self) {
         self.content = Content::Chunked(Arc::new(RwLock::new(ChunkState::new())));
     }
@@ -119,6 +221,7 @@ impl Body {
         match &self.content {
             Content::Full(state) => state.write().unwrap().expected_length = length,
             Content::Chunked(_) => panic!("chunked body"),
+            Content::Reader(_) => panic!("reader body"),
         }
     }
 
@@ -132,7 +235,7 @@ impl Body {
 
     pub fn push_chunk(&self, chunk: Vec<u8>) {
         match &self.content {
-            Content::Full(_) => panic!("not chunked"),
+            Content::Full(_) | Content::Reader(_) => panic!("not chunked"),
             Content::Chunked(state) => {
                 // Notify async stream wakers that there's a new chunk
                 let mut wakers = vec![];
@@ -148,7 +251,7 @@ impl Body {
 
     pub fn end_chunked(&self) {
         match &self.content {
-            Content::Full(_) => panic!("not chunked"),
+            Content::Full(_) | Content::Reader(_) => panic!("not chunked"),
             Content::Chunked(state) => {
                 let mut wakers = vec![];
                 {
@@ -165,6 +268,24 @@ impl Body {
         }
     }
 
+    /// Set the trailer fields of a chunked body. They're sent after the last chunk, so set
+    /// them before calling `end_chunked`.
+    pub fn set_trailers(&self, trailers: Headers) {
+        match &self.content {
+            Content::Full(_) | Content::Reader(_) => panic!("not chunked"),
+            Content::Chunked(state) => state.write().unwrap().trailers = trailers,
+        }
+    }
+
+    /// Returns the trailer fields received after the last chunk. These are only available
+    /// once the body is complete, and are always empty for non-chunked bodies.
+    pub fn trailers(&self) -> Headers {
+        match &self.content {
+            Content::Full(_) | Content::Reader(_) => Headers::new(),
+            Content::Chunked(state) => state.read().unwrap().trailers.clone(),
+        }
+    }
+
     /// Returns true if the body is complete.
     pub fn complete(&self) -> bool {
         match &self.content {
@@ -173,6 +294,7 @@ impl Body {
                 state.content.len() >= state.expected_length
             }
             Content::Chunked(state) => state.read().unwrap().complete,
+            Content::Reader(state) => state.lock().unwrap().reader.is_none(),
         }
     }
 
@@ -199,17 +321,63 @@ impl Body {
                 Ok(done)
             }
             Content::Chunked(_) => panic!("chunked body"),
+            Content::Reader(_) => panic!("reader body"),
+        }
+    }
+
+    /// Returns the length the body is expected to have, i.e., its Content-Length. This is
+    /// None for chunked and reader bodies, whose length isn't known until they're complete
+    /// (unless a reader body has been buffered.)
+    pub fn expected_len(&self) -> Option<usize> {
+        if let Some(buffered) = self.buffered() {
+            return Some(buffered.read().unwrap().expected_length);
+        }
+
+        match &self.content {
+            Content::Full(state) => Some(state.read().unwrap().expected_length),
+            Content::Chunked(_) | Content::Reader(_) => None,
         }
     }
 
-    /// Return as much of the body as is available.
+    /// Returns the number of bytes of the body that are available now, i.e., the length of
+    /// `try_content`.
+    pub fn buffered_len(&self) -> usize {
+        if let Some(buffered) = self.buffered() {
+            return buffered.read().unwrap().content.len();
+        }
+
+        match &self.content {
+            Content::Full(state) => state.read().unwrap().content.len(),
+            Content::Chunked(state) => state.read().unwrap().chunks.iter().map(Vec::len).sum(),
+            Content::Reader(_) => 0,
+        }
+    }
+
+    /// Returns true if the body is known to be empty, i.e., it has a zero length, or it's
+    /// complete without any content. Bodies that are still streaming aren't empty, and
+    /// neither are (unbuffered) reader bodies, whose content isn't kept.
+    pub fn is_empty(&self) -> bool {
+        match self.expected_len() {
+            Some(len) => len == 0,
+            None if self.is_reader() => false,
+            None => self.complete() && self.buffered_len() == 0,
+        }
+    }
+
+    /// Return as much of the body as is available. This is always empty for reader bodies,
+    /// unless they're buffered.
     pub fn try_content(&self) -> Vec<u8> {
+        if let Some(buffered) = self.buffered() {
+            return buffered.read().unwrap().content.clone();
+        }
+
         match &self.content {
             Content::Full(body) => body.read().unwrap().content.clone(),
             Content::Chunked(state) => {
                 let chunk_state = state.read().unwrap();
                 chunk_state.chunks.concat()
             }
+            Content::Reader(_) => vec![],
         }
     }
 
@@ -249,20 +417,43 @@ impl Body {
     }
 
     pub fn stream(&self) -> Pin<Box<dyn Stream<Item = Vec<u8>> + Send + Sync>> {
-        if let Content::Full(_) = &self.content {
-            Box::pin(self.content_stream())
-        } else {
-            Box::pin(self.chunk_stream())
+        if let Some(buffered) = self.buffered() {
+            return Box::pin(ContentStream {
+                state: buffered,
+                current_pos: 0,
+            });
+        }
+
+        match &self.content {
+            Content::Full(_) => Box::pin(self.content_stream()),
+            Content::Chunked(_) => Box::pin(self.chunk_stream()),
+            // End the stream on read errors. Use `try_stream` to see them.
+            Content::Reader(_) => Box::pin(self.try_stream().scan((), |_, chunk| {
+                futures::future::ready(chunk.map_err(|e| warn!("error reading body: {}", e)).ok())
+            })),
         }
     }
 
     pub fn raw_stream(&self) -> Pin<Box<dyn Stream<Item = Vec<u8>> + Send + Sync>> {
-        if let Content::Full(_) = &self.content {
-            Box::pin(self.content_stream())
-        } else {
-            let mut stream = self.chunk_stream();
-            stream.raw = true;
-            Box::pin(stream)
+        match &self.content {
+            Content::Chunked(_) => {
+                let mut stream = self.chunk_stream();
+                stream.raw = true;
+                Box::pin(stream)
+            }
+            _ => self.stream(),
+        }
+    }
+
+    /// Same as `stream`, but also returns errors from reading reader bodies. The stream
+    /// ends after an error.
+    pub fn try_stream(&self) -> Pin<Box<dyn Stream<Item = io::Result<Vec<u8>>> + Send + Sync>> {
+        match &self.content {
+            Content::Reader(state) if self.buffered().is_none() => Box::pin(ReaderStream {
+                state: Arc::clone(state),
+                buf: vec![],
+            }),
+            _ => Box::pin(self.stream().map(Ok)),
         }
     }
 }
@@ -296,9 +487,14 @@ impl Stream for ChunkStream {
                     chunk_state.wakers.push(cx.waker().clone());
                     return_val = Some(Poll::Pending);
                 } else if self.raw && !done {
-                    // No more chunks, send closing '0' chunk
+                    // No more chunks, send closing '0' chunk, and the trailers
                     done = true;
-                    let chunk = vec![b'0', b'\r', b'\n', b'\r', b'\n'];
+                    let mut chunk = b"0\r\n".to_vec();
+                    if !chunk_state.trailers.is_empty() {
+                        chunk.extend(chunk_state.trailers.serialize().as_bytes());
+                        chunk.extend(b"\r\n");
+                    }
+                    chunk.extend(b"\r\n");
                     return_val = Some(Poll::Ready(Some(chunk)));
                 } else {
                     // Closing chunk sent, close stream
@@ -332,6 +528,42 @@ impl Stream for ChunkStream {
     }
 }
 
+pub struct ReaderStream {
+    state: Arc<Mutex<ReaderState>>,
+
+    /// Reused across reads, so polls that aren't ready don't allocate.
+    buf: Vec<u8>,
+}
+
+impl Stream for ReaderStream {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let mut state = this.state.lock().unwrap();
+        let Some(reader) = state.reader.as_mut() else {
+            return Poll::Ready(None);
+        };
+
+        this.buf.resize(READ_CHUNK_SIZE, 0);
+        let mut read_buf = ReadBuf::new(&mut this.buf);
+        let result = ready!(reader.as_mut().poll_read(cx, &mut read_buf));
+        let n = read_buf.filled().len();
+
+        match result {
+            Ok(()) if n > 0 => Poll::Ready(Some(Ok(this.buf[..n].to_vec()))),
+            Ok(()) => {
+                state.reader = None;
+                Poll::Ready(None)
+            }
+            Err(e) => {
+                state.reader = None;
+                Poll::Ready(Some(Err(e)))
+            }
+        }
+    }
+}
+
 pub struct ContentStream {
     state: Arc<RwLock<ContentState>>,
     current_pos: usize,
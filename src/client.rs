@@ -1,20 +1,45 @@
-use std::{error, fmt, net::SocketAddr, sync::Arc};
+use std::{
+    collections::HashMap,
+    error, fmt,
+    io::BufReader,
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
 use futures::StreamExt;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{lookup_host, TcpSocket},
-    sync::{mpsc, Mutex},
+    sync::{mpsc, oneshot, Mutex},
+};
+use tokio_rustls::{
+    rustls::{
+        self,
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate,
+    },
+    TlsConnector,
 };
-use tokio_rustls::{rustls, TlsConnector};
+use url::Url;
 
+#[cfg(feature = "gzip")]
+use crate::encoding::{self, ContentEncoding};
 use crate::{
+    body::Body,
+    cookie::CookieJar,
     handler::{AsyncReadStream, AsyncWriteStream},
+    headers::Headers,
     parser::{self},
-    request::Request,
+    request::{Method, Request},
     response::Response,
 };
 
+/// How long to wait for a `100 Continue` before sending the body anyway, for servers that
+/// don't support `Expect`.
+pub const EXPECT_CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Errors returned by the client.
 #[derive(Debug, Clone)]
 pub enum ClientError {
@@ -39,10 +64,35 @@ pub enum ClientError {
     /// Error closing connection
     ShutdownError(String),
 
+    /// Timed out waiting for response headers
+    Timeout,
+
+    /// Exceeded the maximum number of redirects to follow
+    TooManyRedirects(usize),
+
+    /// Refused to follow a redirect from HTTPS to this plain HTTP URL
+    InsecureRedirect(String),
+
+    /// The server didn't switch protocols, and responded with this instead
+    UpgradeRefused(Box<Response>),
+
+    /// The connection is still in use by an earlier request
+    ConnectionBusy,
+
     /// Other unexpected condition
     InternalError(String),
 }
 
+impl ClientError {
+    /// Returns true if `req`, which failed with this error on a reused (e.g., pooled)
+    /// connection, can be sent again on a fresh one. Requests that were never sent can
+    /// always be retried, others only if they're idempotent, and their bodies can be replayed.
+    pub fn can_retry(&self, req: &Request) -> bool {
+        matches!(self, ClientError::ConnectionClosed)
+            || (req.method.is_idempotent() && !req.body.is_reader())
+    }
+}
+
 impl fmt::Display for ClientError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -58,12 +108,39 @@ impl fmt::Display for ClientError {
                 write!(f, "could not receive data from backend: {}", err)
             }
             ClientError::InternalError(err) => write!(f, "internal error: {}", err),
+            ClientError::Timeout => write!(f, "timed out waiting for response"),
+            ClientError::TooManyRedirects(max) => write!(f, "too many redirects (max {})", max),
+            ClientError::InsecureRedirect(url) => {
+                write!(f, "refusing insecure redirect to {}", url)
+            }
+            ClientError::UpgradeRefused(response) => {
+                write!(f, "upgrade refused: {}", response.status.code)
+            }
+            ClientError::ConnectionBusy => write!(f, "connection busy"),
         }
     }
 }
 
 impl error::Error for ClientError {}
 
+/// A certificate verifier that accepts any server certificate. See
+/// `Client::danger_accept_invalid_certs`.
+struct NoCertificateVerification {}
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
 impl Client {}
 
 #[derive(Debug)]
@@ -71,6 +148,16 @@ pub struct Client {
     address: String,
     enable_tls: bool,
     tls_server_name: String,
+    timeout: Option<Duration>,
+    max_redirects: usize,
+    cookie_jar: Option<CookieJar>,
+
+    /// Trusted CA certificates, in addition to the webpki roots.
+    root_certs: Vec<Certificate>,
+    accept_invalid_certs: bool,
+
+    /// Send `Expect: 100-continue` with request bodies.
+    expect_continue: bool,
 }
 
 impl Client {
@@ -91,15 +178,125 @@ impl Client {
             address: address.into(),
             enable_tls: false,
             tls_server_name: String::from(""),
+            timeout: None,
+            max_redirects: 0,
+            cookie_jar: None,
+            root_certs: vec![],
+            accept_invalid_certs: false,
+            expect_continue: false,
         }
     }
 
+    /// Fail requests with `ClientError::Timeout` if the response headers are not
+    /// received within `timeout`. This does not apply to streaming the response body.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Store cookies from responses in `jar`, and send them back with subsequent requests
+    /// that they apply to. The jar can be shared across clients.
+    pub fn with_cookie_jar(mut self, jar: CookieJar) -> Self {
+        self.cookie_jar = Some(jar);
+        self
+    }
+
     pub fn enable_tls(&mut self, server_name: impl Into<String>) -> &mut Self {
         self.enable_tls = true;
         self.tls_server_name = server_name.into();
         self
     }
 
+    /// Trust the CA certificates in the PEM file at `path`, in addition to the default
+    /// (webpki) roots. Use this to connect to servers signed by a private CA.
+    pub fn add_root_cert(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, ClientError> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path).map_err(|e| {
+            ClientError::TLSError(format!("could not open {}: {}", path.display(), e))
+        })?;
+        let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+            .map_err(|_| ClientError::TLSError(format!("invalid cert in {}", path.display())))?;
+
+        if certs.is_empty() {
+            return Err(ClientError::TLSError(format!(
+                "no certificates found in {}",
+                path.display()
+            )));
+        }
+
+        // Make sure the certificates are usable as trust anchors before accepting them.
+        let mut store = rustls::RootCertStore::empty();
+        for cert in certs {
+            let cert = Certificate(cert);
+            store.add(&cert).map_err(|e| {
+                ClientError::TLSError(format!("bad root cert in {}: {}", path.display(), e))
+            })?;
+            self.root_certs.push(cert);
+        }
+
+        Ok(self)
+    }
+
+    /// Accept any server certificate, including self-signed, expired, or mismatched ones. This
+    /// makes the connection vulnerable to man-in-the-middle attacks, and is only meant for
+    /// local testing.
+    pub fn danger_accept_invalid_certs(&mut self) -> &mut Self {
+        self.accept_invalid_certs = true;
+        self
+    }
+
+    fn tls_config(&self) -> rustls::ClientConfig {
+        let mut root_cert_store = rustls::RootCertStore::empty();
+        root_cert_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
+            |ta| {
+                rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    ta.subject,
+                    ta.spki,
+                    ta.name_constraints,
+                )
+            },
+        ));
+
+        // These were validated in add_root_cert.
+        for cert in &self.root_certs {
+            root_cert_store.add(cert).ok();
+        }
+
+        let mut config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(root_cert_store)
+            .with_no_client_auth(); // i guess this was previously the default?
+
+        if self.accept_invalid_certs {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(NoCertificateVerification {}));
+        }
+
+        config
+    }
+
+    /// Automatically follow up to `max` redirects (3xx responses with a `Location` header.) Each
+    /// redirect is sent over a fresh connection. GET and HEAD requests are always followed, other
+    /// methods are only followed as-is on a 307 or 308, and (as a GET) on a 303 See Other.
+    ///
+    /// Credentials (`Authorization`, `Proxy-Authorization` and `Cookie`) are dropped once a
+    /// redirect leaves the origin (scheme, host and port) of the request, and redirects from
+    /// HTTPS to plain HTTP fail with `ClientError::InsecureRedirect`.
+    pub fn follow_redirects(&mut self, max: usize) -> &mut Self {
+        self.max_redirects = max;
+        self
+    }
+
+    /// Send requests that have bodies with `Expect: 100-continue`, and only send the body once
+    /// the server responds with `100 Continue` (or doesn't respond within
+    /// `EXPECT_CONTINUE_TIMEOUT`.) If the server sends its final response first, e.g., to
+    /// reject a large upload, the body isn't sent, and the connection is closed afterwards.
+    pub fn use_expect_continue(&mut self, enable: bool) -> &mut Self {
+        self.expect_continue = enable;
+        self
+    }
+
     /// Connect to address and return a `ConnectedClient`.
     pub async fn connect(&mut self) -> Result<ConnectedClient, ClientError> {
         let addresses: Vec<SocketAddr> = lookup_host(&self.address)
@@ -107,14 +304,11 @@ impl Client {
             .map_err(|e| ClientError::LookupError(format!("{}: {}", self.address.clone(), e)))?
             .collect();
 
-        match addresses.is_empty() {
-            true => {
-                return Err(ClientError::LookupError(format!(
-                    "no hosts found for {}",
-                    self.address
-                )));
-            }
-            false => (),
+        if addresses.is_empty() {
+            return Err(ClientError::LookupError(format!(
+                "no hosts found for {}",
+                self.address
+            )));
         }
 
         let address = addresses[0];
@@ -137,22 +331,7 @@ impl Client {
         }
 
         if self.enable_tls {
-            let mut root_cert_store = rustls::RootCertStore::empty();
-            root_cert_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
-                |ta| {
-                    rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
-                        ta.subject,
-                        ta.spki,
-                        ta.name_constraints,
-                    )
-                },
-            ));
-
-            let config = rustls::ClientConfig::builder()
-                .with_safe_defaults()
-                .with_root_certificates(root_cert_store)
-                .with_no_client_auth(); // i guess this was previously the default?
-            let connector = TlsConnector::from(Arc::new(config));
+            let connector = TlsConnector::from(Arc::new(self.tls_config()));
             let domain = rustls::ServerName::try_from(self.tls_server_name.as_str())
                 .map_err(|e| ClientError::TLSError(format!("invalid domain: {}", e)))?;
 
@@ -166,6 +345,14 @@ impl Client {
                 writer: Arc::new(Mutex::new(Box::new(writer))),
                 reader: Arc::new(Mutex::new(Box::new(reader))),
                 closed: Arc::new(Mutex::new(false)),
+                timeout: self.timeout,
+                address: self.address.clone(),
+                enable_tls: self.enable_tls,
+                max_redirects: self.max_redirects,
+                cookie_jar: self.cookie_jar.clone(),
+                root_certs: self.root_certs.clone(),
+                accept_invalid_certs: self.accept_invalid_certs,
+                expect_continue: self.expect_continue,
             })
         } else {
             let (reader, writer) = tokio::io::split(tcp_stream);
@@ -173,29 +360,225 @@ impl Client {
                 writer: Arc::new(Mutex::new(Box::new(writer))),
                 reader: Arc::new(Mutex::new(Box::new(reader))),
                 closed: Arc::new(Mutex::new(false)),
+                timeout: self.timeout,
+                address: self.address.clone(),
+                enable_tls: self.enable_tls,
+                max_redirects: self.max_redirects,
+                cookie_jar: self.cookie_jar.clone(),
+                root_certs: self.root_certs.clone(),
+                accept_invalid_certs: self.accept_invalid_certs,
+                expect_continue: self.expect_continue,
             })
         }
     }
 }
 
+/// Returns true if `req` has a body to send.
+fn has_body(req: &Request) -> bool {
+    req.body.chunked()
+        || req.body.is_reader()
+        || req
+            .headers
+            .get_first("content-length")
+            .and_then(|len| len.trim().parse::<usize>().ok())
+            .is_some_and(|len| len > 0)
+}
+
+/// A connection that switched protocols. See `ConnectedClient::send_request_upgrade`.
+pub struct Upgraded {
+    /// The `101 Switching Protocols` response, with the negotiated headers.
+    pub response: Response,
+    pub reader: Box<dyn AsyncReadStream>,
+    pub writer: Box<dyn AsyncWriteStream>,
+
+    /// Bytes of the new protocol that were read along with the response. These come before
+    /// anything read from `reader`.
+    pub buffered: Vec<u8>,
+}
+
 pub struct ConnectedClient {
     writer: Arc<Mutex<Box<dyn AsyncWriteStream>>>,
     reader: Arc<Mutex<Box<dyn AsyncReadStream>>>,
     closed: Arc<Mutex<bool>>,
+    timeout: Option<Duration>,
+
+    /// These are used to follow redirects.
+    address: String,
+    enable_tls: bool,
+    max_redirects: usize,
+    root_certs: Vec<Certificate>,
+    accept_invalid_certs: bool,
+    expect_continue: bool,
+
+    cookie_jar: Option<CookieJar>,
 }
 
 impl ConnectedClient {
     pub async fn send_request(&mut self, req: &Request) -> Result<Response, ClientError> {
+        let response = self.send_request_once(req).await?;
+
+        if self.max_redirects == 0 {
+            return Ok(response);
+        }
+
+        self.follow_redirects(req, response).await
+    }
+
+    /// Follow the redirect chain starting at `response`, up to `self.max_redirects` times.
+    async fn follow_redirects(
+        &mut self,
+        req: &Request,
+        mut response: Response,
+    ) -> Result<Response, ClientError> {
+        let mut url = self.request_url(req)?;
+
+        let mut req = req.clone();
+        let mut redirects = 0;
+
+        while let Some(location) = response.headers.get_first("location") {
+            let code = response.status.code;
+            if !(300..400).contains(&code) {
+                break;
+            }
+
+            if code == 303 {
+                if req.method != Method::HEAD {
+                    req.method = Method::GET;
+                }
+                req.body = Body::new();
+                req.headers.remove("content-length");
+                req.headers.remove("content-type");
+                req.headers.remove("transfer-encoding");
+            } else if !matches!(code, 307 | 308)
+                && req.method != Method::GET
+                && req.method != Method::HEAD
+            {
+                // 307 and 308 keep the method and body, others may only be followed by
+                // GETs and HEADs.
+                break;
+            }
+
+            if redirects >= self.max_redirects {
+                return Err(ClientError::TooManyRedirects(self.max_redirects));
+            }
+            redirects += 1;
+
+            let next = url.join(location).map_err(|e| {
+                ClientError::ParseError(format!("bad location {}: {}", location, e))
+            })?;
+
+            if url.scheme() == "https" && next.scheme() != "https" {
+                return Err(ClientError::InsecureRedirect(next.to_string()));
+            }
+
+            // Don't leak credentials to other origins. The cookie jar, if any, picks the
+            // cookies for the new one.
+            if next.origin() != url.origin() {
+                for header in ["authorization", "proxy-authorization", "cookie"] {
+                    req.headers.remove(header);
+                }
+            }
+            url = next;
+            let host = url
+                .host_str()
+                .ok_or(ClientError::ParseError(format!("no host in {}", url)))?
+                .to_string();
+            let port = url
+                .port_or_known_default()
+                .ok_or(ClientError::ParseError(format!("no port in {}", url)))?;
+
+            debug!("following redirect to {}", url);
+            req.url = Some(url.clone());
+            req.headers.set(
+                "host",
+                match url.port() {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host.clone(),
+                },
+            );
+
+            let mut client = Client::new(format!("{}:{}", host, port));
+            client.timeout = self.timeout;
+            client.cookie_jar = self.cookie_jar.clone();
+            client.root_certs = self.root_certs.clone();
+            client.accept_invalid_certs = self.accept_invalid_certs;
+            client.expect_continue = self.expect_continue;
+            if url.scheme() == "https" {
+                client.enable_tls(host);
+            }
+
+            response = client.connect().await?.send_request_once(&req).await?;
+        }
+
+        Ok(response)
+    }
+
+    /// Returns the full URL for `req` on this connection.
+    fn request_url(&self, req: &Request) -> Result<Url, ClientError> {
+        let scheme = if self.enable_tls { "https" } else { "http" };
+        let host = req.headers.get_first("host").unwrap_or(&self.address);
+        Url::parse(&format!("{}://{}", scheme, host))
+            .and_then(|base| base.join(&req.abs_path()))
+            .map_err(|e| ClientError::InternalError(format!("bad request URL: {}", e)))
+    }
+
+    /// Returns the headers to send with `req`, and its URL if there's a cookie jar.
+    fn request_headers(&self, req: &Request) -> Result<(Headers, Option<Url>), ClientError> {
+        // Add cookies from the jar to the request
+        let url = match self.cookie_jar {
+            Some(_) => Some(self.request_url(req)?),
+            None => None,
+        };
+        let mut headers = req.headers.clone();
+
+        // HTTP/1.1 servers reject requests without a Host.
+        if headers.get_first("host").is_none() {
+            headers.set("host", &self.address);
+        }
+
+        if let (Some(jar), Some(url)) = (&self.cookie_jar, &url) {
+            if let Some(cookies) = jar.cookie_header(url) {
+                let cookies = match headers.get_first("cookie") {
+                    Some(existing) => format!("{}; {}", existing, cookies),
+                    None => cookies,
+                };
+                headers.set("cookie", cookies);
+            }
+        }
+
+        Ok((headers, url))
+    }
+
+    async fn send_request_once(&mut self, req: &Request) -> Result<Response, ClientError> {
         if *self.closed.lock().await {
             return Err(ClientError::ConnectionClosed);
         }
 
+        let (mut headers, url) = self.request_headers(req)?;
+
+        // Hold the body back until the server is ready for it. The response reader lets the
+        // writer know whether to send it.
+        let expect_continue = (self.expect_continue && has_body(req))
+            || headers
+                .get_first("expect")
+                .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"));
+        let (continue_tx, continue_rx) = match expect_continue {
+            true => {
+                headers.set("expect", "100-continue");
+                let (tx, rx) = oneshot::channel();
+                (Some(tx), Some(rx))
+            }
+            false => (None, None),
+        };
+        let continue_tx = Arc::new(std::sync::Mutex::new(continue_tx));
+
         let reader = Arc::clone(&self.reader);
         let writer = Arc::clone(&self.writer);
         let closed = Arc::clone(&self.closed);
-        let request_data = format!("{}\r\n{}", req.serialize_method(), req.headers.serialize());
+        let request_data = format!("{}\r\n{}", req.serialize_method(), headers.serialize());
 
         let mut read_stream = req.body.raw_stream();
+        let no_body = req.method == Method::HEAD;
 
         tokio::spawn(async move {
             let mut write_stream = writer.lock().await;
@@ -210,6 +593,17 @@ impl ConnectedClient {
                 _ = write_stream.shutdown().await;
             }
 
+            if let Some(continue_rx) = continue_rx {
+                match tokio::time::timeout(EXPECT_CONTINUE_TIMEOUT, continue_rx).await {
+                    Ok(Ok(true)) => {}
+                    Err(_) => debug!("no 100 Continue, sending body anyway"),
+                    Ok(_) => {
+                        debug!("server responded without reading the body");
+                        return;
+                    }
+                }
+            }
+
             while let Some(content) = read_stream.next().await {
                 if let Err(e) = write_stream.write_all(content.as_slice()).await {
                     warn!("error writing chunk to socket: {}", e);
@@ -224,18 +618,30 @@ impl ConnectedClient {
         // Background task to read the response. Returns the response struct as soon
         // as the headers are read, and continues to read from the socket in the background
         // until the entire response is read or the connection is closed.
-        tokio::spawn(async move {
+        let closed = Arc::clone(&self.closed);
+        let read_task = tokio::spawn(async move {
             let mut stream = reader.lock().await;
 
             let mut parser = parser::ResponseParser::new();
+            parser.set_no_body(no_body);
             let mut ready = false;
 
+            let interim_tx = Arc::clone(&continue_tx);
+            parser.on_interim_response(move |response| {
+                if response.status.code == 100 {
+                    if let Some(tx) = interim_tx.lock().unwrap().take() {
+                        _ = tx.send(true);
+                    }
+                }
+            });
+
             loop {
                 let mut buf = [0u8; 16384];
 
                 match stream.read(&mut buf).await {
                     Ok(0) => {
                         debug!("0 bytes read");
+                        *closed.lock().await = true;
                         _ = tx.send(Err(ClientError::ConnectionClosed)).await;
                         break;
                     }
@@ -247,6 +653,7 @@ impl ConnectedClient {
                         );
 
                         if let Err(e) = parser.parse_buf(&buf[..n]) {
+                            *closed.lock().await = true;
                             _ = tx.send(Err(ClientError::ParseError(e.to_string()))).await;
                             break;
                         }
@@ -254,7 +661,21 @@ impl ConnectedClient {
                         // No need to wait for a full request. Wait until there's enough data
                         // in the buffer to parse the headers.
                         if parser.ready() && !ready {
-                            _ = tx.send(Ok(parser.get_message())).await;
+                            // The server didn't wait for the body, so it's out of sync with
+                            // the connection.
+                            let pending = continue_tx.lock().unwrap().take();
+                            if let Some(continue_tx) = pending {
+                                _ = continue_tx.send(false);
+                                *closed.lock().await = true;
+                            }
+
+                            // The server closes the connection after this response, so it
+                            // can't take more requests.
+                            let message = parser.get_message();
+                            if message.response().headers.has_token("connection", "close") {
+                                *closed.lock().await = true;
+                            }
+                            _ = tx.send(Ok(message)).await;
                             ready = true; // only send the message once
                         }
 
@@ -264,6 +685,7 @@ impl ConnectedClient {
                     }
                     Err(e) => {
                         debug!("read error: {}", e);
+                        *closed.lock().await = true;
                         _ = tx.send(Err(ClientError::RecvError(e.to_string()))).await;
                         break;
                     }
@@ -271,7 +693,19 @@ impl ConnectedClient {
             }
         });
 
-        let message = rx.recv().await;
+        let message = if let Some(timeout) = self.timeout {
+            match tokio::time::timeout(timeout, rx.recv()).await {
+                Ok(message) => message,
+                Err(_) => {
+                    debug!("timed out waiting for response headers");
+                    read_task.abort();
+                    _ = self.close().await;
+                    return Err(ClientError::Timeout);
+                }
+            }
+        } else {
+            rx.recv().await
+        };
 
         if let Some(message) = message {
             // Error receiving data, shut down the socket
@@ -281,11 +715,17 @@ impl ConnectedClient {
                     "error receiving response".to_string(),
                 ))
             } else {
-                Ok(message
+                let response: Response = message
                     .map_err(|e| {
                         ClientError::InternalError(format!("error receiving response: {}", e))
                     })?
-                    .into())
+                    .into();
+
+                if let (Some(jar), Some(url)) = (&self.cookie_jar, &url) {
+                    jar.store(url, &response.headers);
+                }
+
+                Ok(response)
             }
         } else {
             self.close().await?;
@@ -295,6 +735,127 @@ impl ConnectedClient {
         }
     }
 
+    /// Send `req`, which should ask to switch protocols (e.g., with `Upgrade: websocket`), and
+    /// hand over the connection once the server responds with `101 Switching Protocols`. Fails
+    /// with `UpgradeRefused` if the server responds with anything else. Redirects aren't
+    /// followed.
+    ///
+    /// The connection is used up either way, and this fails with `ConnectionBusy` if an
+    /// earlier response on it is still being read.
+    pub async fn send_request_upgrade(mut self, req: &Request) -> Result<Upgraded, ClientError> {
+        if *self.closed.lock().await {
+            return Err(ClientError::ConnectionClosed);
+        }
+
+        let (headers, url) = self.request_headers(req)?;
+        let request_data = format!("{}\r\n{}", req.serialize_method(), headers.serialize());
+        let timeout = self.timeout;
+
+        let read_response = async {
+            let mut writer = self.writer.lock().await;
+            debug!("sending upgrade request:\n{}", request_data);
+            writer
+                .write_all(format!("{}\r\n\r\n", request_data).as_bytes())
+                .await
+                .map_err(|e| ClientError::SendError(e.to_string()))?;
+
+            let mut body = req.body.raw_stream();
+            while let Some(content) = body.next().await {
+                writer
+                    .write_all(content.as_slice())
+                    .await
+                    .map_err(|e| ClientError::SendError(e.to_string()))?;
+            }
+            writer
+                .flush()
+                .await
+                .map_err(|e| ClientError::SendError(e.to_string()))?;
+
+            let mut reader = self.reader.lock().await;
+            let mut parser = parser::ResponseParser::new();
+            let mut read = 0;
+            let mut buf = [0u8; 16384];
+
+            loop {
+                let n = reader
+                    .read(&mut buf)
+                    .await
+                    .map_err(|e| ClientError::RecvError(e.to_string()))?;
+                if n == 0 {
+                    return Err(ClientError::ConnectionClosed);
+                }
+
+                read += n;
+                parser
+                    .parse_buf(&buf[..n])
+                    .map_err(|e| ClientError::ParseError(e.to_string()))?;
+
+                if parser.ready() {
+                    // Anything the server sent after the 101 is already the new protocol.
+                    let buffered = buf[n - (read - parser.parsed_bytes())..n].to_vec();
+                    let response: Response = parser.get_message().into();
+                    return Ok((response, buffered));
+                }
+            }
+        };
+
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, read_response)
+                .await
+                .unwrap_or(Err(ClientError::Timeout)),
+            None => read_response.await,
+        };
+
+        let (response, buffered) = match result {
+            Ok(response) => response,
+            Err(e) => {
+                _ = self.close().await;
+                return Err(e);
+            }
+        };
+
+        if let (Some(jar), Some(url)) = (&self.cookie_jar, &url) {
+            jar.store(url, &response.headers);
+        }
+
+        if response.status.code != 101 {
+            _ = self.close().await;
+            return Err(ClientError::UpgradeRefused(Box::new(response)));
+        }
+
+        let (Ok(reader), Ok(writer)) = (Arc::try_unwrap(self.reader), Arc::try_unwrap(self.writer))
+        else {
+            return Err(ClientError::ConnectionBusy);
+        };
+
+        Ok(Upgraded {
+            response,
+            reader: reader.into_inner(),
+            writer: writer.into_inner(),
+            buffered,
+        })
+    }
+
+    /// Same as `send_request`, but transparently decodes gzip and deflate response bodies. The
+    /// body is decoded lazily as it streams in, and the `Content-Encoding` and `Content-Length`
+    /// headers are replaced with `Transfer-Encoding: chunked`.
+    ///
+    /// Decoding requires the `gzip` feature. Without it, responses are returned as-is.
+    pub async fn send_request_decoded(&mut self, req: &Request) -> Result<Response, ClientError> {
+        #[allow(unused_mut)]
+        let mut response = self.send_request(req).await?;
+
+        #[cfg(feature = "gzip")]
+        if let Some(encoding) = ContentEncoding::from_headers(&response.headers) {
+            response.body = encoding::decode(&response.body, encoding);
+            response.headers.remove("content-encoding");
+            response.headers.remove("content-length");
+            response.headers.set("transfer-encoding", "chunked");
+        }
+
+        Ok(response)
+    }
+
     async fn close_internal(
         writer: Arc<Mutex<Box<dyn AsyncWriteStream>>>,
     ) -> Result<(), ClientError> {
@@ -314,4 +875,117 @@ impl ConnectedClient {
     pub async fn is_closed(&self) -> bool {
         *self.closed.lock().await
     }
+
+    /// The `host:port` address this client is connected to.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+/// A pool of idle keep-alive connections, keyed by `host:port`. Use `take` to fetch
+/// an idle connection, and `put` to return it after use. Closed connections, and connections
+/// that have been idle for longer than the idle timeout are discarded.
+///
+/// Safe to clone.
+#[derive(Clone)]
+pub struct ClientPool {
+    max_idle_per_host: usize,
+    idle_timeout: Duration,
+    #[allow(clippy::type_complexity)]
+    idle: Arc<Mutex<HashMap<String, Vec<(ConnectedClient, Instant)>>>>,
+}
+
+impl ClientPool {
+    pub fn new(max_idle_per_host: usize) -> Self {
+        Self {
+            max_idle_per_host,
+            idle_timeout: Duration::from_secs(90),
+            idle: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Discard idle connections that haven't been used for `timeout`.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Return an idle connection to `address`, if one is available.
+    pub async fn take(&self, address: &str) -> Option<ConnectedClient> {
+        let mut idle = self.idle.lock().await;
+        let clients = idle.get_mut(address)?;
+
+        while let Some((mut client, since)) = clients.pop() {
+            if client.is_closed().await {
+                continue;
+            }
+
+            if since.elapsed() > self.idle_timeout {
+                _ = client.close().await;
+                continue;
+            }
+
+            debug!("reusing pooled client for {}", address);
+            return Some(client);
+        }
+
+        None
+    }
+
+    /// Return a connection to the pool. The connection is closed if it's no longer
+    /// usable, or if there are already too many idle connections to the host.
+    pub async fn put(&self, mut client: ConnectedClient) {
+        if client.is_closed().await {
+            return;
+        }
+
+        let mut idle = self.idle.lock().await;
+        let clients = idle.entry(client.address.clone()).or_default();
+
+        if clients.len() >= self.max_idle_per_host {
+            _ = client.close().await;
+            return;
+        }
+
+        clients.push((client, Instant::now()));
+    }
+
+    /// Return `client` to the pool once it's done with its last request, i.e., the request
+    /// has been sent, and the response body read. Connections that the server closes (e.g.,
+    /// with `Connection: close`) are discarded.
+    pub fn put_when_idle(&self, client: ConnectedClient) {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            // The background tasks of the request hold these until they're done.
+            drop(client.writer.lock().await);
+            drop(client.reader.lock().await);
+            pool.put(client).await;
+        });
+    }
+
+    /// Return the number of idle connections to `address`.
+    pub async fn idle_count(&self, address: &str) -> usize {
+        self.idle
+            .lock()
+            .await
+            .get(address)
+            .map(|clients| clients.len())
+            .unwrap_or(0)
+    }
+}
+
+impl Default for ClientPool {
+    fn default() -> Self {
+        Self::new(8)
+    }
+}
+
+impl fmt::Debug for ClientPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ClientPool(max_idle_per_host: {})",
+            self.max_idle_per_host
+        )
+    }
 }
@@ -29,7 +29,7 @@ pub struct Route {
     pub handler: Handler,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LogLevel {
     Debug,
     Info,
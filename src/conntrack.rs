@@ -1,4 +1,12 @@
-use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use futures::StreamExt;
 use rand::{thread_rng, Rng};
@@ -14,6 +22,15 @@ use crate::{
     handler::{AsyncReadStream, AsyncStream, AsyncWriteStream},
 };
 
+/// Returns a random alphanumeric ID of `len` characters.
+pub fn random_id(len: usize) -> String {
+    thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct ConnId(pub String);
 
@@ -29,12 +46,34 @@ impl From<ConnId> for String {
     }
 }
 
+/// A snapshot of the tracked connections. See `ConnTracker::stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnStats {
+    /// Open connections.
+    pub active: usize,
+
+    /// Connections accepted so far, including closed ones.
+    pub total: usize,
+
+    /// Requests received so far on each open connection.
+    pub requests: HashMap<ConnId, usize>,
+}
+
 #[derive(Debug)]
 pub struct ConnTracker {
     conns: Arc<std::sync::RwLock<HashMap<ConnId, Conn>>>,
     keepalive_tx: mpsc::Sender<(ConnId, Duration)>,
     keepalive_rx: Arc<Mutex<mpsc::Receiver<(ConnId, Duration)>>>,
     shutdown_notifier: Arc<Notify>,
+
+    /// Connections accepted so far.
+    total: Arc<AtomicUsize>,
+
+    /// These are used to drain connections on graceful shutdown.
+    active: Arc<AtomicUsize>,
+    idle_notifier: Arc<Notify>,
+    draining: Arc<AtomicBool>,
+    drain_notifier: Arc<Notify>,
 }
 
 impl ConnTracker {
@@ -46,16 +85,82 @@ impl ConnTracker {
             keepalive_tx,
             keepalive_rx: Arc::new(Mutex::new(keepalive_rx)),
             shutdown_notifier: Arc::new(Notify::new()),
+            total: Arc::new(AtomicUsize::new(0)),
+            active: Arc::new(AtomicUsize::new(0)),
+            idle_notifier: Arc::new(Notify::new()),
+            draining: Arc::new(AtomicBool::new(false)),
+            drain_notifier: Arc::new(Notify::new()),
         }
     }
 
     pub fn push_stream(&mut self, stream: Box<dyn AsyncStream>) -> Conn {
         let conn = Conn::new(stream);
         let id = conn.id.clone();
-        self.conns.write().unwrap().insert(id, conn.clone());
+        let mut conns = self.conns.write().unwrap();
+        conns.insert(id, conn.clone());
+        self.total.fetch_add(1, Ordering::SeqCst);
+        self.active.fetch_add(1, Ordering::SeqCst);
         conn
     }
 
+    /// Stop tracking the connection `id`. This must be called once for every connection
+    /// returned by `push_stream`, when the connection is closed.
+    pub fn remove(&self, id: &ConnId) {
+        self.conns.write().unwrap().remove(id);
+        if self.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle_notifier.notify_waiters();
+        }
+    }
+
+    /// Returns the number of open connections.
+    pub fn active_conns(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Returns the IDs of the open connections.
+    pub fn list(&self) -> Vec<ConnId> {
+        self.conns.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Returns a snapshot of the open connections, and the number accepted so far.
+    pub fn stats(&self) -> ConnStats {
+        let conns = self.conns.read().unwrap();
+        ConnStats {
+            active: conns.len(),
+            total: self.total.load(Ordering::SeqCst),
+            requests: conns
+                .iter()
+                .map(|(id, conn)| (id.clone(), conn.state.read().unwrap().request_count))
+                .collect(),
+        }
+    }
+
+    /// Ask connections to close once they've finished their current request.
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        self.drain_notifier.notify_waiters();
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// This is notified when draining starts, to wake up idle connections.
+    pub fn drain_notifier(&self) -> Arc<Notify> {
+        Arc::clone(&self.drain_notifier)
+    }
+
+    /// Wait until all connections are closed.
+    pub async fn wait_idle(&self) {
+        loop {
+            let idle = self.idle_notifier.notified();
+            if self.active_conns() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
     pub async fn set_keepalive_timeout(&self, id: ConnId, dur: Duration) {
         self.keepalive_tx.send((id, dur)).await.unwrap();
     }
@@ -82,10 +187,11 @@ impl ConnTracker {
                     _ = shutdown_notifier.notified() => { info!("shutting down connection tracker..."); break; }
                 };
 
+                // The connection may have closed already.
                 if let Some(conn_id) = conn_id {
-                    let mut conns = conns.write().unwrap();
-                    conns.get(conn_id.get_ref()).unwrap().timeout_notify();
-                    conns.remove(conn_id.get_ref());
+                    if let Some(conn) = conns.read().unwrap().get(conn_id.get_ref()) {
+                        conn.timeout_notify();
+                    }
                 }
             }
         });
@@ -103,6 +209,10 @@ pub struct ConnState {
     pub keepalive_timeout: Option<Duration>,
     pub keepalive_max: Option<usize>,
     pub request_count: usize,
+
+    /// Responses written so far. Responses go out in request order, so this is also the
+    /// number of the last request answered.
+    pub response_count: usize,
 }
 
 #[derive(Clone)]
@@ -126,13 +236,7 @@ impl Conn {
         let (reader, writer) = split(stream);
 
         Self {
-            id: ConnId(
-                thread_rng()
-                    .sample_iter(&rand::distributions::Alphanumeric)
-                    .take(16)
-                    .map(char::from)
-                    .collect(),
-            ),
+            id: ConnId(random_id(16)),
             read_stream: Arc::new(RwLock::new(Box::new(reader))),
             write_stream: Arc::new(RwLock::new(Box::new(writer))),
             backend_client: Arc::new(RwLock::new(None)),
@@ -141,6 +245,7 @@ impl Conn {
                 keepalive_timeout: None,
                 keepalive_max: None,
                 request_count: 0,
+                response_count: 0,
             })),
         }
     }
@@ -184,6 +289,16 @@ impl Conn {
         false
     }
 
+    /// Returns true if the response to request number `seq` (counting from 1) is next, i.e.,
+    /// the responses to all earlier requests on the connection have been written.
+    pub fn is_next_response(&self, seq: usize) -> bool {
+        self.state.read().unwrap().response_count + 1 == seq
+    }
+
+    pub fn inc_response_count(&self) {
+        self.state.write().unwrap().response_count += 1;
+    }
+
     pub fn timeout_notifier(&self) -> Arc<Notify> {
         Arc::clone(&self.timeout_notifier)
     }
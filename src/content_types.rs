@@ -12,3 +12,27 @@ lazy_static! {
         ("svg", "image/svg+xml"),
     ]);
 }
+
+/// Files with unknown types are served as this.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Magic numbers at the start of common binary formats, and their types.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"PK\x03\x04", "application/zip"),
+];
+
+/// Returns the content type of `buf`, the first bytes of a file, if it starts with one of
+/// the known magic numbers. This never detects HTML (or other active content), so that
+/// uploaded files can't be sniffed into scripts.
+pub fn sniff(buf: &[u8]) -> Option<&'static str> {
+    SIGNATURES
+        .iter()
+        .find(|(magic, _)| buf.starts_with(magic))
+        .map(|(_, content_type)| *content_type)
+}
@@ -1,10 +1,18 @@
-use std::{collections::HashSet, error, fmt};
+use std::{
+    collections::HashSet,
+    error, fmt,
+    sync::{Arc, RwLock},
+};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+use url::Url;
+
+use crate::headers::Headers;
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub enum Flag {
     Domain(String),
+    Path(String),
     Expires(DateTime<Utc>),
     MaxAge(u32),
     HttpOnly,
@@ -52,22 +60,32 @@ impl TryFrom<&str> for Cookie {
 
     fn try_from(buf: &str) -> Result<Self, Self::Error> {
         // Separate Cookie: or Set-Cookie: from request/response header
-        let kv: Vec<&str> = buf.split(':').collect();
-        if kv.len() != 2 {
-            return Err(CookieError::BadHeader);
+        let (_, value) = buf.split_once(':').ok_or(CookieError::BadHeader)?;
+        Cookie::parse(value)
+    }
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            flags: HashSet::new(),
         }
+    }
 
-        let parts: Vec<&str> = kv[1].trim().split(';').map(|c| c.trim()).collect();
-        if parts.is_empty() {
+    /// Parse a cookie from a Set-Cookie header value (without the "Set-Cookie:" prefix.)
+    pub fn parse(value: &str) -> Result<Self, CookieError> {
+        let parts: Vec<&str> = value.trim().split(';').map(|c| c.trim()).collect();
+        if parts.is_empty() || parts[0].is_empty() {
             return Err(CookieError::MissingCookieLine);
         }
 
-        let cookie_kv: Vec<&str> = parts[0].split('=').map(|c| c.trim()).collect();
-        if cookie_kv.len() != 2 {
-            return Err(CookieError::MissingCookieFields);
-        }
+        let (name, value) = parts[0]
+            .split_once('=')
+            .ok_or(CookieError::MissingCookieFields)?;
 
-        let mut cookie = Cookie::new(cookie_kv[0], cookie_kv[1]);
+        let mut cookie = Cookie::new(name.trim(), value.trim());
 
         if parts.len() > 1 {
             for part in &parts[1..] {
@@ -91,17 +109,20 @@ impl TryFrom<&str> for Cookie {
                         cookie.push_flag(Flag::SameSiteNone);
                     }
                     _ => {
-                        let attrs: Vec<&str> = part.split('=').map(|c| c.trim()).collect();
-                        if attrs.len() != 2 {
-                            return Err(CookieError::MalformedAttribute(part.to_string()));
-                        }
+                        let (attr, value) = part
+                            .split_once('=')
+                            .map(|(k, v)| (k.trim(), v.trim()))
+                            .ok_or(CookieError::MalformedAttribute(part.to_string()))?;
 
-                        match attrs[0].to_lowercase().as_str() {
+                        match attr.to_lowercase().as_str() {
                             "domain" => {
-                                cookie.push_flag(Flag::Domain(attrs[1].into()));
+                                cookie.push_flag(Flag::Domain(value.into()));
+                            }
+                            "path" => {
+                                cookie.push_flag(Flag::Path(value.into()));
                             }
                             "expires" => {
-                                let date = DateTime::parse_from_rfc2822(attrs[1]).or(Err(
+                                let date = DateTime::parse_from_rfc2822(value).or(Err(
                                     CookieError::MalformedAttribute("expiry".to_string()),
                                 ))?;
 
@@ -109,11 +130,11 @@ impl TryFrom<&str> for Cookie {
                             }
                             "max-age" => {
                                 cookie.push_flag(Flag::MaxAge(
-                                    str::parse::<u32>(attrs[1]).unwrap_or(0_u32),
+                                    str::parse::<u32>(value).unwrap_or(0_u32),
                                 ));
                             }
                             _ => {
-                                return Err(CookieError::MalformedAttribute(attrs[0].to_string()));
+                                return Err(CookieError::MalformedAttribute(attr.to_string()));
                             }
                         }
                     }
@@ -123,15 +144,25 @@ impl TryFrom<&str> for Cookie {
 
         Ok(cookie)
     }
-}
 
-impl Cookie {
-    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
-        Cookie {
-            name: name.into(),
-            value: value.into(),
-            flags: HashSet::new(),
-        }
+    /// Parse all the cookies in the Set-Cookie headers of `headers`. Malformed cookies
+    /// are skipped.
+    pub fn parse_all(headers: &Headers) -> Vec<Cookie> {
+        headers
+            .get("set-cookie")
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|value| match Cookie::parse(value) {
+                        Ok(cookie) => Some(cookie),
+                        Err(e) => {
+                            debug!("skipping bad cookie {}: {}", value, e);
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     pub fn name(&self) -> &String {
@@ -152,11 +183,17 @@ impl Cookie {
     }
 
     pub fn get_flags(&self) -> Vec<&Flag> {
-        return self.flags.iter().collect();
+        self.flags.iter().collect()
     }
 
+    /// Serialize as a full Set-Cookie header line.
     pub fn serialize(&self) -> String {
-        let mut buf = String::from("Set-Cookie: ");
+        format!("Set-Cookie: {}", self.serialize_value())
+    }
+
+    /// Serialize as a Set-Cookie header value.
+    pub fn serialize_value(&self) -> String {
+        let mut buf = String::new();
         buf.push_str(&self.name);
         buf.push('=');
         buf.push_str(&self.value);
@@ -166,6 +203,7 @@ impl Cookie {
         for flag in &self.flags {
             match flag {
                 Flag::Domain(domain) => flagvec.push(format!("Domain={}", domain)),
+                Flag::Path(path) => flagvec.push(format!("Path={}", path)),
                 Flag::Expires(dt) => flagvec.push(format!("Expires={}", dt.to_rfc2822())),
                 Flag::MaxAge(seconds) => flagvec.push(format!("Max-Age={}", seconds)),
                 Flag::HttpOnly => flagvec.push("HttpOnly".into()),
@@ -187,3 +225,186 @@ impl Cookie {
         buf
     }
 }
+
+/// A cookie stored in a CookieJar, along with the scope it applies to.
+#[derive(Debug, Clone)]
+struct StoredCookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    expires: Option<DateTime<Utc>>,
+    secure: bool,
+}
+
+impl StoredCookie {
+    fn from_cookie(cookie: &Cookie, url: &Url) -> Option<Self> {
+        let host = url.host_str()?.to_lowercase();
+        let mut domain = None;
+        let mut path = None;
+        let mut expires = None;
+        let mut max_age = None;
+        let mut secure = false;
+
+        for flag in &cookie.flags {
+            match flag {
+                Flag::Domain(d) => domain = Some(d.trim_start_matches('.').to_lowercase()),
+                Flag::Path(p) if p.starts_with('/') => path = Some(p.clone()),
+                Flag::Expires(dt) => expires = Some(*dt),
+                Flag::MaxAge(seconds) => max_age = Some(*seconds),
+                Flag::Secure => secure = true,
+                _ => {}
+            }
+        }
+
+        // Max-Age takes precedence over Expires
+        if let Some(seconds) = max_age {
+            expires = Some(Utc::now() + Duration::seconds(seconds as i64));
+        }
+
+        // Reject cookies for domains that the origin is not a part of
+        let host_only = domain.is_none();
+        let domain = match domain {
+            Some(d) if domain_matches(&host, &d) => d,
+            Some(_) => return None,
+            None => host,
+        };
+
+        Some(Self {
+            name: cookie.name.clone(),
+            value: cookie.value.clone(),
+            domain,
+            host_only,
+            path: path.unwrap_or_else(|| default_path(url.path())),
+            expires,
+            secure,
+        })
+    }
+
+    fn is_expired(&self, now: &DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| expires <= *now)
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_lowercase(),
+            None => return false,
+        };
+
+        if self.secure && url.scheme() != "https" {
+            return false;
+        }
+
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+
+        domain_ok && path_matches(url.path(), &self.path)
+    }
+}
+
+/// Returns true if `host` is `domain` or a subdomain of it.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+/// Returns true if `request_path` is within the cookie path `path` (RFC 6265 5.1.4).
+fn path_matches(request_path: &str, path: &str) -> bool {
+    request_path == path
+        || (request_path.starts_with(path)
+            && (path.ends_with('/') || request_path[path.len()..].starts_with('/')))
+}
+
+/// The default cookie path is the directory of the request path (RFC 6265 5.1.4).
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => request_path[..i].to_string(),
+    }
+}
+
+/// CookieJar stores cookies received in Set-Cookie response headers, and produces
+/// Cookie request headers for subsequent requests. It's safe to clone, clones share
+/// the same cookies.
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Arc<RwLock<Vec<StoredCookie>>>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a cookie received in a response for `url`. Cookies with the same name,
+    /// domain, and path are replaced. Cookies that have already expired are removed.
+    pub fn add(&self, cookie: &Cookie, url: &Url) {
+        let Some(stored) = StoredCookie::from_cookie(cookie, url) else {
+            debug!("rejecting cookie {} for {}", cookie.name, url);
+            return;
+        };
+
+        let mut cookies = self.cookies.write().unwrap();
+        cookies.retain(|c| {
+            !(c.name == stored.name && c.domain == stored.domain && c.path == stored.path)
+        });
+
+        if !stored.is_expired(&Utc::now()) {
+            cookies.push(stored);
+        }
+    }
+
+    /// Store all the cookies in the Set-Cookie headers of a response for `url`.
+    pub fn store(&self, url: &Url, headers: &Headers) {
+        for cookie in Cookie::parse_all(headers) {
+            self.add(&cookie, url);
+        }
+    }
+
+    /// Returns the value of the Cookie header to send with a request for `url`, or
+    /// None if no cookies apply. Expired cookies are pruned.
+    pub fn cookie_header(&self, url: &Url) -> Option<String> {
+        self.prune();
+
+        let cookies = self.cookies.read().unwrap();
+        let mut matching: Vec<&StoredCookie> = cookies.iter().filter(|c| c.matches(url)).collect();
+        if matching.is_empty() {
+            return None;
+        }
+
+        // Cookies with longer paths are listed first
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+
+        Some(
+            matching
+                .iter()
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect::<Vec<String>>()
+                .join("; "),
+        )
+    }
+
+    /// Remove all expired cookies.
+    pub fn prune(&self) {
+        let now = Utc::now();
+        self.cookies
+            .write()
+            .unwrap()
+            .retain(|c| !c.is_expired(&now));
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.cookies.write().unwrap().clear();
+    }
+}
@@ -0,0 +1,151 @@
+/// This file implements support for HTTP content encodings (gzip, deflate). Encoding is
+/// done on complete bodies. Decoding is done lazily: the decoded body is fed from the encoded body's stream in a background
+/// task, so it works for both content-length and chunked bodies.
+use std::io::Write;
+
+use flate2::{
+    write::{GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder},
+    Compression,
+};
+use futures::StreamExt;
+
+use crate::{body::Body, headers::Headers};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// Returns the content encoding specified in `headers`, or None if the content is
+    /// not encoded (or encoded with an unsupported scheme.)
+    pub fn from_headers(headers: &Headers) -> Option<Self> {
+        match headers
+            .get_first("content-encoding")?
+            .to_lowercase()
+            .as_str()
+        {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Returns the preferred encoding that the client accepts, according to the
+    /// Accept-Encoding request header. Gzip is preferred over deflate.
+    pub fn from_accept_encoding(headers: &Headers) -> Option<Self> {
+        let accepted: Vec<String> = headers
+            .accept_encodings()
+            .into_iter()
+            .filter(|(_, q)| *q > 0.0)
+            .map(|(name, _)| name)
+            .collect();
+        if accepted.is_empty() {
+            return None;
+        }
+
+        let accepts = |name: &str| accepted.iter().any(|e| e == name || e == "*");
+        if accepts("gzip") {
+            Some(Self::Gzip)
+        } else if accepts("deflate") {
+            Some(Self::Deflate)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    /// Compress `content` with this encoding.
+    pub fn encode(&self, content: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder.write_all(content)?;
+                encoder.finish()
+            }
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+                encoder.write_all(content)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        match self {
+            Self::Gzip => Box::new(GzDecoder::new(vec![])),
+            Self::Deflate => Box::new(ZlibDecoder::new(vec![])),
+        }
+    }
+}
+
+/// Common interface over the flate2 write-side decoders.
+trait Decoder: Write + Send {
+    fn take_output(&mut self) -> Vec<u8>;
+    fn finish_output(&mut self) -> std::io::Result<Vec<u8>>;
+}
+
+impl Decoder for GzDecoder<Vec<u8>> {
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(self.get_mut())
+    }
+
+    fn finish_output(&mut self) -> std::io::Result<Vec<u8>> {
+        self.try_finish()?;
+        Ok(self.take_output())
+    }
+}
+
+impl Decoder for ZlibDecoder<Vec<u8>> {
+    fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(self.get_mut())
+    }
+
+    fn finish_output(&mut self) -> std::io::Result<Vec<u8>> {
+        self.try_finish()?;
+        Ok(self.take_output())
+    }
+}
+
+/// Returns a new chunked body with the decoded contents of `body`. Chunks are pushed
+/// to the new body as the encoded body streams in.
+pub fn decode(body: &Body, encoding: ContentEncoding) -> Body {
+    let mut decoded = Body::new();
+    decoded.set_chunked();
+
+    let writer = decoded.clone();
+    let mut stream = body.stream();
+    let mut decoder = encoding.decoder();
+
+    tokio::spawn(async move {
+        while let Some(content) = stream.next().await {
+            if let Err(e) = decoder.write_all(content.as_slice()) {
+                warn!("could not decode {:?} body: {}", encoding, e);
+                writer.end_chunked();
+                return;
+            }
+
+            let output = decoder.take_output();
+            if !output.is_empty() {
+                writer.push_chunk(output);
+            }
+        }
+
+        match decoder.finish_output() {
+            Ok(output) if !output.is_empty() => writer.push_chunk(output),
+            Ok(_) => {}
+            Err(e) => warn!("could not finish decoding {:?} body: {}", encoding, e),
+        }
+
+        writer.end_chunked();
+    });
+
+    decoded
+}
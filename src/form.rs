@@ -0,0 +1,111 @@
+/// This file implements parsing of HTML form bodies. Both `application/x-www-form-urlencoded`
+/// and `multipart/form-data` bodies are parsed into a `FormData`, so handlers don't need to
+/// care how the form was sent.
+use std::{error, fmt};
+
+use url::form_urlencoded;
+
+use crate::{
+    headers::MediaType,
+    multipart::{self, MultipartError, Part},
+};
+
+pub const URLENCODED: &str = "application/x-www-form-urlencoded";
+pub const MULTIPART: &str = "multipart/form-data";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormError {
+    /// The content type isn't a form type, or is missing.
+    NotForm,
+
+    /// The multipart body couldn't be parsed.
+    Multipart(MultipartError),
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormError::NotForm => write!(f, "not a form body"),
+            FormError::Multipart(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for FormError {}
+
+impl From<MultipartError> for FormError {
+    fn from(e: MultipartError) -> Self {
+        FormError::Multipart(e)
+    }
+}
+
+/// The fields of a form. Values are decoded, and fields keep the order they were sent in.
+/// File uploads (multipart parts with a file name) are kept apart in `files`.
+#[derive(Debug, Clone, Default)]
+pub struct FormData {
+    pub fields: Vec<(String, String)>,
+    pub files: Vec<Part>,
+}
+
+impl FormData {
+    /// Returns the first value of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns all the values of the field `name`, e.g., for checkboxes.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// Returns the first file uploaded as `name`.
+    pub fn file(&self, name: &str) -> Option<&Part> {
+        self.files
+            .iter()
+            .find(|part| part.name.as_deref() == Some(name))
+    }
+}
+
+/// Returns true if `content_type` is one of the form types that `parse` handles.
+pub fn is_form(content_type: &str) -> bool {
+    content_type
+        .parse::<MediaType>()
+        .is_ok_and(|media_type| media_type.mime == URLENCODED || media_type.mime == MULTIPART)
+}
+
+/// Parse a urlencoded `body`, decoding `+` and percent-encoded bytes.
+pub fn parse_urlencoded(body: &[u8]) -> FormData {
+    FormData {
+        fields: form_urlencoded::parse(body).into_owned().collect(),
+        files: vec![],
+    }
+}
+
+/// Parse a form `body` with the given `content_type`.
+pub fn parse(content_type: &str, body: &[u8]) -> Result<FormData, FormError> {
+    let media_type = content_type
+        .parse::<MediaType>()
+        .map_err(|_| FormError::NotForm)?;
+
+    match media_type.mime.as_str() {
+        URLENCODED => Ok(parse_urlencoded(body)),
+        MULTIPART => {
+            let mut form = FormData::default();
+            for part in multipart::parse(content_type, body)? {
+                match (&part.filename, &part.name) {
+                    (None, Some(name)) => form.fields.push((name.clone(), part.text())),
+                    _ => form.files.push(part),
+                }
+            }
+            Ok(form)
+        }
+        _ => Err(FormError::NotForm),
+    }
+}
@@ -9,7 +9,13 @@ use tokio::{
     net::TcpStream,
 };
 
-use crate::{conntrack::ConnId, request::Request, response::Response, status::Status};
+use crate::{
+    conntrack::ConnId,
+    form::FormError,
+    request::Request,
+    response::Response,
+    status::{self, Status},
+};
 
 /// Handlers can return a follow up action, or an error. Actions may lead too
 /// another handler, or a redirect, or an immediate response. Errors always lead
@@ -24,10 +30,14 @@ pub enum Action {
     /// Respond immediately with the included response.
     Response(Response),
 
-    /// Respond immediately with a 401 Redirect to a new location. Does not
+    /// Respond immediately with a 301 Redirect to a new location. Does not
     /// continue to next handler in the stack.
     Redirect(String),
 
+    /// Respond immediately with a redirect to a new location, with the given 3xx
+    /// status code (see `Response::redirect`.)
+    RedirectWith(String, u16),
+
     /// This session is complete. Do not continue to next handler in the stack.
     Done,
 }
@@ -67,6 +77,21 @@ impl<S: Into<Status> + Clone> fmt::Display for Error<S> {
 
 impl error::Error for Error {}
 
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Failed(e.to_string())
+    }
+}
+
+impl From<FormError> for Error {
+    fn from(e: FormError) -> Self {
+        match e {
+            FormError::NotForm => Error::Status(status::UNSUPPORTED_MEDIA_TYPE.into()),
+            FormError::Multipart(_) => Error::Status(status::BAD_REQUEST.into()),
+        }
+    }
+}
+
 #[async_trait]
 pub trait Handler: Send + Sync {
     async fn new_connection(&self, _id: ConnId) -> Result<(), Error> {
@@ -120,6 +145,14 @@ impl AsyncStream for tokio_rustls::server::TlsStream<tokio::net::TcpStream> {}
 impl AsyncReadStream for tokio_rustls::server::TlsStream<tokio::net::TcpStream> {}
 impl AsyncWriteStream for tokio_rustls::server::TlsStream<tokio::net::TcpStream> {}
 
+impl AsyncStream for tokio_rustls::server::TlsStream<Box<dyn AsyncStream>> {}
+impl AsyncReadStream for tokio_rustls::server::TlsStream<Box<dyn AsyncStream>> {}
+impl AsyncWriteStream for tokio_rustls::server::TlsStream<Box<dyn AsyncStream>> {}
+
+impl AsyncStream for tokio::net::UnixStream {}
+impl AsyncReadStream for tokio::net::UnixStream {}
+impl AsyncWriteStream for tokio::net::UnixStream {}
+
 impl AsyncReadStream for tokio::net::tcp::OwnedReadHalf {}
 impl AsyncWriteStream for tokio::net::tcp::OwnedWriteHalf {}
 
@@ -0,0 +1,327 @@
+/// This file implements an access-log middleware handler. It wraps another handler, observes
+/// the response it writes, and logs a single line per request, in a configurable format
+/// similar to Apache's `LogFormat`.
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use log::Level;
+use tokio::io::AsyncWrite;
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    request::{Request, METHODS_AS_STR},
+    router::RouteHandler,
+};
+
+/// The Apache "combined" log format, with the request duration appended.
+pub const DEFAULT_FORMAT: &str = r#"%a - - [%t] "%r" %s %b "%{Referer}i" "%{User-Agent}i" %D"#;
+
+/// A parsed piece of a log format string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Field(char),
+    RequestHeader(String),
+}
+
+fn parse_format(format: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+
+        let token = match chars.next() {
+            Some('%') => {
+                literal.push('%');
+                continue;
+            }
+            Some('{') => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                if chars.next_if_eq(&'i').is_none() {
+                    // Only request headers are supported, log anything else as is.
+                    literal.push_str(&format!("%{{{}}}", name));
+                    continue;
+                }
+                Token::RequestHeader(name)
+            }
+            Some(c) if "abBDmqrstU".contains(c) => Token::Field(c),
+            Some(c) => {
+                literal.push('%');
+                literal.push(c);
+                continue;
+            }
+            None => {
+                literal.push('%');
+                continue;
+            }
+        };
+
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(std::mem::take(&mut literal)));
+        }
+        tokens.push(token);
+    }
+
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+
+    tokens
+}
+
+/// Logs requests to the wrapped handler. The format string supports these placeholders:
+///
+///   %a  client IP address
+///   %m  request method
+///   %U  URL path
+///   %q  query string, with a leading `?` (or empty)
+///   %r  request line, e.g., `GET /index.html HTTP/1.1`
+///   %s  response status code
+///   %b  response body size in bytes, `-` if empty
+///   %B  response body size in bytes
+///   %D  request duration in microseconds
+///   %t  time the request was received
+///   %{Name}i  value of the request header `Name`
+///   %%  a literal `%`
+///
+/// Fields that aren't known (e.g., the client address of requests over Unix sockets, or the
+/// status of responses written by the server after a handler error) are logged as `-`.
+pub struct AccessLog {
+    handler: RouteHandler,
+    format: Vec<Token>,
+    level: Level,
+}
+
+impl AccessLog {
+    /// Log requests to `handler` in `DEFAULT_FORMAT`, at `Info` level.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hype::{handlers::{access_log::AccessLog, web::Web}, server::Server};
+    ///
+    /// let server = Server::new("localhost", 8080);
+    /// server.route("/", AccessLog::new(Web::new("./www".into())).with_format("%a %m %U %s %b %D"));
+    /// ```
+    pub fn new(handler: impl Into<RouteHandler>) -> Self {
+        Self {
+            handler: handler.into(),
+            format: parse_format(DEFAULT_FORMAT),
+            level: Level::Info,
+        }
+    }
+
+    pub fn with_format(mut self, format: impl AsRef<str>) -> Self {
+        self.format = parse_format(format.as_ref());
+        self
+    }
+
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Render a log line for request `r`, whose response was observed by `w`.
+    fn render(
+        &self,
+        r: &Request,
+        w: &ResponseObserver,
+        received: chrono::DateTime<chrono::Local>,
+        duration: Duration,
+    ) -> String {
+        let mut line = String::new();
+        let method = METHODS_AS_STR.get(&r.method).copied().unwrap_or("-");
+        let query = r
+            .url
+            .as_ref()
+            .and_then(|url| url.query())
+            .map(|q| format!("?{}", q))
+            .unwrap_or_default();
+
+        for token in &self.format {
+            match token {
+                Token::Literal(s) => line.push_str(s),
+                Token::RequestHeader(name) => {
+                    line.push_str(r.headers.get_first(name).map_or("-", |v| v.as_str()))
+                }
+                Token::Field(c) => {
+                    let value = match c {
+                        'a' => r
+                            .peer_addr()
+                            .map_or("-".into(), |addr| addr.ip().to_string()),
+                        'm' => method.into(),
+                        'U' => r.url.as_ref().map_or("-".into(), |url| url.path().into()),
+                        'q' => query.clone(),
+                        'r' => {
+                            let version = if r.version.is_empty() {
+                                "HTTP/1.1"
+                            } else {
+                                r.version.as_str()
+                            };
+                            let path = r.url.as_ref().map_or("-", |url| url.path());
+                            format!("{} {}{} {}", method, path, query, version)
+                        }
+                        's' => w.status.map_or("-".into(), |s| s.to_string()),
+                        'b' if w.body_bytes == 0 => "-".into(),
+                        'b' | 'B' => w.body_bytes.to_string(),
+                        'D' => duration.as_micros().to_string(),
+                        't' => received.format("%d/%b/%Y:%H:%M:%S %z").to_string(),
+                        _ => unreachable!("unknown field {}", c),
+                    };
+                    line.push_str(&value);
+                }
+            }
+        }
+
+        line
+    }
+}
+
+#[async_trait]
+impl Handler for AccessLog {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let received = chrono::Local::now();
+        let start = Instant::now();
+
+        let mut observer = ResponseObserver::new(w);
+        let mut result = self
+            .handler
+            .handler()
+            .read()
+            .await
+            .handle(r, &mut observer)
+            .await;
+
+        // Write returned responses here, so they're observed too.
+        if let Ok(handler::Action::Response(response)) = &mut result {
+            result = response
+                .write_to(&mut observer)
+                .await
+                .map(|_| handler::Action::Done)
+                .map_err(|e| handler::Error::Failed(format!("could not write response: {}", e)));
+        }
+
+        log!(
+            self.level,
+            request_id = r.request_id();
+            "{}",
+            self.render(r, &observer, received, start.elapsed())
+        );
+        result
+    }
+}
+
+/// A writer that passes everything through to `inner`, and records the status code
+/// and body size of the response written to it.
+struct ResponseObserver<'a> {
+    inner: &'a mut dyn AsyncWriteStream,
+
+    /// The response head, until it's complete.
+    head: Vec<u8>,
+    head_done: bool,
+
+    status: Option<u16>,
+    body_bytes: usize,
+}
+
+impl<'a> ResponseObserver<'a> {
+    fn new(inner: &'a mut dyn AsyncWriteStream) -> Self {
+        Self {
+            inner,
+            head: vec![],
+            head_done: false,
+            status: None,
+            body_bytes: 0,
+        }
+    }
+
+    /// Record `buf`, which has been written to `inner`.
+    fn observe(&mut self, mut buf: &[u8]) {
+        while !self.head_done && !buf.is_empty() {
+            let start = self.head.len().saturating_sub(3);
+            self.head.extend_from_slice(buf);
+
+            let Some(end) = self.head[start..]
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .map(|pos| start + pos + 4)
+            else {
+                return;
+            };
+
+            let status = String::from_utf8_lossy(&self.head)
+                .split(' ')
+                .nth(1)
+                .and_then(|code| code.parse::<u16>().ok());
+            let consumed = buf.len() - (self.head.len() - end);
+            buf = &buf[consumed..];
+            self.head.clear();
+
+            // Skip informational (1xx) responses, and wait for the final one. Nothing
+            // after a 101 Switching Protocols is HTTP.
+            if !status.is_some_and(|code| (100..200).contains(&code) && code != 101) {
+                self.status = status;
+                self.head_done = true;
+            }
+        }
+
+        self.body_bytes += buf.len();
+    }
+}
+
+impl<'a> AsyncWrite for ResponseObserver<'a> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut *this.inner).poll_write(cx, buf))?;
+        this.observe(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<'a> AsyncWriteStream for ResponseObserver<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!(
+            parse_format("%m %U 100%% %{User-Agent}i %x"),
+            vec![
+                Token::Field('m'),
+                Token::Literal(" ".into()),
+                Token::Field('U'),
+                Token::Literal(" 100% ".into()),
+                Token::RequestHeader("User-Agent".into()),
+                Token::Literal(" %x".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+/// This file implements a middleware handler for bearer token authentication (RFC 6750).
+/// Requests need an `Authorization: Bearer <token>` header with a token that the verifier
+/// accepts, and get a 401 Unauthorized otherwise.
+use async_trait::async_trait;
+use futures::{future::BoxFuture, Future};
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    request::Request,
+    status,
+};
+
+/// Returns the token in the `Authorization: Bearer <token>` header of `r`, if any.
+pub fn bearer_token(r: &Request) -> Option<&str> {
+    let (scheme, token) = r
+        .headers
+        .get_first("authorization")?
+        .trim()
+        .split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+/// Passes on requests with valid bearer tokens.
+///
+/// # Example
+///
+/// ```
+/// use hype::handlers::auth::BearerAuth;
+///
+/// let auth = BearerAuth::new(|token| async move { token == "secret" }).with_realm("admin");
+/// ```
+pub struct BearerAuth {
+    #[allow(clippy::type_complexity)]
+    verify: Box<dyn Fn(String) -> BoxFuture<'static, bool> + Send + Sync>,
+    realm: Option<String>,
+}
+
+impl BearerAuth {
+    /// Accept tokens that `verify` resolves to true for. The verifier is async, so it can
+    /// look tokens up in a datastore.
+    pub fn new<F, Fut>(verify: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        Self {
+            verify: Box::new(move |token| Box::pin(verify(token))),
+            realm: None,
+        }
+    }
+
+    /// Include `realm` in the `WWW-Authenticate` challenge.
+    pub fn with_realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    fn challenge(&self, error: Option<&str>) -> String {
+        let params: Vec<String> = self
+            .realm
+            .iter()
+            .map(|realm| format!("realm=\"{}\"", realm))
+            .chain(error.map(|error| format!("error=\"{}\"", error)))
+            .collect();
+
+        if params.is_empty() {
+            "Bearer".into()
+        } else {
+            format!("Bearer {}", params.join(", "))
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for BearerAuth {
+    async fn handle(
+        &self,
+        r: &Request,
+        _w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        // Requests without a token don't get an error code, as per RFC 6750.
+        let error = match bearer_token(r) {
+            Some(token) if (self.verify)(token.to_string()).await => {
+                return Ok(handler::Action::Next)
+            }
+            Some(_) => Some("invalid_token"),
+            None => None,
+        };
+
+        debug!("BearerAuth: rejected request to {}", r.abs_path());
+        r.add_response_header("WWW-Authenticate", self.challenge(error));
+        Err(handler::Error::Status(status::UNAUTHORIZED.into()))
+    }
+}
@@ -0,0 +1,154 @@
+/// This file implements a response compression handler. It wraps another handler, captures
+/// its response, and compresses the body with gzip or deflate if the client accepts it and
+/// the content is worth compressing.
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    encoding::ContentEncoding,
+    handler::{self, AsyncWriteStream, Handler},
+    parser::ResponseParser,
+    request::{Method, Request},
+    response::Response,
+    router::RouteHandler,
+};
+
+/// Bodies smaller than this aren't compressed by default.
+pub const DEFAULT_MIN_SIZE: usize = 1024;
+
+pub struct Compress {
+    handler: RouteHandler,
+    min_size: usize,
+}
+
+impl Compress {
+    /// Compress responses from `handler`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hype::{handlers::{compress::Compress, web::Web}, server::Server};
+    ///
+    /// let server = Server::new("localhost", 8080);
+    /// server.route("/", Compress::new(Web::new("./www".into())));
+    /// ```
+    pub fn new(handler: impl Into<RouteHandler>) -> Self {
+        Self {
+            handler: handler.into(),
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// Don't compress bodies smaller than `min_size` bytes.
+    pub fn with_min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Compress the body of `response` in place, if it's worth it. Returns true if the
+    /// body was compressed.
+    async fn compress(&self, response: &mut Response, encoding: ContentEncoding) -> bool {
+        let code = response.status.code;
+        if code < 200 || code == 204 || code == 206 || code == 304 {
+            return false;
+        }
+
+        if response.headers.get_first("content-encoding").is_some()
+            || !response
+                .headers
+                .content_type()
+                .is_some_and(|ct| compressible(&ct.mime))
+        {
+            return false;
+        }
+
+        let content = response.body.content().await;
+        if content.len() < self.min_size {
+            return false;
+        }
+
+        let encoded = match encoding.encode(&content) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                warn!("could not compress response: {}", e);
+                return false;
+            }
+        };
+
+        response.headers.remove("transfer-encoding");
+        response
+            .headers
+            .set("Content-Length", encoded.len().to_string());
+        response.headers.set("Content-Encoding", encoding.as_str());
+        response.headers.add("Vary", "Accept-Encoding");
+        response.set_body(encoded);
+        true
+    }
+}
+
+/// Returns true if content of type `mime` (e.g., `text/html`) is worth compressing.
+fn compressible(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime,
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+        )
+}
+
+fn write_error(e: std::io::Error) -> handler::Error {
+    handler::Error::Failed(format!("could not write response: {}", e))
+}
+
+#[async_trait]
+impl Handler for Compress {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let encoding = match ContentEncoding::from_accept_encoding(&r.headers) {
+            Some(encoding) if r.method != Method::HEAD => encoding,
+            _ => return self.handler.handler().read().await.handle(r, w).await,
+        };
+
+        // Capture the response, whether it's written to the stream, or returned.
+        let mut buf: Vec<u8> = vec![];
+        let result = self
+            .handler
+            .handler()
+            .read()
+            .await
+            .handle(r, &mut buf)
+            .await;
+
+        if let Ok(handler::Action::Response(mut response)) = result {
+            self.compress(&mut response, encoding).await;
+            return Ok(handler::Action::Response(response));
+        }
+
+        let mut parser = ResponseParser::new();
+        let parsed = parser.parse_buf(&buf).is_ok() && parser.is_complete();
+
+        if buf.is_empty() || !parsed {
+            // Pass through anything we can't parse
+            w.write_all(&buf).await.map_err(write_error)?;
+            return result;
+        }
+
+        let mut response: Response = parser.get_message().into();
+        if self.compress(&mut response, encoding).await {
+            w.write_all(&response.serialize_bytes())
+                .await
+                .map_err(write_error)?;
+        } else {
+            w.write_all(&buf).await.map_err(write_error)?;
+        }
+        result
+    }
+}
@@ -0,0 +1,63 @@
+/// This file implements cache validators (ETag and Last-Modified) and conditional GET
+/// support for the static file handlers.
+use std::{fs::Metadata, time::UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    headers::Headers,
+    request::{PreconditionResult, Request},
+};
+
+/// Format a timestamp as an HTTP date, e.g., "Sun, 06 Nov 1994 08:49:37 GMT".
+pub fn http_date(dt: &DateTime<Utc>) -> String {
+    dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[derive(Debug, Clone)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// Build validators from file metadata. The ETag is strong, and derived from the file
+    /// size and modification time (in nanoseconds), so it changes whenever the content
+    /// could have.
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        let modified = metadata.modified().ok()?;
+        let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+        let last_modified = DateTime::<Utc>::from_timestamp(since_epoch.as_secs() as i64, 0)?;
+
+        Some(Self {
+            etag: format!("\"{:x}-{:x}\"", metadata.len(), since_epoch.as_nanos()),
+            last_modified,
+        })
+    }
+
+    pub fn set_headers(&self, headers: &mut Headers) {
+        headers.set("ETag", self.etag.clone());
+        headers.set("Last-Modified", http_date(&self.last_modified));
+    }
+
+    /// Evaluate the conditional request headers of `r` (e.g., If-None-Match) against the
+    /// validators.
+    pub fn preconditions(&self, r: &Request) -> PreconditionResult {
+        r.evaluate_preconditions(Some(&self.etag), Some(self.last_modified))
+    }
+
+    /// Returns true if the `Range` of `r` should be honored, i.e., unless its If-Range
+    /// names an ETag or date that no longer matches, in which case the whole content
+    /// should be sent. ETags need a strong match (RFC 9110 §13.1.5), so weak ones never
+    /// match.
+    pub fn if_range(&self, r: &Request) -> bool {
+        match r.headers.get_first("if-range").map(|v| v.trim()) {
+            None => true,
+            Some(tag) if tag.starts_with("W/") => false,
+            Some(tag) if tag.starts_with('"') => tag == self.etag,
+            Some(date) => {
+                DateTime::parse_from_rfc2822(date).is_ok_and(|date| date == self.last_modified)
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+/// This file implements a CORS (Cross-Origin Resource Sharing) middleware handler. It answers
+/// preflight requests directly, and adds the CORS response headers to all other requests
+/// from allowed origins.
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    request::{Method, Request, METHODS_AS_STR},
+    response::Response,
+    status,
+};
+
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Vec<String>,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    allow_credentials: bool,
+    max_age: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CorsBuilder {
+    cors: Cors,
+}
+
+impl Cors {
+    /// Returns a builder for a CORS policy. By default, no origins are allowed, and the
+    /// allowed methods are GET, HEAD, and POST.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hype::{handlers::cors::Cors, request::Method};
+    ///
+    /// let cors = Cors::builder()
+    ///     .allow_origin("https://example.com")
+    ///     .allow_methods(vec![Method::GET, Method::PUT])
+    ///     .allow_header("content-type")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> CorsBuilder {
+        CorsBuilder {
+            cors: Cors {
+                origins: vec![],
+                methods: vec![Method::GET, Method::HEAD, Method::POST],
+                headers: vec![],
+                allow_credentials: false,
+                max_age: None,
+            },
+        }
+    }
+
+    fn allows_any_origin(&self) -> bool {
+        self.origins.iter().any(|o| o == "*")
+    }
+
+    /// Returns the value of the Access-Control-Allow-Origin header for `origin`, or None
+    /// if the origin is not allowed.
+    fn allow_origin(&self, origin: &str) -> Option<String> {
+        if self.allows_any_origin() {
+            Some("*".into())
+        } else if self.origins.iter().any(|o| o.eq_ignore_ascii_case(origin)) {
+            Some(origin.into())
+        } else {
+            None
+        }
+    }
+
+    async fn preflight(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+        allow_origin: String,
+    ) -> Result<handler::Action, handler::Error> {
+        let mut response = Response::new(status::NO_CONTENT);
+        response
+            .headers
+            .set("Access-Control-Allow-Origin", allow_origin);
+        if !self.allows_any_origin() {
+            response.headers.set("Vary", "Origin");
+        }
+
+        response.headers.set(
+            "Access-Control-Allow-Methods",
+            self.methods
+                .iter()
+                .map(|m| METHODS_AS_STR[m])
+                .collect::<Vec<&str>>()
+                .join(", "),
+        );
+
+        // Without a configured list, allow whatever headers were asked for.
+        let headers = if self.headers.is_empty() {
+            r.headers
+                .get_first("access-control-request-headers")
+                .cloned()
+        } else {
+            Some(self.headers.join(", "))
+        };
+        if let Some(headers) = headers {
+            response
+                .headers
+                .set("Access-Control-Allow-Headers", headers);
+        }
+
+        if self.allow_credentials {
+            response
+                .headers
+                .set("Access-Control-Allow-Credentials", "true");
+        }
+
+        if let Some(max_age) = self.max_age {
+            response
+                .headers
+                .set("Access-Control-Max-Age", max_age.as_secs().to_string());
+        }
+
+        w.write_all(response.serialize().as_bytes())
+            .await
+            .map_err(|e| handler::Error::Failed(format!("could not write response: {}", e)))?;
+        Ok(handler::Action::Done)
+    }
+}
+
+impl CorsBuilder {
+    /// Allow requests from `origin`, e.g., "https://example.com", or "*" for any origin.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.cors.origins.push(origin.into());
+        self
+    }
+
+    pub fn allow_origins<T: Into<String>>(mut self, origins: Vec<T>) -> Self {
+        self.cors
+            .origins
+            .extend(origins.into_iter().map(|o| o.into()));
+        self
+    }
+
+    /// Set the methods allowed in cross-origin requests.
+    pub fn allow_methods(mut self, methods: Vec<Method>) -> Self {
+        self.cors.methods = methods;
+        self
+    }
+
+    /// Allow the request header `header`. If no headers are specified, any headers requested
+    /// in a preflight are allowed.
+    pub fn allow_header(mut self, header: impl Into<String>) -> Self {
+        self.cors.headers.push(header.into());
+        self
+    }
+
+    pub fn allow_headers<T: Into<String>>(mut self, headers: Vec<T>) -> Self {
+        self.cors
+            .headers
+            .extend(headers.into_iter().map(|h| h.into()));
+        self
+    }
+
+    /// Allow cookies and authorization headers in cross-origin requests.
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.cors.allow_credentials = allow;
+        self
+    }
+
+    /// Let browsers cache preflight responses for `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.cors.max_age = Some(max_age);
+        self
+    }
+
+    /// Build the policy. Fails if credentials are allowed for any origin, which the
+    /// CORS spec forbids.
+    pub fn build(self) -> Result<Cors, String> {
+        if self.cors.allow_credentials && self.cors.allows_any_origin() {
+            return Err("CORS: can't allow credentials with a wildcard origin".into());
+        }
+
+        Ok(self.cors)
+    }
+}
+
+#[async_trait]
+impl Handler for Cors {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let Some(origin) = r.headers.get_first("origin") else {
+            return Ok(handler::Action::Next);
+        };
+
+        let Some(allow_origin) = self.allow_origin(origin) else {
+            debug!("CORS: origin {} not allowed", origin);
+            return Ok(handler::Action::Next);
+        };
+
+        if r.method == Method::OPTIONS
+            && r.headers
+                .get_first("access-control-request-method")
+                .is_some()
+        {
+            return self.preflight(r, w, allow_origin).await;
+        }
+
+        r.add_response_header("Access-Control-Allow-Origin", allow_origin);
+        if !self.allows_any_origin() {
+            r.add_response_header("Vary", "Origin");
+        }
+        if self.allow_credentials {
+            r.add_response_header("Access-Control-Allow-Credentials", "true");
+        }
+
+        Ok(handler::Action::Next)
+    }
+}
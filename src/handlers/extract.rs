@@ -0,0 +1,299 @@
+/// This file implements extractors for service functions. Instead of taking a `Request`,
+/// functions passed to `extract` take arguments that pull typed values out of it, e.g., the
+/// route's path parameters with `Path`, or a JSON body with `Json`.
+///
+/// # Example
+///
+/// ```no_run
+/// use hype::{handlers::extract::{extract, Json, Path}, handler::Error, server::Server};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Id {
+///     id: u32,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// async fn update_user(Path(Id { id }): Path<Id>, Json(user): Json<User>) -> Result<String, Error> {
+///     Ok(format!("updated user {}: {}", id, user.name))
+/// }
+///
+/// let server = Server::new("localhost", 8080);
+/// server.route("/users/:id", extract(update_user));
+/// ```
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::Future;
+use serde::{
+    de::{self, value::MapDeserializer, DeserializeOwned, IntoDeserializer, Unexpected, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+
+use crate::{
+    handler::{Action, Error},
+    request::Request,
+    status,
+};
+
+use super::service::{handler, FnHandler};
+
+/// Types that can be extracted from a request.
+#[async_trait]
+pub trait FromRequest: Sized {
+    async fn from_request(r: &Request) -> Result<Self, Error>;
+}
+
+/// Extracts the route's path parameters (e.g., `:id` in `/users/:id`) into a struct with
+/// matching field names. Routes with a single parameter can also extract it directly, e.g.,
+/// as `Path<u32>`. Fails with a 400 if the parameters don't fit `T`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned> FromRequest for Path<T> {
+    async fn from_request(r: &Request) -> Result<Self, Error> {
+        T::deserialize(Params(r)).map(Path).map_err(|e| {
+            debug!("Path: bad parameters {:?}: {}", r.params, e);
+            Error::Status(status::BAD_REQUEST.into())
+        })
+    }
+}
+
+/// Extracts the JSON body of the request, as `Request::json` does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned> FromRequest for Json<T> {
+    async fn from_request(r: &Request) -> Result<Self, Error> {
+        r.json().await.map(Json)
+    }
+}
+
+macro_rules! from_request_tuple {
+    ($($arg:ident),+) => {
+        #[async_trait]
+        impl<$($arg: FromRequest + Send),+> FromRequest for ($($arg,)+) {
+            async fn from_request(r: &Request) -> Result<Self, Error> {
+                Ok(($($arg::from_request(r).await?,)+))
+            }
+        }
+    };
+}
+
+from_request_tuple!(A);
+from_request_tuple!(A, B);
+from_request_tuple!(A, B, C);
+
+/// Async functions whose arguments (`Args`, as a tuple) can be extracted from a request.
+pub trait ExtractFn<Args>: Send + Sync + 'static {
+    type Output;
+    type Future: Future<Output = Self::Output> + Send + 'static;
+
+    fn call(&self, args: Args) -> Self::Future;
+}
+
+macro_rules! extract_fn {
+    ($($arg:ident),+) => {
+        impl<Func, Fut, $($arg),+> ExtractFn<($($arg,)+)> for Func
+        where
+            Func: Fn($($arg),+) -> Fut + Send + Sync + 'static,
+            Fut: Future + Send + 'static,
+        {
+            type Output = Fut::Output;
+            type Future = Fut;
+
+            #[allow(non_snake_case)]
+            fn call(&self, ($($arg,)+): ($($arg,)+)) -> Fut {
+                self($($arg),+)
+            }
+        }
+    };
+}
+
+extract_fn!(A);
+extract_fn!(A, B);
+extract_fn!(A, B, C);
+
+/// Create a new handler from an async function that takes up to three extractors. The
+/// extractors run in order, and the first one to fail fails the request.
+pub fn extract<Func, Args, R>(func: Func) -> FnHandler<R>
+where
+    Func: ExtractFn<Args, Output = Result<R, Error>>,
+    Args: FromRequest + Send + 'static,
+    R: Into<Action> + Send + 'static,
+{
+    let func = Arc::new(func);
+    handler(move |r: Request| {
+        let func = Arc::clone(&func);
+        async move {
+            let args = Args::from_request(&r).await?;
+            func.call(args).await
+        }
+    })
+}
+
+/// Deserializes path parameters, as a map, or as the value of the only parameter.
+struct Params<'a>(&'a Request);
+
+impl<'a> Params<'a> {
+    fn only(&self) -> Result<Param<'a>, de::value::Error> {
+        match self.0.params.values().collect::<Vec<_>>()[..] {
+            [value] => Ok(Param(value)),
+            _ => Err(de::Error::custom(format!(
+                "expected 1 path parameter, got {}",
+                self.0.params.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! deserialize_only {
+    ($($method:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                self.only()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Params<'de> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(MapDeserializer::new(
+            self.0.params.iter().map(|(k, v)| (k.as_str(), Param(v))),
+        ))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.only()?.deserialize_newtype_struct("", visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.only()?.deserialize_enum(name, variants, visitor)
+    }
+
+    deserialize_only!(
+        deserialize_bool,
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64,
+        deserialize_f32,
+        deserialize_f64,
+        deserialize_char,
+        deserialize_str,
+        deserialize_string,
+        deserialize_option
+    );
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct seq tuple tuple_struct identifier ignored_any
+    }
+}
+
+/// Deserializes a single path parameter, parsing it for numeric and boolean types.
+struct Param<'a>(&'a str);
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(self.0), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Param<'de> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    deserialize_parsed!(
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char
+    );
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, de::value::Error> for Param<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
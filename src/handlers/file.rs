@@ -1,26 +1,70 @@
 use std::{
     collections::HashMap,
     ffi::OsStr,
+    io::SeekFrom,
     path::{Path, PathBuf},
 };
 
 use async_trait::async_trait;
 use tokio::{
     fs,
-    io::{self, AsyncWriteExt},
+    io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
 };
 
+use super::{conditional::Validators, fs_path};
 use crate::{
+    body::Body,
     content_types,
     handler::{self, AsyncWriteStream, Handler},
-    request::Request,
+    request::{PreconditionResult, Request},
     response::Response,
     status,
 };
 
+/// Parses a `Range` header of the form `bytes=start-end`, `bytes=start-` or `bytes=-suffix`,
+/// and returns the inclusive byte range to serve from content of length `len`. Returns Ok(None)
+/// if the header should be ignored (e.g., unknown units), and Err(()) if the range is not
+/// satisfiable. Multiple ranges are not supported.
+fn parse_range(header: &str, len: usize) -> Result<Option<(usize, usize)>, ()> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+
+    if spec.contains(',') {
+        return Err(());
+    }
+
+    let (start, end) = spec.split_once('-').ok_or(())?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // Suffix range: the last `end` bytes
+        let suffix = end.parse::<usize>().or(Err(()))?;
+        if suffix == 0 || len == 0 {
+            return Err(());
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start = start.parse::<usize>().or(Err(()))?;
+        let end = if end.is_empty() {
+            len.saturating_sub(1)
+        } else {
+            end.parse::<usize>().or(Err(()))?.min(len.saturating_sub(1))
+        };
+        (start, end)
+    };
+
+    if range.0 >= len || range.0 > range.1 {
+        return Err(());
+    }
+
+    Ok(Some(range))
+}
+
 pub struct File {
     base_fs_path: String,
     content_types: HashMap<&'static str, &'static str>,
+    sniffing: bool,
 }
 
 impl File {
@@ -28,20 +72,27 @@ impl File {
         File {
             base_fs_path,
             content_types: content_types::BY_EXT.clone(),
+            sniffing: false,
         }
     }
 
+    /// Detect the types of files with unknown extensions from their contents. See
+    /// `Web::enable_sniffing`.
+    pub fn enable_sniffing(&mut self, enable: bool) {
+        self.sniffing = enable;
+    }
+
     async fn write_response<'b>(
         w: &mut dyn AsyncWriteStream,
         status: status::Code<'b>,
         content_type: String,
-        body: String,
+        body: impl Into<Body>,
     ) -> io::Result<()> {
         let mut response = Response::new(status);
         response.headers.set("Content-Type", content_type);
         response.set_body(body);
 
-        w.write_all(response.serialize().as_bytes()).await
+        w.write_all(response.serialize_bytes().as_slice()).await
     }
 
     async fn write_dir(
@@ -84,27 +135,113 @@ impl File {
             .or(Err(()))
     }
 
-    async fn write_file_contents(
+    /// Write the file at `path`, honoring conditional and range requests. This is shared
+    /// with the `Web` handler, which also sets the `Cache-Control` header.
+    pub(crate) async fn write_file_contents(
         w: &mut dyn AsyncWriteStream,
+        r: &Request,
         path: String,
+        validators: Option<Validators>,
         content_types: &HashMap<&str, &str>,
+        sniffing: bool,
+        cache_control: Option<String>,
     ) -> Result<(), ()> {
-        let contents = fs::read_to_string(&path).await.or(Err(()))?;
-
-        let ext = Path::new(&path)
-            .extension()
-            .unwrap_or(OsStr::new("txt"))
-            .to_str()
-            .unwrap();
-
-        File::write_response(
-            w,
-            status::OK,
-            content_types.get(ext).unwrap_or(&"txt").to_string(),
-            contents,
-        )
-        .await
-        .or(Err(()))
+        if let Some(validators) = &validators {
+            let response = match validators.preconditions(r) {
+                PreconditionResult::Proceed => None,
+                PreconditionResult::NotModified => {
+                    let mut response = Response::new(status::NOT_MODIFIED);
+                    validators.set_headers(&mut response.headers);
+                    if let Some(cache_control) = &cache_control {
+                        response.headers.set("Cache-Control", cache_control);
+                    }
+                    Some(response)
+                }
+                PreconditionResult::PreconditionFailed => {
+                    Some(Response::new(status::PRECONDITION_FAILED))
+                }
+            };
+            if let Some(mut response) = response {
+                return w
+                    .write_all(response.serialize_bytes().as_slice())
+                    .await
+                    .or(Err(()));
+            }
+        }
+
+        let mut file = fs::File::open(&path).await.or(Err(()))?;
+        let len = file.metadata().await.or(Err(()))?.len() as usize;
+
+        let ext = Path::new(&path).extension().and_then(OsStr::to_str);
+        let content_type = match ext.and_then(|ext| content_types.get(ext)) {
+            Some(content_type) => content_type.to_string(),
+            None if sniffing => {
+                let mut head = vec![0u8; 512];
+                let n = file.read(&mut head).await.or(Err(()))?;
+                file.seek(SeekFrom::Start(0)).await.or(Err(()))?;
+                content_types::sniff(&head[..n])
+                    .unwrap_or(content_types::OCTET_STREAM)
+                    .to_string()
+            }
+            None => content_types
+                .get(ext.unwrap_or("txt"))
+                .unwrap_or(&"txt")
+                .to_string(),
+        };
+
+        // Ignore the range if the client's copy is stale (per If-Range), and send the
+        // whole file instead.
+        let range = r.headers.get_first("range").filter(|_| match &validators {
+            Some(validators) => validators.if_range(r),
+            None => r.headers.get("if-range").is_none(),
+        });
+        let range = match range.map(|r| parse_range(r, len)) {
+            None => None,
+            Some(Ok(range)) => range,
+            Some(Err(())) => {
+                let mut response = Response::new(status::RANGE_NOT_SATISFIABLE);
+                response
+                    .headers
+                    .set("Content-Range", format!("bytes */{}", len));
+                return w
+                    .write_all(response.serialize_bytes().as_slice())
+                    .await
+                    .or(Err(()));
+            }
+        };
+
+        // Stream the file, rather than reading it into memory.
+        let mut response = if let Some((start, end)) = range {
+            let mut response = Response::new(status::PARTIAL_CONTENT);
+            response
+                .headers
+                .set("Content-Range", format!("bytes {}-{}/{}", start, end, len));
+
+            file.seek(SeekFrom::Start(start as u64)).await.or(Err(()))?;
+            let range_len = end - start + 1;
+            response
+                .headers
+                .set("Content-Length", range_len.to_string());
+            response.set_body(Body::from_reader(file.take(range_len as u64)));
+            response
+        } else {
+            let mut response = Response::new(status::OK);
+            response.headers.set("Accept-Ranges", "bytes");
+            response.headers.set("Content-Length", len.to_string());
+            if len > 0 {
+                response.set_body(Body::from_reader(file));
+            }
+            response
+        };
+
+        response.headers.set("Content-Type", content_type);
+        if let Some(cache_control) = cache_control {
+            response.headers.set("Cache-Control", cache_control);
+        }
+        if let Some(validators) = &validators {
+            validators.set_headers(&mut response.headers);
+        }
+        response.write_to(w).await.or(Err(()))
     }
 
     async fn handle_path(
@@ -112,13 +249,7 @@ impl File {
         r: &Request,
         w: &mut dyn AsyncWriteStream,
     ) -> Result<handler::Action, handler::Error> {
-        let mut abs_fs_path = PathBuf::new();
-        abs_fs_path.push(self.base_fs_path.as_str());
-
-        if !r.path().is_empty() {
-            abs_fs_path.push(&r.path()[1..]);
-        }
-
+        let abs_fs_path = fs_path::resolve(&self.base_fs_path, &r.path(), false).await?;
         let abs_fs_path = abs_fs_path.to_str().ok_or(handler::Error::Failed(
             "could not parse request path".into(),
         ))?;
@@ -145,9 +276,17 @@ impl File {
                     "could not list directory".into(),
                 )))?;
         } else {
-            File::write_file_contents(w, abs_fs_path, &self.content_types)
-                .await
-                .or(Err(handler::Error::Failed("could not open file".into())))?;
+            File::write_file_contents(
+                w,
+                r,
+                abs_fs_path,
+                Validators::from_metadata(&metadata),
+                &self.content_types,
+                self.sniffing,
+                None,
+            )
+            .await
+            .or(Err(handler::Error::Failed("could not open file".into())))?;
         }
 
         Ok(handler::Action::Done)
@@ -163,7 +302,9 @@ impl Handler for File {
     ) -> Result<handler::Action, handler::Error> {
         let result = self.handle_path(r, w).await;
 
-        if let Err(err) = &result {
+        // Statuses (e.g., a 403 for a path that escapes the base path) are left for the
+        // server's error handler to write.
+        if let Err(err @ handler::Error::Failed(_)) = &result {
             File::write_response(
                 w,
                 status::NOT_FOUND,
@@ -174,10 +315,8 @@ impl Handler for File {
             .or(Err(handler::Error::Failed(
                 "could not write to stream".into(),
             )))?;
-
-            return result;
         }
 
-        Ok(handler::Action::Done)
+        result
     }
 }
@@ -0,0 +1,96 @@
+/// This file maps request paths to files under the base path of the static file handlers,
+/// without letting requests escape it, e.g., with `..` segments.
+use std::path::{Path, PathBuf};
+
+use percent_encoding::percent_decode_str;
+use tokio::fs;
+
+use crate::{handler, status};
+
+/// Returns the file for the percent-encoded request `path` (relative to the handler's route)
+/// under `base_fs_path`.
+///
+/// Fails with a 403 if the decoded path has `..` segments (the URL parser resolves literal
+/// ones, so these can only come from encoded slashes), backslashes, or NUL bytes. Unless
+/// `follow_symlinks` is set, also fails with a 404 if the file exists, but symlinks take it
+/// outside the base path.
+pub async fn resolve(
+    base_fs_path: &str,
+    path: &str,
+    follow_symlinks: bool,
+) -> Result<PathBuf, handler::Error> {
+    let forbidden = || handler::Error::Status(status::FORBIDDEN.into());
+    let decoded = percent_decode_str(path)
+        .decode_utf8()
+        .map_err(|_| forbidden())?;
+
+    if decoded.contains(['\0', '\\']) {
+        debug!("rejecting request path: {}", path);
+        return Err(forbidden());
+    }
+
+    let mut abs_fs_path = PathBuf::from(base_fs_path);
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                debug!("rejecting request path: {}", path);
+                return Err(forbidden());
+            }
+            segment => abs_fs_path.push(segment),
+        }
+    }
+
+    if !follow_symlinks && !is_contained(base_fs_path, &abs_fs_path).await? {
+        return Err(handler::Error::Status(status::NOT_FOUND.into()));
+    }
+
+    Ok(abs_fs_path)
+}
+
+/// Returns false if `abs_fs_path`, which is under `base_fs_path`, goes through symlinks that
+/// lead outside of it. Paths that don't exist are contained, and left to the caller to report.
+pub async fn is_contained(base_fs_path: &str, abs_fs_path: &Path) -> Result<bool, handler::Error> {
+    let Ok(relative) = abs_fs_path.strip_prefix(base_fs_path) else {
+        return Ok(false);
+    };
+
+    // Without symlinks, the path can't be anywhere else, so skip canonicalizing.
+    let mut path = PathBuf::from(base_fs_path);
+    let mut has_symlinks = false;
+    for component in relative.components() {
+        path.push(component);
+        match fs::symlink_metadata(&path).await {
+            Ok(metadata) if metadata.is_symlink() => {
+                has_symlinks = true;
+                break;
+            }
+            Ok(_) => {}
+            Err(_) => return Ok(true),
+        }
+    }
+
+    if !has_symlinks {
+        return Ok(true);
+    }
+
+    let Ok(real_path) = fs::canonicalize(abs_fs_path).await else {
+        return Ok(true);
+    };
+
+    let real_base = fs::canonicalize(base_fs_path)
+        .await
+        .map_err(|e| handler::Error::Failed(format!("could not resolve base path: {}", e)))?;
+
+    if !real_path.starts_with(&real_base) {
+        debug!(
+            "{} resolves to {}, outside of {}",
+            abs_fs_path.display(),
+            real_path.display(),
+            real_base.display()
+        );
+        return Ok(false);
+    }
+
+    Ok(true)
+}
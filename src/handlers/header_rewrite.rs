@@ -0,0 +1,184 @@
+/// This file implements a handler that rewrites the headers of requests on their way to
+/// another handler, and of the responses on their way back, e.g., to strip a header that a
+/// backend shouldn't see, or to rename one. Like `Http::rewrite_header` in the load
+/// balancer, but for any handler, and in both directions.
+use async_trait::async_trait;
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    headers::Headers,
+    injector::HeadRewriter,
+    request::Request,
+    router::RouteHandler,
+};
+
+/// A change to a set of headers. Names are case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    /// Add a value, keeping any existing ones.
+    Add(String, String),
+
+    /// Replace all values.
+    Set(String, String),
+
+    Remove(String),
+
+    /// Move the values of the first header to the second, replacing its values.
+    Rename(String, String),
+}
+
+impl Rule {
+    pub fn apply(&self, headers: &mut Headers) {
+        match self {
+            Rule::Add(k, v) => headers.add(k, v),
+            Rule::Set(k, v) => headers.set(k, v),
+            Rule::Remove(k) => headers.remove(k),
+            Rule::Rename(from, to) => {
+                if let Some(values) = headers.get(from).cloned() {
+                    headers.remove(from);
+                    headers.set_multiple(to, values);
+                }
+            }
+        }
+    }
+}
+
+/// Rewrites headers by rule, in the order the rules were added.
+///
+/// Response rules apply to the response that the wrapped handler writes (or returns), not
+/// to error responses for failed requests, or headers added by the server afterwards (e.g.,
+/// with `Request::add_response_header`, or the default `Server` header, which can be turned
+/// off with `Server::set_server_header`.)
+///
+/// # Example
+///
+/// ```no_run
+/// use hype::{handlers::{header_rewrite::HeaderRewrite, web::Web}, server::Server};
+///
+/// let server = Server::new("localhost", 8080);
+/// server.route(
+///     "/",
+///     HeaderRewrite::new(Web::new("./www".into()))
+///         .remove_request_header("Cookie")
+///         .rename_response_header("ETag", "X-Version")
+///         .set_response_header("X-Frame-Options", "DENY"),
+/// );
+/// ```
+pub struct HeaderRewrite {
+    handler: RouteHandler,
+    request_rules: Vec<Rule>,
+    response_rules: Vec<Rule>,
+}
+
+impl HeaderRewrite {
+    /// Rewrite the headers of requests to, and responses from, `handler`.
+    pub fn new(handler: impl Into<RouteHandler>) -> Self {
+        Self {
+            handler: handler.into(),
+            request_rules: vec![],
+            response_rules: vec![],
+        }
+    }
+
+    pub fn with_request_rule(mut self, rule: Rule) -> Self {
+        self.request_rules.push(rule);
+        self
+    }
+
+    pub fn with_response_rule(mut self, rule: Rule) -> Self {
+        self.response_rules.push(rule);
+        self
+    }
+
+    pub fn add_request_header(self, k: impl Into<String>, v: impl Into<String>) -> Self {
+        self.with_request_rule(Rule::Add(k.into(), v.into()))
+    }
+
+    pub fn set_request_header(self, k: impl Into<String>, v: impl Into<String>) -> Self {
+        self.with_request_rule(Rule::Set(k.into(), v.into()))
+    }
+
+    pub fn remove_request_header(self, k: impl Into<String>) -> Self {
+        self.with_request_rule(Rule::Remove(k.into()))
+    }
+
+    pub fn rename_request_header(self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.with_request_rule(Rule::Rename(from.into(), to.into()))
+    }
+
+    pub fn add_response_header(self, k: impl Into<String>, v: impl Into<String>) -> Self {
+        self.with_response_rule(Rule::Add(k.into(), v.into()))
+    }
+
+    pub fn set_response_header(self, k: impl Into<String>, v: impl Into<String>) -> Self {
+        self.with_response_rule(Rule::Set(k.into(), v.into()))
+    }
+
+    pub fn remove_response_header(self, k: impl Into<String>) -> Self {
+        self.with_response_rule(Rule::Remove(k.into()))
+    }
+
+    pub fn rename_response_header(self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.with_response_rule(Rule::Rename(from.into(), to.into()))
+    }
+}
+
+/// Returns `head` (including the blank line that ends it) with `rules` applied.
+fn rewrite_head(head: &[u8], rules: &[Rule]) -> Vec<u8> {
+    let text = String::from_utf8_lossy(head);
+    let mut lines = text.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+
+    let mut headers = Headers::new();
+    for (k, v) in lines.filter_map(|line| line.split_once(':')) {
+        headers.add(k.trim(), v.trim());
+    }
+    rules.iter().for_each(|rule| rule.apply(&mut headers));
+
+    let mut out = format!("{}\r\n", status_line);
+    for (k, values) in headers.iter() {
+        for v in values {
+            out.push_str(&format!("{}: {}\r\n", k, v));
+        }
+    }
+    out.push_str("\r\n");
+    out.into_bytes()
+}
+
+#[async_trait]
+impl Handler for HeaderRewrite {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let mut request = r.clone();
+        self.request_rules
+            .iter()
+            .for_each(|rule| rule.apply(&mut request.headers));
+
+        let handler = self.handler.handler();
+        let handler = handler.read().await;
+        if self.response_rules.is_empty() {
+            return handler.handle(&request, w).await;
+        }
+
+        let rules = &self.response_rules;
+        let mut rewriter = HeadRewriter::new(w, |head| rewrite_head(head, rules));
+        let result = handler.handle(&request, &mut rewriter).await;
+        rewriter
+            .finish()
+            .await
+            .map_err(|e| handler::Error::Failed(format!("could not write response: {}", e)))?;
+
+        match result {
+            Ok(handler::Action::Response(mut response)) => {
+                self.response_rules
+                    .iter()
+                    .for_each(|rule| rule.apply(&mut response.headers));
+                Ok(handler::Action::Response(response))
+            }
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,205 @@
+/// This file implements a middleware handler that allows or denies requests based on the
+/// client's IP address, using lists of CIDR blocks (e.g., `10.0.0.0/8` or `fd00::/8`).
+/// Denied requests get a 403 Forbidden.
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use async_trait::async_trait;
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    request::Request,
+    status,
+};
+
+/// A block of IP addresses, e.g., `192.168.0.0/16`. A bare address is a block of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Returns true if `ip` is in the block. IPv4-mapped IPv6 addresses (e.g.,
+    /// `::ffff:10.0.0.1`) match IPv4 blocks.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.trim().split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s.trim(), None),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|e| format!("bad address in {}: {}", s, e))?
+            .to_canonical();
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or(format!("bad prefix length in {}", s))?,
+            None => max_len,
+        };
+
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Decides addresses that are in both the allow and deny lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precedence {
+    /// Addresses that are in both lists are denied.
+    #[default]
+    DenyOverrides,
+
+    /// Addresses that are in both lists are allowed.
+    AllowOverrides,
+}
+
+#[derive(Debug, Clone)]
+pub struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+    precedence: Precedence,
+    default_allow: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct IpFilterBuilder {
+    allow: Vec<String>,
+    deny: Vec<String>,
+    precedence: Precedence,
+    default_allow: bool,
+}
+
+impl IpFilter {
+    /// Returns a builder for an IP filter. By default, addresses that aren't in either list
+    /// are allowed, and the deny list overrides the allow list.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hype::handlers::ip_filter::IpFilter;
+    ///
+    /// let filter = IpFilter::builder()
+    ///     .allow("10.0.0.0/8")
+    ///     .allow("::1")
+    ///     .deny("10.0.0.1")
+    ///     .default_allow(false)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> IpFilterBuilder {
+        IpFilterBuilder {
+            allow: vec![],
+            deny: vec![],
+            precedence: Precedence::default(),
+            default_allow: true,
+        }
+    }
+
+    /// Returns true if requests from `ip` are allowed.
+    pub fn allows(&self, ip: &IpAddr) -> bool {
+        let allowed = self.allow.iter().any(|cidr| cidr.contains(ip));
+        let denied = self.deny.iter().any(|cidr| cidr.contains(ip));
+
+        match (allowed, denied) {
+            (true, true) => self.precedence == Precedence::AllowOverrides,
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => self.default_allow,
+        }
+    }
+}
+
+impl IpFilterBuilder {
+    /// Allow requests from `cidr`, e.g., "10.0.0.0/8" or "::1".
+    pub fn allow(mut self, cidr: impl Into<String>) -> Self {
+        self.allow.push(cidr.into());
+        self
+    }
+
+    /// Deny requests from `cidr`.
+    pub fn deny(mut self, cidr: impl Into<String>) -> Self {
+        self.deny.push(cidr.into());
+        self
+    }
+
+    pub fn precedence(mut self, precedence: Precedence) -> Self {
+        self.precedence = precedence;
+        self
+    }
+
+    /// Whether to allow addresses that aren't in either list. This also applies to
+    /// requests without a client address, e.g., over Unix sockets.
+    pub fn default_allow(mut self, allow: bool) -> Self {
+        self.default_allow = allow;
+        self
+    }
+
+    /// Build the filter. Fails if any of the CIDR blocks are invalid.
+    pub fn build(self) -> Result<IpFilter, String> {
+        let parse = |cidrs: Vec<String>| {
+            cidrs
+                .iter()
+                .map(|cidr| cidr.parse::<Cidr>())
+                .collect::<Result<Vec<Cidr>, String>>()
+        };
+
+        Ok(IpFilter {
+            allow: parse(self.allow)?,
+            deny: parse(self.deny)?,
+            precedence: self.precedence,
+            default_allow: self.default_allow,
+        })
+    }
+}
+
+#[async_trait]
+impl Handler for IpFilter {
+    async fn handle(
+        &self,
+        r: &Request,
+        _w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let allowed = match r.peer_addr() {
+            Some(peer_addr) => self.allows(&peer_addr.ip()),
+            None => self.default_allow,
+        };
+
+        if allowed {
+            Ok(handler::Action::Next)
+        } else {
+            debug!("IpFilter: denied request from {:?}", r.peer_addr());
+            Err(handler::Error::Status(status::FORBIDDEN.into()))
+        }
+    }
+}
@@ -20,6 +20,20 @@ impl<P: Picker<HttpBackend>> Lb<P> {
             lb: Arc::new(RwLock::new(balancer)),
         }
     }
+
+    /// Returns the balancer, e.g., to add or remove backends while the server runs.
+    pub fn balancer(&self) -> Arc<RwLock<Http<HttpBackend, P>>> {
+        Arc::clone(&self.lb)
+    }
+}
+
+// Clones share the balancer.
+impl<P: Picker<HttpBackend>> Clone for Lb<P> {
+    fn clone(&self) -> Self {
+        Self {
+            lb: Arc::clone(&self.lb),
+        }
+    }
 }
 
 #[async_trait]
@@ -6,7 +6,7 @@ use crate::{
 use super::service::{service, ServiceHandler};
 
 async fn log_handler(r: Request, _: ()) -> Result<handler::Action, handler::Error> {
-    info!("Request {}", r.url.as_ref().unwrap());
+    info!(request_id = r.request_id(); "Request {}", r.url.as_ref().unwrap());
     Ok(handler::Action::Next)
 }
 
@@ -1,18 +1,48 @@
+pub mod access_log;
+pub mod auth;
+#[cfg(feature = "gzip")]
+pub mod compress;
+pub(crate) mod conditional;
+pub mod cors;
+pub mod extract;
 pub mod file;
+pub(crate) mod fs_path;
+pub mod header_rewrite;
+pub mod ip_filter;
 pub mod lb;
 pub mod log;
+pub mod proxy;
+pub mod ratelimit;
 pub mod redirect;
+pub mod request_id;
 pub mod rewriter;
+pub mod security_headers;
 pub mod service;
+pub mod sse;
 pub mod status;
+pub mod timeout;
 pub mod web;
+pub mod websocket;
 
+pub use crate::handlers::access_log::AccessLog;
+pub use crate::handlers::auth::BearerAuth;
+#[cfg(feature = "gzip")]
+pub use crate::handlers::compress::Compress;
+pub use crate::handlers::cors::Cors;
 pub use crate::handlers::file::File;
+pub use crate::handlers::header_rewrite::HeaderRewrite;
+pub use crate::handlers::ip_filter::IpFilter;
 pub use crate::handlers::lb::Lb;
 pub use crate::handlers::log::log;
+pub use crate::handlers::proxy::Proxy;
+pub use crate::handlers::ratelimit::RateLimit;
 pub use crate::handlers::redirect::Redirect;
+pub use crate::handlers::request_id::RequestId;
+pub use crate::handlers::security_headers::SecurityHeaders;
 pub use crate::handlers::status::NotFoundHandler;
 pub use crate::handlers::status::Status;
+pub use crate::handlers::timeout::Timeout;
 
+pub use crate::handlers::extract::extract;
 pub use crate::handlers::service::handler;
 pub use crate::handlers::service::service;
@@ -0,0 +1,266 @@
+/// This file implements a forward proxy handler. Requests are sent to the upstream named by
+/// the request's absolute-form target (e.g., `GET http://example.com/ HTTP/1.1`), or by its
+/// `Host` header, and the upstream's response is streamed back to the client. `CONNECT`
+/// requests (e.g., for HTTPS) open a raw TCP tunnel to the target instead.
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::{
+    io::{self, AsyncWriteExt},
+    net::TcpStream,
+};
+use url::Url;
+
+use crate::{
+    client::{Client, ClientError, ClientPool, ConnectedClient},
+    conntrack::random_id,
+    handler::{self, AsyncWriteStream, Handler},
+    headers::Headers,
+    request::{Method, Request, TargetForm},
+    response::Response,
+    status,
+};
+
+/// Headers that only apply to a single connection, and aren't forwarded. Headers named in
+/// the `Connection` header are removed too.
+pub const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authorization",
+    "te",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Remove hop-by-hop headers from `headers`.
+pub fn strip_hop_by_hop(headers: &mut Headers) {
+    let listed: Vec<String> = headers
+        .get("connection")
+        .into_iter()
+        .flatten()
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    for name in listed.iter().map(|name| name.as_str()) {
+        headers.remove(name);
+    }
+
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+/// A forward proxy. Upstream connections are kept in a pool, and reused across requests.
+/// Tunnels for `CONNECT` requests last until either side closes the connection.
+///
+/// # Example
+///
+/// ```no_run
+/// use hype::{handlers::proxy::Proxy, server::Server};
+///
+/// let mut server = Server::new("localhost", 3128);
+/// server.route_default(Proxy::new());
+/// ```
+pub struct Proxy {
+    pool: ClientPool,
+
+    /// Added to the `Via` header of forwarded requests, so loops can be detected.
+    via: String,
+}
+
+impl Proxy {
+    pub fn new() -> Self {
+        Self {
+            pool: ClientPool::default(),
+            via: format!("1.1 hype-{}", random_id(8)),
+        }
+    }
+
+    /// Use `pool` for idle upstream connections.
+    pub fn with_pool(mut self, pool: ClientPool) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Returns true if `r` has already passed through this proxy.
+    fn is_loop(&self, r: &Request) -> bool {
+        r.headers.get("via").into_iter().flatten().any(|value| {
+            value
+                .split(',')
+                .any(|via| via.trim().eq_ignore_ascii_case(&self.via))
+        })
+    }
+
+    async fn send(&self, url: &Url, req: &Request) -> Result<Response, ClientError> {
+        let host = url
+            .host_str()
+            .ok_or(ClientError::LookupError(url.to_string()))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or(ClientError::LookupError(url.to_string()))?;
+        let address = format!("{}:{}", host, port);
+
+        let connect = || async {
+            let mut client = Client::new(&address);
+            if url.scheme() == "https" {
+                client.enable_tls(host);
+            }
+            client.connect().await
+        };
+
+        let (mut client, pooled): (ConnectedClient, bool) = match self.pool.take(&address).await {
+            Some(client) => (client, true),
+            None => (connect().await?, false),
+        };
+
+        let mut response = client.send_request(req).await;
+        if let Err(e) = &response {
+            if pooled && e.can_retry(req) {
+                // The pooled connection went stale, retry with a fresh one.
+                client = connect().await?;
+                response = client.send_request(req).await;
+            }
+        }
+
+        if response.is_ok() {
+            self.pool.put_when_idle(client);
+        }
+        response
+    }
+
+    /// Tunnel the client's connection to `url`, the target of CONNECT request `r`.
+    async fn tunnel(
+        &self,
+        r: &Request,
+        url: &Url,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let conn = r.conn().ok_or(handler::Error::Failed(
+            "CONNECT without a connection".into(),
+        ))?;
+        let address = format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port_or_known_default().unwrap_or_default()
+        );
+
+        let mut upstream = TcpStream::connect(&address).await.map_err(|e| {
+            warn!("Proxy: could not connect to {}: {}", address, e);
+            handler::Error::Status(status::BAD_GATEWAY.into())
+        })?;
+
+        let write_error = |e: io::Error| handler::Error::Failed(format!("proxy: {}", e));
+        w.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await
+            .map_err(write_error)?;
+        w.flush().await.map_err(write_error)?;
+
+        // The server is done reading the request, so the read half of the connection is free.
+        let reader = conn.reader();
+        let mut reader = reader.write().await;
+        let mut client = io::join(&mut *reader, &mut *w);
+
+        match io::copy_bidirectional(&mut client, &mut upstream).await {
+            Ok((sent, received)) => debug!(
+                "Proxy: tunnel to {} closed, sent {} bytes, received {} bytes",
+                address, sent, received
+            ),
+            Err(e) => debug!("Proxy: tunnel to {} failed: {}", address, e),
+        }
+
+        _ = client.shutdown().await;
+        _ = upstream.shutdown().await;
+        Ok(handler::Action::Done)
+    }
+}
+
+impl Default for Proxy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the upstream URL for `r`.
+fn upstream_url(r: &Request) -> Option<Url> {
+    if matches!(
+        r.target_form(),
+        TargetForm::Absolute | TargetForm::Authority
+    ) {
+        return r.url.clone();
+    }
+
+    let host = r.headers.get_first("host")?;
+    let mut url = Url::parse(&format!("http://{}", host)).ok()?;
+    url.set_path(&r.abs_path());
+    url.set_query(r.url.as_ref().and_then(|url| url.query()));
+    Some(url)
+}
+
+#[async_trait]
+impl Handler for Proxy {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let url = upstream_url(r).ok_or(handler::Error::Status(status::BAD_REQUEST.into()))?;
+        if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+            return Err(handler::Error::Status(status::BAD_REQUEST.into()));
+        }
+
+        if r.method == Method::CONNECT {
+            return self.tunnel(r, &url, w).await;
+        }
+
+        if self.is_loop(r) {
+            warn!("Proxy: request loop detected for {}", url);
+            return Err(handler::Error::Status(status::LOOP_DETECTED.into()));
+        }
+
+        let mut req = r.clone();
+        strip_hop_by_hop(&mut req.headers);
+        req.headers.set(
+            "host",
+            &url[url::Position::BeforeHost..url::Position::AfterPort],
+        );
+        req.headers.add("via", &self.via);
+        if req.body.chunked() {
+            req.headers.set("transfer-encoding", "chunked");
+        }
+        req.handler_path = None;
+        req.url = Some(url.clone());
+
+        let mut response = self.send(&url, &req).await.map_err(|e| {
+            warn!("Proxy: could not forward request to {}: {}", url, e);
+            handler::Error::Status(status::BAD_GATEWAY.into())
+        })?;
+
+        strip_hop_by_hop(&mut response.headers);
+        if response.body.chunked() {
+            response.headers.set("transfer-encoding", "chunked");
+        } else if response.headers.get("content-length").is_none()
+            && r.method != Method::HEAD
+            && !matches!(response.status.code, 100..=199 | 204 | 304)
+        {
+            // The upstream ends the body by closing the connection, and so must we.
+            r.add_response_header("Connection", "close");
+        }
+
+        let head = format!(
+            "{}\r\n{}\r\n\r\n",
+            response.serialize_status(),
+            response.headers.serialize()
+        );
+        let write_error = |e: io::Error| handler::Error::Failed(format!("proxy: {}", e));
+        w.write_all(head.as_bytes()).await.map_err(write_error)?;
+
+        let mut stream = response.body.raw_stream();
+        while let Some(content) = stream.next().await {
+            w.write_all(content.as_slice()).await.map_err(write_error)?;
+        }
+
+        Ok(handler::Action::Done)
+    }
+}
@@ -0,0 +1,134 @@
+/// This file implements a token-bucket rate limiter, keyed on the client IP address. Each
+/// client gets a bucket of `requests` tokens that refills over `window`, and every request
+/// takes a token. Requests are rejected with a 429 when the bucket is empty.
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    request::Request,
+    status,
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+#[derive(Debug)]
+struct Shard {
+    buckets: HashMap<IpAddr, Bucket>,
+    last_cleanup: Instant,
+}
+
+#[derive(Debug)]
+pub struct RateLimit {
+    requests: u32,
+    window: Duration,
+    shards: Vec<Mutex<Shard>>,
+}
+
+impl RateLimit {
+    /// Allow each client IP up to `requests` requests per `window`.
+    pub fn new(requests: u32, window: Duration) -> Self {
+        Self::with_shards(requests, window, 16)
+    }
+
+    /// Same as `new`, but spreads the buckets across `shards` separately locked maps,
+    /// to reduce contention.
+    pub fn with_shards(requests: u32, window: Duration, shards: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            requests: requests.max(1),
+            window,
+            shards: (0..shards.max(1))
+                .map(|_| {
+                    Mutex::new(Shard {
+                        buckets: HashMap::new(),
+                        last_cleanup: now,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn shard(&self, ip: &IpAddr) -> &Mutex<Shard> {
+        let mut hasher = DefaultHasher::new();
+        ip.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    /// Take a token from `ip`'s bucket. Returns Err with the time until the next token
+    /// is available if the bucket is empty.
+    fn take(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let capacity = self.requests as f64;
+        let rate = capacity / self.window.as_secs_f64();
+
+        let mut shard = self.shard(&ip).lock().unwrap();
+
+        // Drop buckets that have been idle long enough to refill, they're the same
+        // as new ones.
+        if now.duration_since(shard.last_cleanup) >= self.window {
+            let window = self.window;
+            shard
+                .buckets
+                .retain(|_, b| now.duration_since(b.last) < window);
+            shard.last_cleanup = now;
+        }
+
+        let bucket = shard.buckets.entry(ip).or_insert(Bucket {
+            tokens: capacity,
+            last: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// Returns the number of buckets being tracked.
+    pub fn num_buckets(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|s| s.lock().unwrap().buckets.len())
+            .sum()
+    }
+}
+
+#[async_trait]
+impl Handler for RateLimit {
+    async fn handle(
+        &self,
+        r: &Request,
+        _w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let Some(peer_addr) = r.peer_addr() else {
+            return Ok(handler::Action::Next);
+        };
+
+        match self.take(peer_addr.ip()) {
+            Ok(()) => Ok(handler::Action::Next),
+            Err(wait) => {
+                debug!("rate limiting {}", peer_addr.ip());
+                r.add_response_header("Retry-After", wait.as_secs().max(1).to_string());
+                Err(handler::Error::Status(status::TOO_MANY_REQUESTS.into()))
+            }
+        }
+    }
+}
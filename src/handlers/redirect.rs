@@ -5,7 +5,6 @@ use crate::{
     handler::{self, AsyncWriteStream, Handler},
     request::Request,
     response::Response,
-    status,
 };
 
 pub struct Redirect {
@@ -27,8 +26,7 @@ impl Handler for Redirect {
         _r: &Request,
         w: &mut dyn AsyncWriteStream,
     ) -> Result<handler::Action, handler::Error> {
-        let mut response = Response::new(status::MOVED_PERMANENTLY);
-        response.headers.set("Location", self.location.clone());
+        let mut response = Response::redirect(self.location.clone(), 301);
         let buf = response.serialize();
         w.write_all(buf.as_bytes()).await.unwrap();
         Ok(handler::Action::Done)
@@ -0,0 +1,61 @@
+/// This file implements a middleware handler that tags each request with a unique ID, so
+/// requests can be traced across the load balancer and its backends. The ID is taken from
+/// the request's `X-Request-ID` header if it has a usable one, and generated otherwise.
+use async_trait::async_trait;
+
+use crate::{
+    conntrack::random_id,
+    handler::{self, AsyncWriteStream, Handler},
+    request::Request,
+};
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-ID";
+
+/// Incoming IDs longer than this are replaced.
+pub const MAX_REQUEST_ID_LEN: usize = 200;
+
+/// Assigns an ID to every request, which is available with `Request::request_id`, echoed
+/// in the response's `X-Request-ID` header, and forwarded to backends by the `Lb` handler.
+///
+/// # Example
+///
+/// ```no_run
+/// use hype::{handlers::{request_id::RequestId, web::Web}, middleware::Stack, server::Server};
+///
+/// let server = Server::new("localhost", 8080);
+/// server.route("/", Stack::new().push(RequestId::new()).push(Web::new("./www".into())));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RequestId {}
+
+impl RequestId {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Returns true if `id` is safe to pass along.
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|c| c.is_ascii_graphic())
+}
+
+#[async_trait]
+impl Handler for RequestId {
+    async fn handle(
+        &self,
+        r: &Request,
+        _w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let id = match r.headers.get_first(REQUEST_ID_HEADER) {
+            Some(id) if valid_id(id) => id.clone(),
+            _ => random_id(16),
+        };
+
+        // Requests that pass through more than one RequestId keep their first ID.
+        if r.set_request_id(id) {
+            r.add_response_header(REQUEST_ID_HEADER, r.request_id().unwrap());
+        }
+
+        Ok(handler::Action::Next)
+    }
+}
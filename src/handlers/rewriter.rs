@@ -72,7 +72,7 @@ merchantID=2003&foo=bar"##;
         println!("{:?}", String::from_utf8_lossy(&stream));
 
         assert_eq!(
-            "HTTP/1.1 301 Moved Permanently\r\nlocation: /foo/bar/\r\n\r\n",
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: /foo/bar/\r\n\r\n",
             std::str::from_utf8(&stream).unwrap(),
         );
     }
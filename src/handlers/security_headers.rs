@@ -0,0 +1,141 @@
+/// This file implements a middleware handler that adds security-related headers to every
+/// response: `Strict-Transport-Security` (HSTS), `X-Content-Type-Options`,
+/// `X-Frame-Options`, and optionally `Content-Security-Policy`. Headers that the inner
+/// handler sets itself are left alone.
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    request::Request,
+};
+
+/// The default HSTS max-age, one year.
+pub const DEFAULT_HSTS_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Adds security headers to all responses. HSTS only has an effect on TLS connections, so
+/// this is meant for servers with TLS enabled.
+///
+/// # Example
+///
+/// ```no_run
+/// use hype::{handlers::{security_headers::SecurityHeaders, web::Web}, middleware::Stack, server::Server};
+///
+/// let server = Server::new("localhost", 8080);
+/// let headers = SecurityHeaders::new()
+///     .with_hsts_subdomains()
+///     .with_content_security_policy("default-src 'self'");
+/// server.route("/", Stack::new().push(headers).push(Web::new("./www".into())));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    hsts_max_age: Option<Duration>,
+    hsts_subdomains: bool,
+    hsts_preload: bool,
+    nosniff: bool,
+    frame_options: Option<String>,
+    content_security_policy: Option<String>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecurityHeaders {
+    /// Returns a handler that sends HSTS with a one year max-age, `nosniff`, and
+    /// `X-Frame-Options: DENY`, and no content security policy.
+    pub fn new() -> Self {
+        Self {
+            hsts_max_age: Some(DEFAULT_HSTS_MAX_AGE),
+            hsts_subdomains: false,
+            hsts_preload: false,
+            nosniff: true,
+            frame_options: Some("DENY".into()),
+            content_security_policy: None,
+        }
+    }
+
+    /// Set the HSTS max-age. A zero `max_age` tells browsers to forget the policy.
+    pub fn with_hsts_max_age(mut self, max_age: Duration) -> Self {
+        self.hsts_max_age = Some(max_age);
+        self
+    }
+
+    /// Apply the HSTS policy to subdomains too.
+    pub fn with_hsts_subdomains(mut self) -> Self {
+        self.hsts_subdomains = true;
+        self
+    }
+
+    /// Ask to be included in browsers' HSTS preload lists.
+    pub fn with_hsts_preload(mut self) -> Self {
+        self.hsts_preload = true;
+        self
+    }
+
+    pub fn without_hsts(mut self) -> Self {
+        self.hsts_max_age = None;
+        self
+    }
+
+    pub fn without_nosniff(mut self) -> Self {
+        self.nosniff = false;
+        self
+    }
+
+    /// Set `X-Frame-Options`, e.g., to "SAMEORIGIN".
+    pub fn with_frame_options(mut self, frame_options: impl Into<String>) -> Self {
+        self.frame_options = Some(frame_options.into());
+        self
+    }
+
+    pub fn without_frame_options(mut self) -> Self {
+        self.frame_options = None;
+        self
+    }
+
+    pub fn with_content_security_policy(mut self, policy: impl Into<String>) -> Self {
+        self.content_security_policy = Some(policy.into());
+        self
+    }
+
+    /// Returns the value of the `Strict-Transport-Security` header, if enabled.
+    fn hsts(&self) -> Option<String> {
+        let mut value = format!("max-age={}", self.hsts_max_age?.as_secs());
+        if self.hsts_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.hsts_preload {
+            value.push_str("; preload");
+        }
+        Some(value)
+    }
+}
+
+#[async_trait]
+impl Handler for SecurityHeaders {
+    async fn handle(
+        &self,
+        r: &Request,
+        _w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        // The server adds these to the response once the inner handler writes it.
+        if let Some(hsts) = self.hsts() {
+            r.add_response_header("Strict-Transport-Security", hsts);
+        }
+        if self.nosniff {
+            r.add_response_header("X-Content-Type-Options", "nosniff");
+        }
+        if let Some(frame_options) = &self.frame_options {
+            r.add_response_header("X-Frame-Options", frame_options);
+        }
+        if let Some(policy) = &self.content_security_policy {
+            r.add_response_header("Content-Security-Policy", policy);
+        }
+
+        Ok(handler::Action::Next)
+    }
+}
@@ -2,12 +2,13 @@
 /// way to construct handlers with just async functions.
 use async_trait::async_trait;
 
-use futures::{future::BoxFuture, Future};
+use futures::{future::BoxFuture, Future, StreamExt};
 use serde::Deserialize;
 
 use crate::{
     handler::{Action, AsyncWriteStream, Error, Handler},
     request::Request,
+    status,
 };
 
 /// A service handler is a handler that wraps an async function that takes
@@ -27,6 +28,9 @@ where
 
     /// Any arbitrary state
     state: S,
+
+    /// Reject bodies larger than this many bytes.
+    max_body: Option<usize>,
 }
 
 impl<R, S: Clone> ServiceHandler<R, S>
@@ -35,15 +39,66 @@ where
 {
     pub fn with_state(self, state: &S) -> Self {
         ServiceHandler {
-            f: self.f,
             state: state.clone(),
+            ..self
         }
     }
 }
 
+impl<R, S> ServiceHandler<R, S>
+where
+    R: Into<Action>,
+{
+    /// Reject requests with bodies larger than `max` bytes with a 413 Payload Too Large,
+    /// before the service function runs. Streamed bodies are rejected as soon as they cross
+    /// the limit, and the connection is closed so the rest of the body isn't read.
+    pub fn with_max_body(mut self, max: usize) -> Self {
+        self.max_body = Some(max);
+        self
+    }
+}
+
+/// Wait for the body of `r`, failing as soon as it's larger than `max` bytes.
+async fn check_body_size(r: &Request, max: usize) -> Result<(), Error> {
+    let too_large = || {
+        debug!("ServiceHandler: body larger than {} bytes", max);
+        // Stop reading the rest of the body.
+        r.add_response_header("Connection", "close");
+        Err(Error::Status(status::PAYLOAD_TOO_LARGE.into()))
+    };
+
+    let content_length = r
+        .headers
+        .get_first("content-length")
+        .and_then(|len| len.trim().parse::<usize>().ok());
+    if content_length.is_some_and(|len| len > max) {
+        return too_large();
+    }
+
+    // Reader bodies can only be read once, so leave them to the service.
+    if r.body.is_reader() {
+        return Ok(());
+    }
+
+    let mut len = 0;
+    let mut stream = r.body.stream();
+    while let Some(chunk) = stream.next().await {
+        len += chunk.len();
+        if len > max {
+            return too_large();
+        }
+    }
+
+    Ok(())
+}
+
 #[async_trait]
 impl<R: Into<Action> + Send + Sync, S: Send + Sync + Clone> Handler for ServiceHandler<R, S> {
     async fn handle(&self, r: &Request, _w: &mut dyn AsyncWriteStream) -> Result<Action, Error> {
+        if let Some(max) = self.max_body {
+            check_body_size(r, max).await?;
+        }
+
         let result = (self.f)(r.clone(), self.state.clone()).await?;
         Ok(result.into())
     }
@@ -51,16 +106,15 @@ impl<R: Into<Action> + Send + Sync, S: Send + Sync + Clone> Handler for ServiceH
 
 /// Create a new service handler from an async function. Use `with_state` to attach a state to the
 /// service handler.
-pub fn service<Func: Send + Sync, Fut, S: Default, R: Into<Action>>(
-    func: Func,
-) -> ServiceHandler<R, S>
+pub fn service<Func, Fut, S: Default, R: Into<Action>>(func: Func) -> ServiceHandler<R, S>
 where
-    Func: Send + 'static + Fn(Request, S) -> Fut,
+    Func: Send + Sync + 'static + Fn(Request, S) -> Fut,
     Fut: Send + 'static + Future<Output = Result<R, Error>>,
 {
     ServiceHandler {
         f: Box::new(move |a, b| Box::pin(func(a, b))),
         state: S::default(),
+        max_body: None,
     }
 }
 
@@ -82,9 +136,9 @@ impl<R: Into<Action> + Send + Sync> Handler for FnHandler<R> {
 
 /// Create a new service handler from an async function. Use `with_state` to attach a state to the
 /// service handler.
-pub fn handler<Func: Send + Sync, Fut, R: Into<Action>>(func: Func) -> FnHandler<R>
+pub fn handler<Func, Fut, R: Into<Action>>(func: Func) -> FnHandler<R>
 where
-    Func: Send + 'static + Fn(Request) -> Fut,
+    Func: Send + Sync + 'static + Fn(Request) -> Fut,
     Fut: Send + 'static + Future<Output = Result<R, Error>>,
 {
     FnHandler {
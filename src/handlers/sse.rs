@@ -0,0 +1,116 @@
+/// This file implements Server-Sent Events, i.e., `text/event-stream` responses that push
+/// events to the client as they happen. Events are sent as chunks of a chunked body.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{
+    body::Body,
+    handler::{self, AsyncWriteStream},
+    response::Response,
+    status,
+};
+
+/// The response side of an event stream.
+///
+/// # Example
+///
+/// ```no_run
+/// use hype::{handler::{self, AsyncWriteStream}, handlers::sse::EventStream, request::Request};
+///
+/// async fn handle(_r: &Request, w: &mut dyn AsyncWriteStream) -> Result<handler::Action, handler::Error> {
+///     let (stream, events) = EventStream::new();
+///     tokio::spawn(async move {
+///         events.send_event("greeting", "hello");
+///         // Dropping `events` ends the stream.
+///     });
+///     stream.write(w).await
+/// }
+/// ```
+pub struct EventStream {
+    response: Response,
+    closed: Arc<AtomicBool>,
+}
+
+/// Sends events on an `EventStream`. Dropping the sender ends the stream.
+pub struct EventSender {
+    body: Body,
+    closed: Arc<AtomicBool>,
+}
+
+impl EventStream {
+    pub fn new() -> (Self, EventSender) {
+        let mut response = Response::new(status::OK);
+        response.headers.set("Content-Type", "text/event-stream");
+        response.headers.set("Cache-Control", "no-cache");
+
+        // Ask proxies (e.g., nginx) not to buffer the stream.
+        response.headers.set("X-Accel-Buffering", "no");
+        response.set_chunked();
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let sender = EventSender {
+            body: response.body.clone(),
+            closed: Arc::clone(&closed),
+        };
+
+        (Self { response, closed }, sender)
+    }
+
+    /// Write the response to `w`, and stream events until the sender is dropped, or
+    /// the client goes away.
+    pub async fn write(
+        self,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let result = self.stream_to(w).await;
+        self.closed.store(true, Ordering::Relaxed);
+
+        result
+            .map(|_| handler::Action::Done)
+            .map_err(|e| handler::Error::Failed(format!("event stream: {}", e)))
+    }
+
+    async fn stream_to(&self, w: &mut dyn AsyncWriteStream) -> std::io::Result<()> {
+        // Each event is flushed right away, rather than waiting for more.
+        self.response.clone().write_to(w).await
+    }
+}
+
+impl EventSender {
+    /// Send an event named `event`. Multi-line `data` is split across data fields.
+    pub fn send_event(&self, event: &str, data: &str) {
+        let event = event.replace(['\r', '\n'], "");
+        self.send(format!("event: {}\n{}", event, data_fields(data)));
+    }
+
+    /// Send an unnamed event (i.e., a "message" event.)
+    pub fn send_data(&self, data: &str) {
+        self.send(data_fields(data));
+    }
+
+    /// Returns true if the stream is no longer being written, e.g., because the client
+    /// disconnected. Further events are discarded.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    fn send(&self, event: String) {
+        if !self.is_closed() {
+            self.body.push_chunk(format!("{}\n", event).into_bytes());
+        }
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        self.body.end_chunked();
+    }
+}
+
+fn data_fields(data: &str) -> String {
+    data.split('\n')
+        .map(|line| format!("data: {}\n", line.strip_suffix('\r').unwrap_or(line)))
+        .collect()
+}
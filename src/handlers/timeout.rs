@@ -0,0 +1,145 @@
+/// This file implements a handler that bounds how long another handler may take. Responses
+/// that don't complete in time get a `503 Service Unavailable` (or another status.)
+///
+/// By default, the response is buffered until the handler completes, so an overrun never
+/// leaves a partial response on the wire. This doesn't work for handlers that need the
+/// connection to themselves, or that stream their responses (e.g., WebSockets or server-sent
+/// events), so `with_streaming` writes straight through instead. With streaming, an overrun
+/// before anything is written still gets the error status, but once the handler starts
+/// writing, the response can't be replaced, so it's cut short, and the connection is closed.
+/// Clients can tell by the body being shorter than its length or missing its last chunk.
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    request::Request,
+    router::RouteHandler,
+    status,
+};
+
+pub struct Timeout {
+    handler: RouteHandler,
+    timeout: Duration,
+    status: status::Status,
+    streaming: bool,
+}
+
+impl Timeout {
+    /// Fail responses from `handler` that take longer than `timeout`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use hype::{handlers::{timeout::Timeout, web::Web}, server::Server};
+    ///
+    /// let server = Server::new("localhost", 8080);
+    /// server.route("/", Timeout::new(Web::new("./www".into()), Duration::from_secs(5)));
+    /// ```
+    pub fn new(handler: impl Into<RouteHandler>, timeout: Duration) -> Self {
+        Self {
+            handler: handler.into(),
+            timeout,
+            status: status::SERVICE_UNAVAILABLE.into(),
+            streaming: false,
+        }
+    }
+
+    /// Respond with `status` on overruns, e.g., `504 Gateway Timeout` for proxies.
+    pub fn with_status(mut self, status: impl Into<status::Status>) -> Self {
+        self.status = status.into();
+        self
+    }
+
+    /// Write the response as the handler produces it, instead of buffering it. See above
+    /// for what happens on overruns.
+    pub fn with_streaming(mut self) -> Self {
+        self.streaming = true;
+        self
+    }
+
+    fn overrun(&self, r: &Request) -> handler::Error {
+        debug!(
+            "Timeout: {} took longer than {:?}",
+            r.abs_path(),
+            self.timeout
+        );
+        handler::Error::Status(self.status.clone())
+    }
+}
+
+/// Passes writes through to `inner`, noting whether any were made.
+struct Tracked<'a> {
+    inner: &'a mut dyn AsyncWriteStream,
+    written: bool,
+}
+
+impl AsyncWrite for Tracked<'_> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut *self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            self.written |= n > 0;
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
+    }
+}
+
+impl AsyncWriteStream for Tracked<'_> {}
+
+#[async_trait]
+impl Handler for Timeout {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let handler = self.handler.handler();
+        let handler = handler.read().await;
+
+        if self.streaming {
+            let mut tracked = Tracked {
+                inner: w,
+                written: false,
+            };
+            return match tokio::time::timeout(self.timeout, handler.handle(r, &mut tracked)).await {
+                Ok(result) => result,
+                Err(_) if !tracked.written => Err(self.overrun(r)),
+                Err(_) => {
+                    // Too late for an error response, so cut this one short.
+                    r.add_response_header("Connection", "close");
+                    Ok(handler::Action::Done)
+                }
+            };
+        }
+
+        let mut buf: Vec<u8> = vec![];
+        let result = tokio::time::timeout(self.timeout, handler.handle(r, &mut buf))
+            .await
+            .map_err(|_| self.overrun(r))?;
+
+        w.write_all(&buf)
+            .await
+            .map_err(|e| handler::Error::Failed(format!("could not write response: {}", e)))?;
+        result
+    }
+}
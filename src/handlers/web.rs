@@ -1,29 +1,102 @@
 use std::{
     collections::HashMap,
-    ffi::OsStr,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use async_trait::async_trait;
-use tokio::{
-    fs,
-    io::{self, AsyncWriteExt},
-};
+use regex::Regex;
+use tokio::fs;
 
+use super::{conditional::Validators, file::File, fs_path};
 use crate::{
     config, content_types,
     handler::{self, AsyncWriteStream, Handler},
     request::Request,
-    response::Response,
     status,
 };
 
+/// Matches fingerprinted file names, i.e., with a hash of their contents before the
+/// extension, like `app.3f9a2c1d.js`. These can be cached forever.
+pub const FINGERPRINT_PATTERN: &str = r"\.[0-9a-fA-F]{8,}\.[^./]+$";
+
+/// Who may cache a response: any cache, or only the client's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
+/// A `Cache-Control` policy for file responses. The default policy sets no directives.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CachePolicy {
+    visibility: Option<Visibility>,
+    no_cache: bool,
+    max_age: Option<Duration>,
+    immutable: bool,
+}
+
+impl CachePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = Some(visibility);
+        self
+    }
+
+    /// Ask caches to revalidate (e.g., with If-None-Match) before every use.
+    pub fn with_no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// Let caches use the file for `max_age` (in whole seconds) without revalidating.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Tell clients that the file never changes, so they don't revalidate it on reload.
+    pub fn with_immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// Returns the value of the `Cache-Control` header, or None if there are no directives.
+    pub fn header_value(&self) -> Option<String> {
+        let mut directives = vec![];
+        match self.visibility {
+            Some(Visibility::Public) => directives.push("public".to_string()),
+            Some(Visibility::Private) => directives.push("private".to_string()),
+            None => {}
+        }
+        if self.no_cache {
+            directives.push("no-cache".to_string());
+        }
+        if let Some(max_age) = self.max_age {
+            directives.push(format!("max-age={}", max_age.as_secs()));
+        }
+        if self.immutable {
+            directives.push("immutable".to_string());
+        }
+
+        (!directives.is_empty()).then(|| directives.join(", "))
+    }
+}
+
 pub struct Web {
     base_fs_path: String,
     content_types: HashMap<&'static str, &'static str>,
     index_files: Vec<String>,
     hosts: Vec<String>,
     trailing_slashes: bool,
+    sniffing: bool,
+    spa_fallback: bool,
+    follow_symlinks: bool,
+    cache_policy: Option<CachePolicy>,
+    cache_policies: Vec<(Regex, CachePolicy)>,
 }
 
 impl Web {
@@ -34,6 +107,11 @@ impl Web {
             index_files: vec!["index.html".into(), "index.htm".into()],
             hosts: vec![],
             trailing_slashes: true,
+            sniffing: false,
+            spa_fallback: false,
+            follow_symlinks: false,
+            cache_policy: None,
+            cache_policies: vec![],
         }
     }
 
@@ -44,70 +122,148 @@ impl Web {
             index_files: vec![params.index.clone()],
             hosts: params.hosts.clone(),
             trailing_slashes: params.trailing_slashes,
+            sniffing: false,
+            spa_fallback: false,
+            follow_symlinks: false,
+            cache_policy: None,
+            cache_policies: vec![],
         }
     }
 
-    async fn write_response<'b>(
-        w: &mut dyn AsyncWriteStream,
-        status: status::Code<'b>,
-        content_type: String,
-        body: String,
-    ) -> io::Result<()> {
-        let mut response = Response::new(status);
-        response.headers.set("Content-Type", content_type);
-        response.set_body(body);
+    /// Detect the types of files with unknown extensions from their contents (see
+    /// `content_types::sniff`), rather than serving them as text. Files that can't be
+    /// detected are served as `application/octet-stream`.
+    pub fn enable_sniffing(&mut self, enable: bool) {
+        self.sniffing = enable;
+    }
+
+    /// Set the caching policy for all files. Policies added with `cache_control_for` take
+    /// precedence.
+    pub fn cache_control(&mut self, policy: CachePolicy) {
+        self.cache_policy = Some(policy);
+    }
 
-        w.write_all(response.serialize().as_bytes()).await
+    /// Set the caching policy for files whose paths match the regex `pattern`. If several
+    /// patterns match, the first one added wins.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use hype::handlers::web::{CachePolicy, Visibility, Web, FINGERPRINT_PATTERN};
+    ///
+    /// let mut web = Web::new("./www".into());
+    ///
+    /// // Fingerprinted assets (e.g., app.3f9a2c1d.js) never change, HTML changes often.
+    /// let year = Duration::from_secs(365 * 24 * 60 * 60);
+    /// web.cache_control_for(
+    ///     FINGERPRINT_PATTERN,
+    ///     CachePolicy::new().with_visibility(Visibility::Public).with_max_age(year).with_immutable(),
+    /// )
+    /// .unwrap();
+    /// web.cache_control(CachePolicy::new().with_max_age(Duration::from_secs(60)));
+    /// ```
+    pub fn cache_control_for(
+        &mut self,
+        pattern: impl AsRef<str>,
+        policy: CachePolicy,
+    ) -> Result<(), regex::Error> {
+        self.cache_policies
+            .push((Regex::new(pattern.as_ref())?, policy));
+        Ok(())
+    }
+
+    /// Returns the Cache-Control header value for the file at `path`, if any.
+    fn cache_control_value(&self, path: &str) -> Option<String> {
+        self.cache_policies
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(path))
+            .map(|(_, policy)| policy)
+            .or(self.cache_policy.as_ref())
+            .and_then(|policy| policy.header_value())
     }
 
     async fn write_file_contents(
+        &self,
         w: &mut dyn AsyncWriteStream,
+        r: &Request,
         path: impl AsRef<str>,
-        content_types: &HashMap<&str, &str>,
     ) -> Result<(), ()> {
-        let contents = fs::read_to_string(path.as_ref()).await.or(Err(()))?;
-
-        let ext = Path::new(path.as_ref())
-            .extension()
-            .unwrap_or(OsStr::new("txt"))
-            .to_str()
-            .unwrap();
+        let validators = fs::metadata(path.as_ref())
+            .await
+            .ok()
+            .and_then(|metadata| Validators::from_metadata(&metadata));
 
-        Self::write_response(
+        // Ranges (and If-Range) are handled the same as by the File handler.
+        File::write_file_contents(
             w,
-            status::OK,
-            content_types.get(ext).unwrap_or(&"txt").to_string(),
-            contents,
+            r,
+            path.as_ref().into(),
+            validators,
+            &self.content_types,
+            self.sniffing,
+            self.cache_control_value(path.as_ref()),
         )
         .await
-        .or(Err(()))
     }
 
-    async fn handle_path(
+    /// Serve single-page apps: paths that don't exist get the index file at the root of
+    /// the site, so the app can route them. Paths with extensions (e.g., `/app.js`) are
+    /// assumed to be assets, and still get a 404 if they're missing.
+    pub fn spa_fallback(&mut self, enable: bool) {
+        self.spa_fallback = enable;
+    }
+
+    /// Serve files through symlinks that lead outside of the base path. When disabled (the
+    /// default), such files are 404s.
+    pub fn follow_symlinks(&mut self, enable: bool) {
+        self.follow_symlinks = enable;
+    }
+
+    /// Returns true if the file at `path` (under the base path) may be served, as per the
+    /// symlink policy.
+    async fn can_serve(&self, path: &Path) -> Result<bool, handler::Error> {
+        if self.follow_symlinks {
+            return Ok(true);
+        }
+
+        fs_path::is_contained(&self.base_fs_path, path).await
+    }
+
+    /// Respond to requests for paths that don't exist, or directories without index files.
+    async fn not_found(
         &self,
         r: &Request,
         w: &mut dyn AsyncWriteStream,
     ) -> Result<handler::Action, handler::Error> {
-        let mut abs_fs_path = PathBuf::new();
-        abs_fs_path.push(self.base_fs_path.as_str());
+        if self.spa_fallback && Path::new(&r.path()).extension().is_none() {
+            for index in &self.index_files {
+                let path = PathBuf::from(&self.base_fs_path).join(index);
 
-        if !r.path().is_empty() {
-            abs_fs_path.push(&r.path()[1..]);
+                let is_file = fs::metadata(&path).await.is_ok_and(|m| m.is_file());
+                if is_file && self.can_serve(&path).await? {
+                    self.write_file_contents(w, r, path.to_string_lossy())
+                        .await
+                        .or(Err(handler::Error::Failed("could not open file".into())))?;
+                    return Ok(handler::Action::Done);
+                }
+            }
         }
 
+        Err(handler::Error::Status(status::NOT_FOUND.into()))
+    }
+
+    async fn handle_path(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let abs_fs_path =
+            fs_path::resolve(&self.base_fs_path, &r.path(), self.follow_symlinks).await?;
         let abs_fs_path = abs_fs_path.to_str().ok_or(handler::Error::Failed(
             "could not parse request path".into(),
         ))?;
 
-        info!("Serving FS path {} at location {}", abs_fs_path, r.path());
-        let metadata = fs::metadata(abs_fs_path)
-            .await
-            .or(Err(handler::Error::Failed(
-                "could not fetch file metadata".to_string(),
-            )))?;
-
-        let abs_fs_path = String::from(abs_fs_path);
-
         if let Some(host) = r.headers.get_first("host") {
             if !self.hosts.is_empty() && !self.hosts.contains(host) {
                 return Err(handler::Error::Failed(format!(
@@ -117,6 +273,13 @@ impl Web {
             }
         }
 
+        info!("Serving FS path {} at location {}", abs_fs_path, r.path());
+        let Ok(metadata) = fs::metadata(abs_fs_path).await else {
+            return self.not_found(r, w).await;
+        };
+
+        let abs_fs_path = String::from(abs_fs_path);
+
         if metadata.is_dir() {
             if self.trailing_slashes && !r.abs_path().ends_with('/') {
                 info!("Redirecting {} to {}", r.abs_path(), r.abs_path() + "/");
@@ -126,21 +289,18 @@ impl Web {
             for index in &self.index_files {
                 let path = PathBuf::from(&abs_fs_path).join(index);
 
-                if Path::new(&path).exists() {
-                    Self::write_file_contents(
-                        w,
-                        path.as_os_str().to_str().unwrap(),
-                        &self.content_types,
-                    )
-                    .await
-                    .or(Err(handler::Error::Failed("could not open file".into())))?;
+                let exists = fs::metadata(&path).await.is_ok();
+                if exists && self.can_serve(&path).await? {
+                    self.write_file_contents(w, r, path.as_os_str().to_str().unwrap())
+                        .await
+                        .or(Err(handler::Error::Failed("could not open file".into())))?;
                     return Ok(handler::Action::Done);
                 }
             }
 
-            return Err(handler::Error::Failed("no index file in path".into()));
+            return self.not_found(r, w).await;
         } else {
-            Self::write_file_contents(w, abs_fs_path, &self.content_types)
+            self.write_file_contents(w, r, abs_fs_path)
                 .await
                 .or(Err(handler::Error::Failed("could not open file".into())))?;
         }
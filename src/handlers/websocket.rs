@@ -0,0 +1,234 @@
+/// This file implements WebSocket upgrades for server handlers. `upgrade` completes the
+/// opening handshake, and returns a `WebSocket` that reads and writes messages over the
+/// request's connection.
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{OwnedRwLockWriteGuard, RwLock},
+};
+
+use crate::{
+    handler::{self, AsyncReadStream, AsyncWriteStream},
+    request::{Method, Request},
+    response::Response,
+    status,
+    websocket::{self, Frame, OpCode, WebSocketError},
+};
+
+/// A complete (i.e., reassembled) data message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// Returns true if the comma-separated `header` of `r` has the token `token`.
+fn has_token(r: &Request, header: &str, token: &str) -> bool {
+    r.headers
+        .get(header)
+        .into_iter()
+        .flatten()
+        .flat_map(|value| value.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
+/// Returns true if `r` asks to upgrade the connection to a WebSocket.
+pub fn is_upgrade(r: &Request) -> bool {
+    r.method == Method::GET
+        && has_token(r, "upgrade", "websocket")
+        && has_token(r, "connection", "upgrade")
+}
+
+/// Complete the WebSocket handshake for `r`, writing the `101 Switching Protocols` response
+/// to `w`. Fails with a 400 if `r` isn't a valid upgrade request, and with a 426 if it asks
+/// for an unsupported version.
+pub async fn upgrade<'a>(
+    r: &Request,
+    w: &'a mut dyn AsyncWriteStream,
+) -> Result<WebSocket<'a>, handler::Error> {
+    let key = r
+        .headers
+        .get_first("sec-websocket-key")
+        .filter(|key| is_upgrade(r) && websocket::valid_key(key))
+        .ok_or(handler::Error::Status(status::BAD_REQUEST.into()))?;
+
+    if r.headers
+        .get_first("sec-websocket-version")
+        .map(|v| v.trim())
+        != Some(websocket::VERSION)
+    {
+        r.add_response_header("Sec-WebSocket-Version", websocket::VERSION);
+        return Err(handler::Error::Status(status::UPGRADE_REQUIRED.into()));
+    }
+
+    let conn = r.conn().ok_or(handler::Error::Failed(
+        "websocket without a connection".into(),
+    ))?;
+
+    let mut response = Response::new(status::SWITCHING_PROTOCOLS);
+    response.headers.set("Upgrade", "websocket");
+    response.headers.set("Connection", "Upgrade");
+    response
+        .headers
+        .set("Sec-WebSocket-Accept", websocket::accept_key(key));
+
+    let head = format!(
+        "{}\r\n{}\r\n\r\n",
+        response.serialize_status(),
+        response.headers.serialize()
+    );
+    w.write_all(head.as_bytes())
+        .await
+        .map_err(|e| handler::Error::Failed(format!("could not write response: {}", e)))?;
+    w.flush()
+        .await
+        .map_err(|e| handler::Error::Failed(format!("could not write response: {}", e)))?;
+
+    // The server is done reading the request, so the read half of the connection is free.
+    Ok(WebSocket {
+        reader: RwLock::write_owned(conn.reader()).await,
+        writer: w,
+        max_message_size: websocket::MAX_PAYLOAD_LEN,
+        closed: false,
+    })
+}
+
+/// The server side of a WebSocket connection. Pings are answered automatically.
+///
+/// # Example
+///
+/// ```no_run
+/// use hype::{handler::{self, AsyncWriteStream}, handlers::websocket, request::Request};
+///
+/// async fn handle(r: &Request, w: &mut dyn AsyncWriteStream) -> Result<handler::Action, handler::Error> {
+///     let mut ws = websocket::upgrade(r, w).await?;
+///     while let Ok(Some(message)) = ws.recv().await {
+///         if ws.send(message).await.is_err() {
+///             break;
+///         }
+///     }
+///     Ok(handler::Action::Done)
+/// }
+/// ```
+pub struct WebSocket<'a> {
+    reader: OwnedRwLockWriteGuard<Box<dyn AsyncReadStream>>,
+    writer: &'a mut dyn AsyncWriteStream,
+    max_message_size: usize,
+    closed: bool,
+}
+
+impl<'a> WebSocket<'a> {
+    /// Reject messages larger than `max` bytes, closing the connection.
+    pub fn with_max_message_size(mut self, max: usize) -> Self {
+        self.max_message_size = max;
+        self
+    }
+
+    /// Receive the next message. Returns None once the client closes the connection.
+    pub async fn recv(&mut self) -> Result<Option<Message>, WebSocketError> {
+        let mut message: Option<(OpCode, Vec<u8>)> = None;
+
+        while !self.closed {
+            let frame = match self.read_frame().await {
+                Ok(frame) => frame,
+                Err(e) => {
+                    let code = match e {
+                        WebSocketError::Protocol(_) => websocket::CLOSE_PROTOCOL_ERROR,
+                        WebSocketError::TooLarge(_) => websocket::CLOSE_TOO_BIG,
+                        WebSocketError::Io(_) => return Err(e),
+                    };
+                    _ = self.close(code, "").await;
+                    return Err(e);
+                }
+            };
+
+            match frame.opcode {
+                OpCode::Ping => self.send_frame(Frame::pong(frame.payload)).await?,
+                OpCode::Pong => {}
+                OpCode::Close => {
+                    // Echo the close frame to complete the closing handshake.
+                    let code = frame.close_code().unwrap_or(websocket::CLOSE_NORMAL);
+                    _ = self.close(code, "").await;
+                }
+                OpCode::Text | OpCode::Binary if message.is_none() => {
+                    message = Some((frame.opcode, frame.payload));
+                }
+                OpCode::Continuation if message.is_some() => {
+                    let (_, data) = message.as_mut().unwrap();
+                    data.extend(frame.payload);
+                }
+                _ => {
+                    _ = self.close(websocket::CLOSE_PROTOCOL_ERROR, "").await;
+                    return Err(WebSocketError::Protocol("unexpected fragment".into()));
+                }
+            }
+
+            if let Some((_, data)) = &message {
+                if data.len() > self.max_message_size {
+                    let len = data.len();
+                    _ = self.close(websocket::CLOSE_TOO_BIG, "").await;
+                    return Err(WebSocketError::TooLarge(len));
+                }
+            }
+
+            if frame.fin && !frame.opcode.is_control() {
+                return match message.take() {
+                    Some((OpCode::Text, data)) => match String::from_utf8(data) {
+                        Ok(text) => Ok(Some(Message::Text(text))),
+                        Err(_) => {
+                            _ = self.close(websocket::CLOSE_INVALID_DATA, "").await;
+                            Err(WebSocketError::Protocol("invalid UTF-8 in text".into()))
+                        }
+                    },
+                    Some((_, data)) => Ok(Some(Message::Binary(data))),
+                    None => unreachable!("data frames always start a message"),
+                };
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn read_frame(&mut self) -> Result<Frame, WebSocketError> {
+        let frame = Frame::read_from(&mut **self.reader, self.max_message_size).await?;
+
+        if !frame.masked {
+            return Err(WebSocketError::Protocol("unmasked client frame".into()));
+        }
+
+        Ok(frame)
+    }
+
+    pub async fn send(&mut self, message: Message) -> Result<(), WebSocketError> {
+        match message {
+            Message::Text(text) => self.send_frame(Frame::text(text)).await,
+            Message::Binary(data) => self.send_frame(Frame::binary(data)).await,
+        }
+    }
+
+    pub async fn send_text(&mut self, text: impl Into<String>) -> Result<(), WebSocketError> {
+        self.send_frame(Frame::text(text)).await
+    }
+
+    pub async fn send_frame(&mut self, frame: Frame) -> Result<(), WebSocketError> {
+        if self.closed {
+            return Err(WebSocketError::Io("connection closed".into()));
+        }
+
+        frame.write_to(&mut *self.writer).await
+    }
+
+    /// Send a close frame with `code`, and stop reading messages.
+    pub async fn close(&mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        if self.closed {
+            return Ok(());
+        }
+
+        let result = self.send_frame(Frame::close(code, reason)).await;
+        self.closed = true;
+        result
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
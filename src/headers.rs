@@ -1,14 +1,26 @@
-use std::collections::HashMap;
+use std::str::FromStr;
 
+use indexmap::IndexMap;
+
+/// A header field, with its name as it was added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+/// Header fields, in the order they were added. Lookups are case-insensitive, and fields
+/// are serialized with their original casing.
 #[derive(Debug, Clone)]
 pub struct Headers {
-    pub fields: HashMap<String, Vec<String>>,
+    /// Fields keyed by their lowercased names.
+    pub fields: IndexMap<String, Field>,
 }
 
 impl Headers {
     pub fn new() -> Self {
         Headers {
-            fields: HashMap::new(),
+            fields: IndexMap::new(),
         }
     }
 
@@ -21,10 +33,21 @@ impl Headers {
         self.len() == 0
     }
 
+    /// Returns the values of `key`, creating the field if needed. New fields are named
+    /// `key`, existing ones keep their name.
+    fn values_mut(&mut self, key: String) -> &mut Vec<String> {
+        &mut self
+            .fields
+            .entry(key.to_lowercase())
+            .or_insert_with(|| Field {
+                name: key,
+                values: vec![],
+            })
+            .values
+    }
+
     pub fn add(&mut self, key: impl Into<String>, value: impl Into<String>) {
-        let key = key.into().to_lowercase();
-        let values = self.fields.entry(key).or_insert(Vec::new());
-        values.push(value.into().trim().into());
+        self.values_mut(key.into()).push(value.into().trim().into());
     }
 
     pub fn add_from(&mut self, str: &str) {
@@ -33,48 +56,117 @@ impl Headers {
     }
 
     pub fn get(&self, key: &str) -> Option<&Vec<String>> {
-        self.fields.get(&key.to_lowercase())
+        self.fields.get(&key.to_lowercase()).map(|f| &f.values)
     }
 
     pub fn get_first_or_set(&mut self, key: &str, default: impl Into<String>) -> &String {
-        let values = self.fields.entry(key.to_lowercase()).or_insert(Vec::new());
-        values.push(default.into().trim().into());
+        let values = self.values_mut(key.into());
+        if values.is_empty() {
+            values.push(default.into().trim().into());
+        }
         values.first().unwrap()
     }
 
     pub fn get_first(&self, key: &str) -> Option<&String> {
-        self.fields.get(&key.to_lowercase()).and_then(|v| v.first())
+        self.get(key).and_then(|v| v.first())
     }
 
     pub fn get_last(&self, key: &str) -> Option<&String> {
-        self.fields.get(&key.to_lowercase()).and_then(|v| v.last())
+        self.get(key).and_then(|v| v.last())
     }
 
     pub fn remove(&mut self, key: &str) {
-        self.fields.remove(&key.to_lowercase());
+        self.fields.shift_remove(&key.to_lowercase());
     }
 
+    /// Replace the values of `key`. The field keeps its position, but takes the casing
+    /// of `key`.
     pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
-        let key = key.into().to_lowercase();
-        let values = self.fields.entry(key).or_insert(Vec::new());
-        values.clear();
-        values.push(value.into().trim().into());
+        self.set_multiple(key, vec![value.into().trim().into()]);
     }
 
     pub fn set_multiple(&mut self, key: impl Into<String>, new_values: Vec<String>) {
-        let key = key.into().to_lowercase();
-        let values = self.fields.entry(key).or_insert(Vec::new());
-        values.clear();
-        values.extend(new_values);
+        let key = key.into();
+        let field = self
+            .fields
+            .entry(key.to_lowercase())
+            .or_insert_with(|| Field {
+                name: String::new(),
+                values: vec![],
+            });
+        field.name = key;
+        field.values = new_values;
     }
 
+    /// Iterate over the fields, in order, with their original names.
     pub fn iter(&self) -> impl Iterator<Item = (&String, &Vec<String>)> {
-        self.fields.iter()
+        self.fields.values().map(|f| (&f.name, &f.values))
+    }
+
+    /// Returns true if the comma-separated header `key` has `token` (case-insensitive), e.g.,
+    /// `close` in `Connection: keep-alive, close`.
+    pub fn has_token(&self, key: &str, token: &str) -> bool {
+        self.get(key)
+            .into_iter()
+            .flatten()
+            .flat_map(|value| value.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+
+    /// Returns the body length from the `Content-Length` header, or None if it's missing,
+    /// invalid, or repeated with different values.
+    pub fn content_length(&self) -> Option<usize> {
+        let values = self.get("content-length")?;
+        let first = values.first()?;
+        if values.iter().any(|v| v != first) {
+            return None;
+        }
+
+        first
+            .bytes()
+            .all(|c| c.is_ascii_digit())
+            .then(|| first.parse::<usize>().ok())
+            .flatten()
+    }
+
+    /// Returns the parsed `Content-Type` header, or None if it's missing or invalid.
+    pub fn content_type(&self) -> Option<MediaType> {
+        self.get_first("content-type")?.parse().ok()
+    }
+
+    /// Returns the encodings in the `Accept-Encoding` header with their q-values, most
+    /// preferred first. Encodings with equal q-values keep their order, and ones with
+    /// invalid q-values are skipped. Encodings with a q-value of 0 are not acceptable.
+    pub fn accept_encodings(&self) -> Vec<(String, f32)> {
+        let mut encodings: Vec<(String, f32)> = self
+            .get("accept-encoding")
+            .into_iter()
+            .flatten()
+            .flat_map(|v| v.split(','))
+            .filter_map(|e| {
+                let mut params = split_params(e).into_iter();
+                let (name, _) = params.next()?;
+
+                let q = match params.find(|(k, _)| k == "q") {
+                    Some((_, q)) => q.parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?,
+                    None => 1.0,
+                };
+                Some((name, q))
+            })
+            .collect();
+
+        encodings.sort_by(|a, b| b.1.total_cmp(&a.1));
+        encodings
+    }
+
+    /// Returns the host and port from the `Host` header. See `parse_host`.
+    pub fn host(&self) -> Option<(String, Option<u16>)> {
+        parse_host(self.get_first("host")?)
     }
 
     pub fn serialize(&self) -> String {
         let mut serialized = String::new();
-        for (key, values) in self.fields.iter() {
+        for (key, values) in self.iter() {
             for value in values {
                 serialized.push_str(&format!("{}: {}\r\n", key, value));
             }
@@ -88,6 +180,118 @@ impl Headers {
     }
 }
 
+/// A parsed media type, e.g., from a `Content-Type` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType {
+    /// The lowercased type and subtype, e.g., `text/html`.
+    pub mime: String,
+
+    /// Parameters, with lowercased names, e.g., `[("charset", "utf-8")]`.
+    pub params: Vec<(String, String)>,
+}
+
+impl MediaType {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+}
+
+impl FromStr for MediaType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut params = split_params(s).into_iter();
+        let mime = match params.next() {
+            Some((mime, value)) if value.is_empty() => mime,
+            _ => return Err(format!("bad media type: {}", s)),
+        };
+
+        match mime.split_once('/') {
+            Some((t, subtype))
+                if !t.is_empty() && !subtype.is_empty() && !subtype.contains('/') =>
+            {
+                Ok(Self {
+                    mime,
+                    params: params.collect(),
+                })
+            }
+            _ => Err(format!("bad media type: {}", s)),
+        }
+    }
+}
+
+/// Split a `Host` header value into the host and port, taking care not to split IPv6
+/// addresses, e.g., `[::1]:8080` returns `("[::1]", Some(8080))`. Returns None if the
+/// port is invalid.
+pub fn parse_host(host: &str) -> Option<(String, Option<u16>)> {
+    let host = host.trim();
+    let (name, port) = match host.rsplit_once(':') {
+        // Unbracketed IPv6 addresses have no port.
+        Some((name, _)) if name.contains(':') && !name.ends_with(']') => (host, None),
+        Some((name, port)) => (name, Some(port.parse::<u16>().ok()?)),
+        None => (host, None),
+    };
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name.to_lowercase(), port))
+}
+
+/// Split a header value into its parameters, e.g., `form-data; name="a"` returns
+/// `[("form-data", ""), ("name", "a")]`. Parameter names are lowercased, and quotes and
+/// escapes are removed from values.
+pub(crate) fn split_params(value: &str) -> Vec<(String, String)> {
+    let mut params = vec![];
+    let mut chars = value.chars().peekable();
+
+    loop {
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && *c != ';') {
+            name.push(c);
+        }
+
+        let mut param_value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => param_value.extend(chars.next()),
+                        c => param_value.push(c),
+                    }
+                }
+                // Skip anything between the closing quote and the next parameter.
+                while chars.next_if(|c| *c != ';').is_some() {}
+            } else {
+                while let Some(c) = chars.next_if(|c| *c != ';') {
+                    param_value.push(c);
+                }
+                param_value = param_value.trim().to_string();
+            }
+        }
+
+        let name = name.trim().to_lowercase();
+        if !name.is_empty() {
+            params.push((name, param_value));
+        }
+
+        if chars.next().is_none() {
+            return params;
+        }
+    }
+}
+
 impl Default for Headers {
     fn default() -> Self {
         Self::new()
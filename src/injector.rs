@@ -0,0 +1,321 @@
+/// This file implements a response writer that rewrites the response head as it's written
+/// to the socket. The server uses it to apply headers added with
+/// `Request::add_response_header` to responses written by any handler, and the
+/// `HeaderRewrite` handler uses it to apply its response rules.
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{handler::AsyncWriteStream, headers::Headers};
+
+/// Rewrites a complete response head (including the blank line that ends it.)
+type Rewrite<'a> = Box<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync + 'a>;
+
+/// Passes the head of the final (non-1xx) response through a rewrite function, and
+/// everything else (interim responses, and the body) through as is.
+pub(crate) struct HeadRewriter<'a> {
+    inner: &'a mut dyn AsyncWriteStream,
+    rewrite: Rewrite<'a>,
+
+    /// True until the (final, non-1xx) response head has been written.
+    rewriting: bool,
+
+    /// The status code of the final response, once its head has been written.
+    status: Option<u16>,
+
+    /// The response head, buffered until it's complete.
+    head: Vec<u8>,
+
+    /// Data accepted from the handler, but not yet written to `inner`.
+    pending: Vec<u8>,
+    pos: usize,
+
+    /// If set, compare the body with the final head's Content-Length, and fail (instead of
+    /// warning) on mismatches if `strict`.
+    check_length: bool,
+    strict: bool,
+    content_length: Option<usize>,
+    body_bytes: usize,
+
+    /// If set, drop the body of the final response, e.g., for HEAD requests.
+    omit_body: bool,
+}
+
+/// Returns a writer that adds `headers` (and then `defaults`) to the response head, skipping
+/// ones that the response already has, e.g., a handler's own `Date`.
+pub(crate) fn header_injector<'a>(
+    inner: &'a mut dyn AsyncWriteStream,
+    headers: Arc<RwLock<Headers>>,
+    defaults: Headers,
+) -> HeadRewriter<'a> {
+    HeadRewriter::new(inner, move |head| {
+        inject(head, &headers.read().unwrap(), &defaults)
+    })
+}
+
+/// Add `headers` (and then `defaults`) to `head`, skipping ones that it already has.
+fn inject(head: &[u8], headers: &Headers, defaults: &Headers) -> Vec<u8> {
+    let text = String::from_utf8_lossy(head);
+    let existing: Vec<String> = text
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, _)| k.trim().to_lowercase())
+        .collect();
+
+    let mut extra = String::new();
+    for (k, values) in headers.iter() {
+        if existing.contains(&k.to_lowercase()) {
+            continue;
+        }
+        for v in values {
+            extra.push_str(&format!("{}: {}\r\n", k, v));
+        }
+    }
+    for (k, values) in defaults.iter() {
+        if existing.contains(&k.to_lowercase()) || headers.get(k).is_some() {
+            continue;
+        }
+        for v in values {
+            extra.push_str(&format!("{}: {}\r\n", k, v));
+        }
+    }
+
+    // Insert before the blank line that ends the head
+    let mut out = head[..head.len() - 2].to_vec();
+    out.extend_from_slice(extra.as_bytes());
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+impl<'a> HeadRewriter<'a> {
+    pub fn new(
+        inner: &'a mut dyn AsyncWriteStream,
+        rewrite: impl Fn(&[u8]) -> Vec<u8> + Send + Sync + 'a,
+    ) -> Self {
+        Self {
+            inner,
+            rewrite: Box::new(rewrite),
+            rewriting: true,
+            status: None,
+            head: vec![],
+            pending: vec![],
+            pos: 0,
+            check_length: false,
+            strict: false,
+            content_length: None,
+            body_bytes: 0,
+            omit_body: false,
+        }
+    }
+
+    /// Check that the body of the response is as long as its Content-Length says. Bodies
+    /// that differ are logged, or if `strict`, fail the write (or `finish`) that gives them
+    /// away, so the connection is closed instead of the client getting out of sync. Don't
+    /// use this for responses to HEAD requests, which have no body.
+    pub fn check_content_length(&mut self, strict: bool) {
+        self.check_length = true;
+        self.strict = strict;
+    }
+
+    /// Drop the body of the response, and only send its head, e.g., in response to HEAD
+    /// requests. Handlers write the body as usual, so the head is what it would be for a GET
+    /// (with the same Content-Length.)
+    pub fn omit_body(&mut self) {
+        self.omit_body = true;
+    }
+
+    /// Returns true if the handler has written anything.
+    pub fn started(&self) -> bool {
+        !self.rewriting || !self.head.is_empty() || !self.pending.is_empty()
+    }
+
+    /// Returns the status code of the response written by the handler, if any.
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// Write out anything that's still buffered, e.g., an incomplete head.
+    pub async fn finish(&mut self) -> io::Result<()> {
+        if self.rewriting {
+            self.pending.append(&mut self.head);
+            self.rewriting = false;
+        }
+
+        if let Some(len) = self.content_length {
+            if self.body_bytes < len {
+                let e = format!(
+                    "response body is shorter ({} bytes) than its Content-Length ({})",
+                    self.body_bytes, len
+                );
+                if self.strict {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+                warn!("{}", e);
+            }
+        }
+
+        self.flush().await
+    }
+
+    /// Fail (or warn) if `n` more body bytes would be more than the Content-Length.
+    fn check_body_bytes(&mut self, n: usize) -> io::Result<()> {
+        match self.content_length {
+            Some(len) if self.body_bytes + n > len => {
+                let e = format!(
+                    "response body is longer ({} bytes) than its Content-Length ({})",
+                    self.body_bytes + n,
+                    len
+                );
+                if self.strict {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+
+                // Only warn once.
+                warn!("{}", e);
+                self.content_length = None;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the Content-Length of `head`, unless the response has no body, or is chunked.
+    fn declared_length(head: &[u8], status: Option<u16>) -> Option<usize> {
+        if matches!(status, Some(100..=199 | 204 | 304) | None) {
+            return None;
+        }
+
+        let text = String::from_utf8_lossy(head);
+        let mut length = None;
+        for (k, v) in text
+            .split("\r\n")
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+        {
+            match k.trim().to_lowercase().as_str() {
+                "transfer-encoding" => return None,
+                "content-length" => length = v.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        length
+    }
+
+    /// Move complete response heads from `head` into `pending`. Returns the number of body
+    /// bytes that came after the final head.
+    fn process_head(&mut self) -> usize {
+        let mut body = 0;
+        while self.rewriting {
+            let Some(end) = self.head.windows(4).position(|w| w == b"\r\n\r\n") else {
+                break;
+            };
+
+            let rest = self.head.split_off(end + 4);
+            let head = std::mem::replace(&mut self.head, rest);
+
+            // Leave informational (1xx) responses alone, and wait for the final one. A 101
+            // switches protocols, so nothing after it is HTTP.
+            let informational = head.starts_with(b"HTTP/1.1 1") || head.starts_with(b"HTTP/1.0 1");
+            if informational && !head[9..].starts_with(b"101") {
+                self.pending.extend(head);
+            } else {
+                self.status = head
+                    .get(9..12)
+                    .and_then(|code| std::str::from_utf8(code).ok())
+                    .and_then(|code| code.parse().ok());
+                if self.check_length {
+                    self.content_length = Self::declared_length(&head, self.status);
+                }
+                let head = (self.rewrite)(&head);
+                self.pending.extend(head);
+                body = self.head.len();
+                if self.omit_body {
+                    self.head.clear();
+                } else {
+                    self.pending.append(&mut self.head);
+                }
+                self.rewriting = false;
+            }
+        }
+        body
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pos < self.pending.len() {
+            let n = ready!(Pin::new(&mut *self.inner).poll_write(cx, &self.pending[self.pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pos += n;
+        }
+
+        self.pending.clear();
+        self.pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a> AsyncWrite for HeadRewriter<'a> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+
+        if !this.rewriting && this.omit_body {
+            return Poll::Ready(Ok(buf.len()));
+        }
+
+        if !this.rewriting {
+            this.check_body_bytes(buf.len())?;
+            let n = ready!(Pin::new(&mut *this.inner).poll_write(cx, buf))?;
+            this.body_bytes += n;
+            return Poll::Ready(Ok(n));
+        }
+
+        this.head.extend_from_slice(buf);
+        let body = this.process_head();
+        if let Err(e) = this.check_body_bytes(body) {
+            // Don't send any of the response.
+            this.pending.clear();
+            return Poll::Ready(Err(e));
+        }
+        this.body_bytes += body;
+
+        // Start sending the head right away, handlers don't always flush.
+        if let Poll::Ready(Err(e)) = this.poll_pending(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        Pin::new(&mut *this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        // Send whatever we have, even if it's not a complete head.
+        if this.rewriting {
+            this.pending.append(&mut this.head);
+            this.rewriting = false;
+        }
+
+        ready!(this.poll_pending(cx))?;
+        Pin::new(&mut *this.inner).poll_shutdown(cx)
+    }
+}
+
+impl<'a> AsyncWriteStream for HeadRewriter<'a> {}
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 
 use crate::{
-    client::{Client, ClientError, ConnectedClient},
+    client::{Client, ClientError, ClientPool, ConnectedClient},
     request::Request,
     response::Response,
 };
@@ -11,6 +11,17 @@ pub trait Backend: Send + Sync {
     fn enable_tls(&mut self, _server_name: impl Into<String>) -> &mut Self {
         self
     }
+
+    /// A stable identifier for this backend, used by pickers that need to tell backends
+    /// apart regardless of their position (e.g., `ConsistentHashPicker`.)
+    fn id(&self) -> Option<&str> {
+        None
+    }
+
+    /// Send `req` upstream, and return the response. Streaming is full-duplex: chunks of
+    /// `req.body` are forwarded as they arrive, and the response is returned as soon as the
+    /// upstream sends its headers, even if the request body isn't complete yet (e.g., for
+    /// uploads that are rejected early.) The response body streams as the upstream sends it.
     async fn send_request(&self, req: &Request) -> Result<Response, ClientError>;
 }
 
@@ -18,6 +29,7 @@ pub struct HttpBackend {
     address: String,
     enable_tls: bool,
     tls_server_name: String,
+    pool: ClientPool,
 }
 
 impl HttpBackend {
@@ -26,9 +38,16 @@ impl HttpBackend {
             address: address.into(),
             enable_tls: false,
             tls_server_name: String::from(""),
+            pool: ClientPool::default(),
         }
     }
 
+    /// Use `pool` for idle backend connections. Pools can be shared across backends.
+    pub fn with_pool(mut self, pool: ClientPool) -> Self {
+        self.pool = pool;
+        self
+    }
+
     async fn create_client(&self) -> Result<ConnectedClient, ClientError> {
         let mut client = Client::new(self.address.to_string());
         if self.enable_tls {
@@ -37,6 +56,17 @@ impl HttpBackend {
 
         client.connect().await
     }
+
+    /// Returns an idle client from the pool, or creates a new one. The boolean is
+    /// true if the client came from the pool.
+    async fn pooled_client(&self) -> Result<(ConnectedClient, bool), ClientError> {
+        if let Some(client) = self.pool.take(&self.address).await {
+            return Ok((client, true));
+        }
+
+        debug!("creating new client for {}", &self.address);
+        Ok((self.create_client().await?, false))
+    }
 }
 
 #[async_trait]
@@ -47,6 +77,10 @@ impl Backend for HttpBackend {
         self
     }
 
+    fn id(&self) -> Option<&str> {
+        Some(&self.address)
+    }
+
     async fn send_request(&self, req: &Request) -> Result<Response, ClientError> {
         if let Some(conn) = req.conn() {
             let c = conn.backend_client();
@@ -55,18 +89,43 @@ impl Backend for HttpBackend {
             if let Some(client) = &mut *client {
                 debug!("reusing client for {}", &self.address);
                 let r = client.send_request(req).await;
-                if r.is_ok() {
-                    return r;
+                match &r {
+                    Ok(_) => return r,
+                    Err(e) if !e.can_retry(req) => return r,
+                    Err(_) => {}
                 }
             }
 
+            let (new_client, pooled) = self.pooled_client().await?;
+            *client = Some(new_client);
+            let r = client.as_mut().unwrap().send_request(req).await;
+            match &r {
+                Err(e) if pooled && e.can_retry(req) => {}
+                _ => return r,
+            }
+
+            // The pooled connection went stale, retry with a fresh one.
             debug!("creating new client for {}", &self.address);
             *client = Some(self.create_client().await?);
             client.as_mut().unwrap().send_request(req).await
         } else {
-            // Request has no `conn` so no place to attach client
-            debug!("creating new client for {}", &self.address);
-            self.create_client().await?.send_request(req).await
+            // Request has no `conn` so no place to attach client. Return it to the pool
+            // once the response is read.
+            let (mut client, pooled) = self.pooled_client().await?;
+            let mut r = client.send_request(req).await;
+
+            if let Err(e) = &r {
+                if pooled && e.can_retry(req) {
+                    debug!("creating new client for {}", &self.address);
+                    client = self.create_client().await?;
+                    r = client.send_request(req).await;
+                }
+            }
+
+            if r.is_ok() {
+                self.pool.put_when_idle(client);
+            }
+            r
         }
     }
 }
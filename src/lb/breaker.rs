@@ -0,0 +1,173 @@
+/// This file implements circuit breakers for load balancer backends. After a number of
+/// consecutive failed requests within a window, a backend's breaker "opens" and the backend
+/// is skipped for a cooldown period. The breaker then "half-opens", and lets a single trial
+/// request through: if it succeeds the breaker closes, otherwise it opens again.
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Requests flow normally.
+    Closed,
+
+    /// The backend is skipped until the cooldown expires.
+    Open,
+
+    /// The cooldown has expired, and a single trial request is allowed through.
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed {
+        failures: usize,
+        first_failure: Option<Instant>,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A trial request is in flight. If it doesn't finish by `trial_until` (e.g., because
+    /// it was cancelled), another trial is allowed.
+    HalfOpen {
+        trial_until: Instant,
+    },
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Closed {
+            failures: 0,
+            first_failure: None,
+        }
+    }
+}
+
+/// The circuit breakers of each backend, indexed by the backend's position. It's safe
+/// to clone, clones share state.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: usize,
+    window: Duration,
+    cooldown: Duration,
+    states: Arc<RwLock<Vec<Mutex<State>>>>,
+}
+
+impl CircuitBreaker {
+    /// Open a backend's breaker after `failure_threshold` consecutive failures within
+    /// `window`, and keep it open for `cooldown`.
+    pub fn new(failure_threshold: usize, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            window,
+            cooldown,
+            states: Arc::new(RwLock::new(vec![])),
+        }
+    }
+
+    fn with_state<R>(&self, index: usize, f: impl FnOnce(&mut State) -> R) -> R {
+        if let Some(state) = self.states.read().unwrap().get(index) {
+            return f(&mut state.lock().unwrap());
+        }
+
+        let mut states = self.states.write().unwrap();
+        while states.len() <= index {
+            states.push(Mutex::new(State::default()));
+        }
+        let result = f(&mut states[index].lock().unwrap());
+        result
+    }
+
+    pub fn state(&self, index: usize) -> BreakerState {
+        self.with_state(index, |state| match state {
+            State::Closed { .. } => BreakerState::Closed,
+            State::Open { until } if Instant::now() < *until => BreakerState::Open,
+            State::Open { .. } | State::HalfOpen { .. } => BreakerState::HalfOpen,
+        })
+    }
+
+    /// Returns true if a request to the backend at `index` would be let through.
+    pub fn is_available(&self, index: usize) -> bool {
+        self.with_state(index, |state| match state {
+            State::Closed { .. } => true,
+            State::Open { until } => Instant::now() >= *until,
+            State::HalfOpen { trial_until } => Instant::now() >= *trial_until,
+        })
+    }
+
+    /// Claim the right to send a request to the backend at `index`. For half-open breakers,
+    /// this claims the single trial request. Returns false if the request shouldn't be sent.
+    pub fn try_acquire(&self, index: usize) -> bool {
+        let now = Instant::now();
+        self.with_state(index, |state| match *state {
+            State::Closed { .. } => true,
+            State::Open { until } | State::HalfOpen { trial_until: until } if now < until => false,
+            State::Open { .. } | State::HalfOpen { .. } => {
+                debug!("LB: circuit breaker for backend {} is half-open", index);
+                *state = State::HalfOpen {
+                    trial_until: now + self.cooldown,
+                };
+                true
+            }
+        })
+    }
+
+    /// Forget the backend at `index`, moving the ones after it down a position.
+    pub fn remove(&self, index: usize) {
+        let mut states = self.states.write().unwrap();
+        if index < states.len() {
+            states.remove(index);
+        }
+    }
+
+    /// Record the result of a request to the backend at `index`.
+    pub fn record(&self, index: usize, success: bool) {
+        let now = Instant::now();
+        self.with_state(index, |state| match (*state, success) {
+            (State::Closed { .. }, true) => *state = State::default(),
+            (State::HalfOpen { .. }, true) => {
+                info!("LB: circuit breaker for backend {} closed", index);
+                *state = State::default();
+            }
+            (
+                State::Closed {
+                    failures,
+                    first_failure,
+                },
+                false,
+            ) => {
+                // Only count failures that happen within the window of the first one.
+                let (failures, first_failure) = match first_failure {
+                    Some(first) if now.duration_since(first) <= self.window => {
+                        (failures + 1, first)
+                    }
+                    _ => (1, now),
+                };
+
+                *state = if failures >= self.failure_threshold {
+                    warn!(
+                        "LB: circuit breaker for backend {} opened after {} failures",
+                        index, failures
+                    );
+                    State::Open {
+                        until: now + self.cooldown,
+                    }
+                } else {
+                    State::Closed {
+                        failures,
+                        first_failure: Some(first_failure),
+                    }
+                };
+            }
+            (State::HalfOpen { .. }, false) => {
+                warn!("LB: circuit breaker for backend {} reopened", index);
+                *state = State::Open {
+                    until: now + self.cooldown,
+                };
+            }
+            // Requests that were already in flight when the breaker opened.
+            (State::Open { .. }, _) => {}
+        })
+    }
+}
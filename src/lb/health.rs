@@ -0,0 +1,186 @@
+/// This file implements active health checking for load balancer backends. A background
+/// task periodically sends a request to each backend, and marks backends as unhealthy after
+/// a number of consecutive failures (and healthy again after a number of consecutive successes.)
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock, Weak,
+    },
+    time::Duration,
+};
+
+use crate::request::{Method, Request};
+
+use super::backend::Backend;
+
+/// The health of each backend, indexed by the backend's position. Backends without
+/// a recorded status are considered healthy. It's safe to clone, clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct BackendHealth {
+    healthy: Arc<RwLock<Vec<AtomicBool>>>,
+
+    /// Consecutive health check results, also indexed by position, so they're shifted
+    /// along with the statuses when backends are removed.
+    streaks: Arc<RwLock<Vec<Streak>>>,
+}
+
+impl BackendHealth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_healthy(&self, index: usize) -> bool {
+        self.healthy
+            .read()
+            .unwrap()
+            .get(index)
+            .is_none_or(|h| h.load(Ordering::Relaxed))
+    }
+
+    pub fn set_healthy(&self, index: usize, healthy: bool) {
+        if let Some(h) = self.healthy.read().unwrap().get(index) {
+            h.store(healthy, Ordering::Relaxed);
+            return;
+        }
+
+        let mut statuses = self.healthy.write().unwrap();
+        while statuses.len() <= index {
+            statuses.push(AtomicBool::new(true));
+        }
+        statuses[index].store(healthy, Ordering::Relaxed);
+    }
+
+    /// Forget the backend at `index`, moving the ones after it down a position.
+    pub fn remove(&self, index: usize) {
+        let mut statuses = self.healthy.write().unwrap();
+        if index < statuses.len() {
+            statuses.remove(index);
+        }
+
+        let mut streaks = self.streaks.write().unwrap();
+        if index < streaks.len() {
+            streaks.remove(index);
+        }
+    }
+
+    /// Record the result of a health check of the backend at `index`, and mark it unhealthy
+    /// (or healthy again) if it crossed the thresholds of `check`.
+    fn record_check(&self, index: usize, ok: bool, check: &HealthCheck) {
+        let streak = {
+            let mut streaks = self.streaks.write().unwrap();
+            if streaks.len() <= index {
+                streaks.resize(index + 1, Streak::default());
+            }
+
+            let streak = &mut streaks[index];
+            if ok {
+                streak.successes += 1;
+                streak.failures = 0;
+            } else {
+                streak.failures += 1;
+                streak.successes = 0;
+            }
+            *streak
+        };
+
+        let healthy = self.is_healthy(index);
+        if healthy && streak.failures >= check.unhealthy_threshold {
+            warn!("LB: backend {} is unhealthy", index);
+            self.set_healthy(index, false);
+        } else if !healthy && streak.successes >= check.healthy_threshold {
+            info!("LB: backend {} is healthy again", index);
+            self.set_healthy(index, true);
+        }
+    }
+
+    /// Returns the number of healthy backends out of `num_backends`.
+    pub fn num_healthy(&self, num_backends: usize) -> usize {
+        (0..num_backends).filter(|i| self.is_healthy(*i)).count()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthCheck {
+    pub path: String,
+    pub interval: Duration,
+    pub unhealthy_threshold: usize,
+    pub healthy_threshold: usize,
+}
+
+impl HealthCheck {
+    pub fn new(
+        path: impl Into<String>,
+        interval: Duration,
+        unhealthy_threshold: usize,
+        healthy_threshold: usize,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            unhealthy_threshold: unhealthy_threshold.max(1),
+            healthy_threshold: healthy_threshold.max(1),
+        }
+    }
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self::new("/healthz", Duration::from_secs(10), 3, 2)
+    }
+}
+
+/// Consecutive check results for a backend.
+#[derive(Debug, Default, Clone, Copy)]
+struct Streak {
+    successes: usize,
+    failures: usize,
+}
+
+/// Spawn the health-check task. The task exits when `backends` is dropped.
+pub(crate) fn spawn<T: Backend + 'static>(
+    check: HealthCheck,
+    backends: Weak<tokio::sync::RwLock<Vec<T>>>,
+    health: BackendHealth,
+    headers: HashMap<String, String>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(check.interval);
+
+        loop {
+            interval.tick().await;
+
+            let Some(backends) = backends.upgrade() else {
+                debug!("backends dropped, stopping health checks");
+                return;
+            };
+            let backends = backends.read().await;
+
+            let mut req = Request::new(Method::GET, &check.path);
+            headers.iter().for_each(|(k, v)| req.headers.set(k, v));
+
+            let results = futures::future::join_all(backends.iter().map(|backend| {
+                let mut req = req.clone();
+                if req.headers.get_first("host").is_none() {
+                    if let Some(id) = backend.id() {
+                        req.headers.set("host", id);
+                    }
+                }
+
+                async move {
+                    match tokio::time::timeout(check.interval, backend.send_request(&req)).await {
+                        Ok(Ok(response)) => response.status.code < 400,
+                        _ => false,
+                    }
+                }
+            }))
+            .await;
+
+            // Backends can't be removed while the read lock is held, so the results still
+            // line up with the recorded streaks.
+            for (i, ok) in results.into_iter().enumerate() {
+                health.record_check(i, ok, &check);
+            }
+        }
+    });
+}
@@ -1,15 +1,28 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use tokio::sync::RwLock;
 
-use crate::{client::ClientError, request::Request, response::Response};
+use crate::{
+    client::ClientError, handlers::request_id::REQUEST_ID_HEADER, request::Request,
+    response::Response,
+};
 
-use super::{backend::Backend, picker::Picker};
+use super::{
+    backend::Backend,
+    breaker::CircuitBreaker,
+    health::{self, BackendHealth, HealthCheck},
+    picker::Picker,
+};
 
 pub struct Http<T: Backend, P: Picker<T>> {
     backends: Arc<RwLock<Vec<T>>>,
     picker: P,
     rewrite_headers: HashMap<String, String>,
+    hash_key_header: Option<String>,
+    health: BackendHealth,
+    breaker: Option<CircuitBreaker>,
+    retries: usize,
+    retry_non_idempotent: bool,
 }
 
 impl<T: Backend, P: Picker<T>> Http<T, P> {
@@ -18,27 +31,102 @@ impl<T: Backend, P: Picker<T>> Http<T, P> {
             backends: Arc::new(RwLock::new(backends)),
             picker,
             rewrite_headers: HashMap::new(),
+            hash_key_header: None,
+            health: BackendHealth::new(),
+            breaker: None,
+            retries: 0,
+            retry_non_idempotent: false,
         }
     }
 
+    /// Periodically send a `GET path` to every backend, and stop routing requests to backends
+    /// that fail `unhealthy_threshold` checks in a row, until they pass `healthy_threshold`
+    /// checks in a row. Responses with status codes under 400 pass. Checks include the headers
+    /// added with `rewrite_header` before this call. Must be called from within a tokio runtime.
+    pub fn with_health_check(
+        self,
+        path: impl Into<String>,
+        interval: Duration,
+        unhealthy_threshold: usize,
+        healthy_threshold: usize,
+    ) -> Self
+    where
+        T: 'static,
+    {
+        health::spawn(
+            HealthCheck::new(path, interval, unhealthy_threshold, healthy_threshold),
+            Arc::downgrade(&self.backends),
+            self.health.clone(),
+            self.rewrite_headers.clone(),
+        );
+        self
+    }
+
+    /// Stop sending requests to a backend for `cooldown` after `failure_threshold` requests
+    /// to it fail in a row within `window`. After the cooldown, a single trial request is
+    /// sent to the backend, and it's used again if the request succeeds. Only connection
+    /// and protocol errors count as failures, not error responses.
+    pub fn with_circuit_breaker(
+        mut self,
+        failure_threshold: usize,
+        window: Duration,
+        cooldown: Duration,
+    ) -> Self {
+        self.breaker = Some(CircuitBreaker::new(failure_threshold, window, cooldown));
+        self
+    }
+
+    /// Retry requests on up to `retries` other backends if they fail with connection
+    /// errors. Only idempotent requests are retried, unless `with_non_idempotent_retries`
+    /// is also used. Requests with bodies streamed from readers are never retried.
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Retry non-idempotent requests (e.g., POST and PATCH) too.
+    pub fn with_non_idempotent_retries(mut self) -> Self {
+        self.retry_non_idempotent = true;
+        self
+    }
+
     pub fn rewrite_header(&mut self, k: impl Into<String>, v: impl Into<String>) {
         self.rewrite_headers.insert(k.into(), v.into());
     }
 
-    pub async fn send_request(&self, req: &Request) -> Result<Response, ClientError> {
-        let backends = self.backends.read().await;
-        let index = self
-            .picker
-            .pick_backend(&backends)
-            .map_err(|e| ClientError::InternalError(format!("could not pick backend: {}", e)))?;
+    /// Pass the value of `header` to the picker as the request key, e.g., to route requests
+    /// with a `ConsistentHashPicker`. Requests without the header use `pick_backend`.
+    pub fn hash_key_header(&mut self, header: impl Into<String>) {
+        self.hash_key_header = Some(header.into());
+    }
+
+    /// Start routing requests to `backend` too. Pickers that keep per-backend state (e.g.,
+    /// `WeightedRRPicker`) aren't updated, and must already account for the new backend.
+    pub async fn add_backend(&self, backend: T) {
+        self.backends.write().await.push(backend);
+    }
+
+    /// Stop routing requests to the backend whose `id()` is `id`, and return it. Waits for
+    /// requests that are being sent to finish. Connections that already talk to the backend
+    /// keep using it until they close.
+    pub async fn remove_backend(&self, id: &str) -> Option<T> {
+        let mut backends = self.backends.write().await;
+        let index = backends.iter().position(|b| b.id() == Some(id))?;
 
-        if index > backends.len() {
-            return Err(ClientError::InternalError(format!(
-                "picker returned invalid index: {}, num backends: {}",
-                index,
-                backends.len()
-            )));
+        // Health and breaker states are indexed by position, so shift them along too.
+        self.health.remove(index);
+        if let Some(breaker) = &self.breaker {
+            breaker.remove(index);
         }
+        Some(backends.remove(index))
+    }
+
+    pub async fn send_request(&self, req: &Request) -> Result<Response, ClientError> {
+        let backends = self.backends.read().await;
+        let key = self
+            .hash_key_header
+            .as_ref()
+            .and_then(|header| req.headers.get_first(header));
 
         // Rewrite headers as needed
         let mut req = req.clone();
@@ -46,8 +134,142 @@ impl<T: Backend, P: Picker<T>> Http<T, P> {
             .iter()
             .for_each(|(k, v)| req.headers.set(k, v));
 
-        debug!("LB: sending request to backend {}: {:?}", index, req);
-        backends[index].send_request(&req).await
+        // Carry the request ID through, in case the client didn't send it.
+        if let Some(id) = req.request_id() {
+            let id = id.to_string();
+            req.headers.set(REQUEST_ID_HEADER, id);
+        }
+
+        // Let the backend know who the client is. The chain already names the client if
+        // it came from a trusted proxy, so add the hop the request came from.
+        if let Some(socket_addr) = req.socket_addr() {
+            let forwarded_for = match req.headers.get_first("x-forwarded-for") {
+                Some(existing) => format!("{}, {}", existing, socket_addr.ip()),
+                None => socket_addr.ip().to_string(),
+            };
+            req.headers.set("x-forwarded-for", forwarded_for);
+        }
+
+        let retries = if self.retryable(&req) {
+            self.retries
+        } else {
+            0
+        };
+        let mut tried: Vec<usize> = vec![];
+        let mut last_error = None;
+
+        loop {
+            let index = match self.pick_backend(&backends, key.map(|k| k.as_bytes()), &tried) {
+                Ok(index) => index,
+                // Out of backends to retry on, return the last failure.
+                Err(e) => return Err(last_error.unwrap_or(e)),
+            };
+
+            if index >= backends.len() {
+                return Err(ClientError::InternalError(format!(
+                    "picker returned invalid index: {}, num backends: {}",
+                    index,
+                    backends.len()
+                )));
+            }
+
+            debug!("LB: sending request to backend {}: {:?}", index, req);
+            let result = backends[index].send_request(&req).await;
+            if let Some(breaker) = &self.breaker {
+                breaker.record(index, result.is_ok());
+            }
+
+            tried.push(index);
+            match result {
+                Err(e @ (ClientError::ConnectionError | ClientError::ConnectionBroken))
+                    if tried.len() <= retries =>
+                {
+                    warn!("LB: request to backend {} failed, retrying", index);
+                    last_error = Some(e);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Returns true if `req` may be retried on another backend.
+    fn retryable(&self, req: &Request) -> bool {
+        // Bodies streamed from readers can't be replayed. Other bodies are kept in memory.
+        if req.body.is_reader() {
+            return false;
+        }
+
+        self.retry_non_idempotent || req.method.is_idempotent()
+    }
+
+    /// Pick a healthy backend that hasn't been `tried` yet, and whose circuit breaker lets
+    /// the request through.
+    fn pick_backend(
+        &self,
+        backends: &[T],
+        key: Option<&[u8]>,
+        tried: &[usize],
+    ) -> Result<usize, ClientError> {
+        let pick = |health: &BackendHealth| {
+            self.picker
+                .pick_healthy_backend(backends, key, health)
+                .map_err(|e| ClientError::InternalError(format!("could not pick backend: {}", e)))
+        };
+
+        if backends.is_empty() {
+            debug!("LB: no backends");
+            return Err(ClientError::ConnectionError);
+        }
+
+        if self.breaker.is_none() && tried.is_empty() {
+            return pick(&self.health);
+        }
+
+        let usable = |i: usize| {
+            !tried.contains(&i)
+                && self
+                    .breaker
+                    .as_ref()
+                    .is_none_or(|breaker| breaker.is_available(i))
+        };
+
+        // Backends that can't be used are treated as unhealthy by the picker. If none of the
+        // backends are healthy, pick from all the usable ones, like the picker would.
+        let available = |healthy: &dyn Fn(usize) -> bool| {
+            let available = BackendHealth::new();
+            for i in 0..backends.len() {
+                available.set_healthy(i, healthy(i) && usable(i));
+            }
+            (available.num_healthy(backends.len()) > 0).then_some(available)
+        };
+
+        for _ in 0..backends.len().max(1) {
+            let Some(available) =
+                available(&|i| self.health.is_healthy(i)).or_else(|| available(&|_| true))
+            else {
+                break;
+            };
+
+            let index = pick(&available)?;
+            if self
+                .breaker
+                .as_ref()
+                .is_none_or(|breaker| breaker.try_acquire(index))
+            {
+                return Ok(index);
+            }
+        }
+
+        debug!("LB: no backends available");
+        Err(ClientError::ConnectionError)
+    }
+
+    pub fn health(&self) -> BackendHealth {
+        self.health.clone()
+    }
+
+    pub fn circuit_breaker(&self) -> Option<CircuitBreaker> {
+        self.breaker.clone()
     }
 
     pub fn get_backends(&self) -> Arc<RwLock<Vec<T>>> {
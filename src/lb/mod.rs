@@ -1,8 +1,14 @@
 pub mod backend;
+pub mod breaker;
+pub mod health;
 pub mod http;
 pub mod picker;
+pub mod routes;
 
 pub use backend::Backend;
 pub use backend::HttpBackend;
+pub use breaker::{BreakerState, CircuitBreaker};
+pub use health::{BackendHealth, HealthCheck};
 pub use http::Http;
 pub use picker::Picker;
+pub use routes::Routes;
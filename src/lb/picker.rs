@@ -1,15 +1,18 @@
 use std::{
+    collections::hash_map::DefaultHasher,
     error, fmt,
+    hash::{Hash, Hasher},
     sync::{Arc, Mutex},
 };
 
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use super::backend::Backend;
+use super::{backend::Backend, health::BackendHealth};
 
 #[derive(Debug)]
 pub enum PickerError {
     InconsistentLength(usize, usize),
+    NoBackends,
 }
 
 impl fmt::Display for PickerError {
@@ -20,6 +23,7 @@ impl fmt::Display for PickerError {
                 "Picker: inconsistent number of backends {} vs {}",
                 want, got
             ),
+            Self::NoBackends => write!(f, "Picker: no backends"),
         }
     }
 }
@@ -28,6 +32,46 @@ impl error::Error for PickerError {}
 
 pub trait Picker<T: Backend>: Send + Sync {
     fn pick_backend(&self, backends: &[T]) -> Result<usize, PickerError>;
+
+    /// Pick a backend for a request identified by `key`. Pickers that don't care about
+    /// the request fall back to `pick_backend`.
+    fn pick_backend_for(&self, backends: &[T], _key: &[u8]) -> Result<usize, PickerError> {
+        self.pick_backend(backends)
+    }
+
+    /// Pick a healthy backend, using `key` if available. The default implementation keeps
+    /// picking until it lands on a healthy backend. If no backends are healthy, the first
+    /// pick is returned.
+    fn pick_healthy_backend(
+        &self,
+        backends: &[T],
+        key: Option<&[u8]>,
+        health: &BackendHealth,
+    ) -> Result<usize, PickerError> {
+        let pick = || match key {
+            Some(key) => self.pick_backend_for(backends, key),
+            None => self.pick_backend(backends),
+        };
+
+        let first = pick()?;
+        if health.is_healthy(first) || health.num_healthy(backends.len()) == 0 {
+            return Ok(first);
+        }
+
+        for _ in 1..backends.len() {
+            let index = pick()?;
+            if health.is_healthy(index) {
+                return Ok(index);
+            }
+        }
+
+        // The picker keeps landing on unhealthy backends (e.g., a random picker
+        // on a bad streak), so fall back to the next healthy one.
+        Ok((first..backends.len())
+            .chain(0..first)
+            .find(|i| health.is_healthy(*i))
+            .unwrap_or(first))
+    }
 }
 
 pub struct RRPicker {
@@ -144,3 +188,132 @@ impl<T: Backend> Picker<T> for WeightedRRPicker {
         Ok(0)
     }
 }
+
+/// ConsistentHashPicker maps request keys onto a hash ring of backends, so the same key
+/// always lands on the same backend, and adding or removing a backend only remaps the
+/// keys that it owned. Backends are placed on the ring by their `id()` (or position, if
+/// they don't have one.) Requests without a key are spread randomly.
+pub struct ConsistentHashPicker {
+    replicas: usize,
+    ring: Arc<Mutex<Ring>>,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+#[derive(Default)]
+struct Ring {
+    ids: Vec<String>,
+    points: Vec<(u64, usize)>,
+}
+
+fn hash(buf: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    buf.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl ConsistentHashPicker {
+    pub fn new() -> Self {
+        Self::with_replicas(100)
+    }
+
+    /// Place each backend on the ring `replicas` times. More replicas spread the
+    /// keys more evenly.
+    pub fn with_replicas(replicas: usize) -> Self {
+        Self {
+            replicas: replicas.max(1),
+            ring: Arc::new(Mutex::new(Ring::default())),
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
+        }
+    }
+
+    fn build_ring(&self, ids: Vec<String>) -> Ring {
+        let mut points = Vec::with_capacity(ids.len() * self.replicas);
+        for (index, id) in ids.iter().enumerate() {
+            for replica in 0..self.replicas {
+                points.push((hash(format!("{}-{}", id, replica).as_bytes()), index));
+            }
+        }
+
+        points.sort_unstable();
+        Ring { ids, points }
+    }
+}
+
+impl Default for ConsistentHashPicker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Backend> Picker<T> for ConsistentHashPicker {
+    fn pick_backend(&self, backends: &[T]) -> Result<usize, PickerError> {
+        Ok(self.rng.lock().unwrap().gen_range(0..backends.len()))
+    }
+
+    fn pick_backend_for(&self, backends: &[T], key: &[u8]) -> Result<usize, PickerError> {
+        self.pick_on_ring(backends, key, |_| true)
+    }
+
+    /// Walk the ring clockwise from the key, skipping unhealthy backends.
+    fn pick_healthy_backend(
+        &self,
+        backends: &[T],
+        key: Option<&[u8]>,
+        health: &BackendHealth,
+    ) -> Result<usize, PickerError> {
+        let Some(key) = key else {
+            return Ok(self.pick_random_healthy(backends.len(), health));
+        };
+
+        if health.num_healthy(backends.len()) == 0 {
+            return self.pick_backend_for(backends, key);
+        }
+
+        self.pick_on_ring(backends, key, |i| health.is_healthy(i))
+    }
+}
+
+impl ConsistentHashPicker {
+    fn pick_random_healthy(&self, num_backends: usize, health: &BackendHealth) -> usize {
+        let healthy: Vec<usize> = (0..num_backends)
+            .filter(|i| health.is_healthy(*i))
+            .collect();
+        let mut rng = self.rng.lock().unwrap();
+        if healthy.is_empty() {
+            rng.gen_range(0..num_backends)
+        } else {
+            healthy[rng.gen_range(0..healthy.len())]
+        }
+    }
+
+    fn pick_on_ring<T: Backend>(
+        &self,
+        backends: &[T],
+        key: &[u8],
+        accept: impl Fn(usize) -> bool,
+    ) -> Result<usize, PickerError> {
+        let ids: Vec<String> = backends
+            .iter()
+            .enumerate()
+            .map(|(i, b)| b.id().map_or_else(|| i.to_string(), |id| id.to_string()))
+            .collect();
+
+        let mut ring = self.ring.lock().unwrap();
+        if ring.ids != ids {
+            *ring = self.build_ring(ids);
+        }
+
+        if ring.points.is_empty() {
+            return Err(PickerError::NoBackends);
+        }
+
+        // Find the first acceptable point clockwise from the key's hash, wrapping around
+        let h = hash(key);
+        let start = ring.points.partition_point(|(point, _)| *point < h);
+        let len = ring.points.len();
+        (0..len)
+            .map(|i| ring.points[(start + i) % len].1)
+            .find(|index| accept(*index))
+            .ok_or(PickerError::NoBackends)
+    }
+}
@@ -0,0 +1,211 @@
+/// This file implements the routes of a load balancer built from an `lbconfig::Config`. The
+/// routes can be reloaded from a new config while they're being served, without dropping
+/// connections: backends that are added or removed are updated in place, and the rest of
+/// the route keeps its balancer.
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::{
+    handler::{self, AsyncWriteStream, Handler},
+    handlers::Lb,
+    lbconfig,
+    request::Request,
+    router::{Matcher, Router},
+};
+
+use super::{
+    picker::{RRPicker, WeightedRRPicker},
+    Backend, Http, HttpBackend, Picker,
+};
+
+fn address(backend: &lbconfig::Backend) -> String {
+    format!("{}:{}", backend.host, backend.port)
+}
+
+fn build_backend(backend: &lbconfig::Backend) -> HttpBackend {
+    let mut b = HttpBackend::new(address(backend));
+    if backend.enable_tls {
+        b.enable_tls(backend.host.clone());
+    }
+    b
+}
+
+fn build_lb<P: Picker<HttpBackend>>(route: &lbconfig::Route, picker: P) -> Lb<P> {
+    // Backends stay in config order, so they line up with the picker's weights.
+    let backends: Vec<HttpBackend> = route.backends.iter().map(build_backend).collect();
+
+    let mut balancer = Http::new(backends, picker);
+    if let Some(host_header) = &route.host_header {
+        balancer.rewrite_header("host", host_header);
+    }
+
+    Lb::new(balancer)
+}
+
+enum Balancer {
+    RoundRobin(Lb<RRPicker>),
+    Weighted(Lb<WeightedRRPicker>),
+}
+
+impl Balancer {
+    /// Returns the IDs of the backends, in the order the balancer has them.
+    async fn backend_ids(&self) -> Vec<String> {
+        async fn ids<P: Picker<HttpBackend>>(lb: &Lb<P>) -> Vec<String> {
+            let balancer = lb.balancer();
+            let backends = balancer.read().await.get_backends();
+            let backends = backends.read().await;
+            backends
+                .iter()
+                .filter_map(|b| b.id().map(String::from))
+                .collect()
+        }
+
+        match self {
+            Balancer::RoundRobin(lb) => ids(lb).await,
+            Balancer::Weighted(lb) => ids(lb).await,
+        }
+    }
+}
+
+/// A route that's being served, and the config it was last loaded from.
+struct LiveRoute {
+    route: lbconfig::Route,
+    lb: Balancer,
+}
+
+impl LiveRoute {
+    fn new(route: lbconfig::Route) -> Self {
+        let lb = match route.weights() {
+            Some(weights) => Balancer::Weighted(build_lb(&route, WeightedRRPicker::new(weights))),
+            None => Balancer::RoundRobin(build_lb(&route, RRPicker::new())),
+        };
+
+        Self { route, lb }
+    }
+
+    /// Update the route to `route`. Round-robin routes keep their balancer, and only the
+    /// backends that changed are added or removed. Weights are positional, so weighted
+    /// routes (and routes with other changes) get a new balancer.
+    async fn update(self, route: lbconfig::Route) -> Self {
+        let lb = match self.lb {
+            Balancer::RoundRobin(lb)
+                if route.weights().is_none() && route.host_header == self.route.host_header =>
+            {
+                lb
+            }
+            _ => return Self::new(route),
+        };
+
+        let same = |a: &lbconfig::Backend, b: &lbconfig::Backend| {
+            a.host == b.host && a.port == b.port && a.enable_tls == b.enable_tls
+        };
+
+        {
+            let balancer = lb.balancer();
+            let balancer = balancer.read().await;
+            for old in &self.route.backends {
+                if !route.backends.iter().any(|new| same(old, new)) {
+                    info!("Removing backend {} from {}", address(old), route.location);
+                    balancer.remove_backend(&address(old)).await;
+                }
+            }
+
+            for new in &route.backends {
+                if !self.route.backends.iter().any(|old| same(old, new)) {
+                    info!("Adding backend {} to {}", address(new), route.location);
+                    balancer.add_backend(build_backend(new)).await;
+                }
+            }
+        }
+
+        Self {
+            route,
+            lb: Balancer::RoundRobin(lb),
+        }
+    }
+}
+
+/// Routes requests to the balancers of the configured routes. It's safe to clone, clones
+/// share state.
+///
+/// # Example
+///
+/// ```no_run
+/// use hype::{lb::Routes, lbconfig, server::Server};
+///
+/// # async fn run(config: lbconfig::Config) {
+/// let routes = Routes::new();
+/// routes.apply(config.routes).await;
+///
+/// let mut server = Server::new("localhost", 8080);
+/// server.route_default(routes.clone());
+///
+/// // Later, e.g., on SIGHUP:
+/// // routes.apply(reloaded.routes).await;
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct Routes {
+    router: Arc<RwLock<Router>>,
+    live: Arc<Mutex<HashMap<String, LiveRoute>>>,
+}
+
+impl Routes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bring the routes in line with `routes`, and start routing requests with the result.
+    /// Routes that are gone stop serving, and new ones start.
+    pub async fn apply(&self, routes: Vec<lbconfig::Route>) {
+        let mut live = self.live.lock().await;
+        let mut next = HashMap::new();
+        for route in routes {
+            let location = route.location.clone();
+            let route = match live.remove(&location) {
+                Some(current) => current.update(route).await,
+                None => {
+                    info!("Adding route {}", location);
+                    LiveRoute::new(route)
+                }
+            };
+            next.insert(location, route);
+        }
+
+        for location in live.keys() {
+            info!("Removing route {}", location);
+        }
+
+        let router = Router::new();
+        for (location, route) in &next {
+            match &route.lb {
+                Balancer::RoundRobin(lb) => router.add_route(Matcher::new(location), lb.clone()),
+                Balancer::Weighted(lb) => router.add_route(Matcher::new(location), lb.clone()),
+            }
+        }
+
+        *self.router.write().await = router;
+        *live = next;
+    }
+
+    /// Returns the IDs (`host:port`) of the backends of the route at `location`, in the
+    /// order its balancer has them, or None if there's no such route.
+    pub async fn backends(&self, location: &str) -> Option<Vec<String>> {
+        let live = self.live.lock().await;
+        Some(live.get(location)?.lb.backend_ids().await)
+    }
+}
+
+#[async_trait]
+impl Handler for Routes {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let router = self.router.read().await.clone();
+        Handler::handle(&router, r, w).await
+    }
+}
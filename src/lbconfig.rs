@@ -3,7 +3,7 @@ use std::{error, fmt};
 use rand::{distributions::Alphanumeric, Rng};
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Debug,
@@ -110,11 +110,13 @@ pub struct Backend {
     #[serde(default)]
     pub enable_tls: bool,
 
+    /// The backend's share of requests, relative to the other backends of its route. Zero
+    /// (the default) for every backend means plain round-robin.
     #[serde(default)]
     pub weight: u32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Route {
     #[serde(default)]
     pub id: RouteId,
@@ -123,6 +125,40 @@ pub struct Route {
     pub backends: Vec<Backend>,
 }
 
+impl Route {
+    /// Returns the backend weights, in the same order as `backends`, or None if none of the
+    /// backends have a weight.
+    pub fn weights(&self) -> Option<Vec<usize>> {
+        if self.backends.iter().all(|backend| backend.weight == 0) {
+            return None;
+        }
+
+        Some(
+            self.backends
+                .iter()
+                .map(|backend| backend.weight as usize)
+                .collect(),
+        )
+    }
+
+    /// Weights apply to all of a route's backends or none of them, since a weighted backend
+    /// can't be lined up with one that has no weight.
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.weights().is_none() {
+            return Ok(());
+        }
+
+        if let Some(backend) = self.backends.iter().find(|backend| backend.weight == 0) {
+            return Err(ConfigError(format!(
+                "route {}: backend {}:{} needs a weight, since other backends have one",
+                self.location, backend.host, backend.port
+            )));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Deserialize, Default)]
 pub struct Config {
     #[serde(flatten)]
@@ -143,8 +179,12 @@ impl error::Error for ConfigError {}
 
 impl Config {
     pub fn from(config_str: impl AsRef<str>) -> Result<Self, ConfigError> {
-        let config =
+        let config: Config =
             serde_yaml::from_str(config_str.as_ref()).map_err(|e| ConfigError(e.to_string()))?;
+
+        for route in &config.routes {
+            route.validate()?;
+        }
         Ok(config)
     }
 }
@@ -10,17 +10,25 @@ pub mod config;
 pub mod conntrack;
 pub mod content_types;
 pub mod cookie;
+#[cfg(feature = "gzip")]
+pub mod encoding;
+pub mod form;
 pub mod handler;
 pub mod handlers;
 pub mod headers;
+mod injector;
 pub mod lb;
 pub mod lbconfig;
 pub mod logger;
 pub mod message;
+pub mod metrics;
 pub mod middleware;
+pub mod multipart;
 pub mod parser;
+pub mod proxy_protocol;
 pub mod request;
 pub mod response;
 pub mod router;
 pub mod server;
 pub mod status;
+pub mod websocket;
@@ -1,9 +1,79 @@
 // Set default log level to info. To change, set RUST_LOG as so:
 //
 //    $ RUST_LOG=debug cargo run
+//
+// Applications can pick their own default level with `init_with_level`, e.g., from
+// `config::LogLevel`. RUST_LOG still takes precedence when it's set.
+use std::io::{self, Write};
+
+use env_logger::fmt::Formatter;
+use log::{kv::Key, LevelFilter, Record};
+
+use crate::{config, lbconfig};
+
+/// The key-value pair that log lines can carry request IDs in, e.g.:
+///
+///    info!(request_id = r.request_id(); "Request {}", r.path());
+pub const REQUEST_ID_KEY: &str = "request_id";
+
+fn builder(level: LevelFilter) -> env_logger::Builder {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level.as_str()))
+}
 
 pub fn init() {
+    init_with_level(LevelFilter::Info);
+}
+
+/// Log messages at `level` and above.
+pub fn init_with_level(level: impl Into<LevelFilter>) {
     // We use try_init here so it can by run by tests.
-    _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .try_init();
+    _ = builder(level.into()).try_init();
+}
+
+/// Log messages as JSON lines, for log collectors. See `write_json` for the format.
+pub fn init_json() {
+    init_json_with_level(LevelFilter::Info);
+}
+
+pub fn init_json_with_level(level: impl Into<LevelFilter>) {
+    _ = builder(level.into()).format(write_json).try_init();
+}
+
+/// Write `record` as a single line of JSON with the fields `level`, `timestamp`, `target`,
+/// `message`, and `request_id` (if the record has one.)
+pub fn write_json(buf: &mut Formatter, record: &Record) -> io::Result<()> {
+    let mut line = serde_json::json!({
+        "level": record.level().as_str(),
+        "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+
+    if let Some(request_id) = record.key_values().get(Key::from_str(REQUEST_ID_KEY)) {
+        line[REQUEST_ID_KEY] = request_id.to_string().into();
+    }
+
+    writeln!(buf, "{}", line)
+}
+
+impl From<config::LogLevel> for LevelFilter {
+    fn from(level: config::LogLevel) -> Self {
+        match level {
+            config::LogLevel::Debug => LevelFilter::Debug,
+            config::LogLevel::Info => LevelFilter::Info,
+            config::LogLevel::Warn => LevelFilter::Warn,
+            config::LogLevel::Error => LevelFilter::Error,
+        }
+    }
+}
+
+impl From<lbconfig::LogLevel> for LevelFilter {
+    fn from(level: lbconfig::LogLevel) -> Self {
+        match level {
+            lbconfig::LogLevel::Debug => LevelFilter::Debug,
+            lbconfig::LogLevel::Info => LevelFilter::Info,
+            lbconfig::LogLevel::Warn => LevelFilter::Warn,
+            lbconfig::LogLevel::Error => LevelFilter::Error,
+        }
+    }
 }
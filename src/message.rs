@@ -1,5 +1,6 @@
 use crate::{body::Body, headers::Headers, request::Request, response::Response};
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum Message {
     None,
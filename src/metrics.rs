@@ -0,0 +1,166 @@
+/// This file implements server metrics, and a handler that exposes them in the Prometheus
+/// text format. See `Server::enable_metrics`.
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::{io::AsyncWriteExt, sync::RwLock};
+
+use crate::{
+    conntrack::ConnTracker,
+    handler::{self, AsyncWriteStream, Handler},
+    request::Request,
+    response::Response,
+    status,
+};
+
+/// The content type of the Prometheus text format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Upper bounds (in seconds) of the request duration histogram buckets.
+pub const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Request counters for a server. These are updated at request boundaries, and can be
+/// shared across connections.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: AtomicU64,
+
+    /// Responses by status class, from 1xx to 5xx.
+    responses: [AtomicU64; 5],
+
+    /// Requests per duration bucket (not cumulative), with a last bucket for the rest.
+    durations: [AtomicU64; DURATION_BUCKETS.len() + 1],
+    duration_sum_us: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a request whose headers were just received.
+    pub fn begin_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count the response to a request, which took `duration` to handle. The status is None
+    /// if no response head was written.
+    pub fn end_request(&self, status: Option<u16>, duration: Duration) {
+        let class = status.and_then(|code| (code as usize / 100).checked_sub(1));
+        if let Some(class) = class.and_then(|class| self.responses.get(class)) {
+            class.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let seconds = duration.as_secs_f64();
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(DURATION_BUCKETS.len());
+        self.durations[bucket].fetch_add(1, Ordering::Relaxed);
+        self.duration_sum_us
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Render the metrics in the Prometheus text format, along with the number of open
+    /// connections.
+    pub fn render(&self, active_conns: usize) -> String {
+        let mut out = String::new();
+
+        _ = writeln!(out, "# HELP hype_requests_total Requests received.");
+        _ = writeln!(out, "# TYPE hype_requests_total counter");
+        _ = writeln!(out, "hype_requests_total {}", self.requests());
+
+        _ = writeln!(
+            out,
+            "# HELP hype_responses_total Responses sent, by status class."
+        );
+        _ = writeln!(out, "# TYPE hype_responses_total counter");
+        for (i, count) in self.responses.iter().enumerate() {
+            _ = writeln!(
+                out,
+                "hype_responses_total{{class=\"{}xx\"}} {}",
+                i + 1,
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        _ = writeln!(out, "# HELP hype_active_connections Open connections.");
+        _ = writeln!(out, "# TYPE hype_active_connections gauge");
+        _ = writeln!(out, "hype_active_connections {}", active_conns);
+
+        _ = writeln!(
+            out,
+            "# HELP hype_request_duration_seconds Time taken to handle requests."
+        );
+        _ = writeln!(out, "# TYPE hype_request_duration_seconds histogram");
+        let mut total = 0;
+        for (i, count) in self.durations.iter().enumerate() {
+            total += count.load(Ordering::Relaxed);
+            let le = match DURATION_BUCKETS.get(i) {
+                Some(le) => le.to_string(),
+                None => "+Inf".into(),
+            };
+            _ = writeln!(
+                out,
+                "hype_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                le, total
+            );
+        }
+        _ = writeln!(
+            out,
+            "hype_request_duration_seconds_sum {}",
+            self.duration_sum_us.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        _ = writeln!(out, "hype_request_duration_seconds_count {}", total);
+
+        out
+    }
+}
+
+/// Serves `metrics` in the Prometheus text format.
+pub struct MetricsHandler {
+    metrics: Arc<Metrics>,
+    conn_tracker: Arc<RwLock<ConnTracker>>,
+}
+
+impl MetricsHandler {
+    pub fn new(metrics: Arc<Metrics>, conn_tracker: Arc<RwLock<ConnTracker>>) -> Self {
+        Self {
+            metrics,
+            conn_tracker,
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for MetricsHandler {
+    async fn handle(
+        &self,
+        _r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let active_conns = self.conn_tracker.read().await.active_conns();
+
+        let mut response = Response::new(status::OK);
+        response.headers.set("Content-Type", CONTENT_TYPE);
+        response.set_body(self.metrics.render(active_conns));
+
+        w.write_all(&response.serialize_bytes())
+            .await
+            .map_err(|e| handler::Error::Failed(format!("could not write response: {}", e)))?;
+        Ok(handler::Action::Done)
+    }
+}
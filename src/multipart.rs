@@ -0,0 +1,147 @@
+/// This file implements parsing of multipart/form-data bodies (RFC 7578), as sent by
+/// HTML forms with file uploads.
+use std::{error, fmt};
+
+use crate::headers::{split_params, Headers};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultipartError {
+    /// The content type isn't multipart/form-data.
+    NotMultipart,
+
+    /// The content type has no boundary parameter.
+    MissingBoundary,
+
+    /// The body doesn't match the boundary, or has bad part headers.
+    Malformed(String),
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultipartError::NotMultipart => write!(f, "not multipart/form-data"),
+            MultipartError::MissingBoundary => write!(f, "missing multipart boundary"),
+            MultipartError::Malformed(msg) => write!(f, "malformed multipart body: {}", msg),
+        }
+    }
+}
+
+impl error::Error for MultipartError {}
+
+/// A single part (i.e., form field) of a multipart body.
+#[derive(Debug, Clone)]
+pub struct Part {
+    pub headers: Headers,
+
+    /// The field name, and the file name for file fields, from Content-Disposition.
+    pub name: Option<String>,
+    pub filename: Option<String>,
+
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Part {
+    /// Returns the data as a string, replacing invalid UTF-8 sequences.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.data).into()
+    }
+}
+
+/// Returns the boundary from a multipart/form-data content type.
+pub fn boundary(content_type: &str) -> Result<String, MultipartError> {
+    let params = split_params(content_type);
+
+    match params.first() {
+        Some((mime, _)) if mime == "multipart/form-data" => {}
+        _ => return Err(MultipartError::NotMultipart),
+    }
+
+    params
+        .into_iter()
+        .skip(1)
+        .find(|(k, _)| k == "boundary")
+        .map(|(_, v)| v)
+        .filter(|v| !v.is_empty())
+        .ok_or(MultipartError::MissingBoundary)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn parse_part(raw: &[u8]) -> Result<Part, MultipartError> {
+    let (head, data) = if let Some(data) = raw.strip_prefix(b"\r\n") {
+        // No headers
+        (&raw[..0], data)
+    } else {
+        let end = find(raw, b"\r\n\r\n")
+            .ok_or_else(|| MultipartError::Malformed("unterminated part headers".into()))?;
+        (&raw[..end], &raw[end + 4..])
+    };
+
+    let mut headers = Headers::new();
+    let head = String::from_utf8_lossy(head);
+    for line in head.split("\r\n").filter(|l| !l.is_empty()) {
+        let (k, v) = line
+            .split_once(':')
+            .ok_or_else(|| MultipartError::Malformed(format!("bad part header: {}", line)))?;
+        headers.add(k.trim(), v.trim());
+    }
+
+    let mut name = None;
+    let mut filename = None;
+    if let Some(disposition) = headers.get_first("content-disposition") {
+        for (k, v) in split_params(disposition).into_iter().skip(1) {
+            match k.as_str() {
+                "name" => name = Some(v),
+                "filename" => filename = Some(v),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(Part {
+        name,
+        filename,
+        content_type: headers.get_first("content-type").cloned(),
+        headers,
+        data: data.to_vec(),
+    })
+}
+
+/// Parse a multipart/form-data `body` with the given `content_type` into its parts.
+pub fn parse(content_type: &str, body: &[u8]) -> Result<Vec<Part>, MultipartError> {
+    let boundary = boundary(content_type)?;
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+
+    // The first delimiter may be at the very start of the body, without the leading CRLF.
+    let mut rest = if body.starts_with(&delimiter[2..]) {
+        &body[delimiter.len() - 2..]
+    } else {
+        let start = find(body, &delimiter)
+            .ok_or_else(|| MultipartError::Malformed("boundary not found".into()))?;
+        &body[start + delimiter.len()..]
+    };
+
+    let mut parts = vec![];
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+
+        // Skip transport padding after the delimiter.
+        let padding = rest
+            .iter()
+            .take_while(|c| **c == b' ' || **c == b'\t')
+            .count();
+        rest = rest[padding..]
+            .strip_prefix(b"\r\n")
+            .ok_or_else(|| MultipartError::Malformed("bad delimiter line".into()))?;
+
+        let end = find(rest, &delimiter)
+            .ok_or_else(|| MultipartError::Malformed("missing closing boundary".into()))?;
+        parts.push(parse_part(&rest[..end])?);
+        rest = &rest[end + delimiter.len()..];
+    }
+}
@@ -4,10 +4,11 @@ use std::{collections::HashMap, fmt};
 
 use url::Url;
 
-use crate::body::BodyError;
+use crate::body::{Body, BodyError};
+use crate::headers::Headers;
 use crate::message::Message;
 use crate::{
-    request::{Request, VALID_METHODS},
+    request::{Method, Request, TargetForm, VALID_METHODS},
     response::Response,
     status,
 };
@@ -23,6 +24,7 @@ pub enum State {
     InChunkedBodySize,
     InChunkedBodyContent,
     InChunkComplete,
+    InTrailers,
     EndChunkedBody,
     ParseComplete,
 }
@@ -37,39 +39,91 @@ lazy_static! {
         (State::InChunkedBodySize, vec![State::InHeaders, State::InChunkComplete]),
         (State::InChunkedBodyContent, vec![State::InChunkedBodySize]),
         (State::InChunkComplete, vec![State::InChunkedBodyContent]),
-        (State::EndChunkedBody, vec![State::InChunkComplete, State::InChunkedBodySize]),
+        (State::InTrailers, vec![State::InChunkedBodySize]),
+        (State::EndChunkedBody, vec![State::InTrailers]),
         (State::ParseComplete, vec![State::EndChunkedBody, State::InBody, State::InHeaders]),
     ]);
 
 }
 
+/// Errors in a specific line carry the line, and the offset of its first byte from the
+/// start of the message, to help track down malformed input.
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
     UnexpectedState,
-    InvalidChunkSize,
+    InvalidChunkSize {
+        line: String,
+        offset: usize,
+    },
     NonNumericChunkSize,
     UnexpectedEOF,
-    BadMethodLine(String),
-    BadHeaderLine(String),
-    BadStatusLine(String),
+    BadMethodLine {
+        line: String,
+        offset: usize,
+    },
+    BadHeaderLine {
+        line: String,
+        offset: usize,
+    },
+    BadStatusLine {
+        line: String,
+        offset: usize,
+    },
     InvalidMethod(String),
     InvalidPath(String),
+
+    /// The request line's version is malformed, e.g., `BANANA`.
+    BadVersion(String),
+
+    /// The request line's version is well-formed, but isn't HTTP/1.0 or HTTP/1.1, e.g.,
+    /// `HTTP/2.0` from clients that assume prior knowledge of HTTP/2.
+    UnsupportedVersion(String),
     BodyError(String),
     InvalidStateTransition(State, State),
+    HeadersTooLarge,
+    UriTooLong,
+    BodyTooLarge,
+    AmbiguousBodyLength,
+
+    /// The Content-Length isn't a non-negative decimal number, e.g., `abc` or `-1`.
+    BadContentLength(String),
+
+    /// The Transfer-Encoding can't be used for framing, e.g., `chunked` isn't the last
+    /// coding, or is applied twice.
+    BadTransferEncoding(String),
+
+    /// The Transfer-Encoding has a coding other than `chunked` (or `identity`), e.g., `gzip`.
+    UnsupportedTransferEncoding(String),
+    MissingHost,
+    MultipleHosts,
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ParseError::UnexpectedState => write!(f, "Parser: unexpected state"),
-            ParseError::InvalidChunkSize => write!(f, "Parser: invalid chunk size"),
+            ParseError::InvalidChunkSize { line, offset } => {
+                write!(f, "Parser: invalid chunk size at byte {}: {}", offset, line)
+            }
             ParseError::NonNumericChunkSize => write!(f, "Parser: non-numeric chunk size"),
             ParseError::UnexpectedEOF => write!(f, "Parser: unexpected state"),
-            ParseError::BadMethodLine(msg) => write!(f, "Parser: bad method line: {}", msg),
-            ParseError::BadStatusLine(msg) => write!(f, "Parser: bad status line: {}", msg),
-            ParseError::BadHeaderLine(msg) => write!(f, "Parser: bad header line: {}", msg),
+            ParseError::BadMethodLine { line, offset } => {
+                write!(f, "Parser: bad method line at byte {}: {}", offset, line)
+            }
+            ParseError::BadStatusLine { line, offset } => {
+                write!(f, "Parser: bad status line at byte {}: {}", offset, line)
+            }
+            ParseError::BadHeaderLine { line, offset } => {
+                write!(f, "Parser: bad header line at byte {}: {}", offset, line)
+            }
             ParseError::InvalidMethod(msg) => write!(f, "Parser: invalid method: {}", msg),
             ParseError::InvalidPath(msg) => write!(f, "Parser: invalid path: {}", msg),
+            ParseError::BadVersion(version) => write!(f, "Parser: bad version: {}", version),
+            ParseError::UnsupportedVersion(version) => write!(
+                f,
+                "Parser: unsupported version {}, only HTTP/1.0 and HTTP/1.1 are supported",
+                version
+            ),
             ParseError::BodyError(msg) => write!(f, "Parser: body error: {}", msg),
             ParseError::InvalidStateTransition(src, dest) => {
                 write!(
@@ -78,10 +132,84 @@ impl fmt::Display for ParseError {
                     src, dest
                 )
             }
+            ParseError::HeadersTooLarge => write!(f, "Parser: header section too large"),
+            ParseError::UriTooLong => write!(f, "Parser: request line too long"),
+            ParseError::BodyTooLarge => write!(f, "Parser: body too large"),
+            ParseError::AmbiguousBodyLength => write!(f, "Parser: ambiguous body length"),
+            ParseError::BadContentLength(length) => {
+                write!(f, "Parser: bad content length: {}", length)
+            }
+            ParseError::BadTransferEncoding(encoding) => {
+                write!(f, "Parser: bad transfer encoding: {}", encoding)
+            }
+            ParseError::UnsupportedTransferEncoding(coding) => {
+                write!(f, "Parser: unsupported transfer coding: {}", coding)
+            }
+            ParseError::MissingHost => write!(f, "Parser: missing host header"),
+            ParseError::MultipleHosts => write!(f, "Parser: multiple host headers"),
         }
     }
 }
 
+/// Check the codings of the Transfer-Encoding header `values`, in the order they were
+/// applied, and return true if the body is chunked. Only `chunked` is supported (`identity`
+/// is a no-op), and it has to be the last coding, since it's what frames the body.
+fn transfer_codings(values: &[String]) -> Result<bool, ParseError> {
+    let codings: Vec<String> = values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(|coding| coding.trim().to_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect();
+
+    let chunked = codings.iter().filter(|coding| *coding == "chunked").count();
+    if chunked > 1 || (chunked == 1 && codings.last().is_some_and(|last| last != "chunked")) {
+        return Err(ParseError::BadTransferEncoding(values.join(", ")));
+    }
+
+    match codings.iter().find(|coding| *coding != "chunked") {
+        Some(coding) => Err(ParseError::UnsupportedTransferEncoding(coding.clone())),
+        None => Ok(chunked == 1),
+    }
+}
+
+/// Returns true if `version` is a well-formed HTTP version, i.e., `HTTP/x.y`.
+fn valid_version(version: &str) -> bool {
+    match version.strip_prefix("HTTP/").map(str::as_bytes) {
+        Some([major, b'.', minor]) => major.is_ascii_digit() && minor.is_ascii_digit(),
+        _ => false,
+    }
+}
+
+/// Receives body bytes as they're parsed. See `Parser::on_body_chunk`.
+#[allow(clippy::type_complexity)]
+pub struct BodyCallback(Box<dyn FnMut(&[u8]) + Send + Sync>);
+
+impl fmt::Debug for BodyCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BodyCallback")
+    }
+}
+
+/// Receives interim (1xx) responses as they're parsed. See `Parser::on_interim_response`.
+#[allow(clippy::type_complexity)]
+pub struct InterimCallback(Box<dyn FnMut(&Response) + Send + Sync>);
+
+impl fmt::Debug for InterimCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InterimCallback")
+    }
+}
+
+/// The default limit on the size of the start line and headers.
+pub const DEFAULT_MAX_HEADER_BYTES: usize = 64 * 1024;
+
+/// The default limit on the size of the request line.
+pub const DEFAULT_MAX_URI_BYTES: usize = 8 * 1024;
+
+/// The limit on the size of a chunk size line, including any chunk extensions.
+pub const MAX_CHUNK_LINE_BYTES: usize = 4 * 1024;
+
 pub struct RequestParser {}
 
 impl RequestParser {
@@ -106,10 +234,44 @@ pub struct Parser {
     state: State,
     buf: Vec<u8>,
     chunk_buf: Vec<u8>,
+    trailers: Headers,
     message: Message,
     expected_chunk_size: usize,
     chunk_pos: usize,
     ready: bool,
+
+    /// Bytes consumed so far in the start line and headers, and the limit.
+    header_bytes: usize,
+    max_header_bytes: usize,
+
+    /// The limit on the size of the request line, which is mostly the URI.
+    max_uri_bytes: usize,
+
+    /// Bytes parsed so far, and the offset of the start of the current line.
+    pos: usize,
+    line_start: usize,
+
+    /// Body bytes announced so far (by Content-Length or chunk sizes), and the limit.
+    body_bytes: usize,
+    max_body_bytes: Option<usize>,
+
+    /// Require HTTP/1.1 requests to have exactly one Host header, unless the request
+    /// target is in absolute form (i.e., a full URL.)
+    require_host: bool,
+
+    /// Gets the body instead of the message.
+    body_callback: Option<BodyCallback>,
+
+    /// The number of Content-Length bytes the body is still owed.
+    body_remaining: usize,
+
+    /// Interim responses parsed before the final one, and a callback that gets them as
+    /// they're parsed.
+    interim_responses: Vec<Response>,
+    interim_callback: Option<InterimCallback>,
+
+    /// The message has no body, whatever its headers say.
+    no_body: bool,
 }
 
 impl Parser {
@@ -127,10 +289,24 @@ impl Parser {
             state: start_state,
             buf: Vec::with_capacity(16384),
             chunk_buf: Vec::with_capacity(16384),
+            trailers: Headers::new(),
             message,
             expected_chunk_size: 0,
             chunk_pos: 0,
             ready: false,
+            header_bytes: 0,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_uri_bytes: DEFAULT_MAX_URI_BYTES,
+            pos: 0,
+            line_start: 0,
+            body_bytes: 0,
+            max_body_bytes: None,
+            require_host: false,
+            body_callback: None,
+            body_remaining: 0,
+            interim_responses: vec![],
+            interim_callback: None,
+            no_body: false,
         }
     }
 
@@ -142,6 +318,73 @@ impl Parser {
         self.base_url = base_url.into();
     }
 
+    /// Fail HTTP/1.1 requests that don't have exactly one Host header with `MissingHost`
+    /// or `MultipleHosts`. Requests with absolute-form targets (e.g., `GET http://host/ ...`)
+    /// may leave it out, since the target has the host.
+    pub fn set_require_host(&mut self, require: bool) {
+        self.require_host = require;
+    }
+
+    /// Fail with `ParseError::HeadersTooLarge` if the start line and headers (plus any
+    /// chunked trailers) are longer than `max` bytes (including line endings.)
+    pub fn set_max_header_bytes(&mut self, max: usize) {
+        self.max_header_bytes = max;
+    }
+
+    /// Fail with `ParseError::UriTooLong` if the request line (i.e., the method, URI, and
+    /// version, without the line ending) is longer than `max` bytes. This is checked before
+    /// the header section limit, since the request line comes first.
+    pub fn set_max_uri_bytes(&mut self, max: usize) {
+        self.max_uri_bytes = max;
+    }
+
+    /// Fail with `ParseError::BodyTooLarge` if the body is longer than `max` bytes. By
+    /// default, bodies are unlimited.
+    pub fn set_max_body_bytes(&mut self, max: usize) {
+        self.max_body_bytes = Some(max);
+    }
+
+    /// Pass body bytes to `callback` as they're parsed, instead of keeping them in the
+    /// message's body, e.g., to forward large uploads without holding on to them. The
+    /// message's body ends up empty, but still completes, so waiting for it works.
+    ///
+    /// For Content-Length bodies, `callback` gets exactly that many bytes. For chunked
+    /// bodies, it gets the chunk data without the framing, possibly split up differently
+    /// than the chunks were, and the trailers are still set on the body. Set this before
+    /// the headers are parsed. Body size limits apply either way.
+    pub fn on_body_chunk(&mut self, callback: impl FnMut(&[u8]) + Send + Sync + 'static) {
+        self.body_callback = Some(BodyCallback(Box::new(callback)));
+    }
+
+    /// Pass interim responses (1xx, other than 101 Switching Protocols) to `callback`, e.g.,
+    /// to send a request body once the server responds with `100 Continue`. Interim responses
+    /// are never the parsed message, parsing continues with the final response after them.
+    pub fn on_interim_response(&mut self, callback: impl FnMut(&Response) + Send + Sync + 'static) {
+        self.interim_callback = Some(InterimCallback(Box::new(callback)));
+    }
+
+    /// Don't expect a body after the headers, even if they have a Content-Length or are
+    /// chunked, e.g., for responses to HEAD requests. The headers are kept as sent.
+    pub fn set_no_body(&mut self, no_body: bool) {
+        self.no_body = no_body;
+    }
+
+    /// Returns the interim responses (1xx, other than 101 Switching Protocols) parsed so far,
+    /// in order. These come before the final response, e.g., `100 Continue` or
+    /// `103 Early Hints`.
+    pub fn interim_responses(&self) -> &[Response] {
+        &self.interim_responses
+    }
+
+    /// Count `n` more body bytes against the limit.
+    fn add_body_bytes(&mut self, n: usize) -> Result<(), ParseError> {
+        self.body_bytes = self.body_bytes.saturating_add(n);
+        match self.max_body_bytes {
+            Some(max) if self.body_bytes > max => Err(ParseError::BodyTooLarge),
+            _ => Ok(()),
+        }
+    }
+
     fn update_state(&mut self, target_state: State) -> Result<(), ParseError> {
         if !STATE_MACHINE
             .get(&target_state)
@@ -159,13 +402,32 @@ impl Parser {
         Ok(())
     }
 
+    /// Returns the current line, for errors.
+    fn line(&self) -> String {
+        let line = String::from_utf8_lossy(&self.buf);
+        line.strip_suffix('\r').unwrap_or(&line).to_string()
+    }
+
     fn commit_method(&mut self) -> Result<(), ParseError> {
-        let method_line = std::str::from_utf8(&self.buf[..])
-            .map_err(|e| ParseError::BadMethodLine(e.to_string()))?;
+        let bad_line = || ParseError::BadMethodLine {
+            line: self.line(),
+            offset: self.line_start,
+        };
+        let method_line = std::str::from_utf8(&self.buf[..]).map_err(|_| bad_line())?;
         let parts = method_line.split_ascii_whitespace().collect::<Vec<&str>>();
 
         if parts.len() != 3 {
-            return Err(ParseError::BadMethodLine(method_line.into()));
+            return Err(bad_line());
+        }
+
+        // Check the version first, so HTTP/2 connection prefaces (`PRI * HTTP/2.0`) get a
+        // version error rather than a method error.
+        match parts[2] {
+            "HTTP/1.0" | "HTTP/1.1" => {}
+            version if valid_version(version) => {
+                return Err(ParseError::UnsupportedVersion(version.into()))
+            }
+            version => return Err(ParseError::BadVersion(version.into())),
         }
 
         let got_method = parts[0].to_uppercase();
@@ -175,12 +437,36 @@ impl Parser {
             return Err(ParseError::InvalidMethod(parts[0].into()));
         }
 
+        // Origin-form targets (e.g., `/path`) are relative to the base URL. Proxies get
+        // absolute-form targets (e.g., `http://example.com/path`), which are used as is.
         let base_url = Url::parse(&self.base_url[..])
             .or(Err(ParseError::InvalidPath(self.base_url.clone())))?;
-        let url = base_url
-            .join(parts[1])
-            .or(Err(ParseError::InvalidPath(parts[1].into())))?;
-
+        let method = self.message.request_mut().method;
+        let (url, target_form) = match parts[1] {
+            // CONNECT requests only name the host and port to tunnel to.
+            target if method == Method::CONNECT => {
+                let port = target.rsplit_once(':').map(|(_, port)| port.parse::<u16>());
+                match (port, Url::parse(&format!("http://{}", target))) {
+                    (Some(Ok(_)), Ok(url)) if url.has_host() && url.path() == "/" => {
+                        (url, TargetForm::Authority)
+                    }
+                    _ => return Err(ParseError::InvalidPath(target.into())),
+                }
+            }
+            "*" if method == Method::OPTIONS => (base_url, TargetForm::Asterisk),
+            target if target.starts_with('/') => (
+                base_url
+                    .join(target)
+                    .or(Err(ParseError::InvalidPath(target.into())))?,
+                TargetForm::Origin,
+            ),
+            target => match Url::parse(target) {
+                Ok(url) if url.has_host() => (url, TargetForm::Absolute),
+                _ => return Err(ParseError::InvalidPath(target.into())),
+            },
+        };
+
+        self.message.request_mut().set_target_form(target_form);
         self.message.request_mut().version = parts[2].into();
         self.message.request_mut().url = Some(url);
 
@@ -189,13 +475,17 @@ impl Parser {
     }
 
     fn commit_status_line(&mut self) -> Result<(), ParseError> {
-        let status_line = std::str::from_utf8(&self.buf[..]).unwrap();
+        let bad_line = || ParseError::BadStatusLine {
+            line: self.line(),
+            offset: self.line_start,
+        };
+        let status_line = std::str::from_utf8(&self.buf[..]).map_err(|_| bad_line())?;
         let parts = status_line
             .splitn(3, char::is_whitespace)
             .collect::<Vec<&str>>();
 
         if parts.len() != 3 {
-            return Err(ParseError::BadStatusLine(status_line.into()));
+            return Err(bad_line());
         }
 
         self.message.response_mut().version = parts[0].to_string();
@@ -213,23 +503,84 @@ impl Parser {
 
     fn commit_header(&mut self) -> Result<(), ParseError> {
         let mut result: Result<(), ParseError> = Ok(());
-        let header_line = std::str::from_utf8(&self.buf[..]).unwrap();
+        let header_line =
+            std::str::from_utf8(&self.buf[..]).map_err(|_| ParseError::BadHeaderLine {
+                line: self.line(),
+                offset: self.line_start,
+            })?;
 
         if header_line == "\r" || header_line.is_empty() {
+            if let Message::Response(response) = &self.message {
+                if (100..200).contains(&response.status.code) && response.status.code != 101 {
+                    // Interim responses have no body, start over with the next one.
+                    let interim: Response = std::mem::replace(
+                        &mut self.message,
+                        Message::Response(Response::new(status::OK)),
+                    )
+                    .into();
+                    if let Some(callback) = &mut self.interim_callback {
+                        (callback.0)(&interim);
+                    }
+                    self.interim_responses.push(interim);
+                    self.state = State::StartResponse;
+                    self.buf.clear();
+                    return Ok(());
+                }
+            }
+
+            if let Message::Request(request) = &self.message {
+                if self.require_host && request.version == "HTTP/1.1" {
+                    match request.headers.get("host").map_or(0, |hosts| hosts.len()) {
+                        0 if request.target_form() != TargetForm::Absolute => {
+                            return Err(ParseError::MissingHost)
+                        }
+                        0 | 1 => {}
+                        _ => return Err(ParseError::MultipleHosts),
+                    }
+                }
+            }
+
             let headers = self.message.headers_mut();
 
             let mut has_body = false;
             let mut new_state = State::InBody;
 
-            if let Some(length) = headers.get_first("content-length") {
-                if length.parse::<usize>().unwrap_or(0) != 0 {
+            // Differing lengths, or a length along with chunked encoding, could be interpreted
+            // differently by other hops (i.e., request smuggling), so reject them.
+            let lengths: Vec<&str> = headers
+                .get("content-length")
+                .map(|values| {
+                    values
+                        .iter()
+                        .flat_map(|v| v.split(','))
+                        .map(|l| l.trim())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if lengths.iter().any(|l| *l != lengths[0]) {
+                return Err(ParseError::AmbiguousBodyLength);
+            }
+
+            let mut content_length = 0;
+            if let Some(length) = lengths.first() {
+                // Falling back to 0 would leave the body to be parsed as the next request.
+                content_length = length
+                    .bytes()
+                    .all(|c| c.is_ascii_digit())
+                    .then(|| length.parse::<usize>().ok())
+                    .flatten()
+                    .ok_or_else(|| ParseError::BadContentLength(length.to_string()))?;
+                if content_length != 0 {
                     has_body = true;
                 }
             }
 
-            if let Some(encoding) = headers.get_first("transfer-encoding") {
-                let parts: Vec<&str> = encoding.split(',').map(|p| p.trim()).collect();
-                if parts.contains(&"chunked") {
+            if let Some(values) = headers.get("transfer-encoding") {
+                if transfer_codings(values)? {
+                    if !lengths.is_empty() {
+                        return Err(ParseError::AmbiguousBodyLength);
+                    }
+
                     let body = self.message.body_mut();
                     body.set_chunked();
                     new_state = State::InChunkedBodySize;
@@ -237,6 +588,26 @@ impl Parser {
                 }
             }
 
+            if self.no_body && has_body {
+                *self.message.body_mut() = Body::new();
+                has_body = false;
+            }
+
+            if has_body && new_state == State::InBody {
+                self.add_body_bytes(content_length)?;
+                self.body_remaining = content_length;
+                if self.body_callback.is_some() {
+                    self.message.body_mut().set_content_length(0);
+                }
+            }
+
+            // After a 101, the connection switches protocols, so anything left is not a body.
+            if let Message::Response(response) = &self.message {
+                if response.status.code == 101 {
+                    has_body = false;
+                }
+            }
+
             // Exiting headers, ready for body
             self.ready = true;
 
@@ -248,29 +619,65 @@ impl Parser {
                 return Ok(());
             }
         } else if let Some((k, v)) = header_line.split_once(':') {
-            let key = k.to_lowercase();
-            if key == "content-length" {
+            // Names keep the casing they were sent with, lookups are case-insensitive.
+            let key = k.trim();
+            if key.eq_ignore_ascii_case("content-length") {
+                // Duplicates are checked at the end of the headers.
+                let length = v.split(',').next().unwrap_or_default();
                 let body = self.message.body_mut();
-                body.set_content_length(v.trim().parse::<usize>().unwrap_or(0));
+                body.set_content_length(length.trim().parse::<usize>().unwrap_or(0));
             }
 
             self.message.headers_mut().add(key, v.trim());
         } else {
-            result = Err(ParseError::BadHeaderLine(header_line.into()));
+            result = Err(ParseError::BadHeaderLine {
+                line: self.line(),
+                offset: self.line_start,
+            });
         }
 
         self.buf.clear();
         result
     }
 
+    /// Commit a trailer line. Returns true at the blank line that ends the trailers.
+    fn commit_trailer(&mut self) -> Result<bool, ParseError> {
+        let bad_line = || ParseError::BadHeaderLine {
+            line: self.line(),
+            offset: self.line_start,
+        };
+        let line = std::str::from_utf8(&self.buf[..]).map_err(|_| bad_line())?;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        if line.is_empty() {
+            self.buf.clear();
+            return Ok(true);
+        }
+
+        let (k, v) = line.split_once(':').ok_or_else(bad_line)?;
+        self.trailers.add(k, v.trim());
+        self.buf.clear();
+        Ok(false)
+    }
+
     fn commit_chunksize(&mut self) -> Result<(), ParseError> {
-        self.expected_chunk_size = usize::from_str_radix(
-            str::from_utf8(&self.buf)
-                .or(Err(ParseError::InvalidChunkSize))?
-                .trim(),
-            16,
-        )
-        .or(Err(ParseError::NonNumericChunkSize))?;
+        let invalid = || ParseError::InvalidChunkSize {
+            line: self.line(),
+            offset: self.line_start,
+        };
+        let line = str::from_utf8(&self.buf).map_err(|_| invalid())?;
+
+        // The size can be followed by extensions (`;name=value`), which are ignored.
+        let size = line.split(';').next().unwrap_or_default().trim();
+        if size.is_empty() {
+            return Err(ParseError::NonNumericChunkSize);
+        }
+        if !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        self.expected_chunk_size = usize::from_str_radix(size, 16).map_err(|_| invalid())?;
+        self.add_body_bytes(self.expected_chunk_size)?;
 
         self.chunk_pos = 0;
         self.buf.clear();
@@ -310,12 +717,31 @@ impl Parser {
         self.chunk_buf.push(b);
     }
 
+    /// Append body bytes, and return true if the body is complete.
+    /// Consume as much of `b` as the body is still owed. Anything after it belongs to the
+    /// next message, e.g., a pipelined request.
     fn consume_body(&mut self, b: &[u8]) -> Result<bool, BodyError> {
-        self.message.body_mut().append(b)
+        let n = b.len().min(self.body_remaining);
+        self.body_remaining -= n;
+        self.pos += n;
+
+        if let Some(callback) = &mut self.body_callback {
+            (callback.0)(&b[..n]);
+            return Ok(self.body_remaining == 0);
+        }
+
+        self.message.body_mut().append(&b[..n])
     }
 
     fn commit_chunk(&mut self) {
-        self.message.body_mut().push_chunk(self.chunk_buf.clone());
+        match &mut self.body_callback {
+            Some(callback) => {
+                if !self.chunk_buf.is_empty() {
+                    (callback.0)(&self.chunk_buf);
+                }
+            }
+            None => self.message.body_mut().push_chunk(self.chunk_buf.clone()),
+        }
         self.chunk_buf.clear();
     }
 
@@ -332,41 +758,89 @@ impl Parser {
             return Ok(());
         }
 
-        for c in buf {
+        for (i, c) in buf.iter().enumerate() {
+            // Leave anything after the message alone (e.g., pipelined requests), so
+            // `parsed_bytes` says where it ends.
+            if self.state == State::ParseComplete {
+                break;
+            }
+
             let ch = *c as char;
+            self.pos += 1;
             match self.state {
                 State::StartRequest => {
                     if !ch.is_whitespace() {
+                        self.line_start = self.pos - 1;
+                        self.header_bytes += 1;
                         self.consume(*c);
                         self.update_state(State::InMethod)?;
                     }
                 }
                 State::StartResponse => {
                     if !ch.is_whitespace() {
+                        self.line_start = self.pos - 1;
+                        self.header_bytes += 1;
                         self.consume(*c);
                         self.update_state(State::InStatusLine)?;
                     }
                 }
+                State::InTrailers => {
+                    self.header_bytes += 1;
+                    if self.header_bytes > self.max_header_bytes {
+                        return Err(ParseError::HeadersTooLarge);
+                    }
+
+                    if ch != '\n' {
+                        self.consume(*c);
+                    } else if self.commit_trailer()? {
+                        self.update_state(State::EndChunkedBody)?;
+                        let body = self.message.body_mut();
+                        body.set_trailers(std::mem::take(&mut self.trailers));
+                        body.end_chunked();
+                        self.parse_eof()?;
+                        break;
+                    } else {
+                        self.line_start = self.pos;
+                    }
+                }
                 State::InMethod | State::InHeaders | State::InStatusLine => {
+                    self.header_bytes += 1;
+                    if self.header_bytes > self.max_header_bytes {
+                        return Err(ParseError::HeadersTooLarge);
+                    }
+
                     if ch == '\n' {
                         self.commit_line()?;
+                        self.line_start = self.pos;
                     } else {
+                        // Don't count the \r of the line ending.
+                        if self.state == State::InMethod
+                            && ch != '\r'
+                            && self.buf.len() >= self.max_uri_bytes
+                        {
+                            return Err(ParseError::UriTooLong);
+                        }
                         self.consume(*c);
                     }
                 }
                 State::InChunkedBodySize => {
                     if ch == '\n' {
                         self.commit_chunksize()?;
+                        self.line_start = self.pos;
                         if self.expected_chunk_size == 0 {
-                            self.update_state(State::EndChunkedBody)?;
+                            self.update_state(State::InTrailers)?;
                         } else {
                             self.update_state(State::InChunkedBodyContent)?;
                         }
-                    } else if ch.is_ascii_hexdigit() {
+                    } else {
+                        if self.buf.len() >= MAX_CHUNK_LINE_BYTES {
+                            return Err(ParseError::InvalidChunkSize {
+                                line: self.line(),
+                                offset: self.line_start,
+                            });
+                        }
                         self.consume(*c);
                     }
-
-                    // skip anything else
                 }
                 State::InChunkedBodyContent => {
                     self.consume_chunk(*c);
@@ -380,30 +854,40 @@ impl Parser {
                 }
                 State::InChunkComplete => {
                     if ch == '\n' {
+                        self.line_start = self.pos;
                         self.update_state(State::InChunkedBodySize)?;
                     }
                 }
                 State::InBody => {
-                    self.consume_body(&[*c])
+                    // The rest of the buffer is body, or starts with it.
+                    self.pos -= 1;
+                    let done = self
+                        .consume_body(&buf[i..])
                         .map_err(|e| ParseError::BodyError(e.to_string()))?;
-                    if self.message.body_mut().complete() {
+                    if done {
                         self.parse_eof()?;
                     }
+                    break;
                 }
-                State::EndChunkedBody => {
-                    if ch == '\n' {
-                        self.message.body_mut().end_chunked();
-                        self.parse_eof()?;
-                        break;
-                    }
-                }
-                State::ParseComplete => {}
+                State::EndChunkedBody | State::ParseComplete => {}
             }
         }
 
+        // Don't hold on to partial chunks for callbacks.
+        if self.body_callback.is_some() && self.state == State::InChunkedBodyContent {
+            self.commit_chunk();
+        }
+
         Ok(())
     }
 
+    /// Returns the number of bytes parsed so far, across all calls to `parse_buf`. Once the
+    /// message is complete, bytes past its end (e.g., pipelined requests, or the new protocol
+    /// after a `101 Switching Protocols`) aren't parsed, and this is where they start.
+    pub fn parsed_bytes(&self) -> usize {
+        self.pos
+    }
+
     pub fn is_complete(&self) -> bool {
         debug!("STATE: {:?}", self.state);
         self.state == State::ParseComplete
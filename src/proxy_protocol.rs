@@ -0,0 +1,179 @@
+/// This file implements the PROXY protocol (version 1), which L4 load balancers use to pass
+/// along the original client address. The balancer sends a single text line before any
+/// other data on the connection, e.g.:
+///
+///   PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n
+///
+/// See https://www.haproxy.org/download/2.8/doc/proxy-protocol.txt
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+use crate::handler::{AsyncReadStream, AsyncStream, AsyncWriteStream};
+
+/// The longest possible v1 header, including the CRLF.
+pub const MAX_HEADER_LEN: usize = 107;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyHeader {
+    /// A proxied TCP connection from `source` to `destination`.
+    Tcp {
+        source: SocketAddr,
+        destination: SocketAddr,
+    },
+
+    /// The balancer doesn't know (or won't say) where the connection came from, e.g., for
+    /// health checks. The connection's own addresses should be used.
+    Unknown,
+}
+
+impl ProxyHeader {
+    /// Returns the original client's address, if known.
+    pub fn source(&self) -> Option<SocketAddr> {
+        match self {
+            ProxyHeader::Tcp { source, .. } => Some(*source),
+            ProxyHeader::Unknown => None,
+        }
+    }
+}
+
+/// Parse a v1 header line, with or without the trailing CRLF.
+pub fn parse_header(line: &str) -> Result<ProxyHeader, String> {
+    let line = line.strip_suffix("\r\n").unwrap_or(line);
+    let mut parts = line.split(' ');
+
+    if parts.next() != Some("PROXY") {
+        return Err(format!("not a PROXY header: {}", line));
+    }
+
+    let is_v4 = match parts.next() {
+        Some("TCP4") => true,
+        Some("TCP6") => false,
+        // Anything may follow UNKNOWN, and should be ignored.
+        Some("UNKNOWN") => return Ok(ProxyHeader::Unknown),
+        _ => return Err(format!("bad protocol in PROXY header: {}", line)),
+    };
+
+    let fields: Vec<&str> = parts.collect();
+    if fields.len() != 4 {
+        return Err(format!("bad PROXY header: {}", line));
+    }
+
+    let ip = |s: &str| {
+        s.parse::<IpAddr>()
+            .ok()
+            .filter(|ip| ip.is_ipv4() == is_v4)
+            .ok_or_else(|| format!("bad address in PROXY header: {}", s))
+    };
+    let port = |s: &str| {
+        s.parse::<u16>()
+            .ok()
+            .filter(|_| !s.starts_with('+') && (s == "0" || !s.starts_with('0')))
+            .ok_or_else(|| format!("bad port in PROXY header: {}", s))
+    };
+
+    Ok(ProxyHeader::Tcp {
+        source: SocketAddr::new(ip(fields[0])?, port(fields[2])?),
+        destination: SocketAddr::new(ip(fields[1])?, port(fields[3])?),
+    })
+}
+
+/// Read and parse the header at the start of `stream`. Returns the header, and a stream
+/// that yields everything after it (including any bytes that were read past the header.)
+pub(crate) async fn read_header(
+    mut stream: Box<dyn AsyncStream>,
+) -> io::Result<(ProxyHeader, Box<dyn AsyncStream>)> {
+    let mut buf: Vec<u8> = Vec::with_capacity(MAX_HEADER_LEN);
+
+    let end = loop {
+        if let Some(pos) = buf.windows(2).position(|w| w == b"\r\n") {
+            break pos + 2;
+        }
+
+        if buf.len() >= MAX_HEADER_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "PROXY header too long",
+            ));
+        }
+
+        let mut chunk = [0u8; MAX_HEADER_LEN];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    if end > MAX_HEADER_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "PROXY header too long",
+        ));
+    }
+
+    let line = std::str::from_utf8(&buf[..end])
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "bad PROXY header"))?;
+    let header = parse_header(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let stream = PrefixedStream {
+        prefix: buf.split_off(end),
+        pos: 0,
+        inner: stream,
+    };
+
+    Ok((header, Box::new(stream)))
+}
+
+/// A stream that returns `prefix` before reading from `inner`.
+struct PrefixedStream {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: Box<dyn AsyncStream>,
+}
+
+impl AsyncRead for PrefixedStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.pos < this.prefix.len() {
+            let n = buf.remaining().min(this.prefix.len() - this.pos);
+            buf.put_slice(&this.prefix[this.pos..this.pos + n]);
+            this.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PrefixedStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl AsyncStream for PrefixedStream {}
+impl AsyncReadStream for PrefixedStream {}
+impl AsyncWriteStream for PrefixedStream {}
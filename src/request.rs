@@ -1,10 +1,26 @@
-use std::{collections::HashMap, str::FromStr, sync::Arc};
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use tokio::sync::RwLock;
 use url::Url;
 
 use crate::{
-    body::Body, conntrack::Conn, headers::Headers, message::Message, parser::RequestParser,
+    body::Body,
+    conntrack::Conn,
+    form::{self, FormData, FormError},
+    handler,
+    headers::Headers,
+    message::Message,
+    multipart::{self, MultipartError, Part},
+    parser::RequestParser,
+    status,
 };
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -20,6 +36,53 @@ pub enum Method {
     PATCH,
 }
 
+impl Method {
+    /// Returns true if sending a request with this method more than once has the same
+    /// effect as sending it once (RFC 9110 §9.2.2), so it's safe to retry.
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Method::GET
+                | Method::HEAD
+                | Method::PUT
+                | Method::DELETE
+                | Method::OPTIONS
+                | Method::TRACE
+        )
+    }
+}
+
+/// The form of a request's target (RFC 7230, section 5.3).
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum TargetForm {
+    /// A path relative to the server, e.g., `GET /index.html HTTP/1.1`.
+    #[default]
+    Origin,
+
+    /// A full URL, as sent to proxies, e.g., `GET http://example.com/ HTTP/1.1`.
+    Absolute,
+
+    /// The host and port to tunnel to, as in `CONNECT example.com:443 HTTP/1.1`.
+    Authority,
+
+    /// The whole server, as in `OPTIONS * HTTP/1.1`.
+    Asterisk,
+}
+
+/// The outcome of evaluating the conditional headers of a request (RFC 7232), see
+/// `Request::check_preconditions`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PreconditionResult {
+    /// The preconditions hold (or there are none), so handle the request as usual.
+    Proceed,
+
+    /// The client's copy is current, respond with a 304 Not Modified (GET and HEAD only.)
+    NotModified,
+
+    /// A precondition failed, respond with a 412 Precondition Failed.
+    PreconditionFailed,
+}
+
 lazy_static! {
     pub static ref VALID_METHODS: HashMap<&'static str, Method> = HashMap::from([
         ("GET", Method::GET),
@@ -48,6 +111,15 @@ pub struct Request {
     pub params: HashMap<String, String>,
     pub context: Arc<RwLock<HashMap<String, String>>>,
     conn: Option<Conn>,
+    peer_addr: Option<SocketAddr>,
+    socket_addr: Option<SocketAddr>,
+    target_form: TargetForm,
+
+    /// Headers that middleware wants added to the response. Shared across clones.
+    response_headers: Arc<std::sync::RwLock<Headers>>,
+
+    /// The ID assigned by the `RequestId` middleware. Shared across clones.
+    request_id: Arc<OnceLock<String>>,
 }
 
 impl From<Message> for Request {
@@ -79,6 +151,11 @@ impl Request {
             params: HashMap::new(),
             context: Arc::new(RwLock::new(HashMap::new())),
             conn: None,
+            peer_addr: None,
+            socket_addr: None,
+            target_form: TargetForm::Origin,
+            response_headers: Arc::new(std::sync::RwLock::new(Headers::new())),
+            request_id: Arc::new(OnceLock::new()),
         };
 
         request.set_path(path);
@@ -105,6 +182,68 @@ impl Request {
         self.conn.clone()
     }
 
+    /// Add a header to the response for this request, regardless of which handler writes
+    /// it. This lets middleware (e.g., in a `Stack`) decorate responses written by later
+    /// handlers. Headers that the response already has are left alone.
+    pub fn add_response_header(&self, k: impl Into<String>, v: impl Into<String>) {
+        self.response_headers.write().unwrap().add(k, v);
+    }
+
+    /// Returns the headers added with `add_response_header`.
+    pub fn response_headers(&self) -> Headers {
+        self.response_headers.read().unwrap().clone()
+    }
+
+    pub(crate) fn shared_response_headers(&self) -> Arc<std::sync::RwLock<Headers>> {
+        Arc::clone(&self.response_headers)
+    }
+
+    /// Returns the ID assigned to this request by the `RequestId` middleware, if any.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.get().map(|id| id.as_str())
+    }
+
+    /// Assign an ID to this request. Returns false if it already has one.
+    pub(crate) fn set_request_id(&self, id: impl Into<String>) -> bool {
+        self.request_id.set(id.into()).is_ok()
+    }
+
+    pub fn set_peer_addr(&mut self, peer_addr: SocketAddr) {
+        self.peer_addr = Some(peer_addr)
+    }
+
+    /// Returns the address of the connected client. This is None for requests that
+    /// weren't received by the server.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    pub fn set_socket_addr(&mut self, socket_addr: SocketAddr) {
+        self.socket_addr = Some(socket_addr)
+    }
+
+    /// Returns the address the request's connection came from, i.e., the previous hop.
+    /// Unlike `peer_addr`, this isn't replaced by the client from a trusted `X-Forwarded-For`.
+    /// Falls back to `peer_addr` if it isn't set.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.socket_addr.or(self.peer_addr)
+    }
+
+    pub(crate) fn set_target_form(&mut self, target_form: TargetForm) {
+        self.target_form = target_form
+    }
+
+    /// Returns the form of the target in the request line. For absolute-form targets, `url`
+    /// is the requested URL, which is useful for proxies.
+    pub fn target_form(&self) -> TargetForm {
+        self.target_form
+    }
+
+    /// Returns true if the request line has a full URL, i.e., the client is talking to a proxy.
+    pub fn is_absolute_form(&self) -> bool {
+        self.target_form == TargetForm::Absolute
+    }
+
     pub fn set_path(&mut self, path: impl AsRef<str>) {
         let mut url =
             Url::from_str(&self.base_url).unwrap_or(Url::from_str("http://UNSET").unwrap());
@@ -121,27 +260,83 @@ impl Request {
             .get_first_or_set("transfer-encoding", "chunked");
     }
 
+    /// Returns the fields of an urlencoded body, decoded, or an empty map for other content
+    /// types. Only the body received so far is used. See `form` for other form types.
     pub fn post_params(&mut self) -> Option<HashMap<String, String>> {
-        let mut result: HashMap<String, String> = HashMap::new();
-        if let Some(content_type) = self.headers.get_first("content-type") {
-            if *content_type == "application/x-www-form-urlencoded" {
-                let content = self.body.try_content();
-                let content = String::from_utf8_lossy(content.as_slice());
-                let parts = content.split('&');
-
-                parts.for_each(|part| {
-                    let kv: Vec<&str> = part.split('=').collect();
-                    if kv.len() == 2 {
-                        result.insert(kv[0].into(), kv[1].into());
-                    }
-                });
-            }
-            Some(result)
-        } else {
-            None
+        let content_type = self.headers.content_type()?;
+        if content_type.mime != form::URLENCODED {
+            return Some(HashMap::new());
+        }
+
+        Some(
+            form::parse_urlencoded(&self.body.try_content())
+                .fields
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    /// Parse an urlencoded or multipart/form-data body into its fields and files. This waits
+    /// for the entire body.
+    pub async fn form(&self) -> Result<FormData, FormError> {
+        let content_type = self
+            .headers
+            .get_first("content-type")
+            .ok_or(FormError::NotForm)?;
+
+        // Fail early if the body isn't a form, rather than waiting for it.
+        if !form::is_form(content_type) {
+            return Err(FormError::NotForm);
         }
+        form::parse(content_type, &self.body.content().await)
+    }
+
+    /// Read the body into memory, so that middleware can read it and still leave it for the
+    /// handlers after them. This defeats streaming for the request. See `Body::buffer`.
+    pub async fn buffer_body(&self) -> io::Result<()> {
+        self.body.buffer().await
     }
 
+    /// Wait for the entire body, and deserialize it from JSON. Fails with a 415 if the
+    /// request's `Content-Type` isn't JSON (e.g., `application/json` or
+    /// `application/problem+json`), and with a 400 if the body isn't valid JSON.
+    pub async fn json<T: DeserializeOwned>(&self) -> Result<T, handler::Error> {
+        let is_json = self.headers.content_type().is_some_and(|content_type| {
+            content_type.mime == "application/json" || content_type.mime.ends_with("+json")
+        });
+
+        if !is_json {
+            return Err(handler::Error::Status(
+                status::UNSUPPORTED_MEDIA_TYPE.into(),
+            ));
+        }
+
+        self.json_unchecked().await
+    }
+
+    /// Like `json`, but doesn't check the `Content-Type`, for clients that don't set it.
+    pub async fn json_unchecked<T: DeserializeOwned>(&self) -> Result<T, handler::Error> {
+        serde_json::from_slice(&self.body.content().await).map_err(|e| {
+            debug!("Request: malformed JSON body: {}", e);
+            handler::Error::Status(status::BAD_REQUEST.into())
+        })
+    }
+
+    /// Parse a multipart/form-data body (e.g., a form with file uploads) into its parts. This
+    /// waits for the entire body.
+    pub async fn multipart(&self) -> Result<Vec<Part>, MultipartError> {
+        let content_type = self
+            .headers
+            .get_first("content-type")
+            .ok_or(MultipartError::NotMultipart)?;
+
+        // Fail early if the body isn't multipart, rather than waiting for it.
+        multipart::boundary(content_type)?;
+        multipart::parse(content_type, &self.body.content().await)
+    }
+
+    /// Returns the decoded query parameters. Repeated keys keep their last value, use
+    /// `query_param_all` to get all of them.
     pub fn query_params(&self) -> HashMap<String, String> {
         if let Some(url) = &self.url {
             return url
@@ -153,6 +348,24 @@ impl Request {
         HashMap::new()
     }
 
+    /// Returns the first decoded value of the query parameter `key`.
+    pub fn query_param(&self, key: &str) -> Option<String> {
+        self.query_param_all(key).into_iter().next()
+    }
+
+    /// Returns all the decoded values of the query parameter `key`, in order, e.g.,
+    /// `["a", "b"]` for `?tag=a&tag=b`.
+    pub fn query_param_all(&self, key: &str) -> Vec<String> {
+        let Some(url) = &self.url else {
+            return vec![];
+        };
+
+        url.query_pairs()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+            .collect()
+    }
+
     pub fn cookies(&self) -> Option<HashMap<&str, &str>> {
         if let Some(cookie_vals) = self.headers.get("cookie") {
             let mut result = HashMap::new();
@@ -171,30 +384,145 @@ impl Request {
         }
     }
 
+    /// Set the Cookie header to the given name/value pairs, replacing any existing cookies.
+    pub fn set_cookies<K, V>(&mut self, cookies: impl IntoIterator<Item = (K, V)>)
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let cookies = cookies
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", k.as_ref(), v.as_ref()))
+            .collect::<Vec<String>>()
+            .join("; ");
+
+        if cookies.is_empty() {
+            self.headers.remove("cookie");
+        } else {
+            self.headers.set("cookie", cookies);
+        }
+    }
+
+    /// Returns the trailer fields sent after a chunked body. These are only available once
+    /// the body has been read in full.
+    pub fn trailers(&self) -> Headers {
+        self.body.trailers()
+    }
+
+    /// Evaluate the `If-Match` and `If-None-Match` headers against `current_etag`, the ETag
+    /// of the target resource, e.g., to avoid lost updates with `If-Match` on PUTs. Date
+    /// preconditions are ignored, since there's no modification date to compare them with,
+    /// see `evaluate_preconditions`.
+    pub fn check_preconditions(&self, current_etag: &str) -> PreconditionResult {
+        self.evaluate_preconditions(Some(current_etag), None)
+    }
+
+    /// Evaluate the conditional headers of the request, in the order of RFC 7232, section 6,
+    /// against the current ETag and modification date of the target resource. Either is
+    /// None if the resource doesn't have one, and a None ETag means there's no current
+    /// representation, so `If-Match: *` fails, and `If-None-Match: *` passes.
+    ///
+    /// `If-Match` uses the strong comparison (weak ETags never match), `If-None-Match` the
+    /// weak one. The date headers are only used if the matching ETag header is absent.
+    pub fn evaluate_preconditions(
+        &self,
+        current_etag: Option<&str>,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> PreconditionResult {
+        let date = |name| {
+            let date = self.headers.get_first(name)?;
+            DateTime::parse_from_rfc2822(date).ok()
+        };
+
+        if let Some(tags) = self.etag_list("if-match") {
+            let matched = current_etag.is_some_and(|current| {
+                tags.iter().any(|tag| {
+                    *tag == "*"
+                        || (!tag.starts_with("W/") && !current.starts_with("W/") && *tag == current)
+                })
+            });
+            if !matched {
+                return PreconditionResult::PreconditionFailed;
+            }
+        } else if let (Some(since), Some(last_modified)) =
+            (date("if-unmodified-since"), last_modified)
+        {
+            if last_modified > since {
+                return PreconditionResult::PreconditionFailed;
+            }
+        }
+
+        let safe = matches!(self.method, Method::GET | Method::HEAD);
+        if let Some(tags) = self.etag_list("if-none-match") {
+            let matched = current_etag.is_some_and(|current| {
+                tags.iter().any(|tag| {
+                    *tag == "*" || tag.trim_start_matches("W/") == current.trim_start_matches("W/")
+                })
+            });
+            if matched {
+                return match safe {
+                    true => PreconditionResult::NotModified,
+                    false => PreconditionResult::PreconditionFailed,
+                };
+            }
+        } else if let (true, Some(since), Some(last_modified)) =
+            (safe, date("if-modified-since"), last_modified)
+        {
+            if last_modified <= since {
+                return PreconditionResult::NotModified;
+            }
+        }
+
+        PreconditionResult::Proceed
+    }
+
+    /// Returns the entity tags in all the `name` headers, or None if there are none.
+    fn etag_list(&self, name: &str) -> Option<Vec<&str>> {
+        let values = self.headers.get(name)?;
+        Some(
+            values
+                .iter()
+                .flat_map(|v| v.split(','))
+                .map(|tag| tag.trim())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        )
+    }
+
     pub fn abs_path(&self) -> String {
-        return self.url.as_ref().unwrap().path().to_string();
+        self.url.as_ref().unwrap().path().to_string()
     }
 
+    /// Returns the path relative to the route that matched the request, or the absolute
+    /// path if there's no route. Falls back to `/` if the path isn't under the route.
     pub fn path(&self) -> String {
         if let Some(handler_path) = &self.handler_path {
-            return self
-                .url
+            self.url
                 .as_ref()
                 .unwrap()
                 .path()
                 .strip_prefix(handler_path.as_str())
-                .expect("can't strip handler path")
-                .to_string();
+                .unwrap_or("/")
+                .to_string()
         } else {
             self.abs_path()
         }
     }
 
+    /// Returns the origin-form target for the request line, i.e., the path and query.
+    fn target(&self) -> String {
+        let url = self.url.as_ref().unwrap();
+        match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        }
+    }
+
     pub fn serialize_method(&self) -> String {
         format!(
             "{} {} HTTP/1.1",
             METHODS_AS_STR.get(&self.method).unwrap(),
-            self.abs_path()
+            self.target()
         )
     }
 
@@ -202,7 +530,7 @@ impl Request {
         let mut r = format!(
             "{} {} HTTP/1.1\r\n",
             METHODS_AS_STR.get(&self.method).unwrap(),
-            self.abs_path()
+            self.target()
         );
 
         r.push_str(&self.headers.serialize());
@@ -1,4 +1,13 @@
-use crate::{body::Body, cookie::Cookie, headers::Headers, message::Message, status};
+use std::io;
+
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    body::Body, cookie::Cookie, handler::AsyncWriteStream, headers::Headers, message::Message,
+    status,
+};
 
 #[derive(Debug, Clone)]
 pub struct Response {
@@ -85,6 +94,50 @@ impl Response {
         }
     }
 
+    /// Create an empty response with status `code`, and its standard reason phrase, e.g.,
+    /// `Response::status(404).with_body("gone fishing")`.
+    pub fn status(code: u16) -> Response {
+        Response::new(code)
+    }
+
+    /// Create a new response with `value` serialized as a JSON body, and the content
+    /// type set to `application/json`.
+    pub fn json<T: Serialize + ?Sized>(
+        status: impl Into<status::Status>,
+        value: &T,
+    ) -> Result<Response, serde_json::Error> {
+        let mut response = Response::new(status);
+        response.headers.set("Content-Type", "application/json");
+        response.set_body(serde_json::to_vec(value)?);
+        Ok(response)
+    }
+
+    /// Create a redirect to `location`. `code` picks the kind of redirect: 301 and 308 are
+    /// permanent, 302, 303, and 307 are temporary. Unlike 301 and 302, 307 and 308 ask
+    /// clients to keep the request's method and body. Other codes fall back to 302.
+    pub fn redirect(location: impl Into<String>, code: u16) -> Response {
+        let status = match code {
+            301 => status::MOVED_PERMANENTLY,
+            303 => status::SEE_OTHER,
+            307 => status::TEMPORARY_REDIRECT,
+            308 => status::PERMANENT_REDIRECT,
+            _ => status::FOUND,
+        };
+
+        let mut response = Response::new(status);
+        response.headers.set("Location", location.into());
+        response
+    }
+
+    pub fn with_status(mut self, status: impl Into<status::Status>) -> Self {
+        self.status = status.into();
+        self
+    }
+
+    pub fn set_status(&mut self, status: impl Into<status::Status>) {
+        self.status = status.into();
+    }
+
     pub fn with_body(mut self, body: impl Into<Body>) -> Self {
         self.body = body.into();
         self
@@ -98,8 +151,14 @@ impl Response {
         String::from_utf8_lossy(self.body.content().await.as_slice()).into()
     }
 
+    /// Returns the trailer fields sent after a chunked body. These are only available once
+    /// the body has been read in full.
+    pub fn trailers(&self) -> Headers {
+        self.body.trailers()
+    }
+
     pub fn set_cookie(&mut self, cookie: Cookie) {
-        self.headers.add("set-cookie", cookie.serialize());
+        self.headers.add("set-cookie", cookie.serialize_value());
     }
 
     pub fn set_chunked(&mut self) {
@@ -116,20 +175,164 @@ impl Response {
     }
 
     pub fn serialize(&mut self) -> String {
+        String::from_utf8_lossy(self.serialize_bytes().as_slice()).into()
+    }
+
+    /// Same as `serialize`, but returns raw bytes, so it's safe to use with binary bodies.
+    pub fn serialize_bytes(&mut self) -> Vec<u8> {
         let status_line = format!("HTTP/1.1 {} {}", self.status.code, self.status.text);
-        let length = self.body.try_content().len();
-        if length > 0 {
+        let content = self.body.try_content();
+        if !content.is_empty() {
             self.headers
-                .get_first_or_set("Content-Length", length.to_string());
+                .get_first_or_set("Content-Length", content.len().to_string());
         }
 
         let headers: String = self.headers.serialize();
 
-        let buf = format!(
-            "{status_line}\r\n{headers}\r\n\r\n{}",
-            String::from_utf8_lossy(self.body.try_content().as_slice())
+        let mut buf = format!("{status_line}\r\n{headers}\r\n\r\n").into_bytes();
+        buf.extend(content);
+        buf
+    }
+
+    /// Write the response to `w`. Bodies streamed from readers are written as they're read,
+    /// with chunked encoding unless a Content-Length is set. If reading the body fails
+    /// midway, the stream is shut down so the client can tell the body is incomplete.
+    ///
+    /// Chunked bodies (see `set_chunked`) are written as chunks are pushed, until
+    /// `Body::end_chunked` is called, so handlers can return the response right away and
+    /// push chunks from another task.
+    pub async fn write_to(&mut self, w: &mut dyn AsyncWriteStream) -> io::Result<()> {
+        if self.body.chunked() {
+            return self.write_chunked_to(w).await;
+        }
+
+        if !self.body.is_reader() {
+            return w.write_all(&self.serialize_bytes()).await;
+        }
+
+        let chunked = self.headers.get_first("content-length").is_none();
+        if chunked {
+            self.headers.set("Transfer-Encoding", "chunked");
+        }
+
+        let head = format!(
+            "{}\r\n{}\r\n\r\n",
+            self.serialize_status(),
+            self.headers.serialize()
         );
+        w.write_all(head.as_bytes()).await?;
 
-        buf
+        let mut stream = self.body.try_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    _ = w.shutdown().await;
+                    return Err(e);
+                }
+            };
+
+            if chunked {
+                w.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                    .await?;
+                w.write_all(&chunk).await?;
+                w.write_all(b"\r\n").await?;
+            } else {
+                w.write_all(&chunk).await?;
+            }
+        }
+
+        if chunked {
+            w.write_all(b"0\r\n\r\n").await?;
+        }
+        w.flush().await
+    }
+
+    /// Write the response head to `w`, and return a `StreamingResponse` to write the body
+    /// with, as chunks. Any body set on the response is ignored.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hype::{handler::{self, AsyncWriteStream}, request::Request, response::Response, status};
+    ///
+    /// async fn handle(_r: &Request, w: &mut dyn AsyncWriteStream) -> Result<handler::Action, handler::Error> {
+    ///     let failed = |e: std::io::Error| handler::Error::Failed(e.to_string());
+    ///     let mut stream = Response::new(status::OK).stream(w).await.map_err(failed)?;
+    ///     for line in ["one\n", "two\n"] {
+    ///         stream.write(line).await.map_err(failed)?;
+    ///     }
+    ///     stream.finish().await.map_err(failed)?;
+    ///     Ok(handler::Action::Done)
+    /// }
+    /// ```
+    pub async fn stream(
+        mut self,
+        w: &mut dyn AsyncWriteStream,
+    ) -> io::Result<StreamingResponse<'_>> {
+        self.headers.remove("content-length");
+        self.headers.set("Transfer-Encoding", "chunked");
+
+        let head = format!(
+            "{}\r\n{}\r\n\r\n",
+            self.serialize_status(),
+            self.headers.serialize()
+        );
+        w.write_all(head.as_bytes()).await?;
+        w.flush().await?;
+
+        Ok(StreamingResponse { w })
+    }
+
+    async fn write_chunked_to(&mut self, w: &mut dyn AsyncWriteStream) -> io::Result<()> {
+        self.headers.remove("content-length");
+        self.headers.set("Transfer-Encoding", "chunked");
+
+        let head = format!(
+            "{}\r\n{}\r\n\r\n",
+            self.serialize_status(),
+            self.headers.serialize()
+        );
+        w.write_all(head.as_bytes()).await?;
+        w.flush().await?;
+
+        // The raw stream frames each chunk, and ends with the last chunk and trailers.
+        let mut stream = self.body.raw_stream();
+        while let Some(chunk) = stream.next().await {
+            w.write_all(&chunk).await?;
+            w.flush().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The body of a response that's written as it's produced, as chunks. See `Response::stream`.
+/// Dropping it without calling `finish` leaves the body incomplete, so the client can tell
+/// it was cut short.
+pub struct StreamingResponse<'a> {
+    w: &'a mut dyn AsyncWriteStream,
+}
+
+impl<'a> StreamingResponse<'a> {
+    /// Send `data` as a chunk, right away. Empty data isn't sent, since an empty chunk would
+    /// end the body.
+    pub async fn write(&mut self, data: impl AsRef<[u8]>) -> io::Result<()> {
+        let data = data.as_ref();
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let mut chunk = format!("{:x}\r\n", data.len()).into_bytes();
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(b"\r\n");
+        self.w.write_all(&chunk).await?;
+        self.w.flush().await
+    }
+
+    /// End the body.
+    pub async fn finish(self) -> io::Result<()> {
+        self.w.write_all(b"0\r\n\r\n").await?;
+        self.w.flush().await
     }
 }
@@ -4,10 +4,16 @@ use std::{
     sync::{Arc, RwLock},
 };
 
+use async_trait::async_trait;
+use regex::Regex;
+
 use crate::{
     handler::{self, AsyncWriteStream, Handler},
     handlers,
-    request::{Method, Request},
+    headers::parse_host,
+    request::{Method, Request, METHODS_AS_STR},
+    response::Response,
+    status,
 };
 
 /// This is a wrapper around Handler that allows us easily clone and use them
@@ -40,6 +46,25 @@ impl<T: Handler + 'static> From<T> for RouteHandler {
     }
 }
 
+/// How the router treats trailing slashes in request paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    /// `/foo` and `/foo/` only match routes with the same trailing slash, e.g., `/foo/`
+    /// doesn't match a `/foo` route. Prefix matches (e.g., `/foo/bar/` for `/foo`) and
+    /// wildcard routes are unaffected.
+    Strict,
+
+    /// Redirect requests without a trailing slash to the same path with one.
+    RedirectToSlash,
+
+    /// Redirect requests with a trailing slash to the same path without one.
+    RedirectToNoSlash,
+
+    /// `/foo` and `/foo/` match the same routes.
+    #[default]
+    Ignore,
+}
+
 /// This is the main router struct. It holds a list of routes and their handlers, and
 /// finds the best handler for a given request based on the longest matching route.
 ///
@@ -49,6 +74,11 @@ pub struct Router {
     /// List of routes and their handlers.
     handlers: Arc<RwLock<Vec<(Matcher, RouteHandler)>>>,
     pub default_handler: RouteHandler,
+
+    /// Respond to OPTIONS requests that no handler accepts.
+    auto_options: bool,
+
+    trailing_slash: TrailingSlash,
 }
 
 impl Clone for Router {
@@ -56,6 +86,8 @@ impl Clone for Router {
         Router {
             handlers: Arc::clone(&self.handlers),
             default_handler: self.default_handler.clone(),
+            auto_options: self.auto_options,
+            trailing_slash: self.trailing_slash,
         }
     }
 }
@@ -65,15 +97,46 @@ impl Router {
         Router {
             handlers: Arc::new(RwLock::new(Vec::new())),
             default_handler: RouteHandler::new(Box::new(handlers::status::NotFoundHandler())),
+            auto_options: true,
+            trailing_slash: TrailingSlash::default(),
         }
     }
 
+    /// If enabled (the default), OPTIONS requests to a registered path that no handler accepts
+    /// get a `200 OK` with an `Allow` header listing the methods registered for the path.
+    pub fn set_auto_options(&mut self, enabled: bool) {
+        self.auto_options = enabled;
+    }
+
+    /// Set how trailing slashes in request paths are handled. The default is
+    /// `TrailingSlash::Ignore`. Redirects are permanent (301), and keep the query string.
+    pub fn set_trailing_slash(&mut self, mode: TrailingSlash) {
+        self.trailing_slash = mode;
+    }
+
     /// Associate a handler with a route.
     pub fn add_route(&self, matcher: Matcher, handler: impl Into<RouteHandler>) {
         let mut handlers = self.handlers.write().unwrap();
         handlers.push((matcher, handler.into()));
-        // Sort by matcher length, so that the longest matchers are checked first.
-        handlers.sort_by(|a, b| a.0.len().cmp(&b.0.len()));
+        // Sort by matcher length, so that the last matching route is the longest. Matchers
+        // for specific hosts sort after others of the same length, so they take precedence,
+        // then matchers with more literal segments, so `/users/me` wins over `/users/:id`.
+        // Regex matchers sort before others of the same length, so they don't.
+        handlers.sort_by_key(|a| {
+            (
+                a.0.len(),
+                a.0.host.is_some(),
+                a.0.literal_segments(),
+                a.0.regex.is_none(),
+            )
+        });
+    }
+
+    /// Mount `router` at `prefix`. Requests under `prefix` are routed by `router`, with the
+    /// prefix stripped, e.g., a `/ping` route in a router mounted at `/api` handles requests
+    /// to `/api/ping`.
+    pub fn mount(&self, prefix: impl Into<String>, router: Router) {
+        self.add_route(Matcher::new(prefix), router);
     }
 
     pub async fn handle(
@@ -81,37 +144,179 @@ impl Router {
         r: &mut Request,
         w: &mut dyn AsyncWriteStream,
     ) -> Result<handler::Action, handler::Error> {
-        let path = r.url.as_ref().unwrap().path();
+        if let Some(to) = self.trailing_slash_redirect(r) {
+            info!("Redirecting {} to {}", r.abs_path(), to);
+            return Ok(handler::Action::Redirect(to));
+        }
+
+        // Routers mounted in other routers match the path relative to the mount point.
+        let base = r.handler_path.clone().unwrap_or_default();
+        let base = base.trim_end_matches('/');
+        let path = r.path();
 
-        let mut h = self.default_handler.clone();
+        // Requests for the mount point itself (e.g., `/api` for a router mounted at `/api`)
+        // are routed as `/`, but have nothing more to strip.
+        let at_mount_point = path.is_empty() && r.handler_path.is_some();
+        let path = if path.is_empty() {
+            "/".to_string()
+        } else {
+            path
+        };
+        let host = r.headers.get_first("host").cloned();
+
+        let mut h = None;
+        let mut matched = None;
+
+        // Methods registered for the path, in case no handler accepts the request method.
+        let mut allowed: Vec<Method> = vec![];
 
         // Go through our route handlers ands see if any of them match the request path. The routes
-        // are sorted by length, so the first match is the longest match.
+        // are sorted by length, so the last match is the longest match.
         for handler in self.handlers.read().unwrap().iter() {
-            if let Some((matched_path, params)) = handler.0.extract_params(&path, Some(r.method)) {
-                r.handler_path = Some(String::from(matched_path.to_string_lossy()));
-                r.params = params
-                    .iter()
-                    .map(|(k, v)| (k.to_string(), v.to_string()))
-                    .collect();
-                h = handler.1.clone();
+            if !handler.0.matches_host(host.as_deref()) {
+                continue;
+            }
+
+            if let Some((matched_path, params)) = handler.0.match_path(&path) {
+                if self.trailing_slash == TrailingSlash::Strict
+                    && !handler.0.matches_trailing_slash(&path, &matched_path)
+                {
+                    continue;
+                }
+
+                if !handler.0.matches_method(Some(r.method)) {
+                    for method in &handler.0.methods {
+                        if !allowed.contains(method) {
+                            allowed.push(*method);
+                        }
+                    }
+                    continue;
+                }
+
+                let handler_path = if at_mount_point {
+                    r.handler_path.clone().unwrap_or_default()
+                } else {
+                    format!("{}{}", base, matched_path.to_string_lossy())
+                };
+
+                matched = Some((
+                    handler_path,
+                    params
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect::<Vec<_>>(),
+                ));
+                h = Some(handler.1.clone());
+            }
+        }
+
+        if let Some((handler_path, params)) = matched {
+            r.handler_path = Some(handler_path);
+            r.params.extend(params);
+        }
+
+        match h {
+            Some(h) => h.handler().read().await.handle(r, w).await,
+            None if !allowed.is_empty() && self.auto_options && r.method == Method::OPTIONS => {
+                Ok(handler::Action::Response(Self::options(&allowed)))
+            }
+            None if !allowed.is_empty() => Ok(handler::Action::Response(Self::method_not_allowed(
+                &allowed,
+            ))),
+            None => {
+                self.default_handler
+                    .handler()
+                    .read()
+                    .await
+                    .handle(r, w)
+                    .await
             }
         }
+    }
+
+    /// Returns the location to redirect `r` to, if its path doesn't have the trailing slash
+    /// required by the redirect modes.
+    fn trailing_slash_redirect(&self, r: &Request) -> Option<String> {
+        let url = r.url.as_ref().unwrap();
+        let path = url.path();
+
+        let to = match self.trailing_slash {
+            TrailingSlash::RedirectToSlash if !path.ends_with('/') => format!("{}/", path),
+            TrailingSlash::RedirectToNoSlash if path.len() > 1 && path.ends_with('/') => {
+                match path.trim_end_matches('/') {
+                    "" => "/".to_string(),
+                    path => path.to_string(),
+                }
+            }
+            _ => return None,
+        };
+
+        Some(match url.query() {
+            Some(query) => format!("{}?{}", to, query),
+            None => to,
+        })
+    }
+
+    /// Returns a response to an OPTIONS request listing the `allowed` methods.
+    fn options(allowed: &[Method]) -> Response {
+        let mut allowed = allowed.to_vec();
+        allowed.push(Method::OPTIONS);
+
+        let mut response = Response::new(status::OK);
+        response.headers.set("Allow", allow_header(&allowed));
+        response.headers.set("Content-Length", "0");
+        response
+    }
 
-        h.handler().read().await.handle(r, w).await
+    /// Returns a 405 response listing the `allowed` methods.
+    fn method_not_allowed(allowed: &[Method]) -> Response {
+        let mut response = Response::new(status::METHOD_NOT_ALLOWED);
+        response.headers.set("Allow", allow_header(allowed));
+        response.headers.set("Content-Type", "text/plain");
+        response.set_body("405 Method Not Allowed");
+        response
     }
 }
 
+/// Format `methods` as the value of an `Allow` header.
+fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(|m| METHODS_AS_STR[m])
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
 impl Default for Router {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Routers are handlers too, so they can be mounted in other routers.
+#[async_trait]
+impl Handler for Router {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let mut r = r.clone();
+        Router::handle(self, &mut r, w).await
+    }
+}
+
 #[derive(Debug)]
 pub struct Matcher {
     pub pattern: PathBuf,
     pub methods: Vec<Method>,
+
+    /// If set, the full path must match this regex instead of `pattern`.
+    pub regex: Option<Regex>,
+
+    /// Match only requests for this host (e.g., `example.com`), or its subdomains if it
+    /// starts with `*.` (e.g., `*.example.com`).
+    pub host: Option<String>,
 }
 
 /// Matches a URL path to a specified routing pattern. Returns the path
@@ -122,9 +327,29 @@ impl Matcher {
         Matcher {
             pattern: Path::new(&pattern.into()).into(),
             methods: vec![],
+            regex: None,
+            host: None,
         }
     }
 
+    /// Returns a matcher that matches the full request path against the regex `pattern`.
+    /// Named capture groups are returned as route parameters, e.g., `/users/(?P<id>\d+)`
+    /// matches `/users/42` with the parameter `id` set to `42`.
+    pub fn regex(pattern: impl AsRef<str>) -> Result<Matcher, regex::Error> {
+        let regex = Regex::new(&format!("^(?:{})$", pattern.as_ref()))?;
+        let mut matcher = Matcher::new("");
+        matcher.regex = Some(regex);
+        Ok(matcher)
+    }
+
+    /// Match only requests whose `Host` header matches `pattern`, which is either a host
+    /// name, or a glob like `*.example.com`. Ports are ignored, and matching is case
+    /// insensitive.
+    pub fn with_host(mut self, pattern: impl Into<String>) -> Matcher {
+        self.host = Some(pattern.into().to_lowercase());
+        self
+    }
+
     /// Match only if the request method is the specified method.
     pub fn push_method(&mut self, method: Method) {
         self.methods.push(method)
@@ -135,9 +360,30 @@ impl Matcher {
         self.methods.extend(methods)
     }
 
-    // If there are specific methods to match against, then prioritize this matcher higher.
+    // If there are specific methods to match against, then prioritize this matcher higher. Regex
+    // matchers are as long as a pattern with the same number of slashes.
     pub fn len(&self) -> usize {
-        self.pattern.components().count() + if self.methods.is_empty() { 0 } else { 1 }
+        let len = match &self.regex {
+            // Patterns have a root component, e.g., `/users/:id` has three components.
+            Some(regex) => regex.as_str().matches('/').count() + 1,
+            None => self.pattern.components().count(),
+        };
+
+        len + if self.methods.is_empty() { 0 } else { 1 }
+    }
+
+    /// Returns the number of segments in the pattern that aren't params or wildcards. Regex
+    /// matchers have none.
+    fn literal_segments(&self) -> usize {
+        if self.regex.is_some() {
+            return 0;
+        }
+
+        self.pattern
+            .components()
+            .filter_map(|c| c.as_os_str().to_str())
+            .filter(|c| !c.starts_with([':', '*']) && *c != "/")
+            .count()
     }
 
     #[must_use]
@@ -145,11 +391,83 @@ impl Matcher {
         self.len() == 0
     }
 
+    /// Returns the matched path and parameters if the path, the method, and the host (i.e.,
+    /// the value of the request's `Host` header) match. In patterns, `:name` captures a single
+    /// path component, and a trailing `*name` captures the rest of the path, e.g., `/static/*path`
+    /// matches `/static/css/app.css` with the parameter `path` set to `css/app.css`.
     pub fn extract_params<'a, T: AsRef<str> + ?Sized>(
         &'a self,
         route: &'a T,
         method: Option<Method>,
+        host: Option<&str>,
+    ) -> Option<(PathBuf, HashMap<&'a str, &'a str>)> {
+        if !self.matches_method(method) || !self.matches_host(host) {
+            return None;
+        }
+
+        self.match_path(route)
+    }
+
+    /// Returns true if this matcher accepts requests with the `Host` header `host`. Matchers
+    /// without a host accept every request.
+    pub fn matches_host(&self, host: Option<&str>) -> bool {
+        let Some(pattern) = &self.host else {
+            return true;
+        };
+        let Some((host, _)) = host.and_then(parse_host) else {
+            return false;
+        };
+
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+            None => &host == pattern,
+        }
+    }
+
+    /// Returns true if this matcher accepts `method`. Matchers without specific methods
+    /// accept every method.
+    pub fn matches_method(&self, method: Option<Method>) -> bool {
+        self.methods.is_empty() || method.is_some_and(|m| self.methods.contains(&m))
+    }
+
+    /// Returns true if `path`, which this matcher matched as `matched_path`, has a trailing
+    /// slash exactly when the pattern does. Only full matches of non-wildcard patterns are
+    /// checked, everything else returns true.
+    pub fn matches_trailing_slash(&self, path: &str, matched_path: &Path) -> bool {
+        let pattern = self.pattern.to_string_lossy();
+        let wildcard = self
+            .pattern
+            .components()
+            .next_back()
+            .is_some_and(|c| c.as_os_str().to_string_lossy().starts_with('*'));
+        if self.regex.is_some()
+            || wildcard
+            || Path::new(path).components().count() != matched_path.components().count()
+        {
+            return true;
+        }
+
+        let has_slash = |s: &str| s.len() > 1 && s.ends_with('/');
+        has_slash(path) == has_slash(&pattern)
+    }
+
+    /// Same as `extract_params`, but ignores the request method.
+    pub fn match_path<'a, T: AsRef<str> + ?Sized>(
+        &'a self,
+        route: &'a T,
     ) -> Option<(PathBuf, HashMap<&'a str, &'a str>)> {
+        if let Some(regex) = &self.regex {
+            let captures = regex.captures(route.as_ref())?;
+            let params = regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| Some((name, captures.name(name)?.as_str())))
+                .collect();
+            return Some((PathBuf::from(route.as_ref()), params));
+        }
+
         let pattern = &self.pattern;
         let mut path_i = Path::new(route.as_ref()).components();
         let mut patt_i = pattern.components();
@@ -158,11 +476,24 @@ impl Matcher {
         let mut matched_path = PathBuf::new();
 
         loop {
+            // The rest of the path, including the next component.
+            let rest = path_i.as_path();
             let path = path_i.next();
             let patt = patt_i.next();
 
             debug!("Matching pattern {:?} against path {:?}", patt, path);
 
+            if let Some(name) = patt
+                .and_then(|patt| patt.as_os_str().to_str())
+                .and_then(|patt| patt.strip_prefix('*'))
+                .filter(|name| !name.is_empty())
+            {
+                // Greedy wildcards (e.g., `*path`) capture the rest of the path, if any.
+                params.insert(name, rest.to_str().unwrap());
+                matched_path.push(rest);
+                break;
+            }
+
             if let (Some(path), Some(patt)) = (path, patt) {
                 let patt = patt.as_os_str().to_str().unwrap();
 
@@ -192,9 +523,6 @@ impl Matcher {
         }
 
         debug!("Matched path: {:?}", matched_path);
-        if self.methods.is_empty() || self.methods.contains(&method.unwrap()) {
-            return Some((matched_path, params));
-        }
-        None
+        Some((matched_path, params))
     }
 }
@@ -1,42 +1,103 @@
 use async_trait::async_trait;
+use chrono::Utc;
 /// This file implements the main rx/tx logic for the network server.
 use rustls_pemfile::{certs, rsa_private_keys};
 use tokio::io::AsyncReadExt;
 
 use tokio::{
     io::AsyncWriteExt,
-    net::TcpListener,
-    sync::{mpsc, Notify, RwLock},
+    net::{TcpListener, UnixListener},
+    sync::{mpsc, Notify, RwLock, Semaphore},
 };
 
 use tokio_rustls::{
-    rustls::{self, Certificate, PrivateKey},
+    rustls::{
+        self,
+        server::{ClientHello, ResolvesServerCert, ResolvesServerCertUsingSni},
+        sign::{self, CertifiedKey},
+        Certificate, PrivateKey,
+    },
     TlsAcceptor,
 };
 
 use crate::handler::{self, AsyncWriteStream, ErrorHandler};
+use crate::handlers::conditional::http_date;
 use crate::headers::Headers;
-use crate::parser::RequestParser;
-use crate::request::Method;
-use crate::router::{RouteHandler, Router};
+use crate::injector;
+use crate::parser::{ParseError, RequestParser};
+use crate::proxy_protocol;
+use crate::request::{Method, METHODS_AS_STR};
+use crate::router::{RouteHandler, Router, TrailingSlash};
 use crate::{
     conntrack::{Conn, ConnTracker},
     handler::AsyncStream,
+    metrics::{Metrics, MetricsHandler},
     request::Request,
     response::Response,
     router::Matcher,
     status,
 };
 
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::{
     fs::File,
     io,
     path::{Path, PathBuf},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+/// How long to wait for the PROXY protocol header on new connections.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to keep reading (and discarding) the request on rejected connections, so the
+/// client sees the 503 rather than a reset.
+const REJECT_LINGER: Duration = Duration::from_secs(1);
+
+/// The log target of the lines emitted when tracing is enabled, see `Server::enable_tracing`.
+pub const TRACE_TARGET: &str = "hype::trace";
+
+/// The default `Server` header of responses, see `Server::set_server_header`.
+pub const SERVER_HEADER: &str = concat!("hype/", env!("CARGO_PKG_VERSION"));
+
+/// Decides whether requests with `Expect: 100-continue` may send their bodies. See
+/// `Server::check_continue`.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+struct ContinueCheck(Arc<dyn Fn(&Request) -> bool + Send + Sync>);
+
+impl std::fmt::Debug for ContinueCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ContinueCheck")
+    }
+}
+
+/// What a request expects before it sends its body.
+#[derive(Debug, PartialEq, Eq)]
+enum Expectation {
+    /// Nothing, the body follows the headers.
+    None,
+
+    /// The client waits for a `100 Continue` before sending the body.
+    Continue,
+
+    /// The expectation can't be met, respond with `417 Expectation Failed`.
+    Failed,
+}
+
+/// What the server does with new connections once it's at its connection limit (see
+/// `Server::max_concurrent_connections`.)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverloadPolicy {
+    /// Stop accepting connections until an open one closes. New clients wait in the
+    /// listen backlog.
+    #[default]
+    Wait,
+
+    /// Accept new connections, respond with a 503 Service Unavailable, and close them.
+    Reject,
+}
+
 /// This is the main server struct. It holds all the configuration state for the socket listener.
 #[derive(Debug)]
 pub struct Server {
@@ -44,6 +105,9 @@ pub struct Server {
     port: u16,
     base_url: String,
 
+    /// If set, listen on this Unix domain socket instead of address:port.
+    unix_path: Option<PathBuf>,
+
     /// These handlers are called based on the request path. Every handler here
     /// has a corresponding matcher to determine if it should be called.
     router: Router,
@@ -65,10 +129,58 @@ pub struct Server {
     shutdown_tx: Arc<mpsc::Sender<bool>>,
     shutdown_rx: mpsc::Receiver<bool>,
 
+    /// Proxies whose X-Forwarded-For headers are trusted to report the client address.
+    trusted_proxies: Arc<Vec<IpAddr>>,
+
+    /// The maximum size of request bodies, if any.
+    max_body_bytes: Option<usize>,
+
+    /// If true, expect a PROXY protocol header at the start of each connection.
+    proxy_protocol: bool,
+
+    /// If true, log the beginning and end of every connection and request.
+    tracing: bool,
+
+    /// If set, only requests that pass this check get a `100 Continue`.
+    continue_check: Option<ContinueCheck>,
+
+    /// If set, request counters are kept here. See `enable_metrics`.
+    metrics: Option<Arc<Metrics>>,
+
+    /// If true, close connections whose responses don't match their Content-Length.
+    strict_responses: bool,
+
+    /// The `Server` header added to responses, if any.
+    server_header: Option<String>,
+
+    /// If set, wait up to this long for open connections to finish their current
+    /// request on shutdown.
+    grace_period: Option<Duration>,
+
+    /// If set, close connections that are idle this long between requests, unless the
+    /// client negotiated its own keep-alive timeout.
+    idle_timeout: Option<Duration>,
+
+    /// If set, close connections after this many requests, unless the client negotiated its
+    /// own maximum.
+    keepalive_max: Option<usize>,
+
+    /// If set, close connections that take longer than this to send the headers of a
+    /// request, timed from its first byte.
+    header_timeout: Option<Duration>,
+
+    /// If set, each open connection holds one of these permits.
+    connection_limit: Option<Arc<Semaphore>>,
+    overload_policy: OverloadPolicy,
+
     /// TLS configuration
     enable_tls: bool,
-    cert_file: PathBuf,
-    key_file: PathBuf,
+    cert_file: Option<PathBuf>,
+    key_file: Option<PathBuf>,
+
+    /// Per-hostname certificates, selected by the SNI in the TLS ClientHello: (server_name,
+    /// cert_file, key_file).
+    sni_certs: Vec<(String, PathBuf, PathBuf)>,
 }
 
 // Load TLS certs from `path`
@@ -85,6 +197,70 @@ fn load_keys(path: &Path) -> io::Result<Vec<PrivateKey>> {
         .map(|mut keys| keys.drain(..).map(PrivateKey).collect())
 }
 
+// Load a certificate chain and its private key
+fn load_certified_key(cert_file: &Path, key_file: &Path) -> io::Result<CertifiedKey> {
+    let certs = load_certs(cert_file)?;
+    let key = load_keys(key_file)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no keys found"))?;
+    let key = sign::any_supported_type(&key)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "unsupported key"))?;
+
+    Ok(CertifiedKey::new(certs, key))
+}
+
+/// The socket that the server accepts connections on.
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Accept a connection, returning the stream and the peer's address. Unix sockets
+    /// don't have peer addresses.
+    async fn accept(&self) -> io::Result<(Box<dyn AsyncStream>, Option<SocketAddr>)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (socket, peer_addr) = listener.accept().await?;
+                Ok((Box::new(socket), Some(peer_addr)))
+            }
+            Listener::Unix(listener) => {
+                let (socket, _) = listener.accept().await?;
+                Ok((Box::new(socket), None))
+            }
+        }
+    }
+}
+
+// Remove a stale Unix socket file at `path`, e.g., left behind by a crashed server. Other
+// kinds of files are left alone (and will make bind fail.)
+fn remove_socket_file(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Picks the certificate for the server name requested by the client (SNI), falling back
+/// to the default certificate, if any.
+struct CertResolver {
+    by_name: ResolvesServerCertUsingSni,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.by_name
+            .resolve(client_hello)
+            .or_else(|| self.default.clone())
+    }
+}
+
 /// This is the default error handler, which is called when a route handler returns an error.
 #[derive(Debug, Clone)]
 struct DefaultErrorHandler;
@@ -115,14 +291,17 @@ impl ErrorHandler for DefaultErrorHandler {
             Ok(handler::Action::Done) => Ok(handler::Action::Done),
             Ok(handler::Action::Next) => Ok(handler::Action::Next),
             Ok(handler::Action::Response(mut response)) => {
-                w.write_all(response.serialize().as_bytes()).await.or(Err(
-                    handler::Error::Failed("could not write to stream".into()),
-                ))?;
+                response.write_to(w).await.or(Err(handler::Error::Failed(
+                    "could not write to stream".into(),
+                )))?;
                 Ok(handler::Action::Done)
             }
             Ok(handler::Action::Redirect(to)) => {
-                let mut response = Response::new(status::MOVED_PERMANENTLY);
-                response.headers.set("Location", to);
+                self.handle(_r, w, Ok(handler::Action::RedirectWith(to, 301)))
+                    .await
+            }
+            Ok(handler::Action::RedirectWith(to, code)) => {
+                let mut response = Response::redirect(to, code);
                 w.write_all(response.serialize().as_bytes()).await.or(Err(
                     handler::Error::Failed("could not write to stream".into()),
                 ))?;
@@ -169,6 +348,7 @@ impl Server {
         Self {
             address: address.into(),
             port,
+            unix_path: None,
             router: Router::new(),
             error_handler: Arc::new(RwLock::new(Box::new(DefaultErrorHandler {}))),
             base_url,
@@ -177,17 +357,153 @@ impl Server {
             done_notifier: Arc::new(Notify::new()),
             shutdown_tx: Arc::new(tx),
             shutdown_rx: rx,
+            grace_period: None,
+            idle_timeout: None,
+            keepalive_max: None,
+            header_timeout: None,
+            connection_limit: None,
+            overload_policy: OverloadPolicy::default(),
+            trusted_proxies: Arc::new(vec![]),
+            proxy_protocol: false,
+            tracing: false,
+            continue_check: None,
+            metrics: None,
+            strict_responses: false,
+            server_header: Some(SERVER_HEADER.into()),
+            max_body_bytes: None,
             enable_tls: false,
-            cert_file: PathBuf::from("localhost.crt"),
-            key_file: PathBuf::from("localhost.key"),
+            cert_file: None,
+            key_file: None,
+            sni_certs: vec![],
         }
     }
 
-    /// Enable TLS on the server using the given certificate and key files.
+    /// Create a new server instance listening on the Unix domain socket at `path`. A stale
+    /// socket file at `path` is replaced, and the socket file is removed on shutdown.
+    pub fn new_unix(path: impl Into<PathBuf>) -> Self {
+        let mut server = Self::new("localhost", 0);
+        server.unix_path = Some(path.into());
+        server.base_url = "http://localhost".into();
+        server
+    }
+
+    /// Enable TLS on the server using the given certificate and key files. If certificates are
+    /// also added with `add_tls_cert`, this is the fallback for clients that request some other
+    /// server name (or none at all.)
     pub fn enable_tls(&mut self, cert_file: PathBuf, key_file: PathBuf) {
         self.enable_tls = true;
-        self.cert_file = cert_file;
-        self.key_file = key_file;
+        self.cert_file = Some(cert_file);
+        self.key_file = Some(key_file);
+    }
+
+    /// Enable TLS, and serve the given certificate and key files to clients that request
+    /// `server_name` via SNI. Call this once per virtual host.
+    pub fn add_tls_cert(
+        &mut self,
+        server_name: impl Into<String>,
+        cert_file: PathBuf,
+        key_file: PathBuf,
+    ) {
+        self.enable_tls = true;
+        self.sni_certs
+            .push((server_name.into(), cert_file, key_file));
+    }
+
+    /// Trust the X-Forwarded-For headers on requests from `proxies` (e.g., the load balancer),
+    /// so `Request::peer_addr` reports the original client address instead of the proxy's. The
+    /// forwarded address is the rightmost one that isn't a trusted proxy, and has port 0.
+    pub fn trust_forwarded_for(&mut self, proxies: Vec<IpAddr>) {
+        self.trusted_proxies = Arc::new(proxies);
+    }
+
+    /// Reject requests with bodies larger than `max` bytes with a 413 Payload Too Large. The
+    /// connection is closed afterwards.
+    pub fn set_max_body_bytes(&mut self, max: usize) {
+        self.max_body_bytes = Some(max);
+    }
+
+    /// Close connections that are idle for longer than `timeout` between requests (or before
+    /// the first one.) Clients can ask for a shorter timeout with a `Keep-Alive: timeout=N`
+    /// header, but not a longer one.
+    pub fn idle_timeout(&mut self, timeout: Duration) {
+        self.idle_timeout = Some(timeout);
+    }
+
+    /// Set the keep-alive policy for connections: close them after they're idle for `timeout`
+    /// (as with `idle_timeout`), or after `max` requests. Clients can negotiate lower limits
+    /// (with a `Keep-Alive: timeout=N, max=M` header), but not higher ones. Responses advertise
+    /// the policy in a `Keep-Alive` header, with the number of requests left on the connection,
+    /// and the response to the last request closes the connection.
+    pub fn default_keepalive(&mut self, timeout: Duration, max: usize) {
+        self.idle_timeout = Some(timeout);
+        self.keepalive_max = Some(max);
+    }
+
+    /// Close connections that don't send the full headers of a request within `timeout` of
+    /// its first byte. This protects against clients that hold connections open by sending
+    /// headers very slowly (i.e., slowloris attacks.)
+    pub fn header_timeout(&mut self, timeout: Duration) {
+        self.header_timeout = Some(timeout);
+    }
+
+    /// Limit the number of connections served at once to `max`. What happens to connections
+    /// beyond the limit depends on the overload policy, see `set_overload_policy`.
+    pub fn max_concurrent_connections(&mut self, max: usize) {
+        self.connection_limit = Some(Arc::new(Semaphore::new(max)));
+    }
+
+    /// Set what to do with new connections when the server is at its connection limit. The
+    /// default is `OverloadPolicy::Wait`.
+    pub fn set_overload_policy(&mut self, policy: OverloadPolicy) {
+        self.overload_policy = policy;
+    }
+
+    /// Expect connections to start with a PROXY protocol (v1) header, as sent by L4 load
+    /// balancers, and report the client address from it in `Request::peer_addr`. Connections
+    /// without a valid header are closed.
+    pub fn enable_proxy_protocol(&mut self, enable: bool) {
+        self.proxy_protocol = enable;
+    }
+
+    /// Log a line (with the target `TRACE_TARGET`) when each connection and request begins
+    /// and ends. The lines carry key-values for the connection ID, method, path, request ID,
+    /// response status, and duration, so logs from handlers can be correlated with them via
+    /// the connection ID, which is in the `X-Hype-Connection-ID` request header.
+    pub fn enable_tracing(&mut self, enable: bool) {
+        self.tracing = enable;
+    }
+
+    /// Decide which requests with an `Expect: 100-continue` header may send their bodies.
+    /// Requests that `check` rejects get a `417 Expectation Failed`, and the connection is
+    /// closed. By default, all of them get a `100 Continue` as soon as their headers are
+    /// parsed, so clients that wait for it don't stall.
+    pub fn check_continue(&mut self, check: impl Fn(&Request) -> bool + Send + Sync + 'static) {
+        self.continue_check = Some(ContinueCheck(Arc::new(check)));
+    }
+
+    /// Count requests, responses by status class, and request durations, and serve them
+    /// (along with the number of open connections) at `path` in the Prometheus text format.
+    pub fn enable_metrics(&mut self, path: impl Into<String>) {
+        let metrics = Arc::new(Metrics::new());
+        self.route(
+            path,
+            MetricsHandler::new(Arc::clone(&metrics), Arc::clone(&self.conn_tracker)),
+        );
+        self.metrics = Some(metrics);
+    }
+
+    /// Check that response bodies are as long as their `Content-Length` headers say. A
+    /// mismatch breaks the framing of the connection, so if `strict` is set, the response is
+    /// failed, and the connection is closed. Otherwise, mismatches are only logged, and only
+    /// in debug builds.
+    pub fn strict_responses(&mut self, strict: bool) {
+        self.strict_responses = strict;
+    }
+
+    /// Set the `Server` header added to responses that don't have one, or don't add it if
+    /// `None`. Defaults to `SERVER_HEADER`.
+    pub fn set_server_header(&mut self, server_header: Option<String>) {
+        self.server_header = server_header;
     }
 
     /// Set the base URL for the server. This is used to generate the path and location information.
@@ -225,11 +541,39 @@ impl Server {
         self.router.add_route(matcher, handler);
     }
 
+    /// Add a handler for requests to the given host (i.e., with a matching `Host` header.)
+    /// `host` can be a glob like `*.example.com`, to match all subdomains.
+    pub fn route_host(
+        &self,
+        host: impl Into<String>,
+        path: impl Into<String>,
+        handler: impl Into<RouteHandler>,
+    ) {
+        self.router
+            .add_route(Matcher::new(path.into()).with_host(host), handler);
+    }
+
+    /// Add a handler for requests that match `matcher`, e.g., a `Matcher::regex`.
+    pub fn route_matcher(&self, matcher: Matcher, handler: impl Into<RouteHandler>) {
+        self.router.add_route(matcher, handler);
+    }
+
     /// Set the default handler for the server. This is called if n.o other handlers match the request.
     pub fn route_default(&mut self, handler: impl Into<RouteHandler>) {
         self.router.default_handler = handler.into();
     }
 
+    /// Set how trailing slashes in request paths are handled. See `TrailingSlash`.
+    pub fn set_trailing_slash(&mut self, mode: TrailingSlash) {
+        self.router.set_trailing_slash(mode);
+    }
+
+    /// Automatically respond to OPTIONS requests on registered paths with the allowed methods.
+    /// This is enabled by default. Disable it to handle OPTIONS requests in your own handlers.
+    pub fn enable_auto_options(&mut self, enabled: bool) {
+        self.router.set_auto_options(enabled);
+    }
+
     /// Set the error handler for the server. This is called if any handler returns an error.
     pub fn route_error(&mut self, handler: Box<dyn ErrorHandler>) {
         self.error_handler = Arc::new(RwLock::new(handler));
@@ -240,6 +584,12 @@ impl Server {
         Arc::clone(&self.start_notifier)
     }
 
+    /// Get a reference to the connection tracker, e.g., to inspect open connections with
+    /// `ConnTracker::stats` while the server runs.
+    pub fn conn_tracker(&self) -> Arc<RwLock<ConnTracker>> {
+        Arc::clone(&self.conn_tracker)
+    }
+
     /// Get a reference to a shutdown channel, and a done notifier. The shutdown channel is used to
     /// signal the server to shutdown. The done notifier is used to notify the user that the server
     /// has stopped.
@@ -250,30 +600,83 @@ impl Server {
         )
     }
 
+    /// Same as `shutdown`, but shuts down gracefully: the server stops accepting new connections,
+    /// and waits up to `grace` for open connections to finish their current request before
+    /// closing them. The done notifier is notified once all connections are closed, or the
+    /// grace period expires.
+    pub fn shutdown_graceful(&mut self, grace: Duration) -> (Arc<mpsc::Sender<bool>>, Arc<Notify>) {
+        self.grace_period = Some(grace);
+        self.shutdown()
+    }
+
+    /// Stop accepting requests, and wait for the open connections to close.
+    async fn drain(&self, grace: Duration) {
+        let conn_tracker = self.conn_tracker.read().await;
+        info!(
+            "Draining {} connections (grace period {:?})...",
+            conn_tracker.active_conns(),
+            grace
+        );
+        conn_tracker.drain();
+
+        if tokio::time::timeout(grace, conn_tracker.wait_idle())
+            .await
+            .is_err()
+        {
+            warn!(
+                "Grace period expired with {} open connections",
+                conn_tracker.active_conns()
+            );
+        }
+    }
+
     /// Start the server. This will block until the server is shutdown.
     pub async fn start(&mut self) -> Result<(), String> {
         let mut acceptor = None;
 
         if self.enable_tls {
             info!("Loading TLS certificates...");
-            let certs = load_certs(&self.cert_file).map_err(|e| e.to_string())?;
-            let mut keys = load_keys(&self.key_file).map_err(|e| e.to_string())?;
+            let mut resolver = CertResolver {
+                by_name: ResolvesServerCertUsingSni::new(),
+                default: None,
+            };
+
+            if let (Some(cert_file), Some(key_file)) = (&self.cert_file, &self.key_file) {
+                let key = load_certified_key(cert_file, key_file).map_err(|e| e.to_string())?;
+                resolver.default = Some(Arc::new(key));
+            }
+
+            for (server_name, cert_file, key_file) in &self.sni_certs {
+                let key = load_certified_key(cert_file, key_file).map_err(|e| e.to_string())?;
+                resolver
+                    .by_name
+                    .add(server_name, key)
+                    .map_err(|e| format!("{}: {}", server_name, e))?;
+            }
 
             let config = rustls::ServerConfig::builder()
                 .with_safe_defaults()
                 .with_no_client_auth()
-                .with_single_cert(certs, keys.remove(0))
-                .map_err(|e| e.to_string())?;
+                .with_cert_resolver(Arc::new(resolver));
             acceptor = Some(TlsAcceptor::from(Arc::new(config)));
         }
 
         // Start the listener
-        let hostport = format!("{}:{}", self.address, self.port);
-        let listener = TcpListener::bind(&hostport)
-            .await
-            .map_err(|e| e.to_string())?;
+        let listener = if let Some(path) = &self.unix_path {
+            remove_socket_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let listener =
+                UnixListener::bind(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            info!("Listening on {}", path.display());
+            Listener::Unix(listener)
+        } else {
+            let hostport = format!("{}:{}", self.address, self.port);
+            let listener = TcpListener::bind(&hostport)
+                .await
+                .map_err(|e| e.to_string())?;
+            info!("Listening on {}", hostport);
+            Listener::Tcp(listener)
+        };
         let shutdown_notifier = Arc::new(Notify::new());
-        info!("Listening on {}", hostport);
 
         // Let callers know we're ready
         self.start_notifier.notify_one();
@@ -285,19 +688,34 @@ impl Server {
         'top: loop {
             let shutdown_notifier = Arc::clone(&shutdown_notifier);
             let conn_tracker = Arc::clone(&self.conn_tracker);
-            let (tcp_socket, _) = tokio::select! {
+            let accept = async {
+                // Wait for a free slot before accepting, so clients queue in the backlog.
+                let permit = match (&self.connection_limit, self.overload_policy) {
+                    (Some(limit), OverloadPolicy::Wait) => {
+                        Arc::clone(limit).acquire_owned().await.ok()
+                    }
+                    _ => None,
+                };
+                (listener.accept().await, permit)
+            };
+
+            let ((raw_socket, peer_addr), mut permit) = tokio::select! {
                 // Received a connection...
-                result = listener.accept() => {
+                (result, permit) = accept => {
                     if let Err(err) = result {
                         // Don't propagate accept errors, just continue.
-                        debug!("accept error: {}", err.to_string());
+                        debug!("accept error: {}", err);
                         continue 'top;
                     }
-                    result.unwrap()
+                    (result.unwrap(), permit)
                 },
 
                 // Received a shutdown signal...
                 _ = self.shutdown_rx.recv() => {
+                    if let Some(grace) = self.grace_period {
+                        drop(listener);
+                        self.drain(grace).await;
+                    }
                     shutdown_notifier.notify_one();
                     conn_tracker.read().await.shutdown();
                     info!("Shutting down...");
@@ -305,35 +723,53 @@ impl Server {
                 }
             };
 
-            // Got connection, setup a new ConnectedServer from the stream.
-            let peer_addr = tcp_socket
-                .peer_addr()
-                .map_err(|e| format!("peer_addr(): {}", e))?;
-
-            let socket: Box<dyn AsyncStream>;
-
-            // If TLS, wrap the socket in a TLS stream.
-            if let Some(ref acceptor) = acceptor {
-                let acceptor = acceptor.clone();
-                let connection = acceptor.accept(tcp_socket).await;
-                if let Err(err) = connection {
-                    // Don't propagate TLS connection errors, just continue.
-                    debug!("TLS accept error: {}", err.to_string());
-                    continue 'top;
+            // Claim a slot now, so the connection counts against the limit during the
+            // handshake. If there's none, the connection is rejected once it's open.
+            let mut rejected = false;
+            if let (Some(limit), None) = (&self.connection_limit, &permit) {
+                match Arc::clone(limit).try_acquire_owned() {
+                    Ok(p) => permit = Some(p),
+                    Err(_) => {
+                        debug!(
+                            "rejecting connection from {:?}: too many connections",
+                            peer_addr
+                        );
+                        rejected = true;
+                    }
                 }
-                socket = Box::new(connection.unwrap());
-            } else {
-                // No TLS, just use the raw socket.
-                socket = Box::new(tcp_socket);
             }
 
-            let conn = self.conn_tracker.write().await.push_stream(socket);
+            let proxy_protocol = self.proxy_protocol;
+            let acceptor = acceptor.clone();
             let base_url = self.base_url.clone();
             let router = self.router.clone();
             let error_handler = Arc::clone(&self.error_handler);
+            let trusted_proxies = Arc::clone(&self.trusted_proxies);
+            let max_body_bytes = self.max_body_bytes;
+            let idle_timeout = self.idle_timeout;
+            let keepalive_max = self.keepalive_max;
+            let header_timeout = self.header_timeout;
+            let tracing = self.tracing;
+            let continue_check = self.continue_check.clone();
+            let metrics = self.metrics.clone();
+            let strict_responses = self.strict_responses;
+            let server_header = self.server_header.clone();
 
             // Spawn a new task to handle the connection.
             tokio::spawn(async move {
+                // Slow handshakes only hold up this connection, not the accept loop.
+                let Some((socket, peer_addr)) =
+                    open_stream(raw_socket, peer_addr, proxy_protocol, acceptor).await
+                else {
+                    return;
+                };
+
+                if rejected {
+                    reject_connection(socket).await;
+                    return;
+                }
+
+                let conn = conn_tracker.write().await.push_stream(socket);
                 let mut stream = ConnectedServer {
                     conn,
                     peer_addr,
@@ -342,16 +778,59 @@ impl Server {
                     error_handler,
                     shutdown_notifier,
                     conn_tracker,
+                    trusted_proxies,
+                    max_body_bytes,
+                    idle_timeout,
+                    keepalive_max,
+                    header_timeout,
+                    tracing,
+                    continue_check,
+                    metrics,
+                    strict_responses,
+                    server_header,
                     close_connection: false,
                 };
 
+                let id = stream.conn.id().to_string();
+                let opened = Instant::now();
+                if stream.tracing {
+                    info!(
+                        target: TRACE_TARGET,
+                        conn_id = id.as_str();
+                        "begin connection {} from {:?}", id, stream.peer_addr
+                    );
+                }
+
                 if let Err(err) = stream.process_connection().await {
                     warn!("server error: {err}");
                     _ = stream.conn.writer().write().await.shutdown().await;
                 }
+
+                if stream.tracing {
+                    let requests = stream.conn.state.read().unwrap().request_count;
+                    let duration = opened.elapsed();
+                    info!(
+                        target: TRACE_TARGET,
+                        conn_id = id.as_str(),
+                        requests = requests,
+                        duration_us = duration.as_micros() as u64;
+                        "end connection {} after {} requests in {:?}", id, requests, duration
+                    );
+                }
+
+                stream.conn_tracker.read().await.remove(stream.conn.id());
+
+                // Free up the connection slot.
+                drop(permit);
             });
         }
 
+        if let Some(path) = &self.unix_path {
+            if let Err(e) = remove_socket_file(path) {
+                warn!("could not remove {}: {}", path.display(), e);
+            }
+        }
+
         // Let tests know we're done
         self.done_notifier.notify_one();
 
@@ -359,6 +838,72 @@ impl Server {
     }
 }
 
+/// Read the PROXY header from `socket` (if `proxy_protocol` is set), and then complete the
+/// TLS handshake (if there's an `acceptor`.) Returns the stream to serve, and the client's
+/// address, or None if either step fails.
+async fn open_stream(
+    mut socket: Box<dyn AsyncStream>,
+    mut peer_addr: Option<SocketAddr>,
+    proxy_protocol: bool,
+    acceptor: Option<TlsAcceptor>,
+) -> Option<(Box<dyn AsyncStream>, Option<SocketAddr>)> {
+    // Read the PROXY header before anything else (including the TLS handshake.)
+    if proxy_protocol {
+        let header =
+            tokio::time::timeout(PROXY_HEADER_TIMEOUT, proxy_protocol::read_header(socket)).await;
+
+        match header {
+            Ok(Ok((header, raw_socket))) => {
+                socket = raw_socket;
+                peer_addr = header.source().or(peer_addr);
+            }
+            Ok(Err(err)) => {
+                debug!("PROXY header error: {}", err);
+                return None;
+            }
+            Err(_) => {
+                debug!("timed out waiting for PROXY header");
+                return None;
+            }
+        }
+    }
+
+    // If TLS, wrap the socket in a TLS stream.
+    if let Some(acceptor) = acceptor {
+        match acceptor.accept(socket).await {
+            Ok(connection) => socket = Box::new(connection),
+            Err(err) => {
+                // Don't propagate TLS connection errors, just drop the connection.
+                debug!("TLS accept error: {}", err);
+                return None;
+            }
+        }
+    }
+
+    Some((socket, peer_addr))
+}
+
+/// Respond to a connection over the server's connection limit with a 503, and close it.
+async fn reject_connection(mut socket: Box<dyn AsyncStream>) {
+    let mut response = Response::new(status::SERVICE_UNAVAILABLE);
+    response.headers.set("Content-Type", "text/plain");
+    response.headers.set("Connection", "close");
+    response.set_body(format!(
+        "{} {}",
+        status::SERVICE_UNAVAILABLE.0,
+        status::SERVICE_UNAVAILABLE.1
+    ));
+
+    if socket.write_all(&response.serialize_bytes()).await.is_ok() {
+        _ = socket.shutdown().await;
+        _ = tokio::time::timeout(
+            REJECT_LINGER,
+            tokio::io::copy(&mut socket, &mut tokio::io::sink()),
+        )
+        .await;
+    }
+}
+
 /// This struct represents an open HTTP stream. It's created by the server when a new
 /// connection is received.
 #[derive(Debug)]
@@ -370,8 +915,8 @@ struct ConnectedServer {
     /// request is processed.
     close_connection: bool,
 
-    /// IP address of the connected peer.
-    peer_addr: SocketAddr,
+    /// IP address of the connected peer. This is None for Unix domain sockets.
+    peer_addr: Option<SocketAddr>,
 
     /// Configuration from the server.
     base_url: String,
@@ -379,53 +924,214 @@ struct ConnectedServer {
     error_handler: Arc<RwLock<Box<dyn ErrorHandler>>>,
     shutdown_notifier: Arc<Notify>,
     conn_tracker: Arc<RwLock<ConnTracker>>,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    max_body_bytes: Option<usize>,
+    idle_timeout: Option<Duration>,
+    keepalive_max: Option<usize>,
+    header_timeout: Option<Duration>,
+    tracing: bool,
+    continue_check: Option<ContinueCheck>,
+    metrics: Option<Arc<Metrics>>,
+    strict_responses: bool,
+    server_header: Option<String>,
+}
+
+/// Reasons the connection's reader stops without a request.
+#[derive(Debug)]
+enum ReadError {
+    /// The connection was closed or timed out, or the server is shutting down.
+    Closed(String),
+
+    /// The request couldn't be parsed.
+    Parse(ParseError),
+}
+
+/// Returns the status to respond with for parse errors that need one, e.g., because
+/// the request exceeded one of the server's limits.
+fn parse_error_status(e: &ParseError) -> Option<status::Code<'static>> {
+    match e {
+        ParseError::HeadersTooLarge => Some(status::REQUEST_HEADER_FIELDS_TOO_LARGE),
+        ParseError::BodyTooLarge => Some(status::PAYLOAD_TOO_LARGE),
+        ParseError::UriTooLong => Some(status::URI_TOO_LONG),
+        ParseError::UnsupportedVersion(_) => Some(status::HTTP_VERSION_NOT_SUPPORTED),
+        ParseError::UnsupportedTransferEncoding(_) => Some(status::NOT_IMPLEMENTED),
+        ParseError::AmbiguousBodyLength
+        | ParseError::BadContentLength(_)
+        | ParseError::BadTransferEncoding(_)
+        | ParseError::MissingHost
+        | ParseError::MultipleHosts
+        | ParseError::BadVersion(_) => Some(status::BAD_REQUEST),
+        _ => None,
+    }
+}
+
+/// Write the response for parse error `e`, if it has one.
+async fn write_parse_error(w: &mut dyn AsyncWriteStream, e: &ParseError) -> io::Result<()> {
+    if let Some(status) = parse_error_status(e) {
+        let mut response = Response::new(status);
+        response.headers.set("Content-Type", "text/plain");
+        response.headers.set("Connection", "close");
+        response.set_body(format!("{} {}", status.0, status.1));
+        w.write_all(&response.serialize_bytes()).await?;
+        w.flush().await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the client address reported in the X-Forwarded-For headers, skipping addresses
+/// of trusted proxies from the right. Returns None if the peer isn't a trusted proxy.
+fn forwarded_for(headers: &Headers, peer: IpAddr, trusted: &[IpAddr]) -> Option<IpAddr> {
+    if !trusted.contains(&peer) {
+        return None;
+    }
+
+    headers
+        .get("x-forwarded-for")?
+        .iter()
+        .flat_map(|v| v.split(','))
+        .rev()
+        .map(|addr| addr.trim().parse::<IpAddr>())
+        .take_while(|addr| addr.is_ok())
+        .flatten()
+        .find(|addr| !trusted.contains(addr))
 }
 
 impl ConnectedServer {
-    /// This method processes HTTP headers for connection management.
-    async fn process_headers(&mut self, headers: &Headers) {
-        if let Some(connection) = headers.get_first("connection") {
-            match connection.to_lowercase().as_ref() {
-                // Set the keep-alive parameters of the connection. The ConnTracker will
-                // shutdown the connection when the keep-alive timeout expires.
-                "keep-alive" => {
-                    if let Some(keepalive) = headers.get_first("keep-alive") {
-                        let parts: Vec<&str> = keepalive.split(',').map(|s| s.trim()).collect();
-                        for part in parts {
-                            let kv: Vec<&str> = part.split('=').map(|kv| kv.trim()).collect();
-                            if kv.is_empty() {
-                                break;
-                            }
-                            match kv[0] {
-                                "timeout" => {
-                                    let dur =
-                                        Duration::from_secs(kv[1].parse::<u64>().unwrap_or(60));
-                                    self.conn.set_keepalive_timeout(dur);
-                                    self.conn_tracker
-                                        .read()
-                                        .await
-                                        .set_keepalive_timeout(self.conn.id().clone(), dur)
-                                        .await;
-                                }
-                                "max" => self
-                                    .conn
-                                    .set_keepalive_max(kv[1].parse::<usize>().unwrap_or(100)),
-                                _ => {}
+    /// This method processes HTTP headers for connection management. HTTP/1.1 connections
+    /// are persistent unless the client asks to close them, while HTTP/1.0 connections are
+    /// closed after each request unless the client asks to keep them alive. The response
+    /// tells the client which one it got.
+    async fn process_headers(&mut self, request: &Request) {
+        let headers = &request.headers;
+        let tokens: Vec<String> = headers
+            .get("connection")
+            .into_iter()
+            .flatten()
+            .flat_map(|value| value.split(','))
+            .map(|token| token.trim().to_lowercase())
+            .collect();
+        let close = tokens.iter().any(|token| token == "close");
+        let keep_alive = !close && tokens.iter().any(|token| token == "keep-alive");
+
+        // Set the keep-alive parameters of the connection. The ConnTracker will
+        // shutdown the connection when the keep-alive timeout expires.
+        if keep_alive {
+            if let Some(keepalive) = headers.get_first("keep-alive") {
+                let parts: Vec<&str> = keepalive.split(',').map(|s| s.trim()).collect();
+                for part in parts {
+                    let kv: Vec<&str> = part.split('=').map(|kv| kv.trim()).collect();
+                    if kv.len() < 2 {
+                        continue;
+                    }
+                    match kv[0] {
+                        "timeout" => {
+                            let mut dur = Duration::from_secs(kv[1].parse::<u64>().unwrap_or(60));
+                            if let Some(idle_timeout) = self.idle_timeout {
+                                dur = dur.min(idle_timeout);
                             }
+                            self.conn.set_keepalive_timeout(dur);
+                            self.conn_tracker
+                                .read()
+                                .await
+                                .set_keepalive_timeout(self.conn.id().clone(), dur)
+                                .await;
+                        }
+                        "max" => {
+                            let max = kv[1].parse::<usize>().unwrap_or(100);
+                            self.conn
+                                .set_keepalive_max(self.keepalive_max.map_or(max, |m| max.min(m)));
                         }
+                        _ => {}
                     }
                 }
+            }
+        }
 
-                // Close the connection right after this reqeust.
-                "close" => {
-                    self.close_connection = true;
-                }
-                _ => {}
+        if request.version == "HTTP/1.0" {
+            // Close the connection right after this request, unless asked not to.
+            self.close_connection = !keep_alive;
+            if keep_alive {
+                request.add_response_header("Connection", "keep-alive");
             }
+        } else if close {
+            self.close_connection = true;
+        }
+
+        if self.close_connection {
+            request.add_response_header("Connection", "close");
+        }
+    }
+
+    /// Returns the headers that responses get unless their handlers set them.
+    fn default_headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        headers.set("Date", http_date(&Utc::now()));
+        if let Some(server_header) = &self.server_header {
+            headers.set("Server", server_header);
+        }
+        headers
+    }
+
+    /// Tell the client how long the connection stays open between requests, and how many
+    /// more requests it takes, if there are limits. The connection is closed after the last
+    /// one.
+    fn advertise_keepalive(&mut self, request: &Request) {
+        if self.close_connection {
+            return;
+        }
+
+        let state = self.conn.state.read().unwrap();
+        let mut params = vec![];
+        if let Some(timeout) = state.keepalive_timeout.or(self.idle_timeout) {
+            params.push(format!("timeout={}", timeout.as_secs()));
+        }
+        if let Some(max) = state.keepalive_max {
+            let remaining = max.saturating_sub(state.request_count);
+            if remaining == 0 {
+                drop(state);
+                self.close_connection = true;
+                request.add_response_header("Connection", "close");
+                return;
+            }
+            params.push(format!("max={}", remaining));
+        }
+
+        if !params.is_empty() {
+            request.add_response_header("Keep-Alive", params.join(", "));
+        }
+    }
+
+    /// Returns what `request` expects before sending its body. HTTP/1.0 clients don't know
+    /// about interim responses, so their expectations are ignored.
+    fn expectation(&self, request: &Request) -> Expectation {
+        let Some(expect) = request.headers.get_first("expect") else {
+            return Expectation::None;
+        };
+
+        if request.version == "HTTP/1.0" {
+            return Expectation::None;
+        }
+
+        if !expect.trim().eq_ignore_ascii_case("100-continue") {
+            return Expectation::Failed;
+        }
+
+        match &self.continue_check {
+            Some(check) if !(check.0)(request) => Expectation::Failed,
+            // Don't bother if the body is already here.
+            _ if request.body.complete() => Expectation::None,
+            _ => Expectation::Continue,
         }
     }
 
     /// This method processes multiple reuqests in the same connection.
+    ///
+    /// Requests are handled one at a time, in the order they arrive, and each response is
+    /// written in full before the next request is handled. So even when clients pipeline
+    /// requests (i.e., send more before getting responses), responses go out in request
+    /// order, as HTTP/1.1 requires. Writes are checked against the request count, and the
+    /// connection fails instead of writing a response out of order.
     async fn process_connection(&mut self) -> Result<(), String> {
         info!(
             "Connection ID {} received from {:?}",
@@ -433,6 +1139,14 @@ impl ConnectedServer {
             self.peer_addr
         );
 
+        // Clients can negotiate their own maximum with a `Keep-Alive` header.
+        if let Some(max) = self.keepalive_max {
+            self.conn.set_keepalive_max(max);
+        }
+
+        // Bytes read past the end of the previous request, i.e., pipelined requests.
+        let mut leftover: Vec<u8> = vec![];
+
         // This loop is iterated over for each Request in the same connection.
         'top: loop {
             let conn = self.conn.clone();
@@ -440,41 +1154,98 @@ impl ConnectedServer {
             let writer = conn.writer();
             let timeout_notifier = self.conn.timeout_notifier();
             let shutdown_notifier = Arc::clone(&self.shutdown_notifier);
+            let (drain_notifier, draining) = {
+                let conn_tracker = self.conn_tracker.read().await;
+                (conn_tracker.drain_notifier(), conn_tracker.is_draining())
+            };
 
-            if self.close_connection {
-                // We received `Connection: close`
+            if self.close_connection || draining {
+                // We received `Connection: close`, or the server is shutting down
                 _ = conn.writer().write().await.shutdown().await;
                 break;
             }
 
             let mut parser = RequestParser::new();
             parser.set_base_url(&self.base_url);
+            parser.set_require_host(true);
+            if let Some(max) = self.max_body_bytes {
+                parser.set_max_body_bytes(max);
+            }
             let mut ready = false;
+            let mut started = false;
+
+            // Negotiated keep-alive timeouts take precedence over the server's idle timeout.
+            let idle_timeout = match conn.state.read().unwrap().keepalive_timeout {
+                Some(_) => None,
+                None => self.idle_timeout,
+            };
 
+            let header_timeout = self.header_timeout;
             let (tx, mut rx) = mpsc::channel(1);
 
             // We're trying to keep the connection open here, and keep parsing requests until
             // the socket is closed.
-            tokio::spawn(async move {
+            let read_task = tokio::spawn(async move {
                 // Lock the read stream for the duration of the request.
                 let mut s = reader.write().await;
 
+                let idle = async {
+                    match idle_timeout {
+                        Some(timeout) => tokio::time::sleep(timeout).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::pin!(idle);
+
+                // This is reset when the first byte of the request arrives.
+                let header_deadline = tokio::time::sleep(Duration::ZERO);
+                tokio::pin!(header_deadline);
+
+                // Bytes fed to the parser so far.
+                let mut read = 0;
+
                 // Continue to read from the socket until we can parse a complete request, including
                 // the entire body.
                 while !parser.is_complete() {
                     let mut buf = [0u8; 16384];
 
-                    let result = tokio::select! {
-                        r = s.read(&mut buf) => r,
-                        _ = shutdown_notifier.notified() => {
-                            debug!("Shutting down connection {}...", &conn.id());
-                            tx.send(Err("Shutting down".to_string())).await.unwrap();
-                            break;
-                        }
-                        _ = timeout_notifier.notified() => {
-                            debug!("Keepalive timeout for connection {}...", &conn.id());
-                            tx.send(Err("Keepalive timeout".to_string())).await.unwrap();
-                            break;
+                    let result = if !leftover.is_empty() {
+                        // Pipelined requests are parsed before reading more. There's never more
+                        // left over than a single read.
+                        let n = leftover.len();
+                        buf[..n].copy_from_slice(&leftover);
+                        leftover.clear();
+                        Ok(n)
+                    } else {
+                        tokio::select! {
+                            r = s.read(&mut buf) => r,
+                            _ = shutdown_notifier.notified() => {
+                                debug!("Shutting down connection {}...", &conn.id());
+                                _ = tx.send(Err(ReadError::Closed("Shutting down".into()))).await;
+                                break;
+                            }
+                            _ = timeout_notifier.notified() => {
+                                debug!("Keepalive timeout for connection {}...", &conn.id());
+                                _ = tx.send(Err(ReadError::Closed("Keepalive timeout".into()))).await;
+                                break;
+                            }
+                            // Only close connections that are idle between requests.
+                            _ = &mut idle, if !started => {
+                                debug!("Idle timeout for connection {}...", &conn.id());
+                                _ = tx.send(Err(ReadError::Closed("Idle timeout".into()))).await;
+                                break;
+                            }
+                            _ = &mut header_deadline, if header_timeout.is_some() && started && !ready => {
+                                debug!("Header timeout for connection {}...", &conn.id());
+                                _ = tx.send(Err(ReadError::Closed("Header timeout".into()))).await;
+                                break;
+                            }
+                            // Only close idle connections when draining, let in-flight requests finish.
+                            _ = drain_notifier.notified(), if !started => {
+                                debug!("Draining connection {}...", &conn.id());
+                                _ = tx.send(Err(ReadError::Closed("Draining".into()))).await;
+                                break;
+                            }
                         }
                     };
 
@@ -482,26 +1253,44 @@ impl ConnectedServer {
                         Ok(0) => {
                             // Connection closed, exit
                             debug!("read {} bytes", 0);
-                            tx.send(Err("Connection closed".to_string())).await.unwrap();
+                            _ = tx
+                                .send(Err(ReadError::Closed("Connection closed".into())))
+                                .await;
                             break;
                         }
                         Ok(n) => {
                             debug!("read {} bytes", n);
+                            if let (Some(timeout), false) = (header_timeout, started) {
+                                header_deadline
+                                    .as_mut()
+                                    .reset(tokio::time::Instant::now() + timeout);
+                            }
+                            started = true;
+                            read += n;
                             let result = parser.parse_buf(&buf[..n]);
                             if let Err(e) = result {
                                 // Parser error, exit
                                 warn!("parser error: {:?}", e);
-                                tx.send(Err(e.to_string())).await.unwrap();
+                                // The handler may be done with the request already.
+                                _ = tx.send(Err(ReadError::Parse(e))).await;
                                 break;
                             }
 
                             // Received all headers, send them to the handler. The body can be
-                            // streamed asynchronously.
+                            // streamed asynchronously. Stop reading if the connection loop is
+                            // gone.
                             if parser.ready() && !ready {
-                                tx.send(Ok(parser.get_message())).await.unwrap();
+                                if tx.send(Ok(parser.get_message())).await.is_err() {
+                                    break;
+                                }
                                 ready = true; // send this only once
                             }
 
+                            // Keep anything after the request for the next one.
+                            if parser.is_complete() {
+                                leftover = buf[n - (read - parser.parsed_bytes())..n].to_vec();
+                            }
+
                             // We don't break here because we need to continue reading the rest
                             // of the body from the socket. The parser will continue to populate
                             // the body buffer, and set is_complete() to true when it's done.
@@ -509,42 +1298,173 @@ impl ConnectedServer {
                         Err(e) => {
                             // Socet error, exit
                             debug!("connection closed: {:?}", e);
-                            tx.send(Err("Connection closed".to_string())).await.unwrap();
+                            _ = tx
+                                .send(Err(ReadError::Closed("Connection closed".into())))
+                                .await;
                             break;
                         }
                     }
                 }
+
+                leftover
             });
 
-            let message = rx.recv().await.unwrap();
-            if message.is_err() {
-                // Connection closed
-                break;
-            }
+            let message = match rx.recv().await.unwrap() {
+                Ok(message) => message,
+                Err(ReadError::Parse(e)) => {
+                    // Let the client know why, if it's their fault, and close the connection.
+                    let mut w = writer.write().await;
+                    _ = write_parse_error(&mut *w, &e).await;
+                    _ = w.shutdown().await;
+                    break;
+                }
+                Err(ReadError::Closed(reason)) => {
+                    debug!("Connection {}: {}", self.conn.id(), reason);
+                    break;
+                }
+            };
 
             if self.conn.inc_request_count() {
                 _ = self.conn.writer().write().await.shutdown().await;
                 break 'top;
             }
+            let seq = self.conn.state.read().unwrap().request_count;
 
             // Extract the request from the parser
-            let mut request: Request = message.unwrap().into();
+            let mut request: Request = message.into();
             request
                 .headers
                 .set("X-Hype-Connection-ID", self.conn.id().clone());
             request.set_conn(self.conn.clone());
-            self.process_headers(&request.headers).await;
+            if let Some(peer_addr) = self.peer_addr {
+                request.set_socket_addr(peer_addr);
+                match forwarded_for(&request.headers, peer_addr.ip(), &self.trusted_proxies) {
+                    Some(client) => request.set_peer_addr(SocketAddr::new(client, 0)),
+                    None => request.set_peer_addr(peer_addr),
+                }
+            }
+            self.process_headers(&request).await;
+            self.advertise_keepalive(&request);
 
             debug!("Request: {:?}", request);
 
+            let received = Instant::now();
+            let trace = self.tracing.then(|| {
+                (
+                    self.conn.id().to_string(),
+                    METHODS_AS_STR[&request.method],
+                    request.abs_path(),
+                )
+            });
+            if let Some((conn_id, method, path)) = &trace {
+                info!(
+                    target: TRACE_TARGET,
+                    conn_id = conn_id.as_str(),
+                    method = *method,
+                    path = path.as_str();
+                    "begin request {} {} on connection {}", method, path, conn_id
+                );
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.begin_request();
+            }
+
             let mut s = writer.write().await;
-            let result = self.router.handle(&mut request, &mut *s).await;
+
+            // Interleaving responses would corrupt the framing for the client, so don't.
+            if !self.conn.is_next_response(seq) {
+                _ = s.shutdown().await;
+                return Err(format!(
+                    "response to request {} on connection {} is out of order",
+                    seq,
+                    self.conn.id()
+                ));
+            }
+
+            match self.expectation(&request) {
+                Expectation::None => {}
+                Expectation::Continue => {
+                    s.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                        .await
+                        .map_err(|e| format!("Error writing response: {}", e))?;
+                    s.flush()
+                        .await
+                        .map_err(|e| format!("Error writing response: {}", e))?;
+                }
+                Expectation::Failed => {
+                    // The client may or may not send the body, so don't try to read past it.
+                    read_task.abort();
+                    let mut response = Response::new(status::EXPECTATION_FAILED);
+                    response.headers.set("Connection", "close");
+                    _ = s.write_all(&response.serialize_bytes()).await;
+                    _ = s.shutdown().await;
+                    break 'top;
+                }
+            }
+
+            let mut w = injector::header_injector(
+                &mut *s,
+                request.shared_response_headers(),
+                self.default_headers(),
+            );
+            if request.method == Method::HEAD {
+                w.omit_body();
+            } else if self.strict_responses || cfg!(debug_assertions) {
+                w.check_content_length(self.strict_responses);
+            }
+            let result = tokio::select! {
+                result = self.router.handle(&mut request, &mut w) => result,
+
+                // The body turned out to be bad (e.g., too large) while the handler was running.
+                Some(Err(ReadError::Parse(e))) = rx.recv() => {
+                    if !w.started() {
+                        _ = write_parse_error(&mut w, &e).await;
+                    }
+                    _ = w.finish().await;
+                    drop(w);
+                    _ = s.shutdown().await;
+                    break 'top;
+                }
+            };
             self.error_handler
                 .read()
                 .await
-                .handle(&request, &mut *s, result)
+                .handle(&request, &mut w, result)
                 .await
                 .map_err(|e| format!("Error running error handler: {:?}", e))?;
+            w.finish()
+                .await
+                .map_err(|e| format!("Error writing response: {}", e))?;
+            self.conn.inc_response_count();
+            if let Some(metrics) = &self.metrics {
+                metrics.end_request(w.status(), received.elapsed());
+            }
+
+            if let Some((conn_id, method, path)) = &trace {
+                let duration = received.elapsed();
+                info!(
+                    target: TRACE_TARGET,
+                    conn_id = conn_id.as_str(),
+                    method = *method,
+                    path = path.as_str(),
+                    request_id = request.request_id(),
+                    status = w.status(),
+                    duration_us = duration.as_micros() as u64;
+                    "end request {} {} on connection {}: {:?} in {:?}",
+                    method, path, conn_id, w.status(), duration
+                );
+            }
+
+            // Handlers can close the connection by adding `Connection: close` to the response,
+            // e.g., to stop reading a body they rejected.
+            if request.response_headers().has_token("connection", "close") {
+                self.close_connection = true;
+                read_task.abort();
+            }
+
+            // The next request can't be parsed until this one is, so wait for the rest of
+            // its body.
+            leftover = read_task.await.unwrap_or_default();
         }
 
         info!("Closed connection {}", &self.conn.id());
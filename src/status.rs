@@ -1,10 +1,79 @@
 pub type Code<'a> = (u16, &'a str);
 
+pub const SWITCHING_PROTOCOLS: Code = (101, "Switching Protocols");
 pub const OK: Code = (200, "OK");
+pub const NO_CONTENT: Code = (204, "No Content");
+pub const PARTIAL_CONTENT: Code = (206, "Partial Content");
 pub const MOVED_PERMANENTLY: Code = (301, "Moved Permanently");
+pub const FOUND: Code = (302, "Found");
+pub const SEE_OTHER: Code = (303, "See Other");
+pub const NOT_MODIFIED: Code = (304, "Not Modified");
+pub const TEMPORARY_REDIRECT: Code = (307, "Temporary Redirect");
+pub const PERMANENT_REDIRECT: Code = (308, "Permanent Redirect");
+pub const BAD_REQUEST: Code = (400, "Bad Request");
 pub const UNAUTHORIZED: Code = (401, "Unauthorized");
+pub const FORBIDDEN: Code = (403, "Forbidden");
 pub const NOT_FOUND: Code = (404, "Not Found");
+pub const METHOD_NOT_ALLOWED: Code = (405, "Method Not Allowed");
+pub const PRECONDITION_FAILED: Code = (412, "Precondition Failed");
+pub const PAYLOAD_TOO_LARGE: Code = (413, "Payload Too Large");
+pub const URI_TOO_LONG: Code = (414, "URI Too Long");
+pub const UNSUPPORTED_MEDIA_TYPE: Code = (415, "Unsupported Media Type");
+pub const RANGE_NOT_SATISFIABLE: Code = (416, "Range Not Satisfiable");
+pub const EXPECTATION_FAILED: Code = (417, "Expectation Failed");
+pub const UPGRADE_REQUIRED: Code = (426, "Upgrade Required");
+pub const TOO_MANY_REQUESTS: Code = (429, "Too Many Requests");
+pub const REQUEST_HEADER_FIELDS_TOO_LARGE: Code = (431, "Request Header Fields Too Large");
 pub const SERVER_ERROR: Code = (500, "Server Error");
+pub const NOT_IMPLEMENTED: Code = (501, "Not Implemented");
+pub const BAD_GATEWAY: Code = (502, "Bad Gateway");
+pub const SERVICE_UNAVAILABLE: Code = (503, "Service Unavailable");
+pub const GATEWAY_TIMEOUT: Code = (504, "Gateway Timeout");
+pub const HTTP_VERSION_NOT_SUPPORTED: Code = (505, "HTTP Version Not Supported");
+pub const LOOP_DETECTED: Code = (508, "Loop Detected");
+
+/// The codes above, for looking up reason phrases.
+const CODES: &[Code] = &[
+    SWITCHING_PROTOCOLS,
+    OK,
+    NO_CONTENT,
+    PARTIAL_CONTENT,
+    MOVED_PERMANENTLY,
+    FOUND,
+    SEE_OTHER,
+    NOT_MODIFIED,
+    TEMPORARY_REDIRECT,
+    PERMANENT_REDIRECT,
+    BAD_REQUEST,
+    UNAUTHORIZED,
+    FORBIDDEN,
+    NOT_FOUND,
+    METHOD_NOT_ALLOWED,
+    PRECONDITION_FAILED,
+    PAYLOAD_TOO_LARGE,
+    URI_TOO_LONG,
+    UNSUPPORTED_MEDIA_TYPE,
+    RANGE_NOT_SATISFIABLE,
+    EXPECTATION_FAILED,
+    UPGRADE_REQUIRED,
+    TOO_MANY_REQUESTS,
+    REQUEST_HEADER_FIELDS_TOO_LARGE,
+    SERVER_ERROR,
+    NOT_IMPLEMENTED,
+    BAD_GATEWAY,
+    SERVICE_UNAVAILABLE,
+    GATEWAY_TIMEOUT,
+    HTTP_VERSION_NOT_SUPPORTED,
+    LOOP_DETECTED,
+];
+
+/// Returns the reason phrase of `code`, if it's one of the codes above.
+pub fn reason(code: u16) -> Option<&'static str> {
+    CODES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, text)| *text)
+}
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Status {
@@ -21,6 +90,16 @@ impl<T: Into<String>> From<(u16, T)> for Status {
     }
 }
 
+/// Statuses from bare codes get their reason phrase, or an empty one for unknown codes.
+impl From<u16> for Status {
+    fn from(code: u16) -> Self {
+        Status {
+            code,
+            text: reason(code).unwrap_or_default().into(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -36,4 +115,10 @@ mod tests {
         let code = Status::from(NOT_FOUND);
         assert_eq!(code.code, 404);
     }
+
+    #[test]
+    fn from_code() {
+        assert_eq!(Status::from(404), Status::from(NOT_FOUND));
+        assert_eq!(Status::from(299), Status::from((299, "")));
+    }
 }
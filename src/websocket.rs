@@ -0,0 +1,268 @@
+/// This file implements the WebSocket protocol (RFC 6455): the opening handshake's accept
+/// key, and a codec for frames. Frames sent by clients are masked, frames sent by servers
+/// aren't. See `handlers::websocket` for upgrading server connections.
+use std::{error, fmt};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::Rng;
+use ring::digest;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Appended to the client's key to compute the accept key.
+pub const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The only protocol version in use.
+pub const VERSION: &str = "13";
+
+/// Frames with larger payloads are rejected by default.
+pub const MAX_PAYLOAD_LEN: usize = 16 * 1024 * 1024;
+
+/// Close codes (RFC 6455, section 7.4.1).
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketError {
+    /// The connection failed or was closed without a close frame.
+    Io(String),
+
+    /// The peer broke the protocol, e.g., with a bad opcode or an unmasked client frame.
+    Protocol(String),
+
+    /// A frame or message exceeded the size limit.
+    TooLarge(usize),
+}
+
+impl fmt::Display for WebSocketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebSocketError::Io(msg) => write!(f, "websocket: {}", msg),
+            WebSocketError::Protocol(msg) => write!(f, "websocket: protocol error: {}", msg),
+            WebSocketError::TooLarge(len) => write!(f, "websocket: {} bytes is too large", len),
+        }
+    }
+}
+
+impl error::Error for WebSocketError {}
+
+impl From<std::io::Error> for WebSocketError {
+    fn from(e: std::io::Error) -> Self {
+        WebSocketError::Io(e.to_string())
+    }
+}
+
+/// Returns the `Sec-WebSocket-Accept` value for the client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let hash = digest::digest(
+        &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{}{}", key.trim(), GUID).as_bytes(),
+    );
+    STANDARD.encode(hash.as_ref())
+}
+
+/// Returns true if `key` is a valid `Sec-WebSocket-Key`, i.e., 16 base64-encoded bytes.
+pub fn valid_key(key: &str) -> bool {
+    STANDARD
+        .decode(key.trim())
+        .is_ok_and(|nonce| nonce.len() == 16)
+}
+
+/// Returns a new random `Sec-WebSocket-Key`, for clients.
+pub fn generate_key() -> String {
+    STANDARD.encode(rand::thread_rng().gen::<[u8; 16]>())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0x0 => Some(OpCode::Continuation),
+            0x1 => Some(OpCode::Text),
+            0x2 => Some(OpCode::Binary),
+            0x8 => Some(OpCode::Close),
+            0x9 => Some(OpCode::Ping),
+            0xA => Some(OpCode::Pong),
+            _ => None,
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            OpCode::Continuation => 0x0,
+            OpCode::Text => 0x1,
+            OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
+            OpCode::Ping => 0x9,
+            OpCode::Pong => 0xA,
+        }
+    }
+
+    /// Returns true for close, ping, and pong frames.
+    pub fn is_control(&self) -> bool {
+        matches!(self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// False if more fragments of the message follow.
+    pub fin: bool,
+    pub opcode: OpCode,
+
+    /// True if the frame is (or is to be) masked, as required for client frames.
+    pub masked: bool,
+
+    /// The unmasked payload.
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(opcode: OpCode, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            fin: true,
+            opcode,
+            masked: false,
+            payload: payload.into(),
+        }
+    }
+
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(OpCode::Text, text.into())
+    }
+
+    pub fn binary(data: impl Into<Vec<u8>>) -> Self {
+        Self::new(OpCode::Binary, data)
+    }
+
+    pub fn ping(data: impl Into<Vec<u8>>) -> Self {
+        Self::new(OpCode::Ping, data)
+    }
+
+    pub fn pong(data: impl Into<Vec<u8>>) -> Self {
+        Self::new(OpCode::Pong, data)
+    }
+
+    pub fn close(code: u16, reason: &str) -> Self {
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        Self::new(OpCode::Close, payload)
+    }
+
+    /// Mask the frame with a random key when it's encoded, as clients must.
+    pub fn with_mask(mut self) -> Self {
+        self.masked = true;
+        self
+    }
+
+    /// Returns the status code of a close frame, if it has one.
+    pub fn close_code(&self) -> Option<u16> {
+        match (self.opcode, self.payload.get(..2)) {
+            (OpCode::Close, Some(code)) => Some(u16::from_be_bytes([code[0], code[1]])),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.payload.len();
+        let mut buf = Vec::with_capacity(len + 14);
+        buf.push(((self.fin as u8) << 7) | self.opcode.as_u8());
+
+        let mask_bit = (self.masked as u8) << 7;
+        if len < 126 {
+            buf.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            buf.push(mask_bit | 126);
+            buf.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            buf.push(mask_bit | 127);
+            buf.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        if self.masked {
+            let mask: [u8; 4] = rand::thread_rng().gen();
+            buf.extend_from_slice(&mask);
+            buf.extend(apply_mask(&self.payload, mask));
+        } else {
+            buf.extend_from_slice(&self.payload);
+        }
+
+        buf
+    }
+
+    /// Read a frame from `r`, rejecting payloads larger than `max_len` bytes.
+    pub async fn read_from(
+        r: &mut (dyn AsyncRead + Unpin + Send),
+        max_len: usize,
+    ) -> Result<Frame, WebSocketError> {
+        let mut head = [0u8; 2];
+        r.read_exact(&mut head).await?;
+
+        if head[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol("reserved bits set".into()));
+        }
+
+        let fin = head[0] & 0x80 != 0;
+        let opcode = OpCode::from_u8(head[0] & 0x0F)
+            .ok_or_else(|| WebSocketError::Protocol(format!("bad opcode {}", head[0] & 0x0F)))?;
+        let masked = head[1] & 0x80 != 0;
+
+        let len = match head[1] & 0x7F {
+            126 => r.read_u16().await? as usize,
+            127 => usize::try_from(r.read_u64().await?).unwrap_or(usize::MAX),
+            len => len as usize,
+        };
+
+        if opcode.is_control() && (len > 125 || !fin) {
+            return Err(WebSocketError::Protocol("bad control frame".into()));
+        }
+
+        if len > max_len {
+            return Err(WebSocketError::TooLarge(len));
+        }
+
+        let mut mask = [0u8; 4];
+        if masked {
+            r.read_exact(&mut mask).await?;
+        }
+
+        let mut payload = vec![0u8; len];
+        r.read_exact(&mut payload).await?;
+        if masked {
+            payload = apply_mask(&payload, mask);
+        }
+
+        Ok(Frame {
+            fin,
+            opcode,
+            masked,
+            payload,
+        })
+    }
+
+    pub async fn write_to(
+        &self,
+        w: &mut (dyn AsyncWrite + Unpin + Send),
+    ) -> Result<(), WebSocketError> {
+        w.write_all(&self.encode()).await?;
+        w.flush().await?;
+        Ok(())
+    }
+}
+
+fn apply_mask(data: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    data.iter()
+        .enumerate()
+        .map(|(i, b)| b ^ mask[i % 4])
+        .collect()
+}
@@ -0,0 +1,132 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use hype::{
+    handler::{Action, AsyncWriteStream, Handler},
+    handlers::{self, AccessLog},
+    request::Request,
+    response::Response,
+    status,
+};
+use log::{Level, Log, Metadata, Record};
+use tokio::io::AsyncWriteExt;
+
+/// Collects access log lines, so tests can check them.
+struct TestLogger {
+    lines: Mutex<Vec<String>>,
+}
+
+impl Log for TestLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target().ends_with("access_log") {
+            self.lines
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", record.level(), record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: TestLogger = TestLogger {
+    lines: Mutex::new(vec![]),
+};
+
+/// Send `raw` to `handler`, and return the line it logged.
+async fn logged_line(handler: AccessLog, raw: &str) -> String {
+    _ = log::set_logger(&LOGGER);
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut request = Request::from(raw).unwrap();
+    request.set_peer_addr("10.1.2.3:5555".parse().unwrap());
+
+    let mut stream: Vec<u8> = vec![];
+    let result = handler.handle(&request, &mut stream).await;
+    assert!(matches!(result, Ok(Action::Done)));
+    assert!(String::from_utf8(stream).unwrap().starts_with("HTTP/1.1"));
+
+    // Tests run in parallel, so find our line by the user agent.
+    let agent = request.headers.get_first("user-agent").unwrap().clone();
+    let lines = LOGGER.lines.lock().unwrap();
+    lines
+        .iter()
+        .find(|line| line.contains(&agent))
+        .unwrap()
+        .clone()
+}
+
+#[tokio::test]
+async fn default_format() {
+    let handler = AccessLog::new(handlers::handler(|_| async move { Ok("hello") }));
+    let line = logged_line(
+        handler,
+        "GET /foo?bar=1 HTTP/1.1\r\nHost: localhost\r\nReferer: http://mo.town/\r\nUser-Agent: default-agent\r\n\r\n",
+    )
+    .await;
+
+    assert!(line.starts_with("INFO 10.1.2.3 - - ["));
+    assert!(
+        line.contains(r#"] "GET /foo?bar=1 HTTP/1.1" 200 5 "http://mo.town/" "default-agent" "#)
+    );
+}
+
+#[tokio::test]
+async fn custom_format() {
+    let handler = AccessLog::new(handlers::handler(|_| async move {
+        let mut response = Response::new(status::NOT_FOUND);
+        response.set_body("nope, not here");
+        Ok(response)
+    }))
+    .with_format("%m %U%q %s %b %{X-Missing}i %D %{User-Agent}i 100%%")
+    .with_level(Level::Warn);
+
+    let line = logged_line(
+        handler,
+        "POST /baz HTTP/1.1\r\nHost: localhost\r\nUser-Agent: custom-agent\r\n\r\n",
+    )
+    .await;
+
+    let fields: Vec<&str> = line.split(' ').collect();
+    assert_eq!(fields[..6], ["WARN", "POST", "/baz", "404", "14", "-"]);
+    assert!(fields[6].parse::<u128>().is_ok());
+    assert_eq!(fields[7..], ["custom-agent", "100%"]);
+}
+
+/// Writes a response directly to the stream, in pieces.
+struct PieceWriter;
+
+#[async_trait]
+impl Handler for PieceWriter {
+    async fn handle(
+        &self,
+        _r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<Action, hype::handler::Error> {
+        for piece in [
+            "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 20",
+            "1 Created\r\nContent-Length: 6\r",
+            "\n\r\nabc",
+            "def",
+        ] {
+            w.write_all(piece.as_bytes()).await.unwrap();
+        }
+        Ok(Action::Done)
+    }
+}
+
+#[tokio::test]
+async fn written_responses() {
+    let handler = AccessLog::new(PieceWriter).with_format("%s %b %{User-Agent}i");
+    let line = logged_line(
+        handler,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nUser-Agent: piece-agent\r\n\r\n",
+    )
+    .await;
+
+    assert_eq!(line, "INFO 201 6 piece-agent");
+}
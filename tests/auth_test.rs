@@ -0,0 +1,71 @@
+use hype::{
+    handler::{Action, Error, Handler},
+    handlers::{auth::bearer_token, BearerAuth},
+    request::{Method, Request},
+};
+
+fn request(authorization: Option<&str>) -> Request {
+    let mut request = Request::new(Method::GET, "/admin");
+    if let Some(authorization) = authorization {
+        request.headers.set("authorization", authorization);
+    }
+    request
+}
+
+fn auth() -> BearerAuth {
+    // Stands in for a datastore lookup.
+    BearerAuth::new(|token| async move {
+        tokio::task::yield_now().await;
+        token == "s3cret"
+    })
+    .with_realm("admin")
+}
+
+#[test]
+fn parses_tokens() {
+    assert_eq!(bearer_token(&request(Some("Bearer abc"))), Some("abc"));
+    assert_eq!(bearer_token(&request(Some("bearer  abc "))), Some("abc"));
+    assert_eq!(bearer_token(&request(Some("Basic abc"))), None);
+    assert_eq!(bearer_token(&request(Some("Bearer "))), None);
+    assert_eq!(bearer_token(&request(None)), None);
+}
+
+#[tokio::test]
+async fn valid_token() {
+    let request = request(Some("Bearer s3cret"));
+    let action = auth().handle(&request, &mut vec![]).await.unwrap();
+    assert!(matches!(action, Action::Next));
+    assert!(request.response_headers().get("www-authenticate").is_none());
+}
+
+#[tokio::test]
+async fn invalid_token() {
+    let request = request(Some("Bearer wrong"));
+    let result = auth().handle(&request, &mut vec![]).await;
+    assert!(matches!(result, Err(Error::Status(status)) if status.code == 401));
+    assert_eq!(
+        request
+            .response_headers()
+            .get_first("www-authenticate")
+            .unwrap(),
+        "Bearer realm=\"admin\", error=\"invalid_token\""
+    );
+}
+
+#[tokio::test]
+async fn missing_token() {
+    for authorization in [None, Some("Basic dXNlcjpwYXNz")] {
+        let request = request(authorization);
+        let result = BearerAuth::new(|_| async { true })
+            .handle(&request, &mut vec![])
+            .await;
+        assert!(matches!(result, Err(Error::Status(status)) if status.code == 401));
+        assert_eq!(
+            request
+                .response_headers()
+                .get_first("www-authenticate")
+                .unwrap(),
+            "Bearer"
+        );
+    }
+}
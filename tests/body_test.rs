@@ -220,3 +220,144 @@ async fn read_all_chunks() {
 
     assert_eq!(data, "foobar 0foobar 1foobar 2foobar 3foobar 4".as_bytes());
 }
+
+#[tokio::test]
+async fn from_reader() {
+    let contents: Vec<u8> = (0..=255).cycle().take(200_000).collect();
+    let body = Body::from_reader(std::io::Cursor::new(contents.clone()));
+    assert!(!body.complete());
+
+    let chunks: Vec<Vec<u8>> = body.stream().collect().await;
+    assert!(chunks.len() > 1);
+    assert!(chunks
+        .iter()
+        .all(|c| c.len() <= hype::body::READ_CHUNK_SIZE));
+    assert_eq!(chunks.concat(), contents);
+    assert!(body.complete());
+}
+
+/// A reader that returns `data`, then fails.
+struct FailingReader {
+    data: Option<&'static [u8]>,
+}
+
+impl tokio::io::AsyncRead for FailingReader {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(match self.data.take() {
+            Some(data) => {
+                buf.put_slice(data);
+                Ok(())
+            }
+            None => Err(std::io::Error::other("disk on fire")),
+        })
+    }
+}
+
+#[tokio::test]
+async fn from_reader_error() {
+    let body = Body::from_reader(FailingReader {
+        data: Some(b"foobar"),
+    });
+
+    let mut stream = body.try_stream();
+    assert_eq!(stream.next().await.unwrap().unwrap(), b"foobar");
+    assert_eq!(
+        stream.next().await.unwrap().unwrap_err().to_string(),
+        "disk on fire"
+    );
+    assert!(stream.next().await.is_none());
+    assert!(body.complete());
+}
+
+#[tokio::test]
+async fn buffered_reader() {
+    let contents: Vec<u8> = (0..=255).cycle().take(200_000).collect();
+    let body = Body::from_reader(std::io::Cursor::new(contents.clone()));
+    assert!(body.is_reader());
+
+    body.buffer().await.unwrap();
+    assert!(!body.is_reader());
+    assert!(body.complete());
+    assert_eq!(body.try_content(), contents);
+
+    // Buffered bodies can be read any number of times, including by clones.
+    assert_eq!(body.content().await, contents);
+    assert_eq!(body.clone().content().await, contents);
+
+    // Buffering again is a no-op.
+    body.buffer().await.unwrap();
+    assert_eq!(body.content().await, contents);
+
+    let body = Body::from_reader(FailingReader {
+        data: Some(b"foobar"),
+    });
+    assert_eq!(body.buffer().await.unwrap_err().to_string(), "disk on fire");
+}
+
+#[tokio::test]
+async fn lengths() {
+    // Full bodies know their length up front, even before the content arrives.
+    let body = Body::from("hello");
+    assert_eq!(body.expected_len(), Some(5));
+    assert_eq!(body.buffered_len(), 5);
+    assert!(!body.is_empty());
+
+    let mut body = Body::new();
+    assert_eq!(body.expected_len(), Some(0));
+    assert!(body.is_empty());
+
+    body.set_content_length(10);
+    body.append(b"hello").unwrap();
+    assert_eq!(body.expected_len(), Some(10));
+    assert_eq!(body.buffered_len(), 5);
+    assert!(!body.is_empty());
+
+    // Chunked bodies don't, and are only empty once they're complete.
+    let mut body = Body::new();
+    body.set_chunked();
+    assert_eq!(body.expected_len(), None);
+    assert_eq!(body.buffered_len(), 0);
+    assert!(!body.is_empty());
+
+    body.push_chunk("foobar".into());
+    body.push_chunk("blah".into());
+    assert_eq!(body.buffered_len(), 10);
+    body.end_chunked();
+    assert_eq!(body.expected_len(), None);
+    assert!(!body.is_empty());
+
+    let mut body = Body::new();
+    body.set_chunked();
+    body.end_chunked();
+    assert!(body.is_empty());
+
+    // Reader bodies are unknown until they're buffered.
+    let body = Body::from_reader(std::io::Cursor::new(b"foobar".to_vec()));
+    assert_eq!(body.expected_len(), None);
+    assert_eq!(body.buffered_len(), 0);
+    assert!(!body.is_empty());
+
+    body.buffer().await.unwrap();
+    assert_eq!(body.expected_len(), Some(6));
+    assert_eq!(body.buffered_len(), 6);
+    assert!(!body.is_empty());
+}
+
+#[tokio::test]
+async fn from_bytes() {
+    // Not valid UTF-8, so this would be mangled by a round-trip through a String.
+    let bytes: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0xff, 0x00, 0xfe];
+
+    let body = Body::from(bytes.clone());
+    assert_eq!(body.expected_len(), Some(bytes.len()));
+    assert_eq!(body.content().await, bytes);
+
+    let body = Body::from(&bytes[..]);
+    assert_eq!(body.expected_len(), Some(bytes.len()));
+    assert_eq!(body.try_content(), bytes);
+    assert_eq!(body.content().await, bytes);
+}
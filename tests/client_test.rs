@@ -1,4 +1,71 @@
-use hype::{client::Client, request::Request};
+use std::{sync::Arc, time::Duration};
+
+use hype::{
+    client::{Client, ClientError},
+    cookie::{Cookie, CookieJar, Flag},
+    handler::Action,
+    handlers,
+    request::{Method, Request},
+    response::Response,
+    server::Server,
+    status,
+};
+use tokio::sync::{mpsc, Notify};
+
+const HOST: &str = "127.0.0.1";
+
+async fn start_redirect_server(port: u16) -> (Arc<mpsc::Sender<bool>>, Arc<Notify>) {
+    let mut server = Server::new(HOST, port);
+    server.route("/old", handlers::Redirect::new("/new"));
+    server.route("/loop", handlers::Redirect::new("/loop"));
+    server.route(
+        "/elsewhere",
+        handlers::Redirect::new(format!("http://localhost:{}/new", port)),
+    );
+    server.route(
+        "/see-other",
+        handlers::handler(|_| async move { Ok(Response::redirect("/new", 303)) }),
+    );
+    server.route(
+        "/temporary",
+        handlers::handler(|_| async move { Ok(Action::RedirectWith("/new".into(), 307)) }),
+    );
+    server.route("/credentials-here", handlers::Redirect::new("/credentials"));
+    server.route(
+        "/credentials-elsewhere",
+        handlers::Redirect::new(format!("http://localhost:{}/credentials", port)),
+    );
+    server.route(
+        "/credentials",
+        handlers::handler(|r: Request| async move {
+            let header = |name| r.headers.get_first(name).cloned().unwrap_or_default();
+            Ok(format!(
+                "{}|{}|{}",
+                header("authorization"),
+                header("proxy-authorization"),
+                header("cookie")
+            ))
+        }),
+    );
+    server.route(
+        "/new",
+        handlers::handler(|r: Request| async move {
+            let body = String::from_utf8_lossy(&r.body.content().await).to_string();
+            Ok(format!("new: {:?}{}", r.method, body))
+        }),
+    );
+
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+    shutdown
+}
+
+async fn shutdown_server(shutdown: (Arc<mpsc::Sender<bool>>, Arc<Notify>)) {
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
 
 #[tokio::test]
 async fn it_works() {
@@ -15,3 +82,257 @@ Host: google.com"##;
 
     println!("result: {:?}", result);
 }
+
+#[tokio::test]
+async fn timeout() {
+    // Accept connections, but never respond.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:9120")
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        let (_socket, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    });
+
+    let mut client = Client::new("127.0.0.1:9120").with_timeout(Duration::from_millis(200));
+    let mut client = client.connect().await.unwrap();
+
+    let result = client.send_request(&Request::default()).await;
+    assert!(matches!(result, Err(ClientError::Timeout)));
+    assert!(client.is_closed().await);
+}
+
+#[tokio::test]
+async fn follow_redirects() {
+    let port = 9121;
+    let shutdown = start_redirect_server(port).await;
+
+    // Redirects are not followed by default
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.connect().await.unwrap();
+    let response = client
+        .send_request(&Request::new(Method::GET, "/old"))
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 301);
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.follow_redirects(3).connect().await.unwrap();
+
+    // Relative location
+    let response = client
+        .send_request(&Request::new(Method::GET, "/old"))
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(response.content().await, "new: GET");
+
+    // Absolute location with a different host
+    let response = client
+        .send_request(&Request::new(Method::GET, "/elsewhere"))
+        .await
+        .unwrap();
+    assert_eq!(response.content().await, "new: GET");
+
+    // POST is downgraded to GET on 303
+    let response = client
+        .send_request(&Request::new(Method::POST, "/see-other"))
+        .await
+        .unwrap();
+    assert_eq!(response.content().await, "new: GET");
+
+    // POST is followed on 307, keeping the method and body
+    let mut request = Request::new(Method::POST, "/temporary");
+    request.headers.set("content-length", "5");
+    request.body = "hello".into();
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.content().await, "new: POSThello");
+
+    // POST is not followed on 301
+    let response = client
+        .send_request(&Request::new(Method::POST, "/old"))
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 301);
+
+    let response = client
+        .send_request(&Request::new(Method::GET, "/loop"))
+        .await;
+    assert!(matches!(response, Err(ClientError::TooManyRedirects(3))));
+
+    // Credentials are kept within the origin, and dropped when leaving it
+    let credentials = |path| {
+        let mut request = Request::new(Method::GET, path);
+        request.headers.set("Authorization", "Bearer secret");
+        request.headers.set("Proxy-Authorization", "Basic c2VjcmV0");
+        request.headers.set("Cookie", "session=abc123");
+        request
+    };
+    let response = client
+        .send_request(&credentials("/credentials-here"))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.content().await,
+        "Bearer secret|Basic c2VjcmV0|session=abc123"
+    );
+
+    let response = client
+        .send_request(&credentials("/credentials-elsewhere"))
+        .await
+        .unwrap();
+    assert_eq!(response.content().await, "||");
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn insecure_redirect() {
+    let port = 9124;
+    let certs = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/certs");
+
+    let mut server = Server::new(HOST, port);
+    server.enable_tls(certs.join("localhost.crt"), certs.join("localhost.key"));
+    server.route(
+        "/downgrade",
+        handlers::Redirect::new(format!("http://localhost:{}/", port)),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    client
+        .enable_tls("localhost")
+        .add_root_cert(certs.join("ca.crt"))
+        .unwrap();
+    let mut client = client.follow_redirects(3).connect().await.unwrap();
+
+    // HTTPS never redirects to plain HTTP
+    let mut request = Request::new(Method::GET, "/downgrade");
+    request.headers.set("Host", format!("localhost:{}", port));
+    let response = client.send_request(&request).await;
+    assert!(
+        matches!(response, Err(ClientError::InsecureRedirect(ref url)) if url.starts_with("http://")),
+        "{:?}",
+        response
+    );
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn cookie_jar() {
+    let port = 9122;
+    let mut server = Server::new(HOST, port);
+    server.route(
+        "/account/login",
+        handlers::handler(|_| async move {
+            let mut response = Response::new(status::OK);
+            let mut session = Cookie::new("session", "abc123");
+            session.push_flag(Flag::Path("/".into()));
+            response.set_cookie(session);
+            response.set_cookie(Cookie::new("scoped", "1"));
+            let mut secure = Cookie::new("secure", "1");
+            secure.push_flag(Flag::Secure);
+            response.set_cookie(secure);
+            Ok(response)
+        }),
+    );
+    server.route(
+        "/whoami",
+        handlers::handler(|r: Request| async move {
+            Ok(r.headers.get_first("cookie").cloned().unwrap_or_default())
+        }),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let jar = CookieJar::new();
+    let mut client = Client::new(format!("{}:{}", HOST, port)).with_cookie_jar(jar.clone());
+    let mut client = client.connect().await.unwrap();
+
+    let response = client
+        .send_request(&Request::new(Method::GET, "/whoami"))
+        .await
+        .unwrap();
+    assert_eq!(response.content().await, "");
+
+    client
+        .send_request(&Request::new(Method::GET, "/account/login"))
+        .await
+        .unwrap();
+    assert_eq!(jar.len(), 3);
+
+    // Only the session cookie applies: "scoped" is limited to /account, and "secure"
+    // is never sent over plain HTTP.
+    let response = client
+        .send_request(&Request::new(Method::GET, "/whoami"))
+        .await
+        .unwrap();
+    assert_eq!(response.content().await, "session=abc123");
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn custom_root_cert() {
+    let port = 9123;
+    let certs = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/certs");
+
+    let mut server = Server::new(HOST, port);
+    server.enable_tls(certs.join("localhost.crt"), certs.join("localhost.key"));
+    server.route_default(handlers::handler(|_| async move { Ok("secure") }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    // Not signed by any of the default roots
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    client.enable_tls("localhost");
+    assert!(matches!(
+        client.connect().await,
+        Err(ClientError::TLSError(_))
+    ));
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    client
+        .enable_tls("localhost")
+        .add_root_cert(certs.join("ca.crt"))
+        .unwrap();
+    let response = client
+        .connect()
+        .await
+        .unwrap()
+        .send_request(&Request::default())
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(response.content().await, "secure");
+
+    // The certificate is for localhost, so it's only accepted for other names if we
+    // skip verification.
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    client.enable_tls("example.com");
+    assert!(client.connect().await.is_err());
+
+    client.danger_accept_invalid_certs();
+    let response = client
+        .connect()
+        .await
+        .unwrap()
+        .send_request(&Request::default())
+        .await
+        .unwrap();
+    assert_eq!(response.content().await, "secure");
+
+    assert!(Client::new("localhost:443")
+        .add_root_cert(certs.join("localhost.key"))
+        .is_err());
+
+    shutdown_server(shutdown).await;
+}
@@ -0,0 +1,121 @@
+#![cfg(feature = "gzip")]
+
+use std::io::Read;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use hype::{
+    handler::{Action, Handler},
+    handlers::{self, Compress, File},
+    request::{Method, Request},
+    response::Response,
+    status,
+};
+
+fn text() -> String {
+    "hello world! ".repeat(200)
+}
+
+async fn get(handler: &dyn Handler, path: &str, accept_encoding: Option<&str>) -> Response {
+    let mut request = Request::new(Method::GET, path);
+    if let Some(accept_encoding) = accept_encoding {
+        request.headers.set("Accept-Encoding", accept_encoding);
+    }
+
+    let mut w: Vec<u8> = vec![];
+    match handler.handle(&request, &mut w).await.unwrap() {
+        Action::Response(response) => response,
+        _ => {
+            let mut parser = hype::parser::ResponseParser::new();
+            parser.parse_buf(&w).unwrap();
+            parser.get_message().into()
+        }
+    }
+}
+
+fn gunzip(content: &[u8]) -> String {
+    let mut out = String::new();
+    GzDecoder::new(content).read_to_string(&mut out).unwrap();
+    out
+}
+
+fn text_handler(body: String, content_type: &'static str) -> Compress {
+    Compress::new(handlers::handler(move |_| {
+        let body = body.clone();
+        async move {
+            let mut response = Response::new(status::OK);
+            response.headers.set("Content-Type", content_type);
+            response.set_body(body);
+            Ok(response)
+        }
+    }))
+}
+
+#[tokio::test]
+async fn compresses_returned_responses() {
+    let handler = text_handler(text(), "text/plain; charset=utf-8");
+
+    let response = get(&handler, "/", Some("deflate, gzip;q=1.0")).await;
+    assert_eq!(
+        response.headers.get_first("content-encoding").unwrap(),
+        "gzip"
+    );
+    assert_eq!(
+        response.headers.get_first("vary").unwrap(),
+        "Accept-Encoding"
+    );
+    let content = response.body.content().await;
+    assert!(content.len() < text().len());
+    assert_eq!(
+        response.headers.get_first("content-length").unwrap(),
+        &content.len().to_string()
+    );
+    assert_eq!(gunzip(&content), text());
+
+    let response = get(&handler, "/", Some("gzip;q=0, deflate")).await;
+    assert_eq!(
+        response.headers.get_first("content-encoding").unwrap(),
+        "deflate"
+    );
+    let mut out = String::new();
+    ZlibDecoder::new(response.body.content().await.as_slice())
+        .read_to_string(&mut out)
+        .unwrap();
+    assert_eq!(out, text());
+
+    // Client doesn't accept compression
+    let response = get(&handler, "/", None).await;
+    assert!(response.headers.get_first("content-encoding").is_none());
+    assert_eq!(response.content().await, text());
+}
+
+#[tokio::test]
+async fn skips_small_and_binary_bodies() {
+    let handler = text_handler("tiny".into(), "text/plain");
+    let response = get(&handler, "/", Some("gzip")).await;
+    assert!(response.headers.get_first("content-encoding").is_none());
+    assert_eq!(response.content().await, "tiny");
+
+    let handler = text_handler("tiny".into(), "text/plain").with_min_size(0);
+    let response = get(&handler, "/", Some("gzip")).await;
+    assert_eq!(gunzip(&response.body.content().await), "tiny");
+
+    let handler = text_handler(text(), "image/png");
+    let response = get(&handler, "/", Some("gzip")).await;
+    assert!(response.headers.get_first("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn compresses_written_responses() {
+    let dir = std::env::temp_dir().join("hype-compress-test");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("index.html"), text()).unwrap();
+
+    let handler = Compress::new(File::new(dir.to_string_lossy().to_string()));
+    let response = get(&handler, "/index.html", Some("gzip")).await;
+    assert_eq!(response.status.code, 200);
+    assert_eq!(
+        response.headers.get_first("content-encoding").unwrap(),
+        "gzip"
+    );
+    assert_eq!(gunzip(&response.body.content().await), text());
+}
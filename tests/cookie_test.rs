@@ -1,4 +1,8 @@
-use hype::cookie::{Cookie, Flag};
+use hype::{
+    cookie::{Cookie, CookieJar, Flag},
+    headers::Headers,
+};
+use url::Url;
 
 #[test]
 fn it_works() {
@@ -23,3 +27,92 @@ fn try_from() {
     assert!(cookie.is_ok());
     assert!(cookie.unwrap().has_flag(&Flag::Secure));
 }
+
+#[test]
+fn parse() {
+    let cookie =
+        Cookie::parse("ID=mo; Path=/app; Expires=Wed, 21 Oct 2015 07:28:00 GMT; HttpOnly").unwrap();
+    assert_eq!(cookie.name(), "ID");
+    assert!(cookie.has_flag(&Flag::Path("/app".into())));
+    assert!(cookie.has_flag(&Flag::HttpOnly));
+
+    let cookie = Cookie::try_from("Set-Cookie: token=a=b; Max-Age=60").unwrap();
+    assert_eq!(cookie.value(), "a=b");
+    assert!(cookie.has_flag(&Flag::MaxAge(60)));
+
+    assert_eq!(cookie.serialize(), "Set-Cookie: token=a=b; Max-Age=60");
+}
+
+#[test]
+fn jar_scoping() {
+    let jar = CookieJar::new();
+    let url = Url::parse("http://www.mo.town/app/login").unwrap();
+
+    let mut domain = Cookie::new("domain", "1");
+    domain.push_flag(Flag::Domain(".mo.town".into()));
+    domain.push_flag(Flag::Path("/".into()));
+    jar.add(&domain, &url);
+
+    // Default path is /app
+    jar.add(&Cookie::new("host", "2"), &url);
+
+    let mut secure = Cookie::new("secure", "3");
+    secure.push_flag(Flag::Secure);
+    secure.push_flag(Flag::Path("/".into()));
+    jar.add(&secure, &url);
+
+    // Not a domain that www.mo.town can set cookies for
+    let mut foreign = Cookie::new("foreign", "4");
+    foreign.push_flag(Flag::Domain("other.town".into()));
+    jar.add(&foreign, &url);
+    assert_eq!(jar.len(), 3);
+
+    let header = |u: &str| jar.cookie_header(&Url::parse(u).unwrap());
+    assert_eq!(
+        header("http://www.mo.town/app/x").unwrap(),
+        "host=2; domain=1"
+    );
+    assert_eq!(header("http://api.mo.town/app").unwrap(), "domain=1");
+    assert_eq!(header("http://www.mo.town/apple").unwrap(), "domain=1");
+    assert_eq!(
+        header("https://www.mo.town/").unwrap(),
+        "domain=1; secure=3"
+    );
+    assert!(header("http://other.town/").is_none());
+}
+
+#[test]
+fn jar_expiry() {
+    let jar = CookieJar::new();
+    let url = Url::parse("http://mo.town/").unwrap();
+
+    let mut headers = Headers::new();
+    headers.add("set-cookie", "a=1; Max-Age=3600");
+    headers.add("set-cookie", "b=2; Expires=Wed, 21 Oct 2015 07:28:00 GMT");
+    headers.add("set-cookie", "c=3");
+    jar.store(&url, &headers);
+    assert_eq!(jar.len(), 2);
+
+    // Replacing a cookie with Max-Age=0 deletes it
+    let mut headers = Headers::new();
+    headers.add("set-cookie", "c=3; Max-Age=0");
+    jar.store(&url, &headers);
+    assert_eq!(jar.cookie_header(&url).unwrap(), "a=1");
+}
+
+#[test]
+fn parse_all() {
+    let mut headers = Headers::new();
+    headers.add("set-cookie", "ID=mo; Secure");
+    headers.add("set-cookie", "garbage");
+    headers.add("set-cookie", "lang=en; Path=/");
+    headers.add("set-cookie", "bad=1; Expires=never");
+
+    let cookies = Cookie::parse_all(&headers);
+    assert_eq!(cookies.len(), 2);
+    assert_eq!(cookies[0].name(), "ID");
+    assert!(cookies[0].has_flag(&Flag::Secure));
+    assert_eq!(cookies[1].name(), "lang");
+
+    assert!(Cookie::parse_all(&Headers::new()).is_empty());
+}
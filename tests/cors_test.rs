@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use hype::{
+    client::Client,
+    handler::{Action, Handler},
+    handlers::{self, Cors},
+    middleware::Stack,
+    request::{Method, Request},
+    server::Server,
+};
+
+fn cors() -> Cors {
+    Cors::builder()
+        .allow_origin("https://mo.town")
+        .allow_methods(vec![Method::GET, Method::PUT])
+        .allow_header("content-type")
+        .allow_credentials(true)
+        .max_age(Duration::from_secs(600))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn rejects_wildcard_with_credentials() {
+    assert!(Cors::builder()
+        .allow_origin("*")
+        .allow_credentials(true)
+        .build()
+        .is_err());
+    assert!(Cors::builder().allow_origin("*").build().is_ok());
+}
+
+#[tokio::test]
+async fn preflight() {
+    let mut request = Request::new(Method::OPTIONS, "/api");
+    request.headers.set("Origin", "https://mo.town");
+    request.headers.set("Access-Control-Request-Method", "PUT");
+
+    let mut w: Vec<u8> = vec![];
+    let action = cors().handle(&request, &mut w).await.unwrap();
+    assert!(matches!(action, Action::Done));
+
+    let response = String::from_utf8(w).unwrap();
+    assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+    assert!(response.contains("Access-Control-Allow-Origin: https://mo.town\r\n"));
+    assert!(response.contains("Access-Control-Allow-Methods: GET, PUT\r\n"));
+    assert!(response.contains("Access-Control-Allow-Headers: content-type\r\n"));
+    assert!(response.contains("Access-Control-Allow-Credentials: true\r\n"));
+    assert!(response.contains("Access-Control-Max-Age: 600\r\n"));
+}
+
+#[tokio::test]
+async fn disallowed_origin() {
+    let mut request = Request::new(Method::OPTIONS, "/api");
+    request.headers.set("Origin", "https://evil.town");
+    request.headers.set("Access-Control-Request-Method", "PUT");
+
+    let mut w: Vec<u8> = vec![];
+    let action = cors().handle(&request, &mut w).await.unwrap();
+    assert!(matches!(action, Action::Next));
+    assert!(w.is_empty());
+    assert!(request.response_headers().iter().next().is_none());
+}
+
+#[tokio::test]
+async fn simple_requests() {
+    let mut server = Server::new("127.0.0.1", 9140);
+    server.route(
+        "/api",
+        Stack::new()
+            .push(cors())
+            .push(handlers::handler(|_| async move { Ok("hello") })),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new("127.0.0.1:9140");
+    let mut client = client.connect().await.unwrap();
+
+    let mut request = Request::new(Method::GET, "/api");
+    request.headers.set("Origin", "https://mo.town");
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(
+        response
+            .headers
+            .get_first("access-control-allow-origin")
+            .unwrap(),
+        "https://mo.town"
+    );
+    assert_eq!(response.headers.get_first("vary").unwrap(), "Origin");
+    assert_eq!(response.content().await, "hello");
+
+    // No CORS headers without an origin
+    let response = client
+        .send_request(&Request::new(Method::GET, "/api"))
+        .await
+        .unwrap();
+    assert!(response
+        .headers
+        .get_first("access-control-allow-origin")
+        .is_none());
+    assert_eq!(response.content().await, "hello");
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
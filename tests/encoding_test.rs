@@ -0,0 +1,169 @@
+#![cfg(feature = "gzip")]
+
+use std::{io::Write, sync::Arc};
+
+use async_trait::async_trait;
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use hype::{
+    client::Client,
+    handler::{self, AsyncWriteStream, Handler},
+    request::Request,
+    server::Server,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{mpsc, Notify},
+};
+
+const HOST: &str = "127.0.0.1";
+const CONTENT: &str = "Hello world! Hello world! Hello world! Hello world!";
+
+fn gzip(content: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(content).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn deflate(content: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(content).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Writes an encoded body, either with a content-length, or split up into
+/// multiple chunks.
+struct EncodedHandler {}
+
+#[async_trait]
+impl Handler for EncodedHandler {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let (encoding, body) = match r.path().as_str() {
+            "/deflate" => ("deflate", deflate(CONTENT.as_bytes())),
+            _ => ("gzip", gzip(CONTENT.as_bytes())),
+        };
+
+        if r.headers.get_first("x-hype-test-chunked").is_some() {
+            w.write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-encoding: {}\r\ntransfer-encoding: chunked\r\n\r\n",
+                    encoding
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+            for chunk in body.chunks(10) {
+                w.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                    .await
+                    .unwrap();
+                w.write_all(chunk).await.unwrap();
+                w.write_all(b"\r\n").await.unwrap();
+            }
+            w.write_all(b"0\r\n\r\n").await.unwrap();
+        } else {
+            w.write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-encoding: {}\r\ncontent-length: {}\r\n\r\n",
+                    encoding,
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+            w.write_all(body.as_slice()).await.unwrap();
+        }
+
+        Ok(handler::Action::Done)
+    }
+}
+
+async fn start_server(port: u16) -> (Arc<mpsc::Sender<bool>>, Arc<Notify>) {
+    let mut server = Server::new(HOST, port);
+    server.route_default(EncodedHandler {});
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+    shutdown
+}
+
+async fn shutdown_server(shutdown: (Arc<mpsc::Sender<bool>>, Arc<Notify>)) {
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn decodes_gzip() {
+    let port = 9110;
+    let shutdown = start_server(port).await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.connect().await.unwrap();
+
+    let response = client
+        .send_request_decoded(&Request::default())
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 200);
+    assert!(response.headers.get_first("content-encoding").is_none());
+    assert_eq!(response.content().await, CONTENT);
+
+    let mut request = Request::default();
+    request.headers.set("x-hype-test-chunked", "true");
+    let response = client.send_request_decoded(&request).await.unwrap();
+    assert!(response.headers.get_first("content-encoding").is_none());
+    assert_eq!(response.content().await, CONTENT);
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn decodes_deflate() {
+    let port = 9111;
+    let shutdown = start_server(port).await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.connect().await.unwrap();
+
+    let request = Request::new(hype::request::Method::GET, "/deflate");
+    let response = client.send_request_decoded(&request).await.unwrap();
+    assert_eq!(response.content().await, CONTENT);
+
+    let mut request = Request::new(hype::request::Method::GET, "/deflate");
+    request.headers.set("x-hype-test-chunked", "true");
+    let response = client.send_request_decoded(&request).await.unwrap();
+    assert_eq!(response.content().await, CONTENT);
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn raw_without_decoding() {
+    let port = 9112;
+    let shutdown = start_server(port).await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.connect().await.unwrap();
+
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert_eq!(
+        response.headers.get_first("content-encoding").unwrap(),
+        "gzip"
+    );
+    assert_eq!(
+        response.body.content().await,
+        gzip(CONTENT.as_bytes()).as_slice()
+    );
+
+    shutdown_server(shutdown).await;
+}
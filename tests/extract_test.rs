@@ -0,0 +1,107 @@
+use hype::{
+    client::Client,
+    handler::Error,
+    handlers::extract::{extract, Json, Path},
+    request::{Method, Request},
+    server::Server,
+};
+use serde::Deserialize;
+
+const HOST: &str = "127.0.0.1";
+
+#[derive(Debug, Deserialize)]
+struct UserPath {
+    org: String,
+    id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    name: String,
+    admin: bool,
+}
+
+async fn update_user(Path(path): Path<UserPath>, Json(user): Json<User>) -> Result<String, Error> {
+    Ok(format!(
+        "{}/{}: {} (admin: {})",
+        path.org, path.id, user.name, user.admin
+    ))
+}
+
+async fn delete_user(Path(id): Path<u32>) -> Result<String, Error> {
+    Ok(format!("deleted {}", id))
+}
+
+fn routes(port: u16) -> Server {
+    let server = Server::new(HOST, port);
+    server.route_method(Method::PUT, "/orgs/:org/users/:id", extract(update_user));
+    server.route_method(Method::DELETE, "/users/:id", extract(delete_user));
+    server
+}
+
+fn put(path: &str, body: &str) -> Request {
+    let mut request = Request::new(Method::PUT, path);
+    request.headers.set("Content-Type", "application/json");
+    request
+        .headers
+        .set("Content-Length", body.len().to_string());
+    request.body = body.into();
+    request
+}
+
+#[tokio::test]
+async fn path_and_json() {
+    let port = 8881;
+    let mut server = routes(port);
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port))
+        .connect()
+        .await
+        .unwrap();
+
+    let response = client
+        .send_request(&put(
+            "/orgs/acme/users/42",
+            r#"{"name": "alice", "admin": true}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(response.content().await, "acme/42: alice (admin: true)");
+
+    // Single parameters can be extracted directly.
+    let response = client
+        .send_request(&Request::new(Method::DELETE, "/users/7"))
+        .await
+        .unwrap();
+    assert_eq!(response.content().await, "deleted 7");
+
+    // Parameters that don't fit the path type are rejected.
+    let response = client
+        .send_request(&put(
+            "/orgs/acme/users/abc",
+            r#"{"name": "alice", "admin": true}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 400);
+
+    // So are bad bodies.
+    let response = client
+        .send_request(&put("/orgs/acme/users/42", r#"{"name": "alice"}"#))
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 400);
+
+    let mut request = put("/orgs/acme/users/42", r#"{"name": "alice", "admin": true}"#);
+    request.headers.set("Content-Type", "text/plain");
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.status.code, 415);
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
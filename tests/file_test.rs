@@ -0,0 +1,602 @@
+use std::path::{Path, PathBuf};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use hype::{
+    client::Client,
+    handler::{Error, Handler},
+    handlers::{web::Web, File},
+    request::{Method, Request},
+    server::Server,
+};
+
+const CONTENT: &str = "0123456789abcdefghij";
+
+fn fixture_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hype-file-test-{}", name));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file.txt"), CONTENT).unwrap();
+    dir
+}
+
+async fn get(dir: &Path, range: Option<&str>) -> String {
+    let handler = File::new(dir.to_string_lossy().to_string());
+    let headers: Vec<_> = range.map(|range| ("Range", range)).into_iter().collect();
+    get_with_headers(&handler, &headers).await
+}
+
+fn body(response: &str) -> &str {
+    response.split_once("\r\n\r\n").unwrap().1
+}
+
+#[tokio::test]
+async fn full_content() {
+    let dir = fixture_dir("full");
+    let response = get(&dir, None).await;
+
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Accept-Ranges: bytes\r\n"));
+    assert!(response.contains("Content-Length: 20\r\n"));
+    assert_eq!(body(&response), CONTENT);
+}
+
+#[tokio::test]
+async fn ranges() {
+    let dir = fixture_dir("ranges");
+
+    let response = get(&dir, Some("bytes=2-5")).await;
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    assert!(response.contains("Content-Range: bytes 2-5/20\r\n"));
+    assert!(response.contains("Content-Length: 4\r\n"));
+    assert_eq!(body(&response), "2345");
+
+    // Open-ended range
+    let response = get(&dir, Some("bytes=15-")).await;
+    assert!(response.contains("Content-Range: bytes 15-19/20\r\n"));
+    assert_eq!(body(&response), "fghij");
+
+    // Suffix range
+    let response = get(&dir, Some("bytes=-3")).await;
+    assert!(response.contains("Content-Range: bytes 17-19/20\r\n"));
+    assert_eq!(body(&response), "hij");
+
+    // End past the content length is truncated
+    let response = get(&dir, Some("bytes=18-100")).await;
+    assert_eq!(body(&response), "ij");
+}
+
+#[tokio::test]
+async fn unsatisfiable_ranges() {
+    let dir = fixture_dir("unsatisfiable");
+
+    let response = get(&dir, Some("bytes=20-")).await;
+    assert!(response.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+    assert!(response.contains("Content-Range: bytes */20\r\n"));
+    assert_eq!(body(&response), "");
+
+    // Multiple ranges are not supported
+    let response = get(&dir, Some("bytes=0-1, 4-5")).await;
+    assert!(response.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+}
+
+#[tokio::test]
+async fn binary_content() {
+    let dir = fixture_dir("binary");
+    let contents: Vec<u8> = (0..=255).cycle().take(1000).collect();
+    std::fs::write(dir.join("image.png"), &contents).unwrap();
+
+    let mut server = Server::new("127.0.0.1", 9130);
+    server.route("/files", File::new(dir.to_string_lossy().to_string()));
+    server.route("/web", Web::new(dir.to_string_lossy().to_string()));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new("127.0.0.1:9130");
+    let mut client = client.connect().await.unwrap();
+
+    for path in ["/files/image.png", "/web/image.png"] {
+        let response = client
+            .send_request(&Request::new(Method::GET, path))
+            .await
+            .unwrap();
+        assert_eq!(response.status.code, 200);
+        assert_eq!(
+            response.headers.get_first("content-type").unwrap(),
+            "image/png"
+        );
+        assert_eq!(
+            response.headers.get_first("content-length").unwrap(),
+            "1000"
+        );
+        assert_eq!(response.body.content().await, contents);
+    }
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn large_file() {
+    let dir = fixture_dir("large");
+    let contents: Vec<u8> = (0..=250).cycle().take(5 * 1024 * 1024 + 123).collect();
+    std::fs::write(dir.join("large.bin"), &contents).unwrap();
+
+    let mut server = Server::new("127.0.0.1", 9131);
+    server.route("/files", File::new(dir.to_string_lossy().to_string()));
+    server.route("/web", Web::new(dir.to_string_lossy().to_string()));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new("127.0.0.1:9131");
+    let mut client = client.connect().await.unwrap();
+
+    for path in ["/files/large.bin", "/web/large.bin"] {
+        let response = client
+            .send_request(&Request::new(Method::GET, path))
+            .await
+            .unwrap();
+        assert_eq!(response.status.code, 200);
+        assert_eq!(
+            response.headers.get_first("content-length").unwrap(),
+            &contents.len().to_string()
+        );
+        let body = response.body.content().await;
+        assert_eq!(body.len(), contents.len());
+        assert!(body == contents);
+    }
+
+    // Ranges are streamed too
+    let mut request = Request::new(Method::GET, "/files/large.bin");
+    request.headers.set("Range", "bytes=1000000-3999999");
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.status.code, 206);
+    assert!(response.body.content().await == contents[1000000..4000000]);
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn head() {
+    let dir = fixture_dir("head");
+
+    let mut server = Server::new("127.0.0.1", 9132);
+    server.route("/files", File::new(dir.to_string_lossy().to_string()));
+    server.route("/web", Web::new(dir.to_string_lossy().to_string()));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new("127.0.0.1:9132");
+    let mut client = client.connect().await.unwrap();
+
+    for path in ["/files/file.txt", "/web/file.txt"] {
+        let get = client
+            .send_request(&Request::new(Method::GET, path))
+            .await
+            .unwrap();
+        assert_eq!(get.body.content().await, CONTENT.as_bytes());
+
+        let head = client
+            .send_request(&Request::new(Method::HEAD, path))
+            .await
+            .unwrap();
+        assert_eq!(head.status.code, 200);
+        assert!(head.body.content().await.is_empty());
+
+        // The date can tick over between the requests.
+        let mut get_headers = get.headers.clone();
+        let mut head_headers = head.headers.clone();
+        get_headers.remove("date");
+        head_headers.remove("date");
+        assert_eq!(head_headers.fields, get_headers.fields);
+        assert_eq!(head.headers.get_first("content-length").unwrap(), "20");
+    }
+
+    // The connection is still in sync.
+    let response = client
+        .send_request(&Request::new(Method::GET, "/files/file.txt"))
+        .await
+        .unwrap();
+    assert_eq!(response.body.content().await, CONTENT.as_bytes());
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+/// Sends a GET for `path` (with `headers`) straight to `handler`, and returns the response
+/// it writes.
+async fn get_path(
+    handler: &dyn Handler,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> Result<String, Error> {
+    let mut request =
+        Request::from(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)).unwrap();
+    for (k, v) in headers {
+        request.headers.set(*k, *v);
+    }
+
+    let mut stream: Vec<u8> = vec![];
+    handler
+        .handle(&request, &mut stream)
+        .await
+        .map(|_| String::from_utf8_lossy(&stream).to_string())
+}
+
+async fn get_with_headers(handler: &dyn Handler, headers: &[(&str, &str)]) -> String {
+    get_path(handler, "/file.txt", headers).await.unwrap()
+}
+
+fn header<'a>(response: &'a str, name: &str) -> &'a str {
+    response
+        .lines()
+        .find_map(|l| l.strip_prefix(&format!("{}: ", name)))
+        .unwrap()
+        .trim()
+}
+
+#[tokio::test]
+async fn conditional_get() {
+    let dir = fixture_dir("conditional");
+    let file = File::new(dir.to_string_lossy().to_string());
+    let web = Web::new(dir.to_string_lossy().to_string());
+    let handlers: [&dyn Handler; 2] = [&file, &web];
+
+    for handler in handlers {
+        let response = get_with_headers(handler, &[]).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let etag = header(&response, "ETag").to_string();
+        let last_modified = header(&response, "Last-Modified").to_string();
+        assert!(etag.starts_with('"'));
+
+        let response = get_with_headers(handler, &[("If-None-Match", &etag)]).await;
+        assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert_eq!(header(&response, "ETag"), etag);
+        assert_eq!(body(&response), "");
+
+        // If-None-Match uses weak comparison, so a weak copy of the ETag matches too.
+        let weak = format!("W/{}", etag);
+        let response = get_with_headers(handler, &[("If-None-Match", &weak)]).await;
+        assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+
+        let response = get_with_headers(handler, &[("If-None-Match", "W/\"nope\"")]).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(body(&response), CONTENT);
+
+        let response = get_with_headers(handler, &[("If-Modified-Since", &last_modified)]).await;
+        assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+
+        let response = get_with_headers(
+            handler,
+            &[("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")],
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
+
+#[tokio::test]
+async fn if_range() {
+    let dir = fixture_dir("if-range");
+    let handler = Web::new(dir.to_string_lossy().to_string());
+
+    let response = get_with_headers(&handler, &[]).await;
+    let etag = response
+        .lines()
+        .find_map(|line| line.strip_prefix("ETag: "))
+        .unwrap()
+        .to_string();
+    let last_modified = response
+        .lines()
+        .find_map(|line| line.strip_prefix("Last-Modified: "))
+        .unwrap()
+        .to_string();
+
+    // The date still matches, so the range is served.
+    let response = get_with_headers(
+        &handler,
+        &[("Range", "bytes=2-5"), ("If-Range", &last_modified)],
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+
+    // So does the ETag.
+    let response = get_with_headers(&handler, &[("Range", "bytes=2-5"), ("If-Range", &etag)]).await;
+    assert!(
+        response.starts_with("HTTP/1.1 206 Partial Content\r\n"),
+        "{}",
+        response
+    );
+    assert_eq!(body(&response), &CONTENT[2..=5]);
+
+    // The client's copy is stale, or its ETag is weak, so the whole file is sent.
+    let weak = format!("W/{}", etag);
+    for stale in ["\"0-0\"", "\"abc\"", &weak, "Sun, 06 Nov 1994 08:49:37 GMT"] {
+        let response =
+            get_with_headers(&handler, &[("Range", "bytes=2-5"), ("If-Range", stale)]).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert_eq!(body(&response), CONTENT);
+    }
+}
+
+#[tokio::test]
+async fn sniffing() {
+    let dir = fixture_dir("sniffing");
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend([0u8; 100]);
+    std::fs::write(dir.join("logo"), &png).unwrap();
+    std::fs::write(dir.join("blob.xyz"), [0u8, 1, 2, 3, 255]).unwrap();
+    std::fs::write(dir.join("page"), "<html><script>alert(1)</script></html>").unwrap();
+
+    let content_type = |handler: Web, path: &'static str| async move {
+        let response = get_path(&handler, &format!("/{}", path), &[])
+            .await
+            .unwrap();
+        header(&response, "Content-Type").to_string()
+    };
+    let web = |sniffing| {
+        let mut web = Web::new(dir.to_string_lossy().to_string());
+        web.enable_sniffing(sniffing);
+        web
+    };
+
+    assert_eq!(content_type(web(true), "logo").await, "image/png");
+    assert_eq!(
+        content_type(web(true), "blob.xyz").await,
+        "application/octet-stream"
+    );
+
+    // HTML is never sniffed.
+    assert_eq!(
+        content_type(web(true), "page").await,
+        "application/octet-stream"
+    );
+
+    // Known extensions win.
+    assert_eq!(content_type(web(true), "file.txt").await, "text/plain");
+
+    // Without sniffing, extensionless files are text.
+    assert_eq!(content_type(web(false), "logo").await, "text/plain");
+}
+
+#[tokio::test]
+async fn spa_fallback() {
+    let dir = fixture_dir("spa");
+    std::fs::write(dir.join("index.html"), "<html>app</html>").unwrap();
+    std::fs::write(dir.join("app.js"), "run()").unwrap();
+
+    let mut web = Web::new(dir.to_string_lossy().to_string());
+    web.spa_fallback(true);
+    let get = |path: &'static str| get_path(&web, path, &[]);
+
+    // Client-side routes get the app.
+    let response = get("/some/deep/route").await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: text/html\r\n"));
+    assert_eq!(body(&response), "<html>app</html>");
+
+    // Assets are still served, or 404 if they're missing.
+    assert_eq!(body(&get("/app.js").await.unwrap()), "run()");
+    match get("/missing.js").await {
+        Err(Error::Status(status)) => assert_eq!(status.code, 404),
+        result => panic!("expected a 404, got {:?}", result),
+    }
+
+    // Without the fallback, missing paths are 404s.
+    web.spa_fallback(false);
+    assert!(matches!(
+        get_path(&web, "/some/route", &[]).await,
+        Err(Error::Status(status)) if status.code == 404
+    ));
+}
+
+#[tokio::test]
+async fn cache_control() {
+    use hype::handlers::web::{CachePolicy, Visibility, FINGERPRINT_PATTERN};
+    use std::time::Duration;
+
+    let dir = fixture_dir("cache-control");
+    std::fs::write(dir.join("index.html"), "<html>app</html>").unwrap();
+    std::fs::write(dir.join("app.3f9a2c1d.js"), "run()").unwrap();
+
+    let year = Duration::from_secs(365 * 24 * 60 * 60);
+    let mut web = Web::new(dir.to_string_lossy().to_string());
+    web.cache_control_for(
+        FINGERPRINT_PATTERN,
+        CachePolicy::new()
+            .with_visibility(Visibility::Public)
+            .with_max_age(year)
+            .with_immutable(),
+    )
+    .unwrap();
+    web.cache_control(
+        CachePolicy::new()
+            .with_visibility(Visibility::Private)
+            .with_no_cache()
+            .with_max_age(Duration::from_secs(60)),
+    );
+
+    let get = |path: &'static str, headers: Vec<(&'static str, String)>| {
+        let web = &web;
+        async move {
+            let headers: Vec<_> = headers.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            get_path(web, path, &headers).await.unwrap()
+        }
+    };
+    let header = |response: &str, name: &str| {
+        response
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{}: ", name)))
+            .map(|value| value.to_string())
+    };
+
+    let response = get("/app.3f9a2c1d.js", vec![]).await;
+    assert_eq!(
+        header(&response, "Cache-Control").unwrap(),
+        "public, max-age=31536000, immutable"
+    );
+
+    let response = get("/", vec![]).await;
+    assert_eq!(
+        header(&response, "Cache-Control").unwrap(),
+        "private, no-cache, max-age=60"
+    );
+
+    // Last-Modified is the file's mtime.
+    let mtime: chrono::DateTime<chrono::Utc> = std::fs::metadata(dir.join("index.html"))
+        .unwrap()
+        .modified()
+        .unwrap()
+        .into();
+    assert_eq!(
+        header(&response, "Last-Modified").unwrap(),
+        mtime.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+    );
+
+    // Revalidated files keep their policy.
+    let etag = header(&response, "ETag").unwrap();
+    let response = get("/", vec![("If-None-Match", etag)]).await;
+    assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+    assert_eq!(
+        header(&response, "Cache-Control").unwrap(),
+        "private, no-cache, max-age=60"
+    );
+
+    // No policy, no header.
+    let web = Web::new(dir.to_string_lossy().to_string());
+    let response = get_with_headers(&web, &[]).await;
+    assert!(header(&response, "Cache-Control").is_none());
+}
+
+#[tokio::test]
+async fn traversal() {
+    let dir = fixture_dir("traversal");
+    std::fs::write(dir.join("my file.txt"), CONTENT).unwrap();
+    let secret = std::env::temp_dir().join("hype-file-test-traversal-secret.txt");
+    std::fs::write(&secret, "secret").unwrap();
+
+    let file = File::new(dir.to_string_lossy().to_string());
+    let web = Web::new(dir.to_string_lossy().to_string());
+    let handlers: [&dyn Handler; 2] = [&file, &web];
+
+    for handler in handlers {
+        let get = |path| get_path(handler, path, &[]);
+
+        // Percent-encoded names are decoded.
+        assert_eq!(body(&get("/my%20file.txt").await.unwrap()), CONTENT);
+
+        // Dot segments, encoded or not, are resolved by the URL parser, and can't go above
+        // the root.
+        for path in [
+            "/../hype-file-test-traversal-secret.txt",
+            "/%2e%2e/hype-file-test-traversal-secret.txt",
+            "/a/../../../hype-file-test-traversal-secret.txt",
+        ] {
+            assert!(get(path).await.is_err(), "served {}", path);
+        }
+
+        // Encoded slashes get past the URL parser, so the decoded path is checked too.
+        for path in [
+            "/..%2fhype-file-test-traversal-secret.txt",
+            "/%2e%2e%2Fhype-file-test-traversal-secret.txt",
+            "/a%2f..%2f..%2fhype-file-test-traversal-secret.txt",
+            "/..%5chype-file-test-traversal-secret.txt",
+            "/file.txt%00.html",
+        ] {
+            match get(path).await {
+                Err(Error::Status(status)) => assert_eq!(status.code, 403, "{}", path),
+                result => panic!("expected a 403 for {}, got {:?}", path, result),
+            }
+        }
+    }
+
+    // On the wire, the request gets exactly one 403, and the connection stays in sync.
+    let mut server = Server::new("127.0.0.1", 9133);
+    server.route("/files", file);
+    server.route("/web", web);
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    for prefix in ["/files", "/web"] {
+        let mut stream = tokio::net::TcpStream::connect("127.0.0.1:9133")
+            .await
+            .unwrap();
+        let requests = format!(
+            "GET {0}/..%2fhype-file-test-traversal-secret.txt HTTP/1.1\r\nHost: localhost\r\n\r\n\
+             GET {0}/file.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            prefix
+        );
+        stream.write_all(requests.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert_eq!(response.matches("HTTP/1.1 ").count(), 2, "{}", response);
+        assert!(response.starts_with("HTTP/1.1 403 "), "{}", response);
+        assert!(response.ends_with(CONTENT), "{}", response);
+    }
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let dir = fixture_dir("symlinks");
+    let outside = std::env::temp_dir().join("hype-file-test-symlinks-outside");
+    std::fs::create_dir_all(&outside).unwrap();
+    std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+    std::fs::write(outside.join("index.html"), "secret").unwrap();
+    std::fs::create_dir_all(dir.join("app")).unwrap();
+
+    for (link, target) in [
+        ("secret.txt", outside.join("secret.txt")),
+        ("outside", outside.clone()),
+        ("app/index.html", outside.join("index.html")),
+        ("inside.txt", dir.join("file.txt")),
+    ] {
+        _ = std::fs::remove_file(dir.join(link));
+        symlink(target, dir.join(link)).unwrap();
+    }
+
+    // Symlinks out of the root are 404s by default, whether they're files, directories, or
+    // index files.
+    let file = File::new(dir.to_string_lossy().to_string());
+    let mut web = Web::new(dir.to_string_lossy().to_string());
+    let handlers: [&dyn Handler; 2] = [&file, &web];
+    for handler in handlers {
+        for path in ["/secret.txt", "/outside/secret.txt"] {
+            match get_path(handler, path, &[]).await {
+                Err(Error::Status(status)) => assert_eq!(status.code, 404, "{}", path),
+                result => panic!("expected a 404 for {}, got {:?}", path, result),
+            }
+        }
+
+        // Symlinks within the root are fine.
+        assert_eq!(
+            body(&get_path(handler, "/inside.txt", &[]).await.unwrap()),
+            CONTENT
+        );
+    }
+
+    assert!(matches!(
+        get_path(&web, "/app/", &[]).await,
+        Err(Error::Status(status)) if status.code == 404
+    ));
+
+    // Unless they're explicitly allowed.
+    web.follow_symlinks(true);
+    for path in ["/secret.txt", "/outside/secret.txt", "/app/"] {
+        assert_eq!(body(&get_path(&web, path, &[]).await.unwrap()), "secret");
+    }
+}
@@ -0,0 +1,72 @@
+use hype::{
+    form::{parse, FormError},
+    request::Request,
+};
+
+fn form_request(content_type: &str, body: &str) -> Request {
+    Request::from(format!(
+        "POST /form HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn urlencoded() {
+    let mut request = form_request(
+        "application/x-www-form-urlencoded",
+        "name=a%20b&greeting=hello+world&tag=x&tag=y%26z&empty=",
+    );
+
+    let form = request.form().await.unwrap();
+    assert_eq!(form.get("name"), Some("a b"));
+    assert_eq!(form.get("greeting"), Some("hello world"));
+    assert_eq!(form.get_all("tag"), vec!["x", "y&z"]);
+    assert_eq!(form.get("empty"), Some(""));
+    assert_eq!(form.get("missing"), None);
+    assert!(form.files.is_empty());
+
+    let params = request.post_params().unwrap();
+    assert_eq!(params.get("name").unwrap(), "a b");
+    assert_eq!(params.get("greeting").unwrap(), "hello world");
+}
+
+#[tokio::test]
+async fn multipart() {
+    let body = "--XyZ\r\n\
+Content-Disposition: form-data; name=\"title\"\r\n\
+\r\n\
+a%20b\r\n\
+--XyZ\r\n\
+Content-Disposition: form-data; name=\"upload\"; filename=\"notes.txt\"\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+line 1\r\n\
+--XyZ--\r\n";
+    let request = form_request("multipart/form-data; boundary=XyZ", body);
+
+    let form = request.form().await.unwrap();
+    // Multipart values aren't percent-encoded.
+    assert_eq!(
+        form.fields,
+        vec![("title".to_string(), "a%20b".to_string())]
+    );
+    assert_eq!(form.files.len(), 1);
+
+    let upload = form.file("upload").unwrap();
+    assert_eq!(upload.filename.as_deref(), Some("notes.txt"));
+    assert_eq!(upload.text(), "line 1");
+}
+
+#[tokio::test]
+async fn not_a_form() {
+    let request = form_request("application/json", "{}");
+    assert_eq!(request.form().await.unwrap_err(), FormError::NotForm);
+
+    assert!(matches!(
+        parse("multipart/form-data; boundary=XyZ", b"garbage"),
+        Err(FormError::Multipart(_))
+    ));
+}
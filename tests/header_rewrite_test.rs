@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use hype::{
+    handler::{self, AsyncWriteStream, Handler},
+    handlers::{self, header_rewrite::Rule, HeaderRewrite},
+    request::Request,
+    response::Response,
+    status,
+};
+use tokio::io::AsyncWriteExt;
+
+/// Responds with the request's headers in the body, and some headers of its own.
+struct EchoHeaders {}
+
+#[async_trait]
+impl Handler for EchoHeaders {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let mut response = Response::new(status::OK);
+        response.headers.set("Server", "backend/1.0");
+        response.headers.set("X-Backend-Version", "42");
+        response.set_body(r.headers.serialize());
+        w.write_all(&response.serialize_bytes()).await.unwrap();
+        Ok(handler::Action::Done)
+    }
+}
+
+fn request() -> Request {
+    Request::from(
+        "GET / HTTP/1.1\r\nHost: localhost\r\nCookie: secret=1\r\nX-Old: value\r\nAccept: */*\r\n\r\n",
+    )
+    .unwrap()
+}
+
+async fn handle(handler: &HeaderRewrite) -> String {
+    let mut stream: Vec<u8> = vec![];
+    handler.handle(&request(), &mut stream).await.unwrap();
+    String::from_utf8(stream).unwrap()
+}
+
+fn body(response: &str) -> &str {
+    response.split_once("\r\n\r\n").unwrap().1
+}
+
+#[tokio::test]
+async fn request_headers() {
+    let handler = HeaderRewrite::new(EchoHeaders {})
+        .remove_request_header("cookie")
+        .rename_request_header("X-Old", "X-New")
+        .set_request_header("Accept", "text/html")
+        .add_request_header("X-Forwarded-Proto", "https");
+
+    // The downstream handler never sees the removed header.
+    let response = handle(&handler).await;
+    assert_eq!(
+        body(&response),
+        "Host: localhost\r\nAccept: text/html\r\nX-New: value\r\nX-Forwarded-Proto: https"
+    );
+
+    // The original request is left alone.
+    assert_eq!(request().headers.get_first("cookie").unwrap(), "secret=1");
+}
+
+#[tokio::test]
+async fn response_headers() {
+    let handler = HeaderRewrite::new(EchoHeaders {})
+        .remove_response_header("server")
+        .rename_response_header("X-Backend-Version", "X-Version")
+        .add_response_header("X-Frame-Options", "DENY")
+        .with_response_rule(Rule::Set("X-Version".into(), "43".into()));
+
+    let response = handle(&handler).await;
+    let (head, _) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(!head.to_lowercase().contains("server:"));
+    assert!(!head.contains("X-Backend-Version"));
+    assert!(head.contains("\r\nX-Version: 43"));
+    assert!(head.contains("\r\nX-Frame-Options: DENY"));
+
+    // The body is untouched, and still matches its length.
+    let length = format!("Content-Length: {}", body(&response).len());
+    assert!(head.contains(&length));
+    assert!(body(&response).starts_with("Host: localhost\r\n"));
+
+    // Returned responses are rewritten too.
+    let handler = HeaderRewrite::new(handlers::handler(|_| async move {
+        let mut response = Response::new(status::OK);
+        response.headers.set("Server", "backend/1.0");
+        Ok(response)
+    }))
+    .remove_response_header("Server");
+    let mut stream: Vec<u8> = vec![];
+    match handler.handle(&request(), &mut stream).await.unwrap() {
+        handler::Action::Response(response) => assert!(response.headers.get("server").is_none()),
+        _ => panic!("expected a response"),
+    }
+    assert!(stream.is_empty());
+}
@@ -13,9 +13,15 @@ fn test_headers_add() {
     let mut headers = Headers::new();
     headers.add("Content-Type".to_string(), "text/html".to_string());
     assert_eq!(headers.fields.len(), 1);
-    assert_eq!(headers.fields.get("content-type").unwrap().len(), 1);
+    assert_eq!(headers.fields.get("content-type").unwrap().values.len(), 1);
     assert_eq!(
-        headers.fields.get("content-type").unwrap().first().unwrap(),
+        headers
+            .fields
+            .get("content-type")
+            .unwrap()
+            .values
+            .first()
+            .unwrap(),
         "text/html"
     );
 }
@@ -26,13 +32,25 @@ fn test_headers_add_multiple() {
     headers.add("Content-Type".to_string(), "text/html".to_string());
     headers.add("Content-Type".to_string(), "text/plain".to_string());
     assert_eq!(headers.fields.len(), 1);
-    assert_eq!(headers.fields.get("content-type").unwrap().len(), 2);
+    assert_eq!(headers.fields.get("content-type").unwrap().values.len(), 2);
     assert_eq!(
-        headers.fields.get("content-type").unwrap().first().unwrap(),
+        headers
+            .fields
+            .get("content-type")
+            .unwrap()
+            .values
+            .first()
+            .unwrap(),
         "text/html"
     );
     assert_eq!(
-        headers.fields.get("content-type").unwrap().last().unwrap(),
+        headers
+            .fields
+            .get("content-type")
+            .unwrap()
+            .values
+            .last()
+            .unwrap(),
         "text/plain"
     );
 }
@@ -43,9 +61,15 @@ fn test_headers_set() {
     headers.add("Content-Type".to_string(), "text/html".to_string());
     headers.set("Content-Type".to_string(), "text/plain".to_string());
     assert_eq!(headers.fields.len(), 1);
-    assert_eq!(headers.fields.get("content-type").unwrap().len(), 1);
+    assert_eq!(headers.fields.get("content-type").unwrap().values.len(), 1);
     assert_eq!(
-        headers.fields.get("content-type").unwrap().first().unwrap(),
+        headers
+            .fields
+            .get("content-type")
+            .unwrap()
+            .values
+            .first()
+            .unwrap(),
         "text/plain"
     );
 }
@@ -65,13 +89,25 @@ fn test_headers_get() {
     headers.add("Content-Type".to_string(), "text/html".to_string());
     headers.add("Content-Type".to_string(), "text/plain".to_string());
     assert_eq!(headers.fields.len(), 1);
-    assert_eq!(headers.fields.get("content-type").unwrap().len(), 2);
+    assert_eq!(headers.fields.get("content-type").unwrap().values.len(), 2);
     assert_eq!(
-        headers.fields.get("content-type").unwrap().first().unwrap(),
+        headers
+            .fields
+            .get("content-type")
+            .unwrap()
+            .values
+            .first()
+            .unwrap(),
         "text/html"
     );
     assert_eq!(
-        headers.fields.get("content-type").unwrap().last().unwrap(),
+        headers
+            .fields
+            .get("content-type")
+            .unwrap()
+            .values
+            .last()
+            .unwrap(),
         "text/plain"
     );
 }
@@ -82,13 +118,25 @@ fn test_headers_get_first() {
     headers.add("Content-Type".to_string(), "text/html".to_string());
     headers.add("Content-Type".to_string(), "text/plain".to_string());
     assert_eq!(headers.fields.len(), 1);
-    assert_eq!(headers.fields.get("content-type").unwrap().len(), 2);
+    assert_eq!(headers.fields.get("content-type").unwrap().values.len(), 2);
     assert_eq!(
-        headers.fields.get("content-type").unwrap().first().unwrap(),
+        headers
+            .fields
+            .get("content-type")
+            .unwrap()
+            .values
+            .first()
+            .unwrap(),
         "text/html"
     );
     assert_eq!(
-        headers.fields.get("content-type").unwrap().last().unwrap(),
+        headers
+            .fields
+            .get("content-type")
+            .unwrap()
+            .values
+            .last()
+            .unwrap(),
         "text/plain"
     );
     assert_eq!(headers.get_first("Content-Type").unwrap(), "text/html");
@@ -107,3 +155,129 @@ fn test_headers_serialize() {
 
     println!("Serialized:\n{}", serialized);
 }
+
+#[test]
+fn test_headers_get_first_or_set() {
+    let mut headers = Headers::new();
+    assert_eq!(headers.get_first_or_set("Content-Length", "10"), "10");
+    assert_eq!(headers.get_first_or_set("Content-Length", "20"), "10");
+    assert_eq!(headers.get("content-length").unwrap().len(), 1);
+}
+
+#[test]
+fn test_content_length() {
+    let mut headers = Headers::new();
+    assert_eq!(headers.content_length(), None);
+
+    headers.set("Content-Length", "42");
+    assert_eq!(headers.content_length(), Some(42));
+
+    headers.add("Content-Length", "42");
+    assert_eq!(headers.content_length(), Some(42));
+
+    headers.add("Content-Length", "43");
+    assert_eq!(headers.content_length(), None);
+
+    for bad in ["-1", "+1", "1.0", "abc", ""] {
+        headers.set("Content-Length", bad);
+        assert_eq!(headers.content_length(), None, "{}", bad);
+    }
+}
+
+#[test]
+fn test_content_type() {
+    let mut headers = Headers::new();
+    headers.set("Content-Type", r#"Text/HTML; Charset="UTF-8"; q=1"#);
+
+    let content_type = headers.content_type().unwrap();
+    assert_eq!(content_type.mime, "text/html");
+    assert_eq!(content_type.charset(), Some("UTF-8"));
+    assert_eq!(content_type.param("Q"), Some("1"));
+    assert_eq!(content_type.param("boundary"), None);
+
+    for bad in ["text", "text/", "/html", "a/b/c", "=text/html"] {
+        headers.set("Content-Type", bad);
+        assert!(headers.content_type().is_none(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_accept_encodings() {
+    let mut headers = Headers::new();
+    assert!(headers.accept_encodings().is_empty());
+
+    headers.set(
+        "Accept-Encoding",
+        "deflate;q=0.5, GZIP, br;q=0.8, identity;q=0",
+    );
+    headers.add("Accept-Encoding", "zstd; q=0.8");
+    assert_eq!(
+        headers.accept_encodings(),
+        vec![
+            ("gzip".to_string(), 1.0),
+            ("br".to_string(), 0.8),
+            ("zstd".to_string(), 0.8),
+            ("deflate".to_string(), 0.5),
+            ("identity".to_string(), 0.0),
+        ]
+    );
+
+    // Malformed q-values are skipped.
+    headers.set("Accept-Encoding", "gzip;q=abc, br;q=2, deflate;q=-1, zstd");
+    assert_eq!(headers.accept_encodings(), vec![("zstd".to_string(), 1.0)]);
+}
+
+#[test]
+fn test_host() {
+    let mut headers = Headers::new();
+    assert_eq!(headers.host(), None);
+
+    let cases = [
+        ("Example.com", Some(("example.com", None))),
+        ("example.com:8080", Some(("example.com", Some(8080)))),
+        ("[::1]", Some(("[::1]", None))),
+        ("[::1]:8080", Some(("[::1]", Some(8080)))),
+        ("::1", Some(("::1", None))),
+        ("example.com:http", None),
+        ("example.com:99999", None),
+        (":8080", None),
+    ];
+
+    for (host, expected) in cases {
+        headers.set("Host", host);
+        assert_eq!(
+            headers.host(),
+            expected.map(|(h, p)| (h.to_string(), p)),
+            "{}",
+            host
+        );
+    }
+}
+
+#[test]
+fn test_headers_order_and_casing() {
+    let mut headers = Headers::new();
+    headers.add("X-Zebra", "1");
+    headers.add("Content-Type", "text/html");
+    headers.add("x-amz-date", "20240101T000000Z");
+    headers.add("x-zebra", "2");
+    assert_eq!(
+        headers.serialize(),
+        "X-Zebra: 1\r\nX-Zebra: 2\r\nContent-Type: text/html\r\nx-amz-date: 20240101T000000Z"
+    );
+
+    // Lookups ignore case.
+    assert_eq!(headers.get("X-AMZ-DATE").unwrap().len(), 1);
+
+    // Replaced fields keep their position, but take the new casing.
+    headers.set("CONTENT-TYPE", "text/plain");
+    let names: Vec<&String> = headers.iter().map(|(k, _)| k).collect();
+    assert_eq!(names, vec!["X-Zebra", "CONTENT-TYPE", "x-amz-date"]);
+
+    // Removing fields keeps the order of the rest.
+    headers.remove("x-zebra");
+    assert_eq!(
+        headers.serialize(),
+        "CONTENT-TYPE: text/plain\r\nx-amz-date: 20240101T000000Z"
+    );
+}
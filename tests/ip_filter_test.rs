@@ -0,0 +1,84 @@
+use hype::{
+    handler::{Action, Error, Handler},
+    handlers::ip_filter::{Cidr, IpFilter, Precedence},
+    request::{Method, Request},
+};
+
+async fn check(filter: &IpFilter, peer: &str) -> bool {
+    let mut request = Request::new(Method::GET, "/admin");
+    request.set_peer_addr(peer.parse().unwrap());
+
+    let mut w: Vec<u8> = vec![];
+    match filter.handle(&request, &mut w).await {
+        Ok(Action::Next) => true,
+        Err(Error::Status(status)) if status.code == 403 => false,
+        result => panic!("unexpected result: {:?}", result),
+    }
+}
+
+#[test]
+fn parses_cidrs() {
+    let cidr: Cidr = "10.1.0.0/16".parse().unwrap();
+    assert!(cidr.contains(&"10.1.2.3".parse().unwrap()));
+    assert!(!cidr.contains(&"10.2.0.1".parse().unwrap()));
+    assert!(cidr.contains(&"::ffff:10.1.2.3".parse().unwrap()));
+    assert_eq!(cidr.to_string(), "10.1.0.0/16");
+
+    let cidr: Cidr = "0.0.0.0/0".parse().unwrap();
+    assert!(cidr.contains(&"1.2.3.4".parse().unwrap()));
+    assert!(!cidr.contains(&"::1".parse().unwrap()));
+
+    assert_eq!("::1".parse::<Cidr>().unwrap().to_string(), "::1/128");
+    assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    assert!("10.0.0/8".parse::<Cidr>().is_err());
+    assert!(IpFilter::builder().allow("nope").build().is_err());
+}
+
+#[tokio::test]
+async fn allows_and_denies() {
+    let filter = IpFilter::builder()
+        .allow("10.0.0.0/8")
+        .deny("10.0.0.1")
+        .default_allow(false)
+        .build()
+        .unwrap();
+
+    assert!(check(&filter, "10.1.2.3:1234").await);
+    assert!(!check(&filter, "10.0.0.1:1234").await);
+    assert!(!check(&filter, "192.168.1.1:1234").await);
+
+    // Requests without a client address get the default.
+    let mut w: Vec<u8> = vec![];
+    let result = filter.handle(&Request::new(Method::GET, "/"), &mut w).await;
+    assert!(matches!(result, Err(Error::Status(_))));
+}
+
+#[tokio::test]
+async fn precedence() {
+    let filter = IpFilter::builder()
+        .allow("10.0.0.1")
+        .deny("10.0.0.0/8")
+        .precedence(Precedence::AllowOverrides)
+        .build()
+        .unwrap();
+
+    assert!(check(&filter, "10.0.0.1:1234").await);
+    assert!(!check(&filter, "10.0.0.2:1234").await);
+    assert!(check(&filter, "192.168.1.1:1234").await);
+}
+
+#[tokio::test]
+async fn ipv6() {
+    let filter = IpFilter::builder()
+        .allow("fd00::/8")
+        .allow("127.0.0.1")
+        .default_allow(false)
+        .build()
+        .unwrap();
+
+    assert!(check(&filter, "[fd12:3456::1]:1234").await);
+    assert!(!check(&filter, "[fe80::1]:1234").await);
+
+    // IPv4 clients on dual-stack sockets.
+    assert!(check(&filter, "[::ffff:127.0.0.1]:1234").await);
+}
@@ -1,24 +1,35 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use async_trait::async_trait;
 
 use futures::StreamExt;
 use hype::{
-    client::{self, Client},
+    body::Body,
+    client::{self, Client, ClientPool},
     handler::{self, AsyncWriteStream, Handler},
     handlers,
     lb::{
+        self,
         backend::{Backend, HttpBackend},
+        breaker::BreakerState,
+        health::BackendHealth,
         http::{self, Http},
-        picker::{Picker, RRPicker, RandomPicker, WeightedRRPicker},
+        picker::{ConsistentHashPicker, Picker, RRPicker, RandomPicker, WeightedRRPicker},
     },
+    lbconfig,
     request::{Method, Request},
     response::Response,
     server::Server,
     status,
 };
 use tokio::{
-    io::AsyncWriteExt,
+    io::{AsyncReadExt, AsyncWriteExt},
     sync::{mpsc, Notify},
 };
 
@@ -52,6 +63,10 @@ impl MockBackend {
 
 #[async_trait]
 impl Backend for MockBackend {
+    fn id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+
     async fn send_request(&self, req: &Request) -> Result<Response, client::ClientError> {
         println!("id: {}, request: {:?}", self.id, req);
         let mut stats = self.stats.lock().unwrap();
@@ -152,6 +167,448 @@ async fn weighted_rr_policy() {
     assert_eq!(results[3].send_request_attempts, 8);
 }
 
+#[tokio::test]
+async fn consistent_hash_policy() {
+    let backends = (1..=4)
+        .map(|i| MockBackend::new(format!("b{}", i)))
+        .collect();
+    let mut lb = http::Http::new(backends, ConsistentHashPicker::new());
+    lb.hash_key_header("x-cache-key");
+
+    for i in 0..40 {
+        for _ in 0..3 {
+            let mut req = Request::new(Method::GET, "/");
+            req.headers.set("X-Cache-Key", format!("key-{}", i));
+            lb.send_request(&req).await.unwrap();
+        }
+    }
+
+    // Every request for a key should land on the same backend
+    let results = futures::future::join_all((0..4).map(|i| get_stats(&lb, i))).await;
+    for stats in &results {
+        assert_eq!(stats.send_request_attempts % 3, 0);
+        for chunk in stats.requests.chunks(3) {
+            let keys: Vec<&String> = chunk
+                .iter()
+                .map(|r| r.headers.get_first("x-cache-key").unwrap())
+                .collect();
+            assert!(keys.iter().all(|k| *k == keys[0]));
+        }
+    }
+}
+
+#[tokio::test]
+async fn forwarded_for() {
+    let lb = http::Http::new(vec![MockBackend::new("b1")], RRPicker::new());
+
+    let mut req = Request::new(Method::GET, "/");
+    req.set_peer_addr("203.0.113.7:4000".parse().unwrap());
+    lb.send_request(&req).await.unwrap();
+
+    req.headers.set("X-Forwarded-For", "198.51.100.1");
+    lb.send_request(&req).await.unwrap();
+
+    // The client came through a trusted proxy, so the proxy is the hop that's added.
+    let mut req = Request::new(Method::GET, "/");
+    req.headers.set("X-Forwarded-For", "203.0.113.7");
+    req.set_peer_addr("203.0.113.7:0".parse().unwrap());
+    req.set_socket_addr("10.0.0.1:4000".parse().unwrap());
+    lb.send_request(&req).await.unwrap();
+
+    let stats = get_stats(&lb, 0).await;
+    let forwarded: Vec<&String> = stats
+        .requests
+        .iter()
+        .map(|r| r.headers.get_first("x-forwarded-for").unwrap())
+        .collect();
+    assert_eq!(
+        forwarded,
+        vec![
+            "203.0.113.7",
+            "198.51.100.1, 203.0.113.7",
+            "203.0.113.7, 10.0.0.1"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn request_id() {
+    let lb = http::Http::new(vec![MockBackend::new("b1")], RRPicker::new());
+
+    // Requests without an ID are sent as is.
+    let req = Request::new(Method::GET, "/");
+    lb.send_request(&req).await.unwrap();
+
+    let req = Request::new(Method::GET, "/");
+    handlers::RequestId::new()
+        .handle(&req, &mut vec![])
+        .await
+        .unwrap();
+    lb.send_request(&req).await.unwrap();
+
+    let stats = get_stats(&lb, 0).await;
+    assert!(stats.requests[0]
+        .headers
+        .get_first("x-request-id")
+        .is_none());
+    assert_eq!(
+        stats.requests[1].headers.get_first("x-request-id").unwrap(),
+        req.request_id().unwrap()
+    );
+}
+
+/// A backend that fails requests while `failing` is set.
+#[derive(Debug, Default)]
+struct FlakyBackend {
+    id: Option<String>,
+    failing: AtomicBool,
+    attempts: AtomicUsize,
+}
+
+#[async_trait]
+impl Backend for FlakyBackend {
+    fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    async fn send_request(&self, _req: &Request) -> Result<Response, client::ClientError> {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+        if self.failing.load(Ordering::Relaxed) {
+            return Err(client::ClientError::ConnectionBroken);
+        }
+        Ok(Response::new(status::OK))
+    }
+}
+
+#[tokio::test]
+async fn circuit_breaker() {
+    let cooldown = Duration::from_millis(100);
+    let lb = http::Http::new(vec![FlakyBackend::default()], RRPicker::new()).with_circuit_breaker(
+        3,
+        Duration::from_secs(10),
+        cooldown,
+    );
+    let breaker = lb.circuit_breaker().unwrap();
+    let backends = lb.get_backends();
+    let backend = &backends.read().await[0];
+    let req = Request::new(Method::GET, "/");
+
+    backend.failing.store(true, Ordering::Relaxed);
+    for _ in 0..3 {
+        assert_eq!(breaker.state(0), BreakerState::Closed);
+        assert!(matches!(
+            lb.send_request(&req).await,
+            Err(client::ClientError::ConnectionBroken)
+        ));
+    }
+
+    // Open: requests fail fast, without reaching the backend.
+    assert_eq!(breaker.state(0), BreakerState::Open);
+    assert!(matches!(
+        lb.send_request(&req).await,
+        Err(client::ClientError::ConnectionError)
+    ));
+    assert_eq!(backend.attempts.load(Ordering::Relaxed), 3);
+
+    // Half-open: a failed trial request opens the breaker again.
+    tokio::time::sleep(cooldown).await;
+    assert_eq!(breaker.state(0), BreakerState::HalfOpen);
+    assert!(matches!(
+        lb.send_request(&req).await,
+        Err(client::ClientError::ConnectionBroken)
+    ));
+    assert_eq!(backend.attempts.load(Ordering::Relaxed), 4);
+    assert_eq!(breaker.state(0), BreakerState::Open);
+    assert!(lb.send_request(&req).await.is_err());
+    assert_eq!(backend.attempts.load(Ordering::Relaxed), 4);
+
+    // Half-open: a successful trial request closes it.
+    tokio::time::sleep(cooldown).await;
+    backend.failing.store(false, Ordering::Relaxed);
+    assert_eq!(breaker.state(0), BreakerState::HalfOpen);
+    assert!(lb.send_request(&req).await.is_ok());
+    assert_eq!(breaker.state(0), BreakerState::Closed);
+    assert!(lb.send_request(&req).await.is_ok());
+    assert_eq!(backend.attempts.load(Ordering::Relaxed), 6);
+}
+
+#[tokio::test]
+async fn circuit_breaker_skips_open_backends() {
+    let lb = http::Http::new(
+        vec![FlakyBackend::default(), FlakyBackend::default()],
+        RRPicker::new(),
+    )
+    .with_circuit_breaker(2, Duration::from_secs(10), Duration::from_secs(60));
+    let backends = lb.get_backends();
+    backends.read().await[0]
+        .failing
+        .store(true, Ordering::Relaxed);
+
+    let req = Request::new(Method::GET, "/");
+    let results: Vec<bool> = futures::future::join_all((0..10).map(|_| lb.send_request(&req)))
+        .await
+        .iter()
+        .map(|r| r.is_ok())
+        .collect();
+
+    // Backend 0 gets every other request until its breaker opens, then backend 1 gets the rest.
+    assert_eq!(results.iter().filter(|ok| !**ok).count(), 2);
+    assert_eq!(backends.read().await[0].attempts.load(Ordering::Relaxed), 2);
+    assert_eq!(backends.read().await[1].attempts.load(Ordering::Relaxed), 8);
+    assert_eq!(lb.circuit_breaker().unwrap().state(0), BreakerState::Open);
+}
+
+/// Returns an LB over backends that fail if `failing` is set for them.
+async fn flaky_lb(failing: &[bool]) -> Http<FlakyBackend, RRPicker> {
+    let lb = http::Http::new(
+        failing.iter().map(|_| FlakyBackend::default()).collect(),
+        RRPicker::new(),
+    );
+    for (backend, failing) in lb.get_backends().read().await.iter().zip(failing) {
+        backend.failing.store(*failing, Ordering::Relaxed);
+    }
+    lb
+}
+
+async fn attempts(lb: &Http<FlakyBackend, RRPicker>) -> Vec<usize> {
+    lb.get_backends()
+        .read()
+        .await
+        .iter()
+        .map(|b| b.attempts.load(Ordering::Relaxed))
+        .collect()
+}
+
+#[tokio::test]
+async fn retries() {
+    let lb = flaky_lb(&[true, false, false]).await.with_retries(2);
+    let response = lb.send_request(&Request::new(Method::GET, "/")).await;
+    assert!(response.is_ok());
+    assert_eq!(attempts(&lb).await, vec![1, 1, 0]);
+}
+
+#[tokio::test]
+async fn retries_exhausted() {
+    let lb = flaky_lb(&[true, true, true]).await.with_retries(1);
+    let response = lb.send_request(&Request::new(Method::GET, "/")).await;
+    assert!(matches!(
+        response,
+        Err(client::ClientError::ConnectionBroken)
+    ));
+    assert_eq!(attempts(&lb).await, vec![1, 1, 0]);
+
+    // Requests aren't retried on backends that were already tried.
+    let lb = flaky_lb(&[true, true]).await.with_retries(5);
+    assert!(lb
+        .send_request(&Request::new(Method::GET, "/"))
+        .await
+        .is_err());
+    assert_eq!(attempts(&lb).await, vec![1, 1]);
+}
+
+#[tokio::test]
+async fn retries_non_idempotent() {
+    let lb = flaky_lb(&[true, false]).await.with_retries(1);
+    assert!(lb
+        .send_request(&Request::new(Method::POST, "/"))
+        .await
+        .is_err());
+    assert_eq!(attempts(&lb).await, vec![1, 0]);
+
+    // Bodies streamed from readers can't be replayed.
+    let lb = flaky_lb(&[true, false]).await.with_retries(1);
+    let mut req = Request::new(Method::PUT, "/");
+    req.body = Body::from_reader(std::io::Cursor::new(b"foobar".to_vec()));
+    assert!(lb.send_request(&req).await.is_err());
+    assert_eq!(attempts(&lb).await, vec![1, 0]);
+
+    let lb = flaky_lb(&[true, false])
+        .await
+        .with_retries(1)
+        .with_non_idempotent_retries();
+    assert!(lb
+        .send_request(&Request::new(Method::POST, "/"))
+        .await
+        .is_ok());
+    assert_eq!(attempts(&lb).await, vec![1, 1]);
+}
+
+#[test]
+fn consistent_hash_remapping() {
+    let picker = ConsistentHashPicker::new();
+    let backends: Vec<MockBackend> = (1..=10)
+        .map(|i| MockBackend::new(format!("b{}", i)))
+        .collect();
+    let keys: Vec<String> = (0..1000).map(|i| format!("key-{}", i)).collect();
+
+    let before: Vec<String> = keys
+        .iter()
+        .map(|k| {
+            backends[picker.pick_backend_for(&backends, k.as_bytes()).unwrap()]
+                .id
+                .clone()
+        })
+        .collect();
+
+    // Remove one backend: only the keys it owned should move
+    let backends: Vec<MockBackend> = backends.into_iter().filter(|b| b.id != "b4").collect();
+    let after: Vec<String> = keys
+        .iter()
+        .map(|k| {
+            backends[picker.pick_backend_for(&backends, k.as_bytes()).unwrap()]
+                .id
+                .clone()
+        })
+        .collect();
+
+    let moved = before
+        .iter()
+        .zip(after.iter())
+        .filter(|(b, a)| b != a)
+        .inspect(|(b, _)| assert_eq!(*b, "b4"))
+        .count();
+    assert!(moved > 0 && moved < 250, "moved {} keys", moved);
+}
+
+#[test]
+fn pickers_skip_unhealthy_backends() {
+    let backends: Vec<MockBackend> = (1..=3)
+        .map(|i| MockBackend::new(format!("b{}", i)))
+        .collect();
+    let health = BackendHealth::new();
+    health.set_healthy(1, false);
+
+    let rr = RRPicker::new();
+    for _ in 0..6 {
+        assert_ne!(
+            rr.pick_healthy_backend(&backends, None, &health).unwrap(),
+            1
+        );
+    }
+
+    // Keys owned by the unhealthy backend move to another one, others stay put.
+    let ch = ConsistentHashPicker::new();
+    for i in 0..100 {
+        let key = format!("key-{}", i);
+        let owner = ch.pick_backend_for(&backends, key.as_bytes()).unwrap();
+        let picked = ch
+            .pick_healthy_backend(&backends, Some(key.as_bytes()), &health)
+            .unwrap();
+        assert_ne!(picked, 1);
+        if owner != 1 {
+            assert_eq!(picked, owner);
+        }
+    }
+
+    // If nothing is healthy, pick anyway
+    health.set_healthy(0, false);
+    health.set_healthy(2, false);
+    assert!(rr.pick_healthy_backend(&backends, None, &health).is_ok());
+}
+
+#[tokio::test]
+async fn health_checks() {
+    let shutdown1 = start_server(10400, "server 1".to_string()).await;
+    let shutdown2 = start_server(10401, "server 2".to_string()).await;
+
+    // Don't keep idle connections around, so checks notice the server going away
+    // immediately.
+    let backends = vec![
+        HttpBackend::new("localhost:10400").with_pool(ClientPool::new(0)),
+        HttpBackend::new("localhost:10401").with_pool(ClientPool::new(0)),
+    ];
+    let lb = Http::new(backends, RRPicker::new()).with_health_check(
+        "/healthz",
+        Duration::from_millis(50),
+        1,
+        1,
+    );
+    let health = lb.health();
+
+    let mut responses = vec![];
+    for _ in 0..4 {
+        let response = lb
+            .send_request(&Request::new(Method::GET, "/"))
+            .await
+            .unwrap();
+        responses.push(response.content().await);
+    }
+    assert!(responses.contains(&"server 1".to_string()));
+    assert!(responses.contains(&"server 2".to_string()));
+
+    shutdown_server(shutdown2).await;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(health.is_healthy(0));
+    assert!(!health.is_healthy(1));
+
+    for _ in 0..6 {
+        let response = lb
+            .send_request(&Request::new(Method::GET, "/"))
+            .await
+            .unwrap();
+        assert_eq!(response.content().await, "server 1");
+    }
+
+    shutdown_server(shutdown1).await;
+}
+
+#[tokio::test]
+async fn health_checks_remove_backend() {
+    let backends = ["a", "b", "c"]
+        .iter()
+        .map(|id| FlakyBackend {
+            id: Some(id.to_string()),
+            ..Default::default()
+        })
+        .collect();
+    let lb = Http::new(backends, RRPicker::new()).with_health_check(
+        "/healthz",
+        Duration::from_millis(50),
+        3,
+        100,
+    );
+    let health = lb.health();
+
+    // Waits until `f` is true, checking every few milliseconds.
+    async fn wait_for(f: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !f() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    lb.get_backends().read().await[1]
+        .failing
+        .store(true, Ordering::Relaxed);
+    wait_for(|| !health.is_healthy(1)).await;
+
+    // The backend after the removed one doesn't inherit its failures, so a single failed
+    // check doesn't take it down.
+    assert!(lb.remove_backend("b").await.is_some());
+    let backends = lb.get_backends();
+    let attempts = || {
+        backends
+            .try_read()
+            .map_or(0, |b| b[1].attempts.load(Ordering::Relaxed))
+    };
+    let before = attempts();
+    backends.read().await[1]
+        .failing
+        .store(true, Ordering::Relaxed);
+    wait_for(|| attempts() > before).await;
+    backends.read().await[1]
+        .failing
+        .store(false, Ordering::Relaxed);
+
+    let before = attempts();
+    wait_for(|| attempts() >= before + 2).await;
+    assert!(health.is_healthy(0));
+    assert!(health.is_healthy(1));
+}
+
 async fn start_server(port: u16, text: String) -> (Arc<mpsc::Sender<bool>>, Arc<Notify>) {
     let handler = handlers::status::Status::new(status::OK, text);
     let mut server = Server::new("localhost", port);
@@ -438,3 +895,400 @@ async fn streaming_lb_chunked() {
     }
     shutdown_server(lb_shutdown).await;
 }
+
+/// Waits for the pool to have `n` idle connections to `address`. Connections go back to the
+/// pool in the background, once their responses are read.
+async fn wait_for_idle(pool: &ClientPool, address: &str, n: usize) {
+    for _ in 0..100 {
+        if pool.idle_count(address).await == n {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(pool.idle_count(address).await, n);
+}
+
+// Test that backend connections are reused through the client pool
+#[tokio::test]
+async fn pooled_backend_connections() {
+    let port = 10300;
+    let shutdown = start_echo_server(port).await;
+    let address = format!("localhost:{}", port);
+
+    let pool = ClientPool::new(1);
+    let backend = HttpBackend::new(address.clone()).with_pool(pool.clone());
+
+    let response = backend
+        .send_request(&Request::new(Method::GET, "/"))
+        .await
+        .unwrap();
+    let conn_id = response.headers.get_first("x-hype-connection-id").cloned();
+    assert!(conn_id.is_some());
+    response.content().await;
+    wait_for_idle(&pool, &address, 1).await;
+
+    // Same connection should be reused
+    let response = backend
+        .send_request(&Request::new(Method::GET, "/"))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers.get_first("x-hype-connection-id").cloned(),
+        conn_id
+    );
+    response.content().await;
+    wait_for_idle(&pool, &address, 1).await;
+
+    // Connections that the upstream closes aren't pooled
+    let mut request = Request::new(Method::GET, "/");
+    request.headers.set("Connection", "close");
+    let response = backend.send_request(&request).await.unwrap();
+    assert_eq!(response.headers.get_first("connection").unwrap(), "close");
+    response.content().await;
+    wait_for_idle(&pool, &address, 0).await;
+
+    let response = backend
+        .send_request(&Request::new(Method::GET, "/"))
+        .await
+        .unwrap();
+    response.content().await;
+    wait_for_idle(&pool, &address, 1).await;
+
+    // Pool is capped at one idle connection per host
+    let extra = Client::new(address.clone()).connect().await.unwrap();
+    pool.put(extra).await;
+    assert_eq!(pool.idle_count(&address).await, 1);
+
+    // Closed connections are discarded
+    let mut client = pool.take(&address).await.unwrap();
+    client.close().await.unwrap();
+    pool.put(client).await;
+    assert_eq!(pool.idle_count(&address).await, 0);
+    assert!(pool.take(&address).await.is_none());
+
+    shutdown_server(shutdown).await;
+}
+
+// Test that only requests that are safe to resend are retried on stale pooled connections
+#[tokio::test]
+async fn stale_pooled_backend_connections() {
+    // The upstream answers one request per connection, then hangs up without saying so. It
+    // counts the connections it accepts.
+    let port = 10301;
+    let listener = tokio::net::TcpListener::bind(("localhost", port))
+        .await
+        .unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&connections);
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                if matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {
+                    _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                        .await;
+                }
+            });
+        }
+    });
+
+    let address = format!("localhost:{}", port);
+    let pool = ClientPool::new(1);
+    let backend = HttpBackend::new(address.clone()).with_pool(pool.clone());
+
+    let get = Request::new(Method::GET, "/");
+    let response = backend.send_request(&get).await.unwrap();
+    assert_eq!(response.content().await, "ok");
+    wait_for_idle(&pool, &address, 1).await;
+
+    // The pooled connection is stale, so the GET is resent on a new one.
+    let response = backend.send_request(&get).await.unwrap();
+    assert_eq!(response.content().await, "ok");
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    wait_for_idle(&pool, &address, 1).await;
+
+    // The POST may have been applied, so it's not.
+    let mut post = Request::new(Method::POST, "/");
+    post.headers.set("Content-Length", "2");
+    post.body = "hi".into();
+    assert!(backend.send_request(&post).await.is_err());
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
+
+// Test that backends relay responses that arrive before the request body is complete
+#[tokio::test]
+async fn streaming_lb_early_response() {
+    // The upstream answers right away, without waiting for the body.
+    let port = 10500;
+    let mut server = Server::new("localhost", port);
+    server.route_default(handlers::handler(|r| async move {
+        Ok(format!("accepted {}", r.body.complete()))
+    }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let balancer = Http::new(
+        vec![HttpBackend::new(format!("localhost:{}", port))],
+        RRPicker::new(),
+    );
+    let mut lb_server = Server::new("localhost", 10599);
+    lb_server.route("/lb", handlers::lb::Lb::new(balancer));
+    let lb_ready = lb_server.start_notifier();
+    let lb_shutdown = lb_server.shutdown();
+    tokio::spawn(async move { lb_server.start().await.unwrap() });
+    lb_ready.notified().await;
+
+    let mut client = Client::new("localhost:10599");
+    let mut client = client.connect().await.unwrap();
+
+    let request = &mut Request::new(Method::POST, "/lb");
+    request.set_chunked();
+    request.body.push_chunk(b"first".to_vec());
+
+    let response = tokio::time::timeout(Duration::from_secs(5), client.send_request(request))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(response.content().await, "accepted false");
+    assert!(!request.body.complete());
+
+    // Finish the body, so the upstream writers can wind down.
+    request.body.push_chunk(b"second".to_vec());
+    request.body.end_chunked();
+
+    shutdown_server(shutdown).await;
+    shutdown_server(lb_shutdown).await;
+}
+
+// Test that lbconfig weights drive a weighted picker, in backend order
+#[tokio::test]
+async fn lbconfig_weights() {
+    let config = lbconfig::Config::from(
+        r#"
+listen_ip: localhost
+port: 10699
+log_level: info
+routes:
+    - location: /lb
+      backends:
+          - host: localhost
+            port: 10600
+            weight: 3
+          - host: localhost
+            port: 10601
+            weight: 1
+"#,
+    )
+    .unwrap();
+
+    let route = &config.routes[0];
+    let weights = route.weights().unwrap();
+    assert_eq!(weights, vec![3, 1]);
+
+    let mut shutdowns = vec![];
+    let mut backends = vec![];
+    for backend in &route.backends {
+        let address = format!("{}:{}", backend.host, backend.port);
+        shutdowns.push(start_server(backend.port, address.clone()).await);
+        backends.push(HttpBackend::new(address));
+    }
+
+    let balancer = Http::new(backends, WeightedRRPicker::new(weights));
+    let mut lb_server = Server::new(config.server.listen_ip, config.server.port);
+    lb_server.route(route.location.clone(), handlers::lb::Lb::new(balancer));
+    let lb_ready = lb_server.start_notifier();
+    let lb_shutdown = lb_server.shutdown();
+    tokio::spawn(async move { lb_server.start().await.unwrap() });
+    lb_ready.notified().await;
+
+    // Backends are picked per connection, so use a new one for every request.
+    let mut hits = 0;
+    for _ in 0..8 {
+        let mut client = Client::new("localhost:10699").connect().await.unwrap();
+        let response = client
+            .send_request(&Request::new(Method::GET, "/lb"))
+            .await
+            .unwrap();
+        if response.content().await == "localhost:10600" {
+            hits += 1;
+        }
+        _ = client.close().await;
+    }
+    assert_eq!(hits, 6);
+
+    for shutdown in shutdowns {
+        shutdown_server(shutdown).await;
+    }
+    shutdown_server(lb_shutdown).await;
+}
+
+/// Returns the routes of an lbconfig with the given `location: [ports]` routes.
+fn lbconfig_routes(routes: &[(&str, &[u16])]) -> Vec<lbconfig::Route> {
+    let mut config = "listen_ip: localhost\nport: 4000\nlog_level: info\nroutes:\n".to_string();
+    for (location, ports) in routes {
+        config.push_str(&format!("    - location: {}\n      backends:\n", location));
+        for port in *ports {
+            config.push_str(&format!(
+                "          - host: localhost\n            port: {}\n",
+                port
+            ));
+        }
+    }
+    lbconfig::Config::from(config).unwrap().routes
+}
+
+#[tokio::test]
+async fn reload_routes() {
+    let routes = lb::Routes::new();
+    routes
+        .apply(lbconfig_routes(&[
+            ("/a", &[10801, 10802]),
+            ("/b", &[10803]),
+        ]))
+        .await;
+    assert_eq!(
+        routes.backends("/a").await.unwrap(),
+        vec!["localhost:10801", "localhost:10802"]
+    );
+    assert_eq!(
+        routes.backends("/b").await.unwrap(),
+        vec!["localhost:10803"]
+    );
+
+    // Removed backends are dropped, and new ones are added after the rest.
+    routes
+        .apply(lbconfig_routes(&[
+            ("/a", &[10802, 10804]),
+            ("/b", &[10803]),
+        ]))
+        .await;
+    assert_eq!(
+        routes.backends("/a").await.unwrap(),
+        vec!["localhost:10802", "localhost:10804"]
+    );
+
+    // Unchanged backends are left alone, even if they're listed in a different order.
+    routes
+        .apply(lbconfig_routes(&[
+            ("/a", &[10804, 10802]),
+            ("/b", &[10803]),
+        ]))
+        .await;
+    assert_eq!(
+        routes.backends("/a").await.unwrap(),
+        vec!["localhost:10802", "localhost:10804"]
+    );
+    assert_eq!(
+        routes.backends("/b").await.unwrap(),
+        vec!["localhost:10803"]
+    );
+
+    // Removed routes stop serving.
+    routes.apply(lbconfig_routes(&[("/a", &[10802])])).await;
+    assert_eq!(
+        routes.backends("/a").await.unwrap(),
+        vec!["localhost:10802"]
+    );
+    assert!(routes.backends("/b").await.is_none());
+    let mut stream: Vec<u8> = vec![];
+    let result = routes
+        .handle(&Request::new(Method::GET, "/b"), &mut stream)
+        .await
+        .unwrap();
+    assert!(matches!(result, handler::Action::Done));
+    assert!(String::from_utf8(stream)
+        .unwrap()
+        .starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+fn lbconfig_partial_weights() {
+    let config = r#"
+listen_ip: localhost
+port: 4000
+log_level: info
+routes:
+    - location: /lb
+      backends:
+          - host: localhost
+            port: 10600
+          - host: localhost
+            port: 10601
+"#;
+    assert!(lbconfig::Config::from(config).unwrap().routes[0]
+        .weights()
+        .is_none());
+
+    let config = config.replacen("port: 10601", "port: 10601\n            weight: 2", 1);
+    assert!(lbconfig::Config::from(config).is_err());
+}
+
+// Test adding and removing backends while the load balancer runs
+#[tokio::test]
+async fn add_and_remove_backends() {
+    let first = start_server(10700, "server10700".into()).await;
+    let second = start_server(10701, "server10701".into()).await;
+
+    let lb = handlers::lb::Lb::new(Http::new(
+        vec![HttpBackend::new("localhost:10700")],
+        RRPicker::new(),
+    ));
+    let balancer = lb.balancer();
+
+    let mut lb_server = Server::new("localhost", 10799);
+    lb_server.route("/lb", lb);
+    let lb_ready = lb_server.start_notifier();
+    let lb_shutdown = lb_server.shutdown();
+    tokio::spawn(async move { lb_server.start().await.unwrap() });
+    lb_ready.notified().await;
+
+    // Backends are picked per connection, so use a new one for every request.
+    let get = || async {
+        let mut client = Client::new("localhost:10799").connect().await.unwrap();
+        let response = client
+            .send_request(&Request::new(Method::GET, "/lb"))
+            .await
+            .unwrap();
+        let content = response.content().await;
+        _ = client.close().await;
+        content
+    };
+
+    assert_eq!(get().await, "server10700");
+    assert_eq!(get().await, "server10700");
+
+    balancer
+        .read()
+        .await
+        .add_backend(HttpBackend::new("localhost:10701"))
+        .await;
+    let mut seen = vec![get().await, get().await];
+    seen.sort();
+    assert_eq!(seen, vec!["server10700", "server10701"]);
+
+    let removed = balancer
+        .read()
+        .await
+        .remove_backend("localhost:10700")
+        .await;
+    assert!(removed.is_some());
+    assert!(balancer
+        .read()
+        .await
+        .remove_backend("localhost:10700")
+        .await
+        .is_none());
+    for _ in 0..3 {
+        assert_eq!(get().await, "server10701");
+    }
+
+    shutdown_server(first).await;
+    shutdown_server(second).await;
+    shutdown_server(lb_shutdown).await;
+}
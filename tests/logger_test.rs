@@ -0,0 +1,93 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use env_logger::Target;
+use log::{Level, Log, Record};
+
+/// Captures the output of a logger.
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn json_logger(capture: &Capture) -> env_logger::Logger {
+    env_logger::Builder::new()
+        .parse_filters("info")
+        .format(hype::logger::write_json)
+        .target(Target::Pipe(Box::new(capture.clone())))
+        .build()
+}
+
+fn lines(capture: &Capture) -> Vec<serde_json::Value> {
+    String::from_utf8(capture.0.lock().unwrap().clone())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn json_lines() {
+    let capture = Capture::default();
+    let logger = json_logger(&capture);
+
+    let key_values = [("request_id", "abc-123")];
+    logger.log(
+        &Record::builder()
+            .level(Level::Warn)
+            .target("hype::server")
+            .args(format_args!("hello \"world\""))
+            .key_values(&key_values)
+            .build(),
+    );
+
+    // Records below the level are dropped, and request IDs are optional.
+    logger.log(&Record::builder().level(Level::Debug).build());
+    logger.log(
+        &Record::builder()
+            .level(Level::Info)
+            .target("app")
+            .args(format_args!("no id"))
+            .build(),
+    );
+
+    let lines = lines(&capture);
+    assert_eq!(lines.len(), 2);
+
+    let line = lines[0].as_object().unwrap();
+    assert_eq!(line.len(), 5);
+    assert_eq!(line["level"], "WARN");
+    assert_eq!(line["target"], "hype::server");
+    assert_eq!(line["message"], "hello \"world\"");
+    assert_eq!(line["request_id"], "abc-123");
+    assert!(chrono::DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok());
+
+    let line = lines[1].as_object().unwrap();
+    assert_eq!(line["level"], "INFO");
+    assert_eq!(line["message"], "no id");
+    assert!(!line.contains_key("request_id"));
+}
+
+#[test]
+fn config_levels() {
+    use log::LevelFilter;
+
+    assert_eq!(
+        LevelFilter::from(hype::config::LogLevel::Warn),
+        LevelFilter::Warn
+    );
+    assert_eq!(
+        LevelFilter::from(hype::lbconfig::LogLevel::Debug),
+        LevelFilter::Debug
+    );
+}
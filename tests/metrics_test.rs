@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use hype::{
+    client::Client,
+    handlers,
+    metrics::Metrics,
+    request::{Method, Request},
+    server::Server,
+};
+
+const HOST: &str = "127.0.0.1";
+
+/// Returns the value of the metric line starting with `name`.
+fn value(metrics: &str, name: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("no {} in:\n{}", name, metrics))
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn render() {
+    let metrics = Metrics::new();
+    for (status, ms) in [(Some(200), 1), (Some(404), 30), (None, 20_000)] {
+        metrics.begin_request();
+        metrics.end_request(status, Duration::from_millis(ms));
+    }
+
+    let text = metrics.render(2);
+    assert_eq!(value(&text, "hype_requests_total"), 3.0);
+    assert_eq!(value(&text, "hype_responses_total{class=\"2xx\"}"), 1.0);
+    assert_eq!(value(&text, "hype_responses_total{class=\"4xx\"}"), 1.0);
+    assert_eq!(value(&text, "hype_responses_total{class=\"5xx\"}"), 0.0);
+    assert_eq!(value(&text, "hype_active_connections"), 2.0);
+
+    // Buckets are cumulative.
+    assert_eq!(
+        value(&text, "hype_request_duration_seconds_bucket{le=\"0.005\"}"),
+        1.0
+    );
+    assert_eq!(
+        value(&text, "hype_request_duration_seconds_bucket{le=\"0.05\"}"),
+        2.0
+    );
+    assert_eq!(
+        value(&text, "hype_request_duration_seconds_bucket{le=\"10\"}"),
+        2.0
+    );
+    assert_eq!(
+        value(&text, "hype_request_duration_seconds_bucket{le=\"+Inf\"}"),
+        3.0
+    );
+    assert_eq!(value(&text, "hype_request_duration_seconds_count"), 3.0);
+    assert_eq!(value(&text, "hype_request_duration_seconds_sum"), 20.031);
+}
+
+#[tokio::test]
+async fn scrape() {
+    let port = 9190;
+    let mut server = Server::new(HOST, port);
+    server.enable_metrics("/metrics");
+    server.route("/hello", handlers::Status::new(hype::status::OK, "hello"));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port))
+        .connect()
+        .await
+        .unwrap();
+    for path in ["/hello", "/hello", "/missing"] {
+        let response = client
+            .send_request(&Request::new(Method::GET, path))
+            .await
+            .unwrap();
+        response.content().await;
+    }
+
+    let response = client
+        .send_request(&Request::new(Method::GET, "/metrics"))
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers.get_first("content-type").unwrap(),
+        hype::metrics::CONTENT_TYPE
+    );
+    let text = response.content().await;
+
+    // The scrape itself is counted, but hasn't been answered yet.
+    assert_eq!(value(&text, "hype_requests_total"), 4.0);
+    assert_eq!(value(&text, "hype_responses_total{class=\"2xx\"}"), 2.0);
+    assert_eq!(value(&text, "hype_responses_total{class=\"4xx\"}"), 1.0);
+    assert_eq!(value(&text, "hype_active_connections"), 1.0);
+    assert_eq!(value(&text, "hype_request_duration_seconds_count"), 3.0);
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
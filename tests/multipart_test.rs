@@ -0,0 +1,77 @@
+use hype::{
+    multipart::{boundary, parse, MultipartError},
+    request::Request,
+};
+
+const BODY: &str = "preamble\r\n\
+--XyZ\r\n\
+Content-Disposition: form-data; name=\"title\"\r\n\
+\r\n\
+hello world\r\n\
+--XyZ\r\n\
+Content-Disposition: form-data; name=\"upload\"; filename=\"a \\\"b\\\".txt\"\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+line 1\r\nline 2 --XyZ\r\n\
+--XyZ--\r\n";
+
+#[tokio::test]
+async fn form_fields() {
+    let request = Request::from(format!(
+        "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XyZ\r\nContent-Length: {}\r\n\r\n{}",
+        BODY.len(),
+        BODY
+    ))
+    .unwrap();
+
+    let parts = request.multipart().await.unwrap();
+    assert_eq!(parts.len(), 2);
+
+    assert_eq!(parts[0].name.as_deref(), Some("title"));
+    assert_eq!(parts[0].filename, None);
+    assert_eq!(parts[0].content_type, None);
+    assert_eq!(parts[0].text(), "hello world");
+
+    assert_eq!(parts[1].name.as_deref(), Some("upload"));
+    assert_eq!(parts[1].filename.as_deref(), Some("a \"b\".txt"));
+    assert_eq!(parts[1].content_type.as_deref(), Some("text/plain"));
+    assert_eq!(parts[1].data, b"line 1\r\nline 2 --XyZ");
+}
+
+#[test]
+fn boundaries() {
+    assert_eq!(
+        boundary("multipart/form-data; boundary=\"a b\"").unwrap(),
+        "a b"
+    );
+    assert_eq!(
+        boundary("Multipart/Form-Data;charset=utf-8;BOUNDARY=abc").unwrap(),
+        "abc"
+    );
+    assert_eq!(
+        boundary("application/x-www-form-urlencoded"),
+        Err(MultipartError::NotMultipart)
+    );
+    assert_eq!(
+        boundary("multipart/form-data"),
+        Err(MultipartError::MissingBoundary)
+    );
+}
+
+#[test]
+fn malformed() {
+    let content_type = "multipart/form-data; boundary=XyZ";
+
+    assert!(parse(content_type, b"--XyZ--").unwrap().is_empty());
+    assert!(matches!(
+        parse(content_type, b"no boundary here"),
+        Err(MultipartError::Malformed(_))
+    ));
+    assert!(matches!(
+        parse(
+            content_type,
+            b"--XyZ\r\nContent-Disposition: form-data; name=a\r\n\r\nunterminated"
+        ),
+        Err(MultipartError::Malformed(_))
+    ));
+}
@@ -1,3 +1,4 @@
+use futures::StreamExt;
 use hype::parser;
 use hype::parser::*;
 use hype::request::*;
@@ -112,6 +113,24 @@ fn get_request() {
     assert_eq!(request.version, "HTTP/1.1");
 }
 
+#[test]
+fn header_casing() {
+    let request = assert_parse_ok(
+        "GET / HTTP/1.1\r\nHost: localhost\r\nX-Custom-Header: value\r\ncontent-type: text/plain\r\n\r\n",
+    )
+    .unwrap();
+
+    // Names keep the casing they were sent with, but lookups ignore it.
+    assert_eq!(
+        request.headers.get_first("x-custom-header").unwrap(),
+        "value"
+    );
+    assert_eq!(
+        request.headers.serialize(),
+        "Host: localhost\r\nX-Custom-Header: value\r\ncontent-type: text/plain"
+    );
+}
+
 #[test]
 fn invalid_method() {
     assert_parse_request_result(
@@ -120,6 +139,35 @@ fn invalid_method() {
     );
 }
 
+#[test]
+fn versions() {
+    let request = assert_parse_ok("GET / HTTP/1.0\r\n\r\n").unwrap();
+    assert_eq!(request.version, "HTTP/1.0");
+
+    assert_parse_request_result(
+        "GET / BANANA\r\n",
+        Err(ParseError::BadVersion("BANANA".into())),
+    );
+    assert_parse_request_result(
+        "GET / http/1.1\r\n",
+        Err(ParseError::BadVersion("http/1.1".into())),
+    );
+    assert_parse_request_result(
+        "GET / HTTP/11\r\n",
+        Err(ParseError::BadVersion("HTTP/11".into())),
+    );
+
+    // Well-formed, but unsupported, including HTTP/2 connection prefaces.
+    assert_parse_request_result(
+        "GET / HTTP/1.2\r\n",
+        Err(ParseError::UnsupportedVersion("HTTP/1.2".into())),
+    );
+    assert_parse_request_result(
+        "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n",
+        Err(ParseError::UnsupportedVersion("HTTP/2.0".into())),
+    );
+}
+
 #[test]
 fn post_params() {
     let r = r##"POST / HTTP/1.1
@@ -140,6 +188,23 @@ merchantID=2003&foo=bar"##;
     assert_eq!(post_params.get("foo").unwrap(), &"bar".to_string());
 }
 
+#[test]
+fn post_params_decoded() {
+    let body = "name=John+Doe&city=New%20York&q=a%3Db%26c&eq=x=y&first%20name=Jo";
+    let r = format!(
+        "POST / HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    );
+
+    let post_params = assert_parse_ok(&r).unwrap().post_params().unwrap();
+    assert_eq!(post_params.get("name").unwrap(), "John Doe");
+    assert_eq!(post_params.get("city").unwrap(), "New York");
+    assert_eq!(post_params.get("q").unwrap(), "a=b&c");
+    assert_eq!(post_params.get("eq").unwrap(), "x=y");
+    assert_eq!(post_params.get("first name").unwrap(), "Jo");
+}
+
 #[test]
 fn query_params() {
     let r = r##"GET /admin?user=foo&action=delete HTTP/1.1
@@ -157,6 +222,20 @@ Content-Type: application/x-www-form-urlencoded"##;
     assert_eq!(query_params.get("action").unwrap(), &"delete".to_string());
 }
 
+#[test]
+fn repeated_query_params() {
+    let request = assert_parse_ok("GET /posts?tag=a&tag=b%20c&page=2 HTTP/1.1\r\n\r\n").unwrap();
+
+    assert_eq!(request.query_param_all("tag"), vec!["a", "b c"]);
+    assert_eq!(request.query_param("tag").as_deref(), Some("a"));
+    assert_eq!(request.query_param_all("page"), vec!["2"]);
+    assert!(request.query_param_all("missing").is_empty());
+    assert_eq!(request.query_param("missing"), None);
+
+    // The map keeps the last value.
+    assert_eq!(request.query_params().get("tag").unwrap(), "b c");
+}
+
 #[test]
 fn cookies() {
     let r = r##"GET /admin?user=foo&action=delete HTTP/1.1
@@ -217,3 +296,600 @@ Transfer-Encoding: chunked
     assert_eq!(response.status.code, 200);
     assert_eq!(response.content().await, "".to_string());
 }
+
+#[tokio::test]
+async fn chunk_extensions() {
+    // Extensions after the size are ignored.
+    let response = assert_parse_response_ok(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+         5;name=value\r\nhello\r\n6 ; foo=\"bar baz\";flag\r\n world\r\n0;last\r\n\r\n",
+    )
+    .unwrap();
+    assert_eq!(response.content().await, "hello world");
+
+    // Anything other than hex digits before the extensions is an error.
+    for size in ["5x", "x5", "5 5", "-5", "0x5;a=b"] {
+        let body = format!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{}\r\nhello\r\n0\r\n\r\n",
+            size
+        );
+        assert_parse_request_result(
+            &body,
+            Err(ParseError::InvalidChunkSize {
+                line: size.into(),
+                offset: 47,
+            }),
+        );
+    }
+
+    // A missing size is still its own error.
+    assert_parse_request_result(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n;ext\r\nhello\r\n0\r\n\r\n",
+        Err(ParseError::NonNumericChunkSize),
+    );
+
+    // Extensions can't make the size line unbounded.
+    let body = format!(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;{}\r\nhello\r\n0\r\n\r\n",
+        "a".repeat(parser::MAX_CHUNK_LINE_BYTES)
+    );
+    assert!(matches!(
+        parse_request(&body).1,
+        Err(ParseError::InvalidChunkSize { offset: 47, .. })
+    ));
+}
+
+#[tokio::test]
+async fn chunked_fragments() {
+    use std::sync::{Arc, Mutex};
+
+    let message = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   5\r\nhello\r\n1;ext=1\r\n \r\n10\r\n0123456789abcdef\r\n\
+                   0\r\nX-Checksum: abc\r\n\r\n";
+
+    // Every split of the CRLFs and size lines across reads has to resume where it left off.
+    for size in [1, 2, 3] {
+        let mut parser = RequestParser::new();
+        for fragment in message.as_bytes().chunks(size) {
+            parser.parse_buf(fragment).unwrap();
+        }
+        assert!(parser.is_complete());
+        assert_eq!(parser.parsed_bytes(), message.len());
+
+        let request: Request = parser.get_message().into();
+        assert_eq!(request.body.content().await, b"hello 0123456789abcdef");
+        assert_eq!(request.trailers().get_first("x-checksum").unwrap(), "abc");
+    }
+
+    // Same for bodies passed to a callback.
+    let data = Arc::new(Mutex::new(vec![]));
+    let sink = Arc::clone(&data);
+    let mut parser = RequestParser::new();
+    parser.on_body_chunk(move |chunk| sink.lock().unwrap().extend_from_slice(chunk));
+    for fragment in message.as_bytes().chunks(2) {
+        parser.parse_buf(fragment).unwrap();
+    }
+    assert!(parser.is_complete());
+    assert_eq!(&data.lock().unwrap()[..], b"hello 0123456789abcdef");
+}
+
+#[tokio::test]
+async fn chunked_trailers() {
+    let response = assert_parse_response_ok(
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTrailer: X-Checksum\r\n\r\n\
+         5\r\nhello\r\n0\r\nX-Checksum: abc123\r\nX-Status:  0 \r\n\r\n",
+    )
+    .unwrap();
+    assert_eq!(response.content().await, "hello");
+    let trailers = response.trailers();
+    assert_eq!(trailers.get_first("x-checksum").unwrap(), "abc123");
+    assert_eq!(trailers.get_first("x-status").unwrap(), "0");
+    assert!(response.headers.get_first("x-checksum").is_none());
+
+    // Trailers are forwarded with the raw chunked stream.
+    let raw = response.body.raw_stream().concat().await;
+    assert!(String::from_utf8(raw)
+        .unwrap()
+        .starts_with("5\r\nhello\r\n0\r\nX-"));
+
+    let request = assert_parse_request_result(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nX-Checksum: abc\r\n\r\n",
+        Ok(()),
+    )
+    .unwrap();
+    assert_eq!(request.trailers().get_first("x-checksum").unwrap(), "abc");
+
+    assert_parse_request_result(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nbad trailer\r\n\r\n",
+        Err(ParseError::BadHeaderLine {
+            line: "bad trailer".into(),
+            offset: 50,
+        }),
+    );
+}
+
+#[test]
+fn error_offsets() {
+    assert_parse_request_result(
+        "GET / HTTP/1.1\r\nHost: localhost\r\nbad header\r\n\r\n",
+        Err(ParseError::BadHeaderLine {
+            line: "bad header".into(),
+            offset: 33,
+        }),
+    );
+
+    // Leading blank lines count towards the offset.
+    assert_parse_request_result(
+        "\r\nGET /\r\n\r\n",
+        Err(ParseError::BadMethodLine {
+            line: "GET /".into(),
+            offset: 2,
+        }),
+    );
+
+    assert_parse_response_result(
+        "HTTP/1.1\r\n\r\n",
+        Err(ParseError::BadStatusLine {
+            line: "HTTP/1.1".into(),
+            offset: 0,
+        }),
+    );
+
+    // The offsets are relative to the start of the message, not the buffer.
+    let mut parser = RequestParser::new();
+    parser.parse_buf(b"GET / HTTP/1.1\r\n").unwrap();
+    let result = parser.parse_buf(b"Host: localhost\r\nX-Bad\r\n");
+    assert_eq!(
+        result,
+        Err(ParseError::BadHeaderLine {
+            line: "X-Bad".into(),
+            offset: 33,
+        })
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Parser: bad header line at byte 33: X-Bad"
+    );
+
+    let size = "1".repeat(20);
+    let body = format!(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\na\r\n{}\r\n",
+        size
+    );
+    assert_parse_request_result(
+        &body,
+        Err(ParseError::InvalidChunkSize {
+            line: size.clone(),
+            offset: 53,
+        }),
+    );
+}
+
+#[test]
+fn max_header_bytes() {
+    let head = "GET / HTTP/1.1\r\nHost: localhost\r\nX-Long: aaaaaaaaaa\r\n\r\n";
+
+    let mut parser = RequestParser::new();
+    parser.set_max_header_bytes(head.len());
+    assert_eq!(parser.parse_buf(head.as_bytes()), Ok(()));
+    assert!(parser.is_complete());
+
+    // One byte too many, fed a byte at a time.
+    let mut parser = RequestParser::new();
+    parser.set_max_header_bytes(head.len() - 1);
+    let result = head
+        .as_bytes()
+        .chunks(1)
+        .map(|b| parser.parse_buf(b))
+        .find(|r| r.is_err());
+    assert_eq!(result, Some(Err(ParseError::HeadersTooLarge)));
+
+    // The default limit applies to responses too.
+    let mut parser = ResponseParser::new();
+    let mut response = String::from("HTTP/1.1 200 OK\r\n");
+    while response.len() <= DEFAULT_MAX_HEADER_BYTES {
+        response.push_str("X-Filler: 0123456789012345678901234567890123456789\r\n");
+    }
+    assert_eq!(
+        parser.parse_buf(response.as_bytes()),
+        Err(ParseError::HeadersTooLarge)
+    );
+}
+
+#[test]
+fn max_uri_bytes() {
+    let line = "GET /0123456789 HTTP/1.1";
+
+    let mut parser = RequestParser::new();
+    parser.set_max_uri_bytes(line.len());
+    assert_eq!(
+        parser.parse_buf(format!("{}\r\nHost: localhost\r\n\r\n", line).as_bytes()),
+        Ok(())
+    );
+    assert!(parser.is_complete());
+
+    let mut parser = RequestParser::new();
+    parser.set_max_uri_bytes(line.len() - 1);
+    assert_eq!(
+        parser.parse_buf(format!("{}\r\n", line).as_bytes()),
+        Err(ParseError::UriTooLong)
+    );
+
+    // The default limit fails overlong paths as they arrive, without waiting for the end
+    // of the line.
+    let mut parser = RequestParser::new();
+    let path = format!("/{}", "a".repeat(DEFAULT_MAX_URI_BYTES));
+    let result = format!("GET {}", path)
+        .as_bytes()
+        .chunks(1024)
+        .map(|b| parser.parse_buf(b))
+        .find(|r| r.is_err());
+    assert_eq!(result, Some(Err(ParseError::UriTooLong)));
+}
+
+#[test]
+fn max_body_bytes() {
+    let mut parser = RequestParser::new();
+    parser.set_max_body_bytes(5);
+    assert_eq!(
+        parser.parse_buf(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello"),
+        Ok(())
+    );
+    assert!(parser.is_complete());
+
+    // Content-Length is checked before reading the body.
+    let mut parser = RequestParser::new();
+    parser.set_max_body_bytes(5);
+    assert_eq!(
+        parser.parse_buf(b"POST / HTTP/1.1\r\nContent-Length: 6\r\n\r\n"),
+        Err(ParseError::BodyTooLarge)
+    );
+    assert!(!parser.ready());
+
+    // Chunked bodies fail once the chunks add up to more than the limit.
+    let mut parser = RequestParser::new();
+    parser.set_max_body_bytes(5);
+    assert_eq!(
+        parser.parse_buf(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nhel\r\n"),
+        Ok(())
+    );
+    assert!(parser.ready());
+    assert_eq!(
+        parser.parse_buf(b"3\r\nlo!\r\n0\r\n\r\n"),
+        Err(ParseError::BodyTooLarge)
+    );
+}
+
+#[test]
+fn ambiguous_body_length() {
+    // Identical duplicates are fine.
+    let mut parser = RequestParser::new();
+    assert_eq!(
+        parser.parse_buf(b"POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 2\r\n\r\nhi"),
+        Ok(())
+    );
+    assert!(parser.is_complete());
+
+    let mut parser = RequestParser::new();
+    assert_eq!(
+        parser.parse_buf(b"POST / HTTP/1.1\r\nContent-Length: 2, 2\r\n\r\nhi"),
+        Ok(())
+    );
+    assert!(parser.is_complete());
+
+    assert_parse_request_result(
+        "POST / HTTP/1.1\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nhi!",
+        Err(ParseError::AmbiguousBodyLength),
+    );
+    assert_parse_request_result(
+        "POST / HTTP/1.1\r\nContent-Length: 2, 3\r\n\r\nhi!",
+        Err(ParseError::AmbiguousBodyLength),
+    );
+    assert_parse_request_result(
+        "POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nhi!\r\n0\r\n\r\n",
+        Err(ParseError::AmbiguousBodyLength),
+    );
+
+    // Lengths that aren't numbers are rejected, rather than read as 0, which would leave
+    // the body to be parsed as the next request.
+    for length in ["abc", "-1", "+2", "", "2 2", "99999999999999999999999"] {
+        assert_parse_request_result(
+            &format!(
+                "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\nGET /smuggled HTTP/1.1\r\n\r\n",
+                length
+            ),
+            Err(ParseError::BadContentLength(length.to_string())),
+        );
+    }
+}
+
+#[tokio::test]
+async fn transfer_encodings() {
+    let chunked = |encoding: &str| {
+        format!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: {}\r\n\r\n2\r\nhi\r\n0\r\n\r\n",
+            encoding
+        )
+    };
+
+    // Codings are case-insensitive, and identity is a no-op.
+    for encoding in ["chunked", "Chunked", "identity, chunked"] {
+        let request = assert_parse_ok(&chunked(encoding)).unwrap();
+        assert_eq!(request.body.content().await, b"hi");
+    }
+
+    // The codings can be split across header lines.
+    let request = assert_parse_ok(
+        "POST / HTTP/1.1\r\nTransfer-Encoding: identity\r\nTransfer-Encoding: chunked\r\n\r\n\
+         2\r\nhi\r\n0\r\n\r\n",
+    )
+    .unwrap();
+    assert_eq!(request.body.content().await, b"hi");
+
+    // Chunked has to be last, and only applied once.
+    assert_parse_request_result(
+        &chunked("chunked, gzip"),
+        Err(ParseError::BadTransferEncoding("chunked, gzip".into())),
+    );
+    assert_parse_request_result(
+        &chunked("chunked, chunked"),
+        Err(ParseError::BadTransferEncoding("chunked, chunked".into())),
+    );
+
+    // Other codings aren't supported.
+    assert_parse_request_result(
+        &chunked("gzip, chunked"),
+        Err(ParseError::UnsupportedTransferEncoding("gzip".into())),
+    );
+    assert_parse_request_result(
+        &chunked("gzip"),
+        Err(ParseError::UnsupportedTransferEncoding("gzip".into())),
+    );
+}
+
+#[test]
+fn require_host() {
+    let parse = |buf: &[u8]| {
+        let mut parser = RequestParser::new();
+        parser.set_require_host(true);
+        parser.parse_buf(buf)
+    };
+
+    assert_eq!(parse(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"), Ok(()));
+    assert_eq!(
+        parse(b"GET / HTTP/1.1\r\nAccept: */*\r\n\r\n"),
+        Err(ParseError::MissingHost)
+    );
+    assert_eq!(
+        parse(b"GET / HTTP/1.1\r\nHost: a.com\r\nHost: b.com\r\n\r\n"),
+        Err(ParseError::MultipleHosts)
+    );
+
+    // HTTP/1.0 doesn't require a Host.
+    assert_eq!(parse(b"GET / HTTP/1.0\r\n\r\n"), Ok(()));
+
+    // Not required unless enabled.
+    let mut parser = RequestParser::new();
+    assert_eq!(parser.parse_buf(b"GET / HTTP/1.1\r\n\r\n"), Ok(()));
+}
+
+#[test]
+fn require_host_absolute_form() {
+    let mut parser = RequestParser::new();
+    parser.set_require_host(true);
+    assert_eq!(
+        parser.parse_buf(b"GET http://example.com/foo HTTP/1.1\r\n\r\n"),
+        Ok(())
+    );
+    assert!(parser.is_complete());
+
+    let request: Request = parser.get_message().into();
+    assert_eq!(request.url.unwrap().as_str(), "http://example.com/foo");
+
+    // Only one Host is allowed, even with absolute-form targets.
+    let mut parser = RequestParser::new();
+    parser.set_require_host(true);
+    assert_eq!(
+        parser.parse_buf(b"GET http://example.com/ HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n"),
+        Err(ParseError::MultipleHosts)
+    );
+}
+
+#[test]
+fn absolute_form() {
+    let (request, result) =
+        parse_request("GET http://example.com:8080/foo/bar?a=b HTTP/1.1\r\n\r\n");
+    assert_eq!(result, Ok(()));
+
+    let request = request.unwrap();
+    assert!(request.is_absolute_form());
+    assert_eq!(request.target_form(), TargetForm::Absolute);
+
+    let url = request.url.unwrap();
+    assert_eq!(url.host_str(), Some("example.com"));
+    assert_eq!(url.port(), Some(8080));
+    assert_eq!(url.path(), "/foo/bar");
+    assert_eq!(url.query(), Some("a=b"));
+
+    // Origin-form targets are relative to the base URL.
+    let (request, result) = parse_request("GET /foo?a=b HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(result, Ok(()));
+
+    let request = request.unwrap();
+    assert!(!request.is_absolute_form());
+    assert_eq!(request.target_form(), TargetForm::Origin);
+    assert_eq!(request.url.unwrap().as_str(), "http://unset/foo?a=b");
+
+    // Targets must be either.
+    let (_, result) = parse_request("GET foo HTTP/1.1\r\n\r\n");
+    assert_eq!(result, Err(ParseError::InvalidPath("foo".into())));
+}
+
+#[test]
+fn asterisk_form() {
+    let (request, result) = parse_request("OPTIONS * HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(result, Ok(()));
+
+    let request = request.unwrap();
+    assert_eq!(request.target_form(), TargetForm::Asterisk);
+    assert_eq!(request.url.unwrap().path(), "/");
+
+    // Only OPTIONS requests can target the whole server.
+    let (_, result) = parse_request("GET * HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(result, Err(ParseError::InvalidPath("*".into())));
+}
+
+#[test]
+fn authority_form() {
+    let (request, result) =
+        parse_request("CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n");
+    assert_eq!(result, Ok(()));
+
+    let request = request.unwrap();
+    assert_eq!(request.target_form(), TargetForm::Authority);
+    let url = request.url.unwrap();
+    assert_eq!(url.host_str(), Some("example.com"));
+    assert_eq!(url.port(), Some(443));
+
+    // CONNECT targets need a port, and nothing else.
+    for target in ["example.com", "/foo", "example.com:443/foo", "*"] {
+        let (_, result) = parse_request(&format!("CONNECT {} HTTP/1.1\r\n\r\n", target));
+        assert_eq!(result, Err(ParseError::InvalidPath(target.into())));
+    }
+}
+
+#[tokio::test]
+async fn body_callback() {
+    use std::sync::{Arc, Mutex};
+
+    const LEN: usize = 1024 * 1024;
+    let data: Vec<u8> = (0..LEN).map(|i| (i % 251) as u8).collect();
+
+    // Content-Length bodies
+    let forwarded = Arc::new(Mutex::new(vec![]));
+    let mut parser = RequestParser::new();
+    let sink = Arc::clone(&forwarded);
+    parser.on_body_chunk(move |chunk| sink.lock().unwrap().extend_from_slice(chunk));
+
+    let mut buf = format!("POST /upload HTTP/1.1\r\nContent-Length: {}\r\n\r\n", LEN).into_bytes();
+    buf.extend_from_slice(&data[..1000]);
+    parser.parse_buf(&buf).unwrap();
+    for part in data[1000..].chunks(64 * 1024) {
+        assert!(!parser.is_complete());
+        parser.parse_buf(part).unwrap();
+    }
+    assert!(parser.is_complete());
+    assert_eq!(forwarded.lock().unwrap().len(), LEN);
+    assert!(*forwarded.lock().unwrap() == data);
+
+    // Nothing is retained, but the body completes.
+    let request: Request = parser.get_message().into();
+    assert!(request.body.complete());
+    assert!(request.body.content().await.is_empty());
+
+    // Chunked bodies get the decoded data.
+    let total = Arc::new(Mutex::new(0));
+    let mut parser = RequestParser::new();
+    let sink = Arc::clone(&total);
+    parser.on_body_chunk(move |chunk| *sink.lock().unwrap() += chunk.len());
+
+    parser
+        .parse_buf(b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n")
+        .unwrap();
+    for chunk in data.chunks(100 * 1000) {
+        let mut buf = format!("{:x}\r\n", chunk.len()).into_bytes();
+        buf.extend_from_slice(chunk);
+        buf.extend_from_slice(b"\r\n");
+        for part in buf.chunks(16384) {
+            parser.parse_buf(part).unwrap();
+        }
+    }
+    parser.parse_buf(b"0\r\nX-Done: yes\r\n\r\n").unwrap();
+    assert!(parser.is_complete());
+    assert_eq!(*total.lock().unwrap(), LEN);
+
+    let request: Request = parser.get_message().into();
+    assert!(request.body.content().await.is_empty());
+    assert_eq!(request.trailers().get_first("x-done").unwrap(), "yes");
+}
+
+#[test]
+fn interim_responses() {
+    use std::sync::{Arc, Mutex};
+
+    // Interim responses are skipped, and the final response is the message.
+    let mut parser = ResponseParser::new();
+    parser
+        .parse_buf(
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+        )
+        .unwrap();
+    assert!(parser.is_complete());
+    assert_eq!(parser.interim_responses().len(), 1);
+    assert_eq!(parser.interim_responses()[0].status.code, 100);
+    let response: Response = parser.get_message().into();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(response.headers.get_first("content-length").unwrap(), "5");
+
+    // Interim responses can have headers, and can come in separate reads.
+    let codes = Arc::new(Mutex::new(vec![]));
+    let mut parser = ResponseParser::new();
+    let sink = Arc::clone(&codes);
+    parser.on_interim_response(move |response| sink.lock().unwrap().push(response.status.code));
+
+    parser
+        .parse_buf(b"HTTP/1.1 102 Processing\r\n\r\n")
+        .unwrap();
+    assert!(!parser.ready());
+    parser
+        .parse_buf(b"HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload\r\n\r\n")
+        .unwrap();
+    assert!(!parser.ready());
+    assert_eq!(*codes.lock().unwrap(), vec![102, 103]);
+    assert_eq!(
+        parser.interim_responses()[1]
+            .headers
+            .get_first("link")
+            .unwrap(),
+        "</style.css>; rel=preload"
+    );
+
+    parser
+        .parse_buf(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+        .unwrap();
+    assert!(parser.is_complete());
+    let response: Response = parser.get_message().into();
+    assert_eq!(response.status.code, 404);
+    assert!(response.headers.get_first("link").is_none());
+    assert_eq!(parser.interim_responses().len(), 2);
+
+    // 101 Switching Protocols is final, and what follows it isn't parsed.
+    let head =
+        b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nContent-Length: 3\r\n\r\n";
+    let mut parser = ResponseParser::new();
+    parser
+        .parse_buf(&[&head[..], b"\x81\x00"].concat())
+        .unwrap();
+    assert!(parser.is_complete());
+    assert_eq!(parser.parsed_bytes(), head.len());
+    assert!(parser.interim_responses().is_empty());
+    let response: Response = parser.get_message().into();
+    assert_eq!(response.status.code, 101);
+}
+
+#[tokio::test]
+async fn no_body() {
+    // Responses to HEAD requests keep their framing headers, but have no body.
+    for framing in ["Content-Length: 5", "Transfer-Encoding: chunked"] {
+        let mut parser = ResponseParser::new();
+        parser.set_no_body(true);
+        parser
+            .parse_buf(format!("HTTP/1.1 200 OK\r\n{}\r\n\r\n", framing).as_bytes())
+            .unwrap();
+        assert!(parser.is_complete());
+
+        let response: Response = parser.get_message().into();
+        assert_eq!(response.headers.len(), 1);
+        assert!(response.content().await.is_empty());
+    }
+}
@@ -0,0 +1,46 @@
+use hype::proxy_protocol::{parse_header, ProxyHeader};
+
+#[test]
+fn parse_tcp() {
+    assert_eq!(
+        parse_header("PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n").unwrap(),
+        ProxyHeader::Tcp {
+            source: "192.0.2.1:56324".parse().unwrap(),
+            destination: "198.51.100.1:443".parse().unwrap(),
+        }
+    );
+
+    let header = parse_header("PROXY TCP6 2001:db8::1 2001:db8::2 4000 80").unwrap();
+    assert_eq!(header.source(), Some("[2001:db8::1]:4000".parse().unwrap()));
+}
+
+#[test]
+fn parse_unknown() {
+    assert_eq!(
+        parse_header("PROXY UNKNOWN\r\n").unwrap(),
+        ProxyHeader::Unknown
+    );
+    assert_eq!(
+        parse_header("PROXY UNKNOWN whatever 1 2\r\n").unwrap(),
+        ProxyHeader::Unknown
+    );
+    assert_eq!(ProxyHeader::Unknown.source(), None);
+}
+
+#[test]
+fn parse_errors() {
+    for line in [
+        "GET / HTTP/1.1\r\n",
+        "PROXY\r\n",
+        "PROXY UDP4 192.0.2.1 198.51.100.1 1 2\r\n",
+        "PROXY TCP4 192.0.2.1 198.51.100.1 1\r\n",
+        "PROXY TCP4 192.0.2.1 198.51.100.1 1 2 3\r\n",
+        "PROXY TCP4 2001:db8::1 198.51.100.1 1 2\r\n",
+        "PROXY TCP6 192.0.2.1 2001:db8::1 1 2\r\n",
+        "PROXY TCP4 192.0.2.1 198.51.100.1 65536 2\r\n",
+        "PROXY TCP4 192.0.2.1 198.51.100.1 01 2\r\n",
+        "PROXY  TCP4 192.0.2.1 198.51.100.1 1 2\r\n",
+    ] {
+        assert!(parse_header(line).is_err(), "{}", line);
+    }
+}
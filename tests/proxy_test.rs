@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use hype::{
+    client::Client,
+    handlers::{self, Proxy},
+    headers::Headers,
+    request::{Method, Request},
+    server::Server,
+    status,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::{mpsc, Notify},
+};
+
+const HOST: &str = "127.0.0.1";
+
+async fn start_server(
+    port: u16,
+    server: impl FnOnce(&mut Server),
+) -> (Arc<mpsc::Sender<bool>>, Arc<Notify>) {
+    let mut s = Server::new(HOST, port);
+    server(&mut s);
+    let ready = s.start_notifier();
+    let shutdown = s.shutdown();
+    tokio::spawn(async move { s.start().await.unwrap() });
+    ready.notified().await;
+    shutdown
+}
+
+#[test]
+fn strips_hop_by_hop_headers() {
+    let mut headers = Headers::new();
+    headers.set("connection", "keep-alive, X-Secret");
+    headers.set("keep-alive", "timeout=5");
+    headers.set("transfer-encoding", "chunked");
+    headers.set("x-secret", "abc");
+    headers.set("accept", "*/*");
+
+    handlers::proxy::strip_hop_by_hop(&mut headers);
+    assert_eq!(headers.len(), 1);
+    assert_eq!(headers.get_first("accept").unwrap(), "*/*");
+}
+
+#[tokio::test]
+async fn forwards_requests() {
+    let upstream = start_server(9170, |s| {
+        s.route("/status", handlers::Status::new(status::OK, "upstream"));
+        s.route(
+            "/echo",
+            handlers::handler(|r| async move {
+                Ok(format!(
+                    "{}?{} host={} via={} connection={} secret={}",
+                    r.abs_path(),
+                    r.url.as_ref().unwrap().query().unwrap_or(""),
+                    r.headers.get_first("host").unwrap(),
+                    r.headers.get("via").is_some(),
+                    r.headers.get("connection").is_some(),
+                    r.headers.get("x-secret").is_some(),
+                ))
+            }),
+        );
+    })
+    .await;
+    let proxy = start_server(9171, |s| s.route_default(Proxy::new())).await;
+
+    // Proxies by Host header
+    let mut client = Client::new(format!("{}:9171", HOST))
+        .connect()
+        .await
+        .unwrap();
+    let mut request = Request::new(Method::GET, "/status");
+    request.headers.set("host", format!("{}:9170", HOST));
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(response.content().await, "upstream");
+
+    // Proxies absolute-form targets, and strips hop-by-hop headers.
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:9171", HOST))
+        .await
+        .unwrap();
+    stream
+        .write_all(
+            format!(
+                "GET http://{}:9170/echo?a=b HTTP/1.1\r\nConnection: X-Secret\r\nX-Secret: abc\r\n\r\n",
+                HOST
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    // The server keeps the connection open, so read until the whole body is in.
+    let expected = format!(
+        "/echo?a=b host={}:9170 via=true connection=false secret=false",
+        HOST
+    );
+    let mut buf = vec![];
+    while !String::from_utf8_lossy(&buf).ends_with(&expected) {
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert_ne!(n, 0, "connection closed: {}", String::from_utf8_lossy(&buf));
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    assert!(buf.starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+    proxy.0.send(true).await.unwrap();
+    proxy.1.notified().await;
+    upstream.0.send(true).await.unwrap();
+    upstream.1.notified().await;
+}
+
+#[tokio::test]
+async fn close_delimited_responses() {
+    // The upstream has no framing for the body, other than closing the connection.
+    let listener = tokio::net::TcpListener::bind(format!("{}:9176", HOST))
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 1024];
+        _ = socket.read(&mut buf).await.unwrap();
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n")
+            .await
+            .unwrap();
+    });
+
+    let proxy = start_server(9177, |s| s.route_default(Proxy::new())).await;
+
+    // The client is told the connection closes, and it does, instead of hanging.
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:9177", HOST))
+        .await
+        .unwrap();
+    stream
+        .write_all(
+            format!(
+                "GET http://{}:9176/ HTTP/1.1\r\nHost: {0}:9176\r\n\r\n",
+                HOST
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        stream.read_to_string(&mut response),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert_eq!(
+        response
+            .to_lowercase()
+            .matches("connection: close\r\n")
+            .count(),
+        1,
+        "{}",
+        response
+    );
+
+    proxy.0.send(true).await.unwrap();
+    proxy.1.notified().await;
+}
+
+#[tokio::test]
+async fn bad_gateway() {
+    let proxy = start_server(9172, |s| s.route_default(Proxy::new())).await;
+
+    // Nothing is listening on the upstream port.
+    let mut client = Client::new(format!("{}:9172", HOST))
+        .connect()
+        .await
+        .unwrap();
+    let mut request = Request::new(Method::GET, "/");
+    request.headers.set("host", format!("{}:9173", HOST));
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.status.code, 502);
+
+    // Requests that loop back to the proxy are rejected.
+    request.headers.set("host", format!("{}:9172", HOST));
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.status.code, 508);
+
+    proxy.0.send(true).await.unwrap();
+    proxy.1.notified().await;
+}
+
+#[tokio::test]
+async fn connect_tunnels() {
+    // An echo server to tunnel to.
+    let listener = tokio::net::TcpListener::bind(format!("{}:9174", HOST))
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let (mut reader, mut writer) = socket.split();
+        tokio::io::copy(&mut reader, &mut writer).await.unwrap();
+    });
+    let proxy = start_server(9175, |s| s.route_default(Proxy::new())).await;
+
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:9175", HOST))
+        .await
+        .unwrap();
+    stream
+        .write_all(format!("CONNECT {0}:9174 HTTP/1.1\r\nHost: {0}:9174\r\n\r\n", HOST).as_bytes())
+        .await
+        .unwrap();
+
+    let mut buf = vec![];
+    while !buf.ends_with(b"\r\n\r\n") {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).await.unwrap();
+        buf.push(byte[0]);
+    }
+    assert!(buf.starts_with(b"HTTP/1.1 200 Connection Established\r\n"));
+
+    // Bytes flow both ways.
+    for message in ["hello", "world"] {
+        stream.write_all(message.as_bytes()).await.unwrap();
+        let mut echoed = vec![0u8; message.len()];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(echoed, message.as_bytes());
+    }
+
+    // Closing the client side closes the tunnel.
+    stream.shutdown().await.unwrap();
+    let mut rest = vec![];
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+
+    proxy.0.send(true).await.unwrap();
+    proxy.1.notified().await;
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use hype::{
+    client::Client,
+    handler::{Action, Handler},
+    handlers::{self, RateLimit},
+    middleware::Stack,
+    request::{Method, Request},
+    server::Server,
+};
+
+#[tokio::test]
+async fn limits_requests() {
+    let mut server = Server::new("127.0.0.1", 9150);
+    server.route(
+        "/",
+        Stack::new()
+            .push(RateLimit::new(3, Duration::from_millis(300)))
+            .push(handlers::handler(|r: Request| async move {
+                Ok(r.peer_addr().unwrap().ip().to_string())
+            })),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new("127.0.0.1:9150");
+    let mut client = client.connect().await.unwrap();
+
+    for _ in 0..3 {
+        let response = client.send_request(&Request::default()).await.unwrap();
+        assert_eq!(response.status.code, 200);
+        assert_eq!(response.content().await, "127.0.0.1");
+    }
+
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert_eq!(response.status.code, 429);
+    assert_eq!(response.headers.get_first("retry-after").unwrap(), "1");
+    response.content().await;
+
+    // Tokens refill over the window
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert_eq!(response.status.code, 200);
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn cleans_up_idle_buckets() {
+    let limit = RateLimit::with_shards(1, Duration::from_millis(50), 1);
+    let mut w: Vec<u8> = vec![];
+
+    for port in 1..=5 {
+        let mut request = Request::new(Method::GET, "/");
+        request.set_peer_addr(format!("10.0.0.{}:{}", port, port).parse().unwrap());
+        assert!(matches!(
+            limit.handle(&request, &mut w).await.unwrap(),
+            Action::Next
+        ));
+    }
+    assert_eq!(limit.num_buckets(), 5);
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    let mut request = Request::new(Method::GET, "/");
+    request.set_peer_addr("10.0.0.9:9".parse().unwrap());
+    limit.handle(&request, &mut w).await.unwrap();
+    assert_eq!(limit.num_buckets(), 1);
+
+    // Requests without a peer address aren't limited
+    let request = Request::new(Method::GET, "/");
+    for _ in 0..3 {
+        assert!(matches!(
+            limit.handle(&request, &mut w).await.unwrap(),
+            Action::Next
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use hype::request::*;
+use hype::{handler::Error, request::*};
 
 #[test]
 fn it_works_with_body() {
@@ -7,7 +7,7 @@ fn it_works_with_body() {
     assert_eq!(
         request.serialize(),
         "GET /foobar HTTP/1.1\r
-host: localhost:8080\r
+Host: localhost:8080\r
 \r
 "
     );
@@ -20,8 +20,260 @@ fn it_works_with_cookies() {
     assert_eq!(
         request.serialize(),
         "GET /foobar HTTP/1.1\r
-cookie: foo=bar; id=blah\r
+Cookie: foo=bar; id=blah\r
 \r
 "
     );
 }
+
+#[test]
+fn set_cookies() {
+    let mut request = Request::new(Method::GET, "/foobar");
+    request.set_cookies([("foo", "bar"), ("id", "blah")]);
+    assert_eq!(
+        request.headers.get_first("cookie").unwrap(),
+        "foo=bar; id=blah"
+    );
+
+    let cookies = request.cookies().unwrap();
+    assert_eq!(cookies.get("foo"), Some(&"bar"));
+    assert_eq!(cookies.get("id"), Some(&"blah"));
+}
+
+#[derive(Debug, serde::Deserialize, PartialEq)]
+struct Backend {
+    host: String,
+    port: u16,
+}
+
+fn json_request(content_type: &str, body: &str) -> Request {
+    Request::from(format!(
+        "POST /backends HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn json_body() {
+    let request = json_request(
+        "application/json; charset=utf-8",
+        r#"{"host": "localhost", "port": 8000}"#,
+    );
+    let backend: Backend = request.json().await.unwrap();
+    assert_eq!(
+        backend,
+        Backend {
+            host: "localhost".into(),
+            port: 8000
+        }
+    );
+
+    let request = json_request("application/problem+json", r#"{"host": "a", "port": 1}"#);
+    assert!(request.json::<Backend>().await.is_ok());
+}
+
+#[tokio::test]
+async fn json_wrong_content_type() {
+    let request = json_request("text/plain", r#"{"host": "localhost", "port": 8000}"#);
+    assert!(matches!(
+        request.json::<Backend>().await,
+        Err(Error::Status(status)) if status.code == 415
+    ));
+
+    // Unless the content type isn't checked.
+    assert!(request.json_unchecked::<Backend>().await.is_ok());
+}
+
+#[tokio::test]
+async fn json_malformed() {
+    for body in [r#"{"host": "localhost""#, r#"{"host": "localhost"}"#] {
+        let request = json_request("application/json", body);
+        assert!(matches!(
+            request.json::<Backend>().await,
+            Err(Error::Status(status)) if status.code == 400
+        ));
+    }
+}
+
+/// Reads the body, like a middleware that verifies a signature would.
+struct BodyChecker {}
+
+#[async_trait::async_trait]
+impl hype::handler::Handler for BodyChecker {
+    async fn handle(
+        &self,
+        r: &Request,
+        _w: &mut dyn hype::handler::AsyncWriteStream,
+    ) -> Result<hype::handler::Action, Error> {
+        r.buffer_body()
+            .await
+            .map_err(|e| Error::Failed(e.to_string()))?;
+        if r.body.content().await != b"signed payload" {
+            return Err(Error::Status(hype::status::BAD_REQUEST.into()));
+        }
+        Ok(hype::handler::Action::Next)
+    }
+}
+
+#[tokio::test]
+async fn buffer_body() {
+    use hype::handler::{Action, Handler};
+
+    let stack = hype::middleware::Stack::new()
+        .push(BodyChecker {})
+        .push(hype::handlers::handler(|r: Request| async move {
+            Ok(String::from_utf8(r.body.content().await).unwrap())
+        }));
+
+    let mut request = Request::new(Method::POST, "/");
+    request.body = hype::body::Body::from_reader(std::io::Cursor::new(b"signed payload".to_vec()));
+
+    // The handler after the middleware sees the same body.
+    let result = stack.handle(&request, &mut vec![]).await.unwrap();
+    let Action::Response(response) = result else {
+        panic!("expected a response");
+    };
+    assert_eq!(response.content().await, "signed payload");
+}
+
+fn conditional(method: Method, headers: &[(&str, &str)]) -> Request {
+    let mut request = Request::new(method, "/resource");
+    for (k, v) in headers {
+        request.headers.add(*k, *v);
+    }
+    request
+}
+
+#[test]
+fn preconditions_if_match() {
+    use PreconditionResult::*;
+
+    let etag = "\"v2\"";
+    let check =
+        |headers: &[(&str, &str)]| conditional(Method::PUT, headers).check_preconditions(etag);
+
+    assert_eq!(check(&[]), Proceed);
+    assert_eq!(check(&[("If-Match", "\"v2\"")]), Proceed);
+    assert_eq!(check(&[("If-Match", "\"v1\", \"v2\"")]), Proceed);
+    assert_eq!(check(&[("If-Match", "\"v1\"")]), PreconditionFailed);
+    assert_eq!(check(&[("If-Match", "*")]), Proceed);
+
+    // Weak tags never match strongly.
+    assert_eq!(check(&[("If-Match", "W/\"v2\"")]), PreconditionFailed);
+    let request = conditional(Method::PUT, &[("If-Match", "W/\"v2\"")]);
+    assert_eq!(request.check_preconditions("W/\"v2\""), PreconditionFailed);
+
+    // Without a current representation, even `*` fails.
+    let request = conditional(Method::PUT, &[("If-Match", "*")]);
+    assert_eq!(
+        request.evaluate_preconditions(None, None),
+        PreconditionFailed
+    );
+}
+
+#[test]
+fn preconditions_if_none_match() {
+    use PreconditionResult::*;
+
+    let etag = "W/\"v2\"";
+    let check =
+        |method, headers: &[(&str, &str)]| conditional(method, headers).check_preconditions(etag);
+
+    // Weak comparison, so the W/ prefix doesn't matter.
+    assert_eq!(
+        check(Method::GET, &[("If-None-Match", "\"v2\"")]),
+        NotModified
+    );
+    assert_eq!(
+        check(Method::HEAD, &[("If-None-Match", "W/\"v2\"")]),
+        NotModified
+    );
+    assert_eq!(check(Method::GET, &[("If-None-Match", "\"v1\"")]), Proceed);
+    assert_eq!(check(Method::GET, &[("If-None-Match", "*")]), NotModified);
+
+    // Unsafe methods fail instead.
+    assert_eq!(
+        check(Method::PUT, &[("If-None-Match", "\"v2\"")]),
+        PreconditionFailed
+    );
+    assert_eq!(
+        check(Method::PUT, &[("If-None-Match", "*")]),
+        PreconditionFailed
+    );
+
+    // `If-None-Match: *` lets a PUT create a resource that doesn't exist yet.
+    let request = conditional(Method::PUT, &[("If-None-Match", "*")]);
+    assert_eq!(request.evaluate_preconditions(None, None), Proceed);
+
+    // If-Match is checked first.
+    assert_eq!(
+        check(
+            Method::GET,
+            &[("If-Match", "\"v1\""), ("If-None-Match", "\"v1\"")]
+        ),
+        PreconditionFailed
+    );
+}
+
+#[test]
+fn preconditions_dates() {
+    use chrono::{DateTime, Utc};
+    use PreconditionResult::*;
+
+    let modified: DateTime<Utc> = "2024-05-01T12:00:00Z".parse().unwrap();
+    let before = "Tue, 30 Apr 2024 12:00:00 GMT";
+    let at = "Wed, 01 May 2024 12:00:00 GMT";
+    let after = "Thu, 02 May 2024 12:00:00 GMT";
+    let check = |method, headers: &[(&str, &str)]| {
+        conditional(method, headers).evaluate_preconditions(Some("\"v2\""), Some(modified))
+    };
+
+    assert_eq!(
+        check(Method::GET, &[("If-Modified-Since", at)]),
+        NotModified
+    );
+    assert_eq!(
+        check(Method::GET, &[("If-Modified-Since", after)]),
+        NotModified
+    );
+    assert_eq!(
+        check(Method::GET, &[("If-Modified-Since", before)]),
+        Proceed
+    );
+    assert_eq!(
+        check(Method::GET, &[("If-Modified-Since", "garbage")]),
+        Proceed
+    );
+
+    // Only GET and HEAD are conditional on modification.
+    assert_eq!(check(Method::POST, &[("If-Modified-Since", at)]), Proceed);
+
+    assert_eq!(check(Method::PUT, &[("If-Unmodified-Since", at)]), Proceed);
+    assert_eq!(
+        check(Method::PUT, &[("If-Unmodified-Since", before)]),
+        PreconditionFailed
+    );
+
+    // The ETag headers take precedence over the dates.
+    assert_eq!(
+        check(
+            Method::PUT,
+            &[("If-Match", "\"v2\""), ("If-Unmodified-Since", before)]
+        ),
+        Proceed
+    );
+    assert_eq!(
+        check(
+            Method::GET,
+            &[("If-None-Match", "\"v1\""), ("If-Modified-Since", after)]
+        ),
+        Proceed
+    );
+
+    // Without a modification date, the date headers are ignored.
+    let request = conditional(Method::PUT, &[("If-Unmodified-Since", before)]);
+    assert_eq!(request.check_preconditions("\"v2\""), Proceed);
+}
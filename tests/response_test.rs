@@ -9,7 +9,7 @@ fn it_works_with_body() {
     assert_eq!(
         response.serialize(),
         "HTTP/1.1 200 OK\r
-content-length: 32\r
+Content-Length: 32\r
 \r
 <HTML><b>Hello world!</b></HTML>"
     );
@@ -27,3 +27,95 @@ fn it_works_with_cookies() {
     response.set_cookie(cookie);
     response.set_cookie(Cookie::new("SID", "foobar"));
 }
+
+#[test]
+fn binary_body() {
+    let bytes: &[u8] = &[0x89, b'P', b'N', b'G', 0xff, 0x00];
+    let mut response = Response::new(status::OK).with_body(bytes);
+    let serialized = response.serialize_bytes();
+    assert!(serialized.starts_with(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\n"));
+    assert!(serialized.ends_with(bytes));
+}
+
+#[test]
+fn set_status() {
+    let mut response = Response::new(status::OK).with_body("nope");
+    response.set_status(status::NOT_FOUND);
+    assert_eq!(
+        response.serialize(),
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\nnope"
+    );
+
+    // Codes get their standard reason phrase.
+    let mut response = Response::status(503).with_body("busy");
+    assert!(response
+        .serialize()
+        .starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+    let response = Response::status(200).with_status((418, "I'm a teapot"));
+    assert_eq!(response.serialize_status(), "HTTP/1.1 418 I'm a teapot");
+}
+
+#[test]
+fn redirect() {
+    let mut response = Response::redirect("/new", 307);
+    assert_eq!(
+        response.serialize(),
+        "HTTP/1.1 307 Temporary Redirect\r\nLocation: /new\r\n\r\n"
+    );
+
+    for (code, expected) in [(301, 301), (302, 302), (303, 303), (308, 308), (200, 302)] {
+        assert_eq!(Response::redirect("/", code).status.code, expected);
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Backend {
+    host: String,
+    port: u16,
+}
+
+#[tokio::test]
+async fn json() {
+    let backend = Backend {
+        host: "localhost".into(),
+        port: 8000,
+    };
+
+    let response = Response::json(status::OK, &backend).unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(
+        response.headers.get_first("content-type").unwrap(),
+        "application/json"
+    );
+    assert_eq!(
+        response.content().await,
+        r#"{"host":"localhost","port":8000}"#
+    );
+}
+
+#[tokio::test]
+async fn json_service() {
+    let handler = hype::handlers::handler(|_| async move {
+        let backend = Backend {
+            host: "localhost".into(),
+            port: 8000,
+        };
+        Ok(Response::json(status::OK, &backend)?)
+    });
+
+    let request = hype::request::Request::default();
+    let mut stream: Vec<u8> = vec![];
+    let action = hype::handler::Handler::handle(&handler, &request, &mut stream)
+        .await
+        .unwrap();
+
+    if let hype::handler::Action::Response(response) = action {
+        assert_eq!(
+            response.headers.get_first("content-type").unwrap(),
+            "application/json"
+        );
+    } else {
+        panic!("expected response");
+    }
+}
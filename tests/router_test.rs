@@ -1,69 +1,85 @@
-use hype::{request::Method, router::Matcher};
+use hype::{
+    handler::Action,
+    handlers,
+    request::{Method, Request},
+    router::{Matcher, Router, TrailingSlash},
+    status,
+};
 
 #[test]
 fn matcher_test() {
     // Pattern -> Path
     assert!(Matcher::new("/foo".to_string())
-        .extract_params("/foo", None)
+        .extract_params("/foo", None, None)
         .is_some());
     assert!(Matcher::new("/foo/*")
-        .extract_params(&"/foo/bar".to_string(), None)
+        .extract_params(&"/foo/bar".to_string(), None, None)
+        .is_some());
+    assert!(Matcher::new("/").extract_params("/", None, None).is_some());
+    assert!(Matcher::new("/*")
+        .extract_params("/foo", None, None)
+        .is_some());
+    assert!(Matcher::new("*").extract_params("/", None, None).is_some());
+    assert!(Matcher::new("/")
+        .extract_params("/foo", None, None)
         .is_some());
-    assert!(Matcher::new("/").extract_params("/", None).is_some());
-    assert!(Matcher::new("/*").extract_params("/foo", None).is_some());
-    assert!(Matcher::new("*").extract_params("/", None).is_some());
-    assert!(Matcher::new("/").extract_params("/foo", None).is_some());
     assert!(Matcher::new("/foo")
-        .extract_params("/foo/bar", None)
+        .extract_params("/foo/bar", None, None)
+        .is_some());
+    assert!(Matcher::new("")
+        .extract_params("/foo", None, None)
         .is_some());
-    assert!(Matcher::new("").extract_params("/foo", None).is_some());
     assert!(Matcher::new("/foo/*")
-        .extract_params("/foo/boo/blah", None)
+        .extract_params("/foo/boo/blah", None, None)
         .is_some());
 
-    assert!(Matcher::new("/foo").extract_params("", None).is_none());
+    assert!(Matcher::new("/foo")
+        .extract_params("", None, None)
+        .is_none());
 }
 
 #[test]
 fn matched_path() {
     let r = Matcher::new("/files");
-    let r = r.extract_params("/files", None);
+    let r = r.extract_params("/files", None, None);
     assert_eq!(r.unwrap().0.to_string_lossy(), "/files");
 
     let r = Matcher::new("/files");
-    let r = r.extract_params("/files/README.md", None);
+    let r = r.extract_params("/files/README.md", None, None);
     assert_eq!(r.unwrap().0.to_string_lossy(), "/files");
 
     let r = Matcher::new("/files/*");
-    let r = r.extract_params("/files/README.md", None);
+    let r = r.extract_params("/files/README.md", None, None);
     assert_eq!(r.unwrap().0.to_string_lossy(), "/files/README.md");
 
     let r = Matcher::new("/x/files");
-    let r = r.extract_params("/x/files/README.md", None);
+    let r = r.extract_params("/x/files/README.md", None, None);
     assert_eq!(r.unwrap().0.to_string_lossy(), "/x/files");
 
     let r = Matcher::new("/x/files");
-    let r = r.extract_params("/x/files/dist/README.md", None);
+    let r = r.extract_params("/x/files/dist/README.md", None, None);
     assert_eq!(r.unwrap().0.to_string_lossy(), "/x/files");
 
     let r = Matcher::new("/*/admin");
-    let r = r.extract_params("/x/admin/README.md", None);
+    let r = r.extract_params("/x/admin/README.md", None, None);
     assert_eq!(r.unwrap().0.to_string_lossy(), "/x/admin");
 
     let r = Matcher::new("/*/admin");
-    let r = r.extract_params("/x/admin/dist/README.md", None);
+    let r = r.extract_params("/x/admin/dist/README.md", None, None);
     assert_eq!(r.unwrap().0.to_string_lossy(), "/x/admin");
 }
 
 #[test]
 fn extract_params() {
     let r = Matcher::new("/files/:name");
-    let r = r.extract_params("/files/README.md", None).unwrap();
+    let r = r.extract_params("/files/README.md", None, None).unwrap();
     assert_eq!(r.0.to_string_lossy(), "/files/README.md");
     assert_eq!(r.1.get("name").unwrap(), &"README.md");
 
     let r = Matcher::new("/files/:name/*/:ext");
-    let r = r.extract_params("/files/README/dist/md", None).unwrap();
+    let r = r
+        .extract_params("/files/README/dist/md", None, None)
+        .unwrap();
     assert_eq!(r.0.to_string_lossy(), "/files/README/dist/md");
     assert_eq!(r.1.get("name").unwrap(), &"README");
     assert_eq!(r.1.get("ext").unwrap(), &"md");
@@ -74,19 +90,377 @@ fn match_methods() {
     let mut r = Matcher::new("/files/:name");
     r.push_methods(vec![Method::GET, Method::POST]);
     let result = r
-        .extract_params("/files/README.md", Some(Method::GET))
+        .extract_params("/files/README.md", Some(Method::GET), None)
         .unwrap();
     assert_eq!(result.0.to_string_lossy(), "/files/README.md");
     assert_eq!(result.1.get("name").unwrap(), &"README.md");
 
     assert_ne!(
-        r.extract_params("/files/README.md", Some(Method::POST)),
+        r.extract_params("/files/README.md", Some(Method::POST), None),
         None
     );
 
     // This should not match
     assert_eq!(
-        r.extract_params("/files/README.md", Some(Method::HEAD)),
+        r.extract_params("/files/README.md", Some(Method::HEAD), None),
         None
     );
 }
+
+#[test]
+fn match_path_ignores_method() {
+    let mut r = Matcher::new("/x");
+    r.push_method(Method::GET);
+
+    assert!(r.match_path("/x").is_some());
+    assert!(r.matches_method(Some(Method::GET)));
+    assert!(!r.matches_method(Some(Method::POST)));
+    assert!(r.extract_params("/x", Some(Method::POST), None).is_none());
+}
+
+#[tokio::test]
+async fn method_not_allowed() {
+    let router = Router::new();
+    let mut matcher = Matcher::new("/x");
+    matcher.push_method(Method::GET);
+    router.add_route(matcher, handlers::handler(|_| async move { Ok("x") }));
+
+    let mut w: Vec<u8> = vec![];
+
+    let mut request = Request::new(Method::GET, "/x");
+    let action = router.handle(&mut request, &mut w).await.unwrap();
+    assert!(matches!(action, Action::Response(r) if r.status.code == 200));
+
+    let mut request = Request::new(Method::POST, "/x");
+    match router.handle(&mut request, &mut w).await.unwrap() {
+        Action::Response(response) => {
+            assert_eq!(response.status.code, 405);
+            assert_eq!(response.headers.get_first("allow").unwrap(), "GET");
+        }
+        _ => panic!("expected a response"),
+    }
+
+    // Unregistered paths still fall through to the default handler
+    let mut request = Request::new(Method::POST, "/y");
+    router.handle(&mut request, &mut w).await.unwrap();
+    assert!(String::from_utf8(w).unwrap().starts_with("HTTP/1.1 404"));
+}
+
+#[tokio::test]
+async fn auto_options() {
+    let mut router = Router::new();
+    let mut matcher = Matcher::new("/x");
+    matcher.push_method(Method::GET);
+    router.add_route(matcher, handlers::handler(|_| async move { Ok("x") }));
+    let mut matcher = Matcher::new("/x");
+    matcher.push_methods(vec![Method::POST, Method::GET]);
+    router.add_route(matcher, handlers::handler(|_| async move { Ok("x") }));
+
+    let mut w: Vec<u8> = vec![];
+    let mut request = Request::new(Method::OPTIONS, "/x");
+    match router.handle(&mut request, &mut w).await.unwrap() {
+        Action::Response(response) => {
+            assert_eq!(response.status.code, 200);
+            assert_eq!(
+                response.headers.get_first("allow").unwrap(),
+                "GET, POST, OPTIONS"
+            );
+        }
+        _ => panic!("expected a response"),
+    }
+
+    // Without auto OPTIONS, it's just another method that isn't allowed
+    router.set_auto_options(false);
+    let mut request = Request::new(Method::OPTIONS, "/x");
+    match router.handle(&mut request, &mut w).await.unwrap() {
+        Action::Response(response) => {
+            assert_eq!(response.status.code, 405);
+            assert_eq!(response.headers.get_first("allow").unwrap(), "GET, POST");
+        }
+        _ => panic!("expected a response"),
+    }
+}
+
+#[test]
+fn match_hosts() {
+    let r = Matcher::new("/api").with_host("Example.com");
+    assert!(r
+        .extract_params("/api", None, Some("example.com"))
+        .is_some());
+    assert!(r
+        .extract_params("/api", None, Some("EXAMPLE.COM:8080"))
+        .is_some());
+    assert!(r.extract_params("/api", None, Some("other.com")).is_none());
+    assert!(r
+        .extract_params("/api", None, Some("www.example.com"))
+        .is_none());
+    assert!(r.extract_params("/api", None, None).is_none());
+
+    let r = Matcher::new("/api").with_host("*.example.com");
+    assert!(r.matches_host(Some("www.example.com")));
+    assert!(r.matches_host(Some("a.b.example.com:443")));
+    assert!(!r.matches_host(Some("example.com")));
+    assert!(!r.matches_host(Some("wwwexample.com")));
+
+    let r = Matcher::new("/api").with_host("[::1]");
+    assert!(r.matches_host(Some("[::1]:8080")));
+    assert!(r.matches_host(Some("[::1]")));
+
+    // Matchers without hosts match every host.
+    assert!(Matcher::new("/api").matches_host(None));
+    assert!(Matcher::new("/api").matches_host(Some("other.com")));
+}
+
+#[tokio::test]
+async fn route_hosts() {
+    let router = Router::new();
+    router.add_route(
+        Matcher::new("/api").with_host("example.com"),
+        handlers::Status::new(status::OK, "example"),
+    );
+    router.add_route(
+        Matcher::new("/api").with_host("other.com"),
+        handlers::Status::new(status::UNAUTHORIZED, "other"),
+    );
+    router.add_route(
+        Matcher::new("/api"),
+        handlers::Status::new(status::NOT_FOUND, "any"),
+    );
+
+    for (host, expected) in [
+        ("example.com", "HTTP/1.1 200 OK"),
+        ("other.com:8080", "HTTP/1.1 401 Unauthorized"),
+        ("unknown.com", "HTTP/1.1 404 Not Found"),
+    ] {
+        let mut w: Vec<u8> = vec![];
+        let mut request = Request::new(Method::GET, "/api");
+        request.headers.set("Host", host);
+        router.handle(&mut request, &mut w).await.unwrap();
+        assert!(String::from_utf8(w).unwrap().starts_with(expected));
+    }
+}
+
+#[test]
+fn greedy_wildcard() {
+    let r = Matcher::new("/static/*path");
+    let (path, params) = r.extract_params("/static/css/app.css", None, None).unwrap();
+    assert_eq!(path.to_string_lossy(), "/static/css/app.css");
+    assert_eq!(params.get("path").unwrap(), &"css/app.css");
+
+    let (_, params) = r.extract_params("/static/app.js", None, None).unwrap();
+    assert_eq!(params.get("path").unwrap(), &"app.js");
+
+    // Nothing left to capture.
+    let (_, params) = r.extract_params("/static", None, None).unwrap();
+    assert_eq!(params.get("path").unwrap(), &"");
+
+    assert!(r.extract_params("/other/app.js", None, None).is_none());
+
+    // Mixed with named parameters.
+    let r = Matcher::new("/users/:id/files/*rest");
+    let (_, params) = r
+        .extract_params("/users/42/files/a/b/c.txt", None, None)
+        .unwrap();
+    assert_eq!(params.get("id").unwrap(), &"42");
+    assert_eq!(params.get("rest").unwrap(), &"a/b/c.txt");
+}
+
+#[test]
+fn match_regex() {
+    let r = Matcher::regex(r"/users/(?P<id>\d+)").unwrap();
+    let (path, params) = r.extract_params("/users/42", None, None).unwrap();
+    assert_eq!(path.to_string_lossy(), "/users/42");
+    assert_eq!(params.get("id").unwrap(), &"42");
+
+    assert!(r.extract_params("/users/abc", None, None).is_none());
+    assert!(r.extract_params("/users/42/posts", None, None).is_none());
+    assert!(r.extract_params("/x/users/42", None, None).is_none());
+
+    // Optional groups that don't participate aren't returned.
+    let r = Matcher::regex(r"/files/(?P<name>[^/]+?)(\.(?P<ext>[a-z]+))?").unwrap();
+    let (_, params) = r.extract_params("/files/README.md", None, None).unwrap();
+    assert_eq!(params.get("name").unwrap(), &"README");
+    assert_eq!(params.get("ext").unwrap(), &"md");
+    let (_, params) = r.extract_params("/files/LICENSE", None, None).unwrap();
+    assert_eq!(params.get("name").unwrap(), &"LICENSE");
+    assert!(!params.contains_key("ext"));
+
+    assert!(Matcher::regex("/(unclosed").is_err());
+}
+
+#[tokio::test]
+async fn route_regex() {
+    let router = Router::new();
+    router.add_route(
+        Matcher::regex(r"/users/(?P<id>\d+)").unwrap(),
+        handlers::handler(|r| async move { Ok(format!("user {}", r.params["id"])) }),
+    );
+    router.add_route(
+        Matcher::new("/users/admin"),
+        handlers::Status::new(status::UNAUTHORIZED, "admin"),
+    );
+    router.add_route(
+        Matcher::new("/users"),
+        handlers::Status::new(status::BAD_REQUEST, "users"),
+    );
+
+    let mut w: Vec<u8> = vec![];
+    let mut request = Request::new(Method::GET, "/users/42");
+    match router.handle(&mut request, &mut w).await.unwrap() {
+        Action::Response(response) => assert_eq!(response.content().await, "user 42"),
+        _ => panic!("expected a response"),
+    }
+
+    // Segment matchers take precedence over regex matchers of the same length, but not
+    // over longer ones.
+    let mut request = Request::new(Method::GET, "/users/admin");
+    router.handle(&mut request, &mut w).await.unwrap();
+    assert!(String::from_utf8(w).unwrap().starts_with("HTTP/1.1 401"));
+
+    // Paths that the regex rejects fall through to shorter matchers.
+    let mut w: Vec<u8> = vec![];
+    let mut request = Request::new(Method::GET, "/users/abc");
+    router.handle(&mut request, &mut w).await.unwrap();
+    assert!(String::from_utf8(w).unwrap().starts_with("HTTP/1.1 400"));
+}
+
+async fn route(router: &Router, path: &str) -> String {
+    let mut w: Vec<u8> = vec![];
+    let mut request = Request::new(Method::GET, path);
+    match router.handle(&mut request, &mut w).await.unwrap() {
+        Action::Response(response) => response.content().await,
+        _ => String::from_utf8(w).unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn literal_segments_first() {
+    let router = Router::new();
+    router.add_route(
+        Matcher::new("/users/me"),
+        handlers::handler(|_| async move { Ok("me") }),
+    );
+    router.add_route(
+        Matcher::new("/users/:id"),
+        handlers::handler(|r| async move { Ok(format!("user {}", r.params["id"])) }),
+    );
+    router.add_route(
+        Matcher::new("/:kind/:id"),
+        handlers::handler(
+            |r| async move { Ok(format!("{} {}", r.params["kind"], r.params["id"])) },
+        ),
+    );
+
+    // Routes of the same length are ranked by their literal segments, whatever order they
+    // were added in.
+    assert_eq!(route(&router, "/users/me").await, "me");
+    assert_eq!(route(&router, "/users/42").await, "user 42");
+    assert_eq!(route(&router, "/teams/7").await, "teams 7");
+}
+
+#[tokio::test]
+async fn sub_routers() {
+    let api = Router::new();
+    api.add_route(
+        Matcher::new("/ping"),
+        handlers::handler(|r| async move { Ok(format!("pong {:?}", r.path())) }),
+    );
+    api.add_route(
+        Matcher::new("/users/:id"),
+        handlers::handler(|r| async move {
+            Ok(format!(
+                "user {} in {} at {:?}",
+                r.params["id"],
+                r.params.get("tenant").map_or("-", |t| t.as_str()),
+                r.path()
+            ))
+        }),
+    );
+
+    let router = Router::new();
+    router.add_route(
+        Matcher::new("/ping"),
+        handlers::handler(|_| async move { Ok("top-level pong") }),
+    );
+    router.mount("/api", api.clone());
+    router.mount("/t/:tenant/api", api);
+
+    assert_eq!(route(&router, "/api/ping").await, "pong \"\"");
+    assert_eq!(route(&router, "/ping").await, "top-level pong");
+    assert_eq!(
+        route(&router, "/api/users/42/avatar").await,
+        "user 42 in - at \"/avatar\""
+    );
+
+    // Params from the mount point are kept.
+    assert_eq!(
+        route(&router, "/t/mo/api/users/7").await,
+        "user 7 in mo at \"\""
+    );
+
+    // Unmatched paths under the mount point go to the sub-router's default handler.
+    assert!(route(&router, "/api/nope")
+        .await
+        .starts_with("HTTP/1.1 404"));
+}
+
+#[tokio::test]
+async fn mount_point() {
+    let api = Router::new();
+    api.add_route(
+        Matcher::new("/"),
+        handlers::handler(|r| async move { Ok(format!("index {:?}", r.path())) }),
+    );
+
+    let router = Router::new();
+    router.mount("/api", api);
+
+    assert_eq!(route(&router, "/api").await, "index \"\"");
+    assert_eq!(route(&router, "/api/").await, "index \"\"");
+    assert_eq!(route(&router, "/api/docs").await, "index \"docs\"");
+
+    // Requests outside the route don't panic.
+    let mut request = Request::new(Method::GET, "/other");
+    request.handler_path = Some("/api".into());
+    assert_eq!(request.path(), "/");
+}
+
+#[tokio::test]
+async fn trailing_slashes() {
+    let mut router = Router::new();
+    router.add_route(
+        Matcher::new("/foo"),
+        handlers::handler(|_| async move { Ok("foo") }),
+    );
+
+    async fn action(router: &Router, path: &str) -> Action {
+        let mut request = Request::from(format!("GET {} HTTP/1.1\r\n\r\n", path)).unwrap();
+        router.handle(&mut request, &mut vec![]).await.unwrap()
+    }
+    fn redirect(action: Action) -> String {
+        match action {
+            Action::Redirect(to) => to,
+            _ => panic!("expected a redirect"),
+        }
+    }
+
+    // Ignore (the default)
+    assert_eq!(route(&router, "/foo").await, "foo");
+    assert_eq!(route(&router, "/foo/").await, "foo");
+
+    router.set_trailing_slash(TrailingSlash::Strict);
+    assert_eq!(route(&router, "/foo").await, "foo");
+    assert!(route(&router, "/foo/").await.starts_with("HTTP/1.1 404"));
+    assert_eq!(route(&router, "/foo/bar/").await, "foo");
+
+    router.set_trailing_slash(TrailingSlash::RedirectToSlash);
+    assert_eq!(redirect(action(&router, "/foo").await), "/foo/");
+    assert_eq!(redirect(action(&router, "/foo?a=b").await), "/foo/?a=b");
+    assert_eq!(route(&router, "/foo/").await, "foo");
+
+    router.set_trailing_slash(TrailingSlash::RedirectToNoSlash);
+    assert_eq!(redirect(action(&router, "/foo/").await), "/foo");
+    assert_eq!(redirect(action(&router, "/foo//?a=b").await), "/foo?a=b");
+    assert_eq!(route(&router, "/foo").await, "foo");
+    assert!(route(&router, "/").await.starts_with("HTTP/1.1 404"));
+}
@@ -2,9 +2,10 @@ use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use hype::{
-    client::Client,
+    client::{Client, ClientError},
     handler::{self, AsyncWriteStream, Handler},
-    request::Request,
+    handlers, parser,
+    request::{Method, Request},
     response::Response,
     server::Server,
     status,
@@ -256,14 +257,14 @@ async fn keep_alive_max() {
     assert_eq!(response.body.content().await, "OK".as_bytes());
     assert!(!client.is_closed().await);
 
+    // The last response closes the connection.
     let response = client.send_request(&request).await.unwrap();
     assert_eq!(response.status.code, 200);
     assert_eq!(response.body.content().await, "OK".as_bytes());
-    assert!(!client.is_closed().await);
+    assert!(client.is_closed().await);
 
     let response = client.send_request(&request).await;
     assert!(response.is_err());
-    assert!(client.is_closed().await);
 
     shutdown_server(shutdown).await;
 }
@@ -284,11 +285,11 @@ async fn connection_close() {
     let response = client.send_request(&request).await.unwrap();
     assert_eq!(response.status.code, 200);
     assert_eq!(response.content().await, "OK");
-    assert!(!client.is_closed().await);
+    assert_eq!(response.headers.get_first("connection").unwrap(), "close");
+    assert!(client.is_closed().await);
 
     let response = client.send_request(&request).await;
-    assert!(response.is_err());
-    assert!(client.is_closed().await);
+    assert!(matches!(response, Err(ClientError::ConnectionClosed)));
 
     shutdown_server(shutdown).await;
 }
@@ -351,3 +352,1531 @@ async fn default_error() {
     // Shutdown server
     shutdown_server(shutdown).await;
 }
+
+#[tokio::test]
+async fn graceful_shutdown() {
+    let port = 8862;
+    let address = format!("{}:{}", HOST, port);
+
+    let mut server = Server::new(HOST, port);
+    server.route(
+        "/slow",
+        handlers::handler(|_| async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok("slow response")
+        }),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown_graceful(Duration::from_secs(5));
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    // An idle connection shouldn't hold up the shutdown
+    let mut idle = Client::new(address.clone());
+    let _idle = idle.connect().await.unwrap();
+
+    let mut client = Client::new(address.clone());
+    let mut client = client.connect().await.unwrap();
+    let request = tokio::spawn(async move {
+        let response = client
+            .send_request(&Request::new(Method::GET, "/slow"))
+            .await
+            .unwrap();
+        (response.status.code, response.content().await)
+    });
+
+    // Request shutdown while the response is in progress
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let started = std::time::Instant::now();
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let (code, content) = request.await.unwrap();
+    assert_eq!(code, 200);
+    assert_eq!(content, "slow response");
+
+    // No new connections are accepted
+    assert!(Client::new(address).connect().await.is_err());
+}
+
+#[tokio::test]
+async fn forwarded_for() {
+    let port = 8863;
+    let address = format!("{}:{}", HOST, port);
+
+    let mut server = Server::new(HOST, port);
+    server.trust_forwarded_for(vec![
+        "127.0.0.1".parse().unwrap(),
+        "10.0.0.1".parse().unwrap(),
+    ]);
+    server.route(
+        "/",
+        handlers::handler(|r: Request| async move { Ok(r.peer_addr().unwrap().ip().to_string()) }),
+    );
+    server.route(
+        "/socket",
+        handlers::handler(
+            |r: Request| async move { Ok(r.socket_addr().unwrap().ip().to_string()) },
+        ),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(address);
+    let mut client = client.connect().await.unwrap();
+
+    let cases = [
+        (None, "127.0.0.1"),
+        (Some("203.0.113.7"), "203.0.113.7"),
+        (Some("198.51.100.1, 203.0.113.7, 10.0.0.1"), "203.0.113.7"),
+        (Some("garbage, 10.0.0.1"), "127.0.0.1"),
+    ];
+
+    for (header, want) in cases {
+        let mut request = Request::default();
+        if let Some(header) = header {
+            request.headers.set("X-Forwarded-For", header);
+        }
+        let response = client.send_request(&request).await.unwrap();
+        assert_eq!(response.content().await, want);
+    }
+
+    // The socket address is still the proxy's.
+    let mut request = Request::new(Method::GET, "/socket");
+    request.headers.set("X-Forwarded-For", "203.0.113.7");
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.content().await, "127.0.0.1");
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn sni_certs() {
+    let port = 8864;
+    let certs = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/certs");
+
+    let mut server = Server::new(HOST, port);
+    server.enable_tls(certs.join("localhost.crt"), certs.join("localhost.key"));
+    server.add_tls_cert(
+        "hype.test",
+        certs.join("hype.test.crt"),
+        certs.join("hype.test.key"),
+    );
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let connect = |server_name: &str| {
+        let mut client = Client::new(format!("{}:{}", HOST, port));
+        client
+            .enable_tls(server_name)
+            .add_root_cert(certs.join("ca.crt"))
+            .unwrap();
+        async move { client.connect().await }
+    };
+
+    // Each name gets its own certificate, which the client validates against the name.
+    for server_name in ["hype.test", "localhost"] {
+        let response = connect(server_name)
+            .await
+            .unwrap()
+            .send_request(&Request::default())
+            .await
+            .unwrap();
+        assert_eq!(response.content().await, "hello");
+    }
+
+    // Unknown names get the default (localhost) certificate, which doesn't match.
+    assert!(connect("unknown.test").await.is_err());
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn unix_socket() {
+    use tokio::io::AsyncReadExt;
+
+    let path = std::env::temp_dir().join(format!("hype-test-{}.sock", std::process::id()));
+
+    // Stale socket files are replaced.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let mut server = Server::new_unix(&path);
+    server.route(
+        "/peer",
+        handlers::handler(|r: Request| async move { Ok(format!("{:?}", r.peer_addr())) }),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    stream
+        .write_all(b"GET /peer HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\nNone"));
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn proxy_protocol() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8865;
+    let mut server = Server::new(HOST, port);
+    server.enable_proxy_protocol(true);
+    server.route(
+        "/peer",
+        handlers::handler(|r: Request| async move { Ok(format!("{:?}", r.peer_addr())) }),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let send = |data: String| async move {
+        let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+            .await
+            .unwrap();
+        stream.write_all(data.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    const REQUEST: &str = "GET /peer HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+    let response = send(format!(
+        "PROXY TCP4 192.0.2.1 127.0.0.1 56324 {}\r\n{}",
+        port, REQUEST
+    ))
+    .await;
+    assert!(response.ends_with("\r\n\r\nSome(192.0.2.1:56324)"));
+
+    // The connection's own address is used for UNKNOWN.
+    let response = send(format!("PROXY UNKNOWN\r\n{}", REQUEST)).await;
+    assert!(response.contains("\r\n\r\nSome(127.0.0.1:"));
+
+    // Connections without a valid header are closed.
+    assert_eq!(send(REQUEST.into()).await, "");
+
+    // Clients that never send a header don't hold up other connections.
+    let _silent = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    let response = tokio::time::timeout(
+        Duration::from_secs(2),
+        send(format!("PROXY UNKNOWN\r\n{}", REQUEST)),
+    )
+    .await
+    .expect("request blocked by a silent client");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn headers_too_large() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8866;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    let filler = "x".repeat(1024);
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    for i in 0..100 {
+        let header = format!("X-Filler-{}: {}\r\n", i, filler);
+        if stream.write_all(header.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+    stream.read_to_string(&mut response).await.ok();
+    assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+    // Overlong request lines are rejected before the headers.
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    let path = format!("/{}", "x".repeat(parser::DEFAULT_MAX_URI_BYTES));
+    _ = stream
+        .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+        .await;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.ok();
+    assert!(response.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn body_too_large() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8867;
+    let mut server = Server::new(HOST, port);
+    server.set_max_body_bytes(10);
+    server.route_default(handlers::handler(|r: Request| async move {
+        Ok(format!("got {} bytes", r.body.content().await.len()))
+    }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    // Sends each part separately, so the handler can start before the body is read.
+    let send = |parts: &'static [&'static str]| async move {
+        let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+            .await
+            .unwrap();
+        for part in parts {
+            stream.write_all(part.as_bytes()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.ok();
+        response
+    };
+
+    let response =
+        send(&["POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\nConnection: close\r\n\r\n0123456789"])
+            .await;
+    assert!(response.ends_with("\r\n\r\ngot 10 bytes"));
+
+    let response =
+        send(&["POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\n01234567890"])
+            .await;
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    let response = send(&[
+        "POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n01234\r\n",
+        "6\r\n567890\r\n0\r\n\r\n",
+    ])
+    .await;
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn ambiguous_body_length() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8868;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET /smuggled HTTP/1.1\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(!response.contains("hello"));
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn request_ids() {
+    let port = 8869;
+    let mut server = Server::new(HOST, port);
+    server.route_default(
+        hype::middleware::Stack::new()
+            .push(handlers::RequestId::new())
+            .push(handlers::handler(|r| async move {
+                Ok(r.request_id().unwrap().to_string())
+            })),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.connect().await.unwrap();
+
+    // A new ID is generated for every request, and echoed on the response.
+    let mut ids = vec![];
+    for _ in 0..2 {
+        let response = client
+            .send_request(&Request::new(Method::GET, "/"))
+            .await
+            .unwrap();
+        let id = response.headers.get_first("x-request-id").unwrap().clone();
+        assert_eq!(id.len(), 16);
+        assert_eq!(response.content().await, id);
+        ids.push(id);
+    }
+    assert_ne!(ids[0], ids[1]);
+
+    // Incoming IDs are kept.
+    let mut request = Request::new(Method::GET, "/");
+    request.headers.set("X-Request-ID", "abc-123");
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(
+        response.headers.get_first("x-request-id").unwrap(),
+        "abc-123"
+    );
+    assert_eq!(response.content().await, "abc-123");
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn missing_host() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8870;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    for (request, expected) in [
+        ("GET / HTTP/1.1\r\n\r\n", "HTTP/1.1 400 Bad Request\r\n"),
+        (
+            "GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n",
+            "HTTP/1.1 400 Bad Request\r\n",
+        ),
+        (
+            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\n",
+        ),
+    ] {
+        let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+            .await
+            .unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with(expected), "{}", response);
+    }
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn bad_versions() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8882;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    for (request, expected) in [
+        (
+            "GET / BANANA\r\nHost: localhost\r\n\r\n",
+            "HTTP/1.1 400 Bad Request\r\n",
+        ),
+        (
+            "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n",
+            "HTTP/1.1 505 HTTP Version Not Supported\r\n",
+        ),
+    ] {
+        let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+            .await
+            .unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with(expected), "{}", response);
+    }
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn bad_transfer_encodings() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8893;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    for (encoding, expected) in [
+        ("gzip, chunked", "HTTP/1.1 501 Not Implemented\r\n"),
+        ("chunked, gzip", "HTTP/1.1 400 Bad Request\r\n"),
+    ] {
+        let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+            .await
+            .unwrap();
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: {}\r\n\r\n0\r\n\r\n",
+            encoding
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with(expected), "{}", response);
+    }
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn http10_closes_by_default() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8871;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+
+    // The server closes the connection after the response.
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("Connection: close\r\n"), "{}", response);
+    assert!(response.ends_with("hello"), "{}", response);
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn http10_keep_alive() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8872;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|r| async move {
+        Ok(format!("hello {}", r.abs_path()))
+    }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+
+    for path in ["/a", "/b"] {
+        stream
+            .write_all(
+                format!("GET {} HTTP/1.0\r\nConnection: keep-alive\r\n\r\n", path).as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        // The connection stays open, so read until the whole body is in.
+        let expected = format!("hello {}", path);
+        let mut buf = vec![];
+        while !String::from_utf8_lossy(&buf).ends_with(&expected) {
+            let mut chunk = [0u8; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert_ne!(n, 0, "connection closed: {}", String::from_utf8_lossy(&buf));
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        let response = String::from_utf8_lossy(&buf);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(
+            response.contains("Connection: keep-alive\r\n"),
+            "{}",
+            response
+        );
+    }
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn streams_chunked_responses() {
+    use futures::StreamExt;
+
+    let port = 8873;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move {
+        let mut response = Response::new(status::OK);
+        response.set_chunked();
+
+        // Push the chunks after the response is returned.
+        let body = response.body.clone();
+        tokio::spawn(async move {
+            for chunk in ["one", "two", "three"] {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                body.push_chunk(chunk.into());
+            }
+            body.end_chunked();
+        });
+
+        Ok(response)
+    }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port))
+        .connect()
+        .await
+        .unwrap();
+    let response = client
+        .send_request(&Request::new(Method::GET, "/"))
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(
+        response.headers.get_first("transfer-encoding").unwrap(),
+        "chunked"
+    );
+    assert!(response.body.chunked());
+
+    let chunks: Vec<Vec<u8>> = response.body.stream().collect().await;
+    assert_eq!(
+        chunks,
+        vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+    );
+
+    // The connection can be reused after the last chunk.
+    let response = client
+        .send_request(&Request::new(Method::GET, "/"))
+        .await
+        .unwrap();
+    assert_eq!(response.content().await, "onetwothree");
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+/// Send a request on `stream`, and return the response once `expected` is in it. Returns
+/// None if there's no response within `wait`.
+async fn try_get(
+    stream: &mut tokio::net::TcpStream,
+    expected: &str,
+    wait: Duration,
+) -> Option<String> {
+    use tokio::io::AsyncReadExt;
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+
+    let read = async {
+        let mut buf = vec![];
+        while !String::from_utf8_lossy(&buf).contains(expected) {
+            let mut chunk = [0u8; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert_ne!(n, 0, "connection closed: {}", String::from_utf8_lossy(&buf));
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        String::from_utf8_lossy(&buf).to_string()
+    };
+
+    tokio::time::timeout(wait, read).await.ok()
+}
+
+#[tokio::test]
+async fn connection_limit_waits() {
+    let port = 8874;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    server.max_concurrent_connections(1);
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let connect = || tokio::net::TcpStream::connect(format!("{}:{}", HOST, port));
+    let mut first = connect().await.unwrap();
+    let wait = Duration::from_secs(5);
+    assert!(try_get(&mut first, "hello", wait).await.is_some());
+
+    // The second connection is queued while the first is open...
+    let mut second = connect().await.unwrap();
+    let queued = Duration::from_millis(200);
+    assert!(try_get(&mut second, "hello", queued).await.is_none());
+
+    // ...and served once it closes.
+    drop(first);
+    let mut third = connect().await.unwrap();
+    let response = tokio::time::timeout(wait, async {
+        use tokio::io::AsyncReadExt;
+
+        let mut buf = vec![];
+        while !String::from_utf8_lossy(&buf).contains("hello") {
+            let mut chunk = [0u8; 1024];
+            let n = second.read(&mut chunk).await.unwrap();
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        String::from_utf8_lossy(&buf).to_string()
+    })
+    .await
+    .unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+
+    // The third connection is queued behind the second.
+    assert!(try_get(&mut third, "hello", queued).await.is_none());
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn connection_limit_rejects() {
+    use hype::server::OverloadPolicy;
+
+    let port = 8875;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    server.max_concurrent_connections(2);
+    server.set_overload_policy(OverloadPolicy::Reject);
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let connect = || tokio::net::TcpStream::connect(format!("{}:{}", HOST, port));
+    let wait = Duration::from_secs(5);
+    let mut open = vec![];
+    for _ in 0..2 {
+        let mut stream = connect().await.unwrap();
+        assert!(try_get(&mut stream, "hello", wait).await.is_some());
+        open.push(stream);
+    }
+
+    // The third connection is over the limit.
+    let mut stream = connect().await.unwrap();
+    let response = try_get(&mut stream, "503 Service Unavailable", wait)
+        .await
+        .unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+        "{}",
+        response
+    );
+
+    // Closing a connection frees up a slot.
+    open.pop();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut stream = connect().await.unwrap();
+    assert!(try_get(&mut stream, "hello", wait).await.is_some());
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn idle_timeout() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8876;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    server.idle_timeout(Duration::from_millis(200));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let connect = || tokio::net::TcpStream::connect(format!("{}:{}", HOST, port));
+    let wait = Duration::from_secs(5);
+
+    // Connections that never send anything are closed.
+    let mut stream = connect().await.unwrap();
+    let start = std::time::Instant::now();
+    let mut buf = vec![];
+    let n = tokio::time::timeout(wait, stream.read_to_end(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(n, 0);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // So are connections that go idle after a request.
+    let mut stream = connect().await.unwrap();
+    assert!(try_get(&mut stream, "hello", wait).await.is_some());
+    let mut buf = vec![];
+    tokio::time::timeout(wait, stream.read_to_end(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(buf.is_empty());
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn header_timeout() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8877;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::handler(|_| async move { Ok("hello") }));
+    server.header_timeout(Duration::from_millis(200));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let connect = || tokio::net::TcpStream::connect(format!("{}:{}", HOST, port));
+    let wait = Duration::from_secs(5);
+
+    // Headers that stall midway get the connection closed.
+    let mut stream = connect().await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n")
+        .await
+        .unwrap();
+    let start = std::time::Instant::now();
+    let mut buf = vec![];
+    tokio::time::timeout(wait, stream.read_to_end(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(buf.is_empty());
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // Idle connections aren't affected, and neither are requests that complete in time.
+    let mut stream = connect().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(try_get(&mut stream, "hello", wait).await.is_some());
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn service_max_body() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8878;
+    let mut server = Server::new(HOST, port);
+    server.route_default(
+        handlers::service(|r: Request, _: ()| async move { Ok(r.content().await) })
+            .with_max_body(10),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let read_all = |mut stream: tokio::net::TcpStream| async move {
+        let mut buf = vec![];
+        stream.read_to_end(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf).to_string()
+    };
+
+    // Small bodies get through.
+    let mut client = Client::new(format!("{}:{}", HOST, port))
+        .connect()
+        .await
+        .unwrap();
+    let mut request = Request::new(Method::POST, "/");
+    request.headers.set("content-length", "5");
+    request.body = "hello".into();
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(response.content().await, "hello");
+
+    // Large bodies are rejected by their length, without waiting for them.
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\nabc")
+        .await
+        .unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(5), read_all(stream))
+        .await
+        .unwrap();
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+    // Chunked bodies are rejected as soon as they cross the limit, and the connection closes.
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n8\r\n12345678\r\n8\r\n12345678\r\n",
+        )
+        .await
+        .unwrap();
+    let response = tokio::time::timeout(Duration::from_secs(5), read_all(stream))
+        .await
+        .unwrap();
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    assert!(response.contains("Connection: close\r\n"));
+
+    shutdown_server(shutdown).await;
+}
+
+struct FramedHandler {}
+
+#[async_trait]
+impl Handler for FramedHandler {
+    async fn handle(
+        &self,
+        _r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let mut response = Response::new(status::OK);
+        response.headers.set("X-Frame-Options", "SAMEORIGIN");
+        response.set_body("framed");
+        w.write_all(response.serialize().as_bytes()).await.unwrap();
+        Ok(handler::Action::Done)
+    }
+}
+
+#[tokio::test]
+async fn security_headers() {
+    let port = 8879;
+    let mut server = Server::new(HOST, port);
+    let headers = handlers::SecurityHeaders::new()
+        .with_hsts_max_age(Duration::from_secs(600))
+        .with_hsts_subdomains()
+        .with_content_security_policy("default-src 'self'");
+    server.route(
+        "/framed",
+        hype::middleware::Stack::new()
+            .push(headers.clone())
+            .push(FramedHandler {}),
+    );
+    server.route_default(
+        hype::middleware::Stack::new()
+            .push(headers)
+            .push(handlers::handler(
+                |_| async move { Ok("hello".to_string()) },
+            )),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.connect().await.unwrap();
+
+    // The headers are added to the downstream handler's response.
+    let response = client
+        .send_request(&Request::new(Method::GET, "/"))
+        .await
+        .unwrap();
+    assert_eq!(response.status.code, 200);
+    assert_eq!(
+        response
+            .headers
+            .get_first("strict-transport-security")
+            .unwrap(),
+        "max-age=600; includeSubDomains"
+    );
+    assert_eq!(
+        response
+            .headers
+            .get_first("x-content-type-options")
+            .unwrap(),
+        "nosniff"
+    );
+    assert_eq!(
+        response.headers.get_first("x-frame-options").unwrap(),
+        "DENY"
+    );
+    assert_eq!(
+        response
+            .headers
+            .get_first("content-security-policy")
+            .unwrap(),
+        "default-src 'self'"
+    );
+    assert_eq!(response.content().await, "hello");
+
+    // Headers set by the downstream handler win.
+    let response = client
+        .send_request(&Request::new(Method::GET, "/framed"))
+        .await
+        .unwrap();
+    assert_eq!(response.headers.get("x-frame-options").unwrap().len(), 1);
+    assert_eq!(
+        response.headers.get_first("x-frame-options").unwrap(),
+        "SAMEORIGIN"
+    );
+    assert_eq!(
+        response
+            .headers
+            .get_first("x-content-type-options")
+            .unwrap(),
+        "nosniff"
+    );
+    assert_eq!(response.content().await, "framed");
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn expect_continue() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8883;
+    let mut server = Server::new(HOST, port);
+    server.check_continue(|r| r.path() != "/reject");
+    server.route_default(handlers::service(|r: Request, _: ()| async move {
+        Ok(r.content().await)
+    }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let connect = || tokio::net::TcpStream::connect(format!("{}:{}", HOST, port));
+    async fn read_some(stream: &mut tokio::net::TcpStream) -> String {
+        let mut buf = vec![0u8; 1024];
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .expect("timed out waiting for the server")
+            .unwrap();
+        String::from_utf8_lossy(&buf[..n]).to_string()
+    }
+
+    // The client waits for the 100 before sending the body.
+    let mut stream = connect().await.unwrap();
+    stream
+        .write_all(
+            b"POST /upload HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        read_some(&mut stream).await,
+        "HTTP/1.1 100 Continue\r\n\r\n"
+    );
+    stream.write_all(b"hello").await.unwrap();
+    let mut response = String::new();
+    while !response.ends_with("hello") {
+        response.push_str(&read_some(&mut stream).await);
+    }
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+    // Rejected requests get a 417, and the connection is closed.
+    for request in [
+        "POST /reject HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n",
+        "POST /upload HTTP/1.1\r\nHost: localhost\r\nExpect: magic\r\nContent-Length: 5\r\n\r\n",
+    ] {
+        let mut stream = connect().await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 417 Expectation Failed\r\n"),
+            "{}",
+            response
+        );
+    }
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn client_expect_continue() {
+    let port = 8884;
+    let mut server = Server::new(HOST, port);
+    server.check_continue(|r| r.path() != "/reject");
+    server.route_default(handlers::service(|r: Request, _: ()| async move {
+        Ok(r.content().await)
+    }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.use_expect_continue(true).connect().await.unwrap();
+    let upload = |path: &str| {
+        let mut request = Request::new(Method::POST, path);
+        request.headers.set("content-length", "5");
+        request.body = "hello".into();
+        request
+    };
+
+    // The body is sent as soon as the server continues, well before the timeout.
+    for _ in 0..2 {
+        let start = std::time::Instant::now();
+        let response = client.send_request(&upload("/upload")).await.unwrap();
+        assert_eq!(response.status.code, 200);
+        assert_eq!(response.content().await, "hello");
+        assert!(start.elapsed() < hype::client::EXPECT_CONTINUE_TIMEOUT);
+        assert!(!client.is_closed().await);
+    }
+
+    // Rejected uploads get the final response without sending the body.
+    let response = client.send_request(&upload("/reject")).await.unwrap();
+    assert_eq!(response.status.code, 417);
+    assert!(client.is_closed().await);
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn pipelining() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8885;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::service(|r: Request, _: ()| async move {
+        Ok(format!("{}{}", r.path(), r.content().await))
+    }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    // Requests sent in one write, with and without bodies, are handled in order.
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream
+        .write_all(
+            b"GET /a HTTP/1.1\r\nHost: localhost\r\n\r\n\
+              POST /b HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello\
+              GET /c HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    let bodies: Vec<&str> = response
+        .split("HTTP/1.1 200 OK\r\n")
+        .skip(1)
+        .map(|r| r.split("\r\n\r\n").nth(1).unwrap())
+        .collect();
+    assert_eq!(bodies, vec!["/a", "/bhello", "/c"], "{}", response);
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn pipelined_response_order() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8886;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::service(|r: Request, _: ()| async move {
+        // Earlier requests take longer, so they'd finish last if handled concurrently.
+        let delay = r.headers.get_first("x-delay").unwrap().parse().unwrap();
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        Ok(r.content().await)
+    }));
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let requests: String = [("one", 100), ("two", 50), ("three", 0)]
+        .iter()
+        .map(|(body, delay)| {
+            format!(
+                "POST / HTTP/1.1\r\nHost: localhost\r\nX-Delay: {}\r\nContent-Length: {}\r\n\r\n{}",
+                delay,
+                body.len(),
+                body
+            )
+        })
+        .collect();
+
+    let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream.write_all(requests.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    while !response.ends_with("three") {
+        let mut buf = vec![0u8; 1024];
+        let n = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(n > 0, "{}", response);
+        response.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+
+    let bodies: Vec<&str> = response
+        .split("HTTP/1.1 200 OK\r\n")
+        .skip(1)
+        .map(|r| r.split("\r\n\r\n").nth(1).unwrap())
+        .collect();
+    assert_eq!(bodies, vec!["one", "two", "three"], "{}", response);
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn conn_stats() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8887;
+    let mut server = Server::new(HOST, port);
+    server.route_default(handlers::Status::new(status::OK, "OK"));
+    let tracker = server.conn_tracker();
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    // Waits for the tracker to catch up with connections opening and closing.
+    let wait_for_active = |active: usize| {
+        let tracker = Arc::clone(&tracker);
+        async move {
+            for _ in 0..100 {
+                let stats = tracker.read().await.stats();
+                if stats.active == active {
+                    return stats;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("expected {} active connections", active);
+        }
+    };
+
+    let connect = || tokio::net::TcpStream::connect(format!("{}:{}", HOST, port));
+    let mut first = connect().await.unwrap();
+    let second = connect().await.unwrap();
+    first
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = [0u8; 1024];
+    assert!(first.read(&mut buf).await.unwrap() > 0);
+
+    let stats = wait_for_active(2).await;
+    assert_eq!(stats.total, 2);
+    let mut requests: Vec<usize> = stats.requests.values().cloned().collect();
+    requests.sort();
+    assert_eq!(requests, vec![0, 1]);
+    let mut ids = tracker.read().await.list();
+    ids.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut expected: Vec<_> = stats.requests.keys().cloned().collect();
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(ids, expected);
+
+    drop(second);
+    let stats = wait_for_active(1).await;
+    assert_eq!(stats.total, 2);
+    assert_eq!(stats.requests.values().collect::<Vec<_>>(), vec![&1]);
+
+    shutdown_server(shutdown).await;
+}
+
+/// Writes a response with a `Content-Length` taken from the `length` query parameter.
+struct MislabeledHandler {}
+
+#[async_trait]
+impl Handler for MislabeledHandler {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let length = r.query_params()["length"].clone();
+        let body = if r.method == Method::HEAD {
+            ""
+        } else {
+            "hello"
+        };
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+            length, body
+        );
+        w.write_all(response.as_bytes())
+            .await
+            .map_err(|e| handler::Error::Failed(e.to_string()))?;
+        Ok(handler::Action::Done)
+    }
+}
+
+#[tokio::test]
+async fn strict_responses() {
+    use tokio::io::AsyncReadExt;
+
+    let port = 8888;
+    let mut server = Server::new(HOST, port);
+    server.strict_responses(true);
+    server.route_default(MislabeledHandler {});
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    // Reads until the connection is closed, or times out.
+    async fn send(request: &str) -> Result<String, tokio::time::error::Elapsed> {
+        let mut stream = tokio::net::TcpStream::connect(format!("{}:{}", HOST, 8888))
+            .await
+            .unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        tokio::time::timeout(
+            Duration::from_millis(500),
+            stream.read_to_string(&mut response),
+        )
+        .await
+        .map(|_| response)
+    }
+
+    // Matching responses keep the connection open.
+    assert!(send("GET /?length=5 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .is_err());
+
+    // Short bodies close the connection once the handler is done.
+    let response = send("GET /?length=10 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+    // Long bodies aren't sent at all.
+    let response = send("GET /?length=2 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(!response.contains("hello"), "{}", response);
+
+    // HEAD responses have no body.
+    assert!(send("HEAD /?length=10 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .is_err());
+
+    shutdown_server(shutdown).await;
+}
+
+struct StreamingHandler {}
+
+#[async_trait]
+impl Handler for StreamingHandler {
+    async fn handle(
+        &self,
+        _r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let failed = |e: std::io::Error| handler::Error::Failed(e.to_string());
+        let mut stream = Response::new(status::OK).stream(w).await.map_err(failed)?;
+        for i in 1..=5 {
+            stream
+                .write(format!("line {}\n", i))
+                .await
+                .map_err(failed)?;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        stream.finish().await.map_err(failed)?;
+        Ok(handler::Action::Done)
+    }
+}
+
+#[tokio::test]
+async fn streaming_response() {
+    use futures::StreamExt;
+
+    let port = 8889;
+    let mut server = Server::new(HOST, port);
+    server.route_default(StreamingHandler {});
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port))
+        .connect()
+        .await
+        .unwrap();
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert_eq!(
+        response.headers.get_first("transfer-encoding").unwrap(),
+        "chunked"
+    );
+    let chunks: Vec<String> = response
+        .body
+        .stream()
+        .map(|chunk| String::from_utf8(chunk).unwrap())
+        .collect()
+        .await;
+    assert_eq!(
+        chunks,
+        (1..=5).map(|i| format!("line {}\n", i)).collect::<Vec<_>>()
+    );
+
+    // The connection can be reused after the stream.
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert_eq!(response.content().await.lines().count(), 5);
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn default_keepalive() {
+    let port = 8890;
+    let mut server = Server::new(HOST, port);
+    server.route_default(MyHandler {});
+    server.default_keepalive(Duration::from_secs(5), 3);
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port))
+        .connect()
+        .await
+        .unwrap();
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert_eq!(
+        response.headers.get_first("keep-alive").unwrap(),
+        "timeout=5, max=2"
+    );
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert_eq!(
+        response.headers.get_first("keep-alive").unwrap(),
+        "timeout=5, max=1"
+    );
+
+    // The last request closes the connection, rather than having the next one dropped.
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert!(response.headers.get("keep-alive").is_none());
+    assert_eq!(response.headers.get_first("connection").unwrap(), "close");
+    assert!(client.send_request(&Request::default()).await.is_err());
+
+    // Clients can negotiate lower limits, but not higher ones.
+    let negotiate = |keepalive: &'static str| async move {
+        let mut request = Request::default();
+        request.headers.set("Connection", "Keep-Alive");
+        request.headers.set("Keep-Alive", keepalive);
+        let mut client = Client::new(format!("{}:{}", HOST, port))
+            .connect()
+            .await
+            .unwrap();
+        let response = client.send_request(&request).await.unwrap();
+        response.headers.get_first("keep-alive").unwrap().clone()
+    };
+    assert_eq!(negotiate("timeout=2, max=2").await, "timeout=2, max=1");
+    assert_eq!(negotiate("timeout=10, max=5").await, "timeout=5, max=2");
+
+    shutdown_server(shutdown).await;
+}
+
+#[tokio::test]
+async fn date_and_server_headers() {
+    let port = 8891;
+    let mut server = Server::new(HOST, port);
+    server.route(
+        "/custom",
+        handlers::handler(|_: Request| async {
+            let mut response = Response::new(status::OK);
+            response.headers.set("Server", "custom");
+            Ok(response)
+        }),
+    );
+    server.route_default(MyHandler {});
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port))
+        .connect()
+        .await
+        .unwrap();
+    let response = client.send_request(&Request::default()).await.unwrap();
+    let date = response.headers.get_first("date").unwrap();
+    let date = chrono::DateTime::parse_from_rfc2822(date).unwrap();
+    assert!((chrono::Utc::now() - date.with_timezone(&chrono::Utc)).num_seconds() < 5);
+    assert_eq!(
+        response.headers.get_first("server").unwrap(),
+        hype::server::SERVER_HEADER
+    );
+
+    // Headers set by the handler take precedence.
+    let response = client
+        .send_request(&Request::new(Method::GET, "/custom"))
+        .await
+        .unwrap();
+    assert_eq!(response.headers.get("server").unwrap(), &vec!["custom"]);
+    assert!(response.headers.get_first("date").is_some());
+
+    shutdown_server(shutdown).await;
+
+    // The Server header can be turned off.
+    let mut server = Server::new(HOST, port + 1);
+    server.route_default(MyHandler {});
+    server.set_server_header(None);
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port + 1))
+        .connect()
+        .await
+        .unwrap();
+    let response = client.send_request(&Request::default()).await.unwrap();
+    assert!(response.headers.get_first("server").is_none());
+    assert!(response.headers.get_first("date").is_some());
+
+    shutdown_server(shutdown).await;
+}
@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use hype::{
+    client::Client,
+    handler::{self, AsyncWriteStream, Handler},
+    handlers::sse::EventStream,
+    request::Request,
+    server::Server,
+};
+
+struct Events {}
+
+#[async_trait]
+impl Handler for Events {
+    async fn handle(
+        &self,
+        _r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let (stream, events) = EventStream::new();
+        tokio::spawn(async move {
+            events.send_event("greeting", "hello");
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            events.send_data("line 1\nline 2");
+        });
+        stream.write(w).await
+    }
+}
+
+#[tokio::test]
+async fn event_stream() {
+    let port = 9160;
+    let mut server = Server::new("127.0.0.1", port);
+    server.route("/events", Events {});
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("127.0.0.1:{}", port));
+    let response = client
+        .connect()
+        .await
+        .unwrap()
+        .send_request(&Request::new(hype::request::Method::GET, "/events"))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        response.headers.get_first("content-type").unwrap(),
+        "text/event-stream"
+    );
+    assert_eq!(
+        response.headers.get_first("transfer-encoding").unwrap(),
+        "chunked"
+    );
+    assert_eq!(
+        response.content().await,
+        "event: greeting\ndata: hello\n\ndata: line 1\ndata: line 2\n\n"
+    );
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hype::{
+    client::Client,
+    handler::{self, AsyncWriteStream, Handler},
+    handlers::{self, Timeout},
+    request::{Method, Request},
+    server::Server,
+    status,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const HOST: &str = "127.0.0.1";
+
+/// Starts a chunked response, and takes its time finishing it.
+struct SlowStream {}
+
+#[async_trait]
+impl Handler for SlowStream {
+    async fn handle(
+        &self,
+        _r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        w.write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n")
+            .await
+            .unwrap();
+        w.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        w.write_all(b"0\r\n\r\n").await.unwrap();
+        Ok(handler::Action::Done)
+    }
+}
+
+fn sleeper(delay: Duration) -> handlers::service::FnHandler<String> {
+    handlers::handler(move |_: Request| async move {
+        tokio::time::sleep(delay).await;
+        Ok("done".to_string())
+    })
+}
+
+#[tokio::test]
+async fn timeouts() {
+    let port = 9200;
+    let short = Duration::from_millis(50);
+    let mut server = Server::new(HOST, port);
+    server.route("/fast", Timeout::new(sleeper(Duration::ZERO), short));
+    server.route(
+        "/slow",
+        Timeout::new(sleeper(Duration::from_secs(5)), short),
+    );
+    server.route(
+        "/gateway",
+        Timeout::new(sleeper(Duration::from_secs(5)), short).with_status(status::GATEWAY_TIMEOUT),
+    );
+    server.route(
+        "/slow-start",
+        Timeout::new(sleeper(Duration::from_secs(5)), short).with_streaming(),
+    );
+    server.route(
+        "/stream",
+        Timeout::new(SlowStream {}, short).with_streaming(),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port))
+        .connect()
+        .await
+        .unwrap();
+    for (path, code) in [
+        ("/fast", 200),
+        ("/slow", 503),
+        ("/gateway", 504),
+        ("/slow-start", 503),
+    ] {
+        let response = client
+            .send_request(&Request::new(Method::GET, path))
+            .await
+            .unwrap();
+        assert_eq!(response.status.code, code, "{}", path);
+        if code == 200 {
+            assert_eq!(response.content().await, "done");
+        } else {
+            response.content().await;
+        }
+    }
+
+    // Streamed responses are cut short, and the connection is closed.
+    let mut stream = TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET /stream HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(2), stream.read_to_string(&mut response))
+        .await
+        .expect("connection should be closed")
+        .unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("5\r\nhello\r\n"), "{}", response);
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use hype::{
+    client::Client,
+    handlers,
+    request::{Method, Request},
+    server::{Server, TRACE_TARGET},
+};
+use log::{
+    kv::{Key, Value, VisitSource},
+    Log, Metadata, Record,
+};
+
+const HOST: &str = "127.0.0.1";
+
+/// The key-values of a captured log line.
+type Line = HashMap<String, String>;
+
+/// A logger that captures the key-values of trace lines.
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<Line>>>);
+
+impl Log for Capture {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == TRACE_TARGET
+    }
+
+    fn log(&self, record: &Record) {
+        struct Visitor(Line);
+
+        impl<'kvs> VisitSource<'kvs> for Visitor {
+            fn visit_pair(
+                &mut self,
+                key: Key<'kvs>,
+                value: Value<'kvs>,
+            ) -> Result<(), log::kv::Error> {
+                self.0.insert(key.to_string(), value.to_string());
+                Ok(())
+            }
+        }
+
+        if self.enabled(record.metadata()) {
+            let mut visitor = Visitor(Line::new());
+            record.key_values().visit(&mut visitor).unwrap();
+            visitor
+                .0
+                .insert("message".into(), record.args().to_string());
+            self.0.lock().unwrap().push(visitor.0);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[tokio::test]
+async fn traces_requests() {
+    let capture = Capture::default();
+    log::set_boxed_logger(Box::new(capture.clone())).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let port = 8880;
+    let mut server = Server::new(HOST, port);
+    server.enable_tracing(true);
+    server.route_default(
+        hype::middleware::Stack::new()
+            .push(handlers::RequestId::new())
+            .push(handlers::handler(|_| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                Ok("hello".to_string())
+            })),
+    );
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let mut client = Client::new(format!("{}:{}", HOST, port));
+    let mut client = client.connect().await.unwrap();
+    let mut request = Request::new(Method::GET, "/trace");
+    request.headers.set("X-Request-ID", "trace-1");
+    let response = client.send_request(&request).await.unwrap();
+    assert_eq!(response.content().await, "hello");
+    drop(client);
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+
+    // Wait for the connection to wind down.
+    let lines = capture.0.clone();
+    let done = || {
+        lines
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|line| line["message"].starts_with("end connection"))
+    };
+    for _ in 0..50 {
+        if done() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let lines = capture.0.lock().unwrap().clone();
+    let messages: Vec<&str> = lines
+        .iter()
+        .map(|line| line["message"].split(' ').take(2).collect::<Vec<_>>())
+        .map(|words| match words[..] {
+            ["begin", "connection"] => "begin connection",
+            ["begin", "request"] => "begin request",
+            ["end", "request"] => "end request",
+            ["end", "connection"] => "end connection",
+            _ => "other",
+        })
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            "begin connection",
+            "begin request",
+            "end request",
+            "end connection"
+        ]
+    );
+
+    // All lines are keyed by the connection ID.
+    let conn_id = &lines[0]["conn_id"];
+    assert_eq!(conn_id.len(), 16);
+    assert!(lines.iter().all(|line| &line["conn_id"] == conn_id));
+
+    let begin = &lines[1];
+    assert_eq!(begin["method"], "GET");
+    assert_eq!(begin["path"], "/trace");
+
+    let end = &lines[2];
+    assert_eq!(end["status"], "200");
+    assert_eq!(end["request_id"], "trace-1");
+    let duration: u64 = end["duration_us"].parse().unwrap();
+    assert!(duration >= 5000, "duration {}us", duration);
+
+    assert_eq!(lines[3]["requests"], "1");
+    let duration: u64 = lines[3]["duration_us"].parse().unwrap();
+    assert!(duration > 0);
+}
@@ -0,0 +1,235 @@
+use async_trait::async_trait;
+use hype::{
+    client::{Client, ClientError},
+    handler::{self, AsyncWriteStream, Handler},
+    handlers::websocket,
+    request::{Method, Request},
+    server::Server,
+    websocket::{accept_key, generate_key, Frame, OpCode},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const HOST: &str = "127.0.0.1";
+
+struct Echo {}
+
+#[async_trait]
+impl Handler for Echo {
+    async fn handle(
+        &self,
+        r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let mut ws = websocket::upgrade(r, w).await?;
+        while let Ok(Some(message)) = ws.recv().await {
+            if ws.send(message).await.is_err() {
+                break;
+            }
+        }
+        Ok(handler::Action::Done)
+    }
+}
+
+/// Switches protocols, and greets the client in the same write, so the greeting is likely
+/// read along with the response.
+struct Greeter {}
+
+#[async_trait]
+impl Handler for Greeter {
+    async fn handle(
+        &self,
+        _r: &Request,
+        w: &mut dyn AsyncWriteStream,
+    ) -> Result<handler::Action, handler::Error> {
+        let mut data = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: greeting\r\n\r\n".to_vec();
+        data.extend(Frame::text("welcome").encode());
+        w.write_all(&data).await.unwrap();
+        w.flush().await.unwrap();
+        Ok(handler::Action::Done)
+    }
+}
+
+#[test]
+fn accept_keys() {
+    // From RFC 6455, section 1.3
+    assert_eq!(
+        accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+        "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+}
+
+#[tokio::test]
+async fn frames() {
+    for len in [0, 5, 125, 126, 65535, 65536] {
+        for masked in [false, true] {
+            let mut frame = Frame::binary(vec![7u8; len]);
+            frame.masked = masked;
+
+            let encoded = frame.encode();
+            let decoded = Frame::read_from(&mut encoded.as_slice(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(decoded, frame);
+        }
+    }
+
+    // Oversized frames and bad control frames are rejected.
+    let encoded = Frame::text("hello").encode();
+    assert!(Frame::read_from(&mut encoded.as_slice(), 4).await.is_err());
+
+    let encoded = Frame::ping(vec![0u8; 126]).encode();
+    assert!(Frame::read_from(&mut encoded.as_slice(), usize::MAX)
+        .await
+        .is_err());
+}
+
+async fn read_head(stream: &mut TcpStream) -> String {
+    let mut buf = vec![];
+    while !buf.ends_with(b"\r\n\r\n") {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).await.unwrap();
+        buf.push(byte[0]);
+    }
+    String::from_utf8(buf).unwrap()
+}
+
+#[tokio::test]
+async fn echo() {
+    let port = 9180;
+    let mut server = Server::new(HOST, port);
+    server.route("/ws", Echo {});
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    // Requests without the upgrade headers are rejected.
+    let mut stream = TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET /ws HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(read_head(&mut stream)
+        .await
+        .starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    let mut stream = TcpStream::connect(format!("{}:{}", HOST, port))
+        .await
+        .unwrap();
+    stream
+        .write_all(
+            b"GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+              Connection: keep-alive, Upgrade\r\nSec-WebSocket-Version: 13\r\n\
+              Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+    let head = read_head(&mut stream).await;
+    assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+    assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+    // Messages are echoed, unmasked.
+    stream
+        .write_all(&Frame::text("hello").with_mask().encode())
+        .await
+        .unwrap();
+    let frame = Frame::read_from(&mut stream, 1024).await.unwrap();
+    assert_eq!(frame, Frame::text("hello"));
+
+    // Pings are answered.
+    stream
+        .write_all(&Frame::ping("hi").with_mask().encode())
+        .await
+        .unwrap();
+    let frame = Frame::read_from(&mut stream, 1024).await.unwrap();
+    assert_eq!(frame, Frame::pong("hi"));
+
+    // The closing handshake is echoed.
+    stream
+        .write_all(&Frame::close(1000, "bye").with_mask().encode())
+        .await
+        .unwrap();
+    let frame = Frame::read_from(&mut stream, 1024).await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    assert_eq!(frame.close_code(), Some(1000));
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}
+
+#[tokio::test]
+async fn client_upgrade() {
+    let port = 9181;
+    let mut server = Server::new(HOST, port);
+    server.route("/ws", Echo {});
+    server.route("/greet", Greeter {});
+    let ready = server.start_notifier();
+    let shutdown = server.shutdown();
+    tokio::spawn(async move { server.start().await.unwrap() });
+    ready.notified().await;
+
+    let address = format!("{}:{}", HOST, port);
+    let upgrade_request = |path: &str| {
+        let key = generate_key();
+        let mut request = Request::new(Method::GET, path);
+        request.headers.set("Upgrade", "websocket");
+        request.headers.set("Connection", "Upgrade");
+        request.headers.set("Sec-WebSocket-Version", "13");
+        request.headers.set("Sec-WebSocket-Key", &key);
+        (request, key)
+    };
+
+    // The client gets the raw connection, and speaks the new protocol over it.
+    let client = Client::new(&address).connect().await.unwrap();
+    let (request, key) = upgrade_request("/ws");
+    let mut upgraded = client.send_request_upgrade(&request).await.unwrap();
+    assert_eq!(upgraded.response.status.code, 101);
+    assert_eq!(
+        upgraded
+            .response
+            .headers
+            .get_first("sec-websocket-accept")
+            .unwrap(),
+        &accept_key(&key)
+    );
+    assert!(upgraded.buffered.is_empty());
+
+    upgraded
+        .writer
+        .write_all(&Frame::text("hello").with_mask().encode())
+        .await
+        .unwrap();
+    let frame = Frame::read_from(&mut upgraded.reader, 1024).await.unwrap();
+    assert_eq!(frame, Frame::text("hello"));
+
+    // Bytes read past the response are handed over.
+    let client = Client::new(&address).connect().await.unwrap();
+    let (request, _) = upgrade_request("/greet");
+    let upgraded = client.send_request_upgrade(&request).await.unwrap();
+    assert_eq!(
+        upgraded.response.headers.get_first("upgrade").unwrap(),
+        "greeting"
+    );
+    let mut reader = upgraded.buffered.as_slice().chain(upgraded.reader);
+    let frame = Frame::read_from(&mut reader, 1024).await.unwrap();
+    assert_eq!(frame, Frame::text("welcome"));
+
+    // Other responses are errors.
+    let client = Client::new(&address).connect().await.unwrap();
+    let result = client
+        .send_request_upgrade(&Request::new(Method::GET, "/ws"))
+        .await;
+    match result {
+        Err(ClientError::UpgradeRefused(response)) => assert_eq!(response.status.code, 400),
+        _ => panic!("upgrade should be refused"),
+    }
+
+    shutdown.0.send(true).await.unwrap();
+    shutdown.1.notified().await;
+}